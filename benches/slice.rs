@@ -0,0 +1,91 @@
+//! Compare `SliceParser` with `Parser` reading the same dump from memory
+//!
+//! Run with `cargo bench --bench slice`. Prints the best of several runs for each parser, on a
+//! dump of scalar changes only and on one that mixes scalars, vectors and reals.
+
+extern crate vcd;
+
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use vcd::{Parser, SliceParser};
+
+const RUNS: usize = 5;
+
+/// Build a dump with 64 scalars, and optionally 16 8-bit vectors and 4 reals, changing at each of
+/// `steps` timestamps
+fn dump(steps: usize, mixed: bool) -> Vec<u8> {
+    let mut s = String::from("$timescale 1ns $end\n$scope module top $end\n");
+    for i in 0..64 {
+        writeln!(s, "$var wire 1 s{} bit{} $end", i, i).unwrap();
+    }
+    if mixed {
+        for i in 0..16 {
+            writeln!(s, "$var wire 8 v{} bus{} $end", i, i).unwrap();
+        }
+        for i in 0..4 {
+            writeln!(s, "$var real 64 r{} level{} $end", i, i).unwrap();
+        }
+    }
+    s.push_str("$upscope $end\n$enddefinitions $end\n");
+
+    for t in 0..steps {
+        writeln!(s, "#{}", t * 10).unwrap();
+        for i in 0..64 {
+            if (t + i) % 3 == 0 {
+                writeln!(s, "{}s{}", (t + i) & 1, i).unwrap();
+            }
+        }
+        if mixed {
+            for i in 0..16 {
+                writeln!(s, "b{:b} v{}", (t * 7 + i) & 0xff, i).unwrap();
+            }
+            for i in 0..4 {
+                writeln!(s, "r{} r{}", t as f64 * 0.25 + i as f64, i).unwrap();
+            }
+        }
+    }
+    s.into_bytes()
+}
+
+fn best<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    let mut best = None;
+    let mut count = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        count = f();
+        let elapsed = start.elapsed();
+        if best.map_or(true, |b| elapsed < b) {
+            best = Some(elapsed);
+        }
+    }
+    (best.unwrap(), count)
+}
+
+fn compare(name: &str, data: &[u8]) {
+    let (read, n1) = best(|| {
+        let mut p = Parser::new(data);
+        p.parse_header().unwrap();
+        let mut n = 0;
+        while let Some(_) = p.next_event().unwrap() { n += 1; }
+        n
+    });
+    let (slice, n2) = best(|| {
+        let mut p = SliceParser::new(data);
+        p.parse_header().unwrap();
+        let mut n = 0;
+        while let Some(_) = p.next_event().unwrap() { n += 1; }
+        n
+    });
+    assert_eq!(n1, n2);
+
+    println!("{}: {} MB, {} commands", name, data.len() / 1_000_000, n1);
+    println!("  Parser::next_event       {:>8.1} ms", read.as_secs_f64() * 1e3);
+    println!("  SliceParser::next_event  {:>8.1} ms  ({:.2}x)", slice.as_secs_f64() * 1e3,
+        read.as_secs_f64() / slice.as_secs_f64());
+}
+
+fn main() {
+    compare("scalar", &dump(200_000, false));
+    compare("mixed", &dump(100_000, true));
+}
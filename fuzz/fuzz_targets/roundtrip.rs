@@ -0,0 +1,28 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate vcd;
+
+use vcd::{Command, Header, Parser, Writer};
+
+// Headers and commands written by `Writer` must parse back unchanged
+fuzz_target!(|input: (Header, Vec<Command>)| {
+    let (header, commands) = input;
+    let commands: Vec<Command> = commands.into_iter().filter(|c| match *c {
+        Command::End(_) => false,
+        _ => true,
+    }).collect();
+
+    let mut buf = Vec::new();
+    {
+        let mut w = Writer::new(&mut buf);
+        w.header(&header).unwrap();
+        for c in &commands { w.command(c).unwrap(); }
+    }
+
+    let mut p = Parser::new(&buf[..]);
+    let parsed = p.parse_header().unwrap();
+    assert_eq!(format!("{:?}", parsed), format!("{:?}", header));
+    let parsed: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(parsed, commands);
+});
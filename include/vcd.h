@@ -0,0 +1,72 @@
+/* C interface to the vcd crate, built with `cargo build --release --features ffi` */
+
+#ifndef VCD_H
+#define VCD_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+    VCD_OK = 0,
+    VCD_END = 1,
+    VCD_IO_ERROR = -1,
+    VCD_PARSE_ERROR = -2,
+    VCD_INVALID_ARGUMENT = -3,
+    VCD_PANIC = -4,  /* internal error; only free the handles passed to the call */
+} VcdStatus;
+
+typedef enum {
+    VCD_TIMESTAMP = 0,
+    VCD_CHANGE = 1,
+} VcdCommandKind;
+
+/* Other commands in the data section, such as $dumpvars and $comment, are skipped */
+typedef struct {
+    VcdCommandKind kind;
+    uint64_t time;
+    uint64_t code;
+    const char *value;  /* valid until the next call on the parser */
+    double real;        /* NaN unless the variable is real */
+} VcdCommand;
+
+typedef struct {
+    uint64_t code;
+    uint32_t size;
+    const char *var_type;  /* valid until the parser is freed */
+    const char *path;      /* valid until the parser is freed */
+} VcdVar;
+
+typedef struct VcdParser VcdParser;
+typedef struct VcdWriter VcdWriter;
+
+const char *vcd_last_error(void);
+
+VcdParser *vcd_parser_open(const char *path);
+VcdParser *vcd_parser_from_memory(const uint8_t *data, size_t len);
+void vcd_parser_free(VcdParser *parser);
+VcdStatus vcd_parser_header(VcdParser *parser);
+size_t vcd_parser_var_count(const VcdParser *parser);
+VcdStatus vcd_parser_var(const VcdParser *parser, size_t index, VcdVar *out);
+VcdStatus vcd_parser_next(VcdParser *parser, VcdCommand *out);
+
+VcdWriter *vcd_writer_create(const char *path);
+VcdStatus vcd_writer_timescale(VcdWriter *writer, uint32_t ts, const char *unit);
+VcdStatus vcd_writer_scope(VcdWriter *writer, const char *name);
+VcdStatus vcd_writer_upscope(VcdWriter *writer);
+VcdStatus vcd_writer_var(VcdWriter *writer, const char *var_type, uint32_t size,
+                         const char *reference, uint64_t *code);
+VcdStatus vcd_writer_enddefinitions(VcdWriter *writer);
+VcdStatus vcd_writer_timestamp(VcdWriter *writer, uint64_t time);
+VcdStatus vcd_writer_change(VcdWriter *writer, uint64_t code, const char *value);
+VcdStatus vcd_writer_change_real(VcdWriter *writer, uint64_t code, double value);
+VcdStatus vcd_writer_close(VcdWriter *writer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
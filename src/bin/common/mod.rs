@@ -0,0 +1,19 @@
+//! Helpers shared by the command-line tools
+
+use regex::{self, Regex};
+
+/// Convert a glob, where `*` matches any run of characters and `?` any one character, to a regex
+/// matching the whole path
+pub fn glob_to_regex(glob: &str) -> Regex {
+    let mut re = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Regex::new(&re).expect("escaped glob is a valid regex")
+}
+
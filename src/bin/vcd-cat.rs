@@ -0,0 +1,104 @@
+extern crate vcd;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::process;
+
+/// Read a VCD file (or stdin) and write it to `out` in canonical form, returning what had to be
+/// worked around. Commands with unknown keywords are kept, and after a syntax error in the data
+/// section the parser skips ahead to the next command it can read. An error in the header, or
+/// while reading or writing, ends the copy after writing everything before it.
+fn cat(input: Box<io::Read>, out: &mut io::Write) -> Result<vcd::Diagnostics, vcd::Error> {
+    let mut writer = vcd::Writer::new(out);
+    let mut reader = vcd::Parser::new(BufReader::new(input));
+    reader.set_strict(false);
+
+    let header = try!(reader.parse_header());
+    try!(writer.header(&header));
+    loop {
+        match reader.next() {
+            Some(Ok(cmd)) => try!(writer.command(&cmd)),
+            Some(Err(vcd::Error::Io(e))) => return Err(vcd::Error::Io(e)),
+            Some(Err(_)) => if !try!(reader.resync()) { break },
+            None => break,
+        }
+    }
+    Ok(reader.diagnostics().clone())
+}
+
+/// Print what was skipped or kept without being understood to stderr. Returns `false` if any
+/// part of the input was skipped.
+fn report(diagnostics: &vcd::Diagnostics) -> bool {
+    for &(ref keyword, position) in &diagnostics.unknown_commands {
+        eprintln!("vcd-cat: {}: kept unknown command ${}", position, keyword);
+    }
+    for &(ref kind, position) in &diagnostics.recovered_errors {
+        eprintln!("vcd-cat: {}: {}, skipped to the next command", position, kind);
+    }
+    if !diagnostics.is_clean() {
+        eprintln!("vcd-cat: {}", diagnostics);
+    }
+    diagnostics.recovered_errors.is_empty()
+}
+
+/// Exits with status 1 and a message on stderr if part of the input had to be skipped, or if the
+/// input could not be read at all.
+pub fn main() {
+    let input: Box<io::Read> = match env::args().nth(1) {
+        None => Box::new(io::stdin()),
+        Some(ref path) if path == "-" => Box::new(io::stdin()),
+        Some(ref path) if path.starts_with("-") => {
+            eprintln!("usage: vcd-cat [FILE]");
+            process::exit(2);
+        }
+        Some(path) => match File::open(&path) {
+            Ok(f) => Box::new(f),
+            Err(e) => {
+                eprintln!("vcd-cat: {}: {}", path, e);
+                process::exit(2);
+            }
+        },
+    };
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let result = cat(input, &mut out).and_then(|d| {
+        try!(out.flush());
+        Ok(d)
+    });
+    match result {
+        Ok(ref d) if report(d) => (),
+        Ok(_) => process::exit(1),
+        Err(e) => {
+            eprintln!("vcd-cat: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[test]
+fn malformed() {
+    let sample = b"$scope module top $end $var wire 1 ! a $end $upscope $end $enddefinitions $end
+#0 1!
+#1x 0!
+$vendor note $end
+#2 q!
+0!
+#3 1!
+";
+    let mut out = Vec::new();
+    let diagnostics = cat(Box::new(&sample[..]), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.ends_with("#0\n1!\n$vendor note $end\n#2\n0!\n#3\n1!\n"), "{}", out);
+
+    assert_eq!(diagnostics.unknown_commands.iter().map(|u| (&u.0[..], u.1.line))
+        .collect::<Vec<_>>(), vec![("vendor", 4)]);
+    assert_eq!(diagnostics.recovered_errors.iter().map(|e| (e.0.clone(), e.1.line))
+        .collect::<Vec<_>>(),
+        vec![(vcd::ErrorKind::InvalidNumber, 3), (vcd::ErrorKind::UnexpectedCharacter(b'q'), 5)]);
+    assert!(!report(&diagnostics));
+
+    // Errors in the header cannot be skipped
+    assert!(cat(Box::new(&b"$scope module $end\n"[..]), &mut Vec::new()).is_err());
+}
@@ -0,0 +1,102 @@
+extern crate vcd;
+extern crate regex;
+
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+use std::collections::HashSet;
+
+use vcd::{Header, Radix, VarValue};
+use vcd::diff::{diff, DiffOptions};
+
+mod common;
+use common::glob_to_regex;
+
+const USAGE: &'static str = "\
+usage: vcd-diff [OPTIONS] LEFT RIGHT
+
+Compare two VCD files signal by signal, printing the first time each signal differs. Exits with
+status 0 if they match, 1 if they differ and 2 on error.
+
+  --from T          ignore differences before time T
+  --to T            ignore differences at or after time T
+  --signal GLOB     only compare signals whose dotted path matches GLOB (may be repeated)
+  --tolerance X     treat real values within X of each other as equal
+  -q                print nothing, only set the exit status";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail<E: ::std::fmt::Display>(context: &str, e: E) -> ! {
+    eprintln!("vcd-diff: {}: {}", context, e);
+    process::exit(2);
+}
+
+fn open(path: &str) -> (Header, vcd::Parser<BufReader<File>>) {
+    let f = File::open(path).unwrap_or_else(|e| fail(path, e));
+    let mut parser = vcd::Parser::new(BufReader::new(f));
+    let header = parser.parse_header().unwrap_or_else(|e| fail(path, e));
+    (header, parser)
+}
+
+fn show(v: &Option<VarValue>) -> String {
+    v.as_ref().map_or("(unassigned)".to_string(), |v| v.format(Radix::Binary))
+}
+
+fn parse_arg<T: ::std::str::FromStr>(arg: Option<String>) -> T {
+    arg.and_then(|a| a.parse().ok()).unwrap_or_else(|| usage())
+}
+
+pub fn main() {
+    let mut options = DiffOptions::default();
+    let (mut from, mut to) = (None, None);
+    let mut globs = Vec::new();
+    let mut quiet = false;
+    let mut files = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--from" => from = Some(parse_arg(args.next())),
+            "--to" => to = Some(parse_arg(args.next())),
+            "--signal" => globs.push(glob_to_regex(&args.next().unwrap_or_else(|| usage()))),
+            "--tolerance" => options.tolerance = parse_arg(args.next()),
+            "-q" => quiet = true,
+            a if a.starts_with("-") => usage(),
+            _ => files.push(arg.clone()),
+        }
+    }
+    if files.len() != 2 { usage(); }
+    if from.is_some() || to.is_some() {
+        options.window = Some(from.unwrap_or(0)..to.unwrap_or(::std::u64::MAX));
+    }
+
+    let (left, left_commands) = open(&files[0]);
+    let (right, right_commands) = open(&files[1]);
+
+    if !globs.is_empty() {
+        let selected: HashSet<String> = left.iter_vars().chain(right.iter_vars())
+            .map(|(path, _)| path.join("."))
+            .filter(|p| globs.iter().any(|g| g.is_match(p))).collect();
+        options.signals = Some(selected);
+    }
+
+    let result = diff(&left, left_commands, &right, right_commands, &options)
+        .unwrap_or_else(|e| fail("error", e));
+
+    if !quiet {
+        for d in &result.divergences {
+            println!("{}: differs at {}: {} != {}", d.path, d.time, show(&d.left), show(&d.right));
+        }
+        for p in &result.only_left {
+            println!("{}: only in {}", p, files[0]);
+        }
+        for p in &result.only_right {
+            println!("{}: only in {}", p, files[1]);
+        }
+    }
+    process::exit(if result.is_equal() { 0 } else { 1 });
+}
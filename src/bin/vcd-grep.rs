@@ -0,0 +1,81 @@
+extern crate vcd;
+extern crate regex;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::process;
+
+use regex::Regex;
+
+mod common;
+use common::glob_to_regex;
+
+const USAGE: &'static str = "\
+usage: vcd-grep [-E] [-v] PATTERN... [-f FILE]
+
+Write the signals whose dotted path (e.g. top.cpu.pc) matches any PATTERN from a VCD file (or
+stdin) to stdout.
+
+  -E        patterns are regular expressions, matched anywhere in the path
+            (default: globs matching the whole path, with * and ?)
+  -v        keep the signals that do not match
+  -f FILE   read FILE instead of stdin";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn run(input: Box<io::Read>, patterns: &[Regex], invert: bool) -> Result<(), vcd::Error> {
+    let mut reader = vcd::Parser::new(BufReader::new(input));
+    let header = try!(reader.parse_header());
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    try!(vcd::transform::filter(&header, reader, |path, _| {
+        patterns.iter().any(|p| p.is_match(path)) != invert
+    }, &mut out));
+    try!(out.flush());
+    Ok(())
+}
+
+pub fn main() {
+    let (mut regex, mut invert, mut path) = (false, false, None);
+    let mut patterns = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-E" => regex = true,
+            "-v" => invert = true,
+            "-f" => path = Some(args.next().unwrap_or_else(|| usage())),
+            a if a.starts_with("-") => usage(),
+            _ => patterns.push(arg.clone()),
+        }
+    }
+    if patterns.is_empty() { usage(); }
+
+    let patterns: Vec<Regex> = patterns.iter().map(|p| {
+        if !regex { return glob_to_regex(p); }
+        Regex::new(p).unwrap_or_else(|e| {
+            eprintln!("vcd-grep: invalid pattern {}: {}", p, e);
+            process::exit(2);
+        })
+    }).collect();
+
+    let input: Box<io::Read> = match path {
+        None => Box::new(io::stdin()),
+        Some(path) => match File::open(&path) {
+            Ok(f) => Box::new(f),
+            Err(e) => {
+                eprintln!("vcd-grep: {}: {}", path, e);
+                process::exit(2);
+            }
+        },
+    };
+
+    if let Err(e) = run(input, &patterns, invert) {
+        eprintln!("vcd-grep: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,96 @@
+extern crate vcd;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, LineWriter, Write};
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+const USAGE: &'static str = "\
+usage: vcd-merge [--top NAME] [-o OUT] [--follow SECS] FILE[=PREFIX]...
+
+Combine VCD files into one, written to OUT or stdout. Each file's top scope is renamed to PREFIX
+(default: the file name without extension) under a scope NAME (default: top). Times are converted
+to the finest timescale of the inputs.
+
+With --follow, the files may still be being written: the merged output is written as data
+arrives, and each file ends when it has not grown for SECS seconds.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail<E: ::std::fmt::Display>(context: &str, e: E) -> ! {
+    eprintln!("vcd-merge: {}: {}", context, e);
+    process::exit(1);
+}
+
+pub fn main() {
+    let mut top = "top".to_string();
+    let mut output = None;
+    let mut follow = None;
+    let mut files = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--top" => top = args.next().unwrap_or_else(|| usage()),
+            "-o" => output = Some(args.next().unwrap_or_else(|| usage())),
+            "--follow" => {
+                let secs = args.next().and_then(|s| s.parse::<f64>().ok());
+                let secs = secs.unwrap_or_else(|| usage());
+                follow = Some(Duration::from_millis((secs * 1000.0) as u64));
+            }
+            a if a.starts_with("-") => usage(),
+            _ => files.push(arg.clone()),
+        }
+    }
+    if files.is_empty() { usage(); }
+
+    let mut inputs = Vec::new();
+    for arg in &files {
+        let (path, prefix) = match arg.find('=') {
+            Some(i) => (&arg[..i], arg[i + 1..].to_string()),
+            None => {
+                let stem = Path::new(arg).file_stem().map(|s| s.to_string_lossy().into_owned());
+                (&arg[..], stem.unwrap_or_else(|| arg.clone()))
+            }
+        };
+        inputs.push((path, prefix));
+    }
+
+    let out: Box<Write> = match output {
+        Some(path) => Box::new(File::create(&path).unwrap_or_else(|e| fail(&path, e))),
+        None => Box::new(io::stdout()),
+    };
+
+    if let Some(idle) = follow {
+        // Write each line as it is produced, for consumers watching the output
+        let mut out = LineWriter::new(out);
+        let poll = Duration::from_millis(100);
+        let merged = vcd::follow::follow_merge(inputs, &top, poll, idle)
+            .unwrap_or_else(|e| fail("error", e));
+        let mut writer = vcd::Writer::new(&mut out);
+        writer.header(merged.header()).unwrap_or_else(|e| fail("error", e));
+        for c in merged {
+            let c = c.unwrap_or_else(|e| fail("error", e));
+            writer.command(&c).unwrap_or_else(|e| fail("error", e));
+        }
+        return;
+    }
+
+    let mut parsed = Vec::new();
+    for (path, prefix) in inputs {
+        let f = File::open(path).unwrap_or_else(|e| fail(path, e));
+        let mut parser = vcd::Parser::new(BufReader::new(f));
+        let header = parser.parse_header().unwrap_or_else(|e| fail(path, e));
+        parsed.push((header, parser, prefix));
+    }
+
+    let mut out = BufWriter::new(out);
+    if let Err(e) = vcd::merge::merge(parsed, &top, &mut out) {
+        fail("error", e);
+    }
+    out.flush().unwrap_or_else(|e| fail("error", e));
+}
@@ -0,0 +1,150 @@
+extern crate vcd;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process;
+use std::collections::HashMap;
+
+use vcd::{Command, IdCode, Scope, ScopeItem, Header};
+
+const USAGE: &'static str = "usage: vcd-stats [-n TOP] [FILE]";
+
+/// Activity of one identifier code
+#[derive(Default)]
+struct Activity {
+    changes: u64,
+    bytes: u64,
+}
+
+/// The number of scopes in `scope`, counting itself
+fn count_scopes(scope: &Scope) -> usize {
+    1 + scope.children.iter().map(|item| match *item {
+        ScopeItem::Scope(ref s) => count_scopes(s),
+        ScopeItem::Var(_) => 0,
+    }).sum::<usize>()
+}
+
+/// The path of the first variable with each code, the number of scopes and the number of
+/// variables
+fn declarations(header: &Header) -> (HashMap<IdCode, String>, usize, usize) {
+    let mut names = HashMap::new();
+    let mut vars = 0;
+    for (path, v) in header.iter_vars() {
+        vars += 1;
+        names.entry(v.code).or_insert_with(|| path.join("."));
+    }
+    (names, header.top_scopes().map(count_scopes).sum(), vars)
+}
+
+/// Approximate size in bytes of a value change as written in a VCD file
+fn change_size(c: &Command) -> u64 {
+    let value = match *c {
+        Command::ChangeScalar(..) => 1,
+        Command::ChangeVector(_, ref v) => v.len() as u64 + 2,
+        Command::ChangeReal(_, v) => v.to_string().len() as u64 + 2,
+        Command::ChangeString(_, ref v) => v.len() as u64 + 2,
+        _ => 0,
+    };
+    value + c.change_value().map_or(0, |(id, _)| id.to_string().len() as u64 + 1)
+}
+
+fn print_header(header: &Header) {
+    if let Some(ref date) = header.date { println!("date:       {}", date.trim()); }
+    if let Some(ref version) = header.version { println!("version:    {}", version.trim()); }
+    if let Some((v, unit)) = header.timescale { println!("timescale:  {} {}", v, unit); }
+}
+
+fn run(input: Box<io::Read>, top: usize) -> Result<(), vcd::Error> {
+    let mut reader = vcd::Parser::new(BufReader::new(input));
+    let header = try!(reader.parse_header());
+
+    let (names, scopes, vars) = declarations(&header);
+
+    let mut activity: HashMap<IdCode, Activity> = HashMap::new();
+    let (mut first, mut last) = (None, None);
+    let (mut timestamps, mut changes) = (0u64, 0u64);
+    for c in reader {
+        let c = try!(c);
+        if let Command::Timestamp(t) = c {
+            first = first.or(Some(t));
+            last = Some(t);
+            timestamps += 1;
+        } else if let Some((id, _)) = c.change_value() {
+            let a = activity.entry(id).or_insert_with(Activity::default);
+            a.changes += 1;
+            a.bytes += change_size(&c);
+            changes += 1;
+        }
+    }
+
+    print_header(&header);
+    println!("scopes:     {}", scopes);
+    println!("variables:  {} ({} distinct ids)", vars, names.len());
+    match (first, last) {
+        (Some(first), Some(last)) => println!("time:       {} to {}", first, last),
+        _ => println!("time:       no timestamps"),
+    }
+    println!("timestamps: {}", timestamps);
+    println!("changes:    {}", changes);
+
+    let total: u64 = activity.values().map(|a| a.bytes).sum();
+    let mut active: Vec<(IdCode, Activity)> = activity.into_iter().collect();
+    active.sort_by(|a, b| b.1.changes.cmp(&a.1.changes).then(b.1.bytes.cmp(&a.1.bytes)));
+
+    if top > 0 && !active.is_empty() {
+        println!();
+        println!("{:>12} {:>12} {:>6}  signal", "changes", "bytes", "share");
+        for &(id, ref a) in active.iter().take(top) {
+            let name = names.get(&id).cloned().unwrap_or_else(|| format!("<undeclared {}>", id));
+            let share = 100.0 * a.bytes as f64 / total as f64;
+            println!("{:>12} {:>12} {:>5.1}%  {}", a.changes, a.bytes, share, name);
+        }
+    }
+    Ok(())
+}
+
+pub fn main() {
+    let mut top = 10;
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-n" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => top = n,
+                None => { eprintln!("{}", USAGE); process::exit(2); }
+            },
+            "-" => path = None,
+            a if a.starts_with("-") => { eprintln!("{}", USAGE); process::exit(2); }
+            _ => path = Some(arg.clone()),
+        }
+    }
+
+    let input: Box<io::Read> = match path {
+        None => Box::new(io::stdin()),
+        Some(path) => match File::open(&path) {
+            Ok(f) => Box::new(f),
+            Err(e) => {
+                eprintln!("vcd-stats: {}: {}", path, e);
+                process::exit(2);
+            }
+        },
+    };
+
+    if let Err(e) = run(input, top) {
+        eprintln!("vcd-stats: {}", e);
+        process::exit(1);
+    }
+}
+
+#[test]
+fn top_level_vars() {
+    let sample = b"$var wire 1 ! a $end $scope module top $end $var wire 1 \" b $end $upscope $end
+$scope module glbl $end $var wire 1 # GSR $end $upscope $end $enddefinitions $end
+";
+    let header = vcd::Parser::new(&sample[..]).parse_header().unwrap();
+    let (names, scopes, vars) = declarations(&header);
+    assert_eq!((scopes, vars), (2, 3));
+    assert_eq!(names[&IdCode::from(0)], "a");
+    assert_eq!(names[&IdCode::from(2)], "glbl.GSR");
+}
@@ -0,0 +1,60 @@
+extern crate vcd;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::process;
+
+const USAGE: &'static str = "\
+usage: vcd-trim [--from T0] [--to T1] [FILE]
+
+Write the part of a VCD file (or stdin) between times T0 and T1 inclusive to stdout, as a
+standalone VCD starting with the value of every signal at T0.";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn run(input: Box<io::Read>, from: u64, to: u64) -> Result<(), vcd::Error> {
+    let mut reader = vcd::Parser::new(BufReader::new(input));
+    let header = try!(reader.parse_header());
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    try!(vcd::transform::window(&header, reader, from, to, &mut out));
+    try!(out.flush());
+    Ok(())
+}
+
+pub fn main() {
+    let (mut from, mut to) = (0, ::std::u64::MAX);
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--from" => from = args.next().and_then(|t| t.parse().ok()).unwrap_or_else(|| usage()),
+            "--to" => to = args.next().and_then(|t| t.parse().ok()).unwrap_or_else(|| usage()),
+            "-" => path = None,
+            a if a.starts_with("-") => usage(),
+            _ => path = Some(arg.clone()),
+        }
+    }
+    if to < from { usage(); }
+
+    let input: Box<io::Read> = match path {
+        None => Box::new(io::stdin()),
+        Some(path) => match File::open(&path) {
+            Ok(f) => Box::new(f),
+            Err(e) => {
+                eprintln!("vcd-trim: {}: {}", path, e);
+                process::exit(2);
+            }
+        },
+    };
+
+    if let Err(e) = run(input, from, to) {
+        eprintln!("vcd-trim: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,162 @@
+extern crate vcd;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process;
+use std::collections::{HashMap, HashSet};
+
+use vcd::{Command, IdCode, Var, VarType};
+
+const USAGE: &'static str = "\
+usage: vcd-validate [-W] [FILE]
+
+Check that a VCD file (or stdin) is valid, printing a diagnostic for each problem found. Exits
+with status 1 if there were errors, or warnings with -W.
+
+Errors: syntax errors, changes to undeclared ids, vector changes wider than the declared
+variable or of the wrong kind, timestamps that go backwards, and ids shared by variables of
+different widths. Warnings: ids shared by several variables, and repeated variable names.
+
+  -W    treat warnings as errors";
+
+/// Collected diagnostics
+struct Report {
+    name: String,
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn error(&mut self, position: &str, msg: &str) {
+        self.errors += 1;
+        println!("{}:{}: error: {}", self.name, position, msg);
+    }
+
+    fn warning(&mut self, position: &str, msg: &str) {
+        self.warnings += 1;
+        println!("{}:{}: warning: {}", self.name, position, msg);
+    }
+}
+
+fn check(input: Box<io::Read>, report: &mut Report) -> Result<(), vcd::Error> {
+    let mut parser = vcd::Parser::new(BufReader::new(input));
+    let header = try!(parser.parse_header());
+
+    let vars: Vec<(String, &Var)> = header.iter_vars().map(|(p, v)| (p.join("."), v)).collect();
+
+    let mut declared: HashMap<IdCode, (u32, VarType, &str)> = HashMap::new();
+    let mut paths = HashSet::new();
+    for &(ref path, v) in &vars {
+        if !paths.insert(path.clone()) {
+            report.warning("header", &format!("variable {} is declared more than once", path));
+        }
+        match declared.get(&v.code) {
+            Some(&(size, _, other)) if size != v.size => report.error("header", &format!(
+                "id {} is used by {} ({} bits) and {} ({} bits)", v.code, other, size, path,
+                v.size)),
+            Some(&(_, _, other)) => report.warning("header", &format!(
+                "id {} is shared by {} and {}", v.code, other, path)),
+            None => { declared.insert(v.code, (v.size, v.var_type.clone(), path)); }
+        }
+    }
+
+    let mut time = None;
+    let mut undeclared = HashSet::new();
+    while let Some(c) = parser.next() {
+        let c = try!(c);
+        let p = parser.command_position();
+        let position = format!("{}:{}", p.line, p.column);
+        match c {
+            Command::Timestamp(t) => {
+                if time.map_or(false, |prev| t < prev) {
+                    report.error(&position, &format!("time goes backwards to {}", t));
+                }
+                time = Some(t);
+                continue;
+            }
+            _ => (),
+        }
+
+        let (id, kind) = match c {
+            Command::ChangeScalar(id, _) => (id, "scalar"),
+            Command::ChangeVector(id, _) => (id, "vector"),
+            Command::ChangeReal(id, _) => (id, "real"),
+            Command::ChangeString(id, _) => (id, "string"),
+            _ => continue,
+        };
+        let (size, var_type, path) = match declared.get(&id) {
+            Some(&(size, ref var_type, path)) => (size, var_type, path),
+            None => {
+                if undeclared.insert(id) {
+                    report.error(&position, &format!("change to undeclared id {}", id));
+                }
+                continue;
+            }
+        };
+        match c {
+            Command::ChangeVector(_, ref v) if v.len() > size as usize => {
+                report.error(&position, &format!("{}-bit value for {}-bit variable {}",
+                    v.len(), size, path));
+            }
+            Command::ChangeScalar(..) | Command::ChangeVector(..) if var_type.is_real() => {
+                report.error(&position, &format!("{} value for real variable {}", kind, path));
+            }
+            Command::ChangeScalar(..) if size != 1 => {
+                report.error(&position, &format!("scalar value for {}-bit variable {}",
+                    size, path));
+            }
+            Command::ChangeReal(..) if !var_type.is_real() => {
+                report.error(&position, &format!("real value for {} variable {}", var_type, path));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+pub fn main() {
+    let mut werror = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match &arg[..] {
+            "-W" => werror = true,
+            "-" => path = None,
+            a if a.starts_with("-") => { eprintln!("{}", USAGE); process::exit(2); }
+            _ => path = Some(arg.clone()),
+        }
+    }
+
+    let (name, input): (String, Box<io::Read>) = match path {
+        None => ("<stdin>".to_string(), Box::new(io::stdin())),
+        Some(path) => match File::open(&path) {
+            Ok(f) => (path, Box::new(f)),
+            Err(e) => {
+                eprintln!("vcd-validate: {}: {}", path, e);
+                process::exit(2);
+            }
+        },
+    };
+
+    let mut report = Report { name: name, errors: 0, warnings: 0 };
+    match check(input, &mut report) {
+        Err(vcd::Error::Syntax { kind, position }) => {
+            report.error(&format!("{}:{}", position.line, position.column), &kind.to_string());
+        }
+        Err(e) => report.error("", &e.to_string()),
+        Ok(()) => (),
+    }
+    if report.errors > 0 || (werror && report.warnings > 0) {
+        process::exit(1);
+    }
+}
+
+#[test]
+fn top_level_vars() {
+    let sample = b"$var wire 1 ! a $end $scope module top $end $var wire 1 \" b $end $upscope $end
+$enddefinitions $end #0 1! 0\"
+";
+    let mut report = Report { name: "sample".to_string(), errors: 0, warnings: 0 };
+    check(Box::new(&sample[..]), &mut report).unwrap();
+    assert_eq!((report.errors, report.warnings), (0, 0));
+}
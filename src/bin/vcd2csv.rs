@@ -0,0 +1,104 @@
+extern crate vcd;
+extern crate regex;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::process;
+
+use regex::Regex;
+use vcd::{Header, Radix};
+use vcd::export::{csv, CsvOptions};
+
+mod common;
+use common::glob_to_regex;
+
+const USAGE: &'static str = "\
+usage: vcd2csv [OPTIONS] [FILE]
+
+Write the values of signals from a VCD file (or stdin) to stdout as CSV, with a row per timestamp.
+
+  --signal GLOB     include signals whose dotted path matches GLOB (may be repeated;
+                    default: all signals)
+  --radix R         show vectors in base R: bin, dec or hex (default: hex)
+  --interval N      write a row every N time units instead of one per timestamp
+  --separator C     separate columns with C instead of a comma";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+/// The dotted paths of the variables matching any of `globs`, or of all of them if there are none
+fn select(header: &Header, globs: &[Regex]) -> Vec<String> {
+    header.iter_vars().map(|(path, _)| path.join("."))
+        .filter(|name| globs.is_empty() || globs.iter().any(|g| g.is_match(name))).collect()
+}
+
+fn run(input: Box<io::Read>, globs: &[Regex], options: &CsvOptions) -> Result<(), vcd::Error> {
+    let mut reader = vcd::Parser::new(BufReader::new(input));
+    let header = try!(reader.parse_header());
+    let signals = select(&header, globs);
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    try!(csv(&header, reader, &signals, options, &mut out));
+    try!(out.flush());
+    Ok(())
+}
+
+pub fn main() {
+    let mut options = CsvOptions::default();
+    let mut globs = Vec::new();
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--signal" => globs.push(glob_to_regex(&args.next().unwrap_or_else(|| usage()))),
+            "--radix" => options.radix = match args.next().as_ref().map(|r| &r[..]) {
+                Some("bin") => Radix::Binary,
+                Some("dec") => Radix::Decimal,
+                Some("hex") => Radix::Hex,
+                _ => usage(),
+            },
+            "--interval" => {
+                options.interval = Some(args.next().and_then(|n| n.parse().ok())
+                    .unwrap_or_else(|| usage()));
+            }
+            "--separator" => {
+                options.separator = args.next().and_then(|s| s.chars().next())
+                    .unwrap_or_else(|| usage());
+            }
+            "-" => path = None,
+            a if a.starts_with("-") => usage(),
+            _ => path = Some(arg.clone()),
+        }
+    }
+
+    let input: Box<io::Read> = match path {
+        None => Box::new(io::stdin()),
+        Some(path) => match File::open(&path) {
+            Ok(f) => Box::new(f),
+            Err(e) => {
+                eprintln!("vcd2csv: {}: {}", path, e);
+                process::exit(2);
+            }
+        },
+    };
+
+    if let Err(e) = run(input, &globs, &options) {
+        eprintln!("vcd2csv: {}", e);
+        process::exit(1);
+    }
+}
+
+#[test]
+fn top_level_vars() {
+    let sample = b"$var wire 1 ! a $end $scope module top $end $var wire 1 \" b $end $upscope $end
+$enddefinitions $end
+";
+    let header = vcd::Parser::new(&sample[..]).parse_header().unwrap();
+    assert_eq!(select(&header, &[]), ["a", "top.b"]);
+    assert_eq!(select(&header, &[glob_to_regex("a")]), ["a"]);
+    assert_eq!(select(&header, &[glob_to_regex("top.*")]), ["top.b"]);
+}
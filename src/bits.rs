@@ -0,0 +1,58 @@
+//! Conversion of vector values to and from `bitvec` types (requires the `bitvec` feature)
+//!
+//! A `Vec<Value>` lists bits from the most significant, as they appear in a VCD file, while the
+//! `BitVec`s here are indexed from the least significant bit. Unknown bits are described by a
+//! separate mask, using the same encoding as Verilog's VPI: a bit set in the mask is `z` if the
+//! corresponding value bit is clear and `x` if it is set. 9-state values are stored as their
+//! closest 4-state value.
+
+use bitvec::vec::BitVec;
+use bitvec::slice::BitSlice;
+
+use Value;
+
+/// Split a vector value into its value bits and its mask of `x` and `z` bits, both indexed from
+/// the least significant bit.
+pub fn to_bitvec(v: &[Value]) -> (BitVec, BitVec) {
+    let mut bits = BitVec::with_capacity(v.len());
+    let mut mask = BitVec::with_capacity(v.len());
+    for &b in v.iter().rev() {
+        let (bit, unknown) = match b.to_four_state() {
+            Value::V1 => (true, false),
+            Value::Z => (false, true),
+            Value::X => (true, true),
+            _ => (false, false),
+        };
+        bits.push(bit);
+        mask.push(unknown);
+    }
+    (bits, mask)
+}
+
+/// Build a vector value from bits indexed from the least significant bit, and an optional mask of
+/// `x` and `z` bits as returned by `to_bitvec`. Bits beyond the end of the mask are known.
+pub fn from_bitvec(bits: &BitSlice, mask: Option<&BitSlice>) -> Vec<Value> {
+    (0..bits.len()).rev().map(|i| {
+        let unknown = mask.and_then(|m| m.get(i).map(|b| *b)).unwrap_or(false);
+        match (bits[i], unknown) {
+            (false, false) => Value::V0,
+            (true, false) => Value::V1,
+            (false, true) => Value::Z,
+            (true, true) => Value::X,
+        }
+    }).collect()
+}
+
+#[test]
+fn roundtrip() {
+    use Value::*;
+
+    let v = vec![V1, X, V0, Z, V0, V1];
+    let (bits, mask) = to_bitvec(&v);
+    assert_eq!(bits.iter().map(|b| *b).collect::<Vec<_>>(),
+        vec![true, false, false, false, true, true]);
+    assert_eq!(mask.iter().map(|b| *b).collect::<Vec<_>>(),
+        vec![false, false, true, false, true, false]);
+    assert_eq!(from_bitvec(&bits, Some(&mask)), v);
+    assert_eq!(from_bitvec(&bits, None), vec![V1, V1, V0, V0, V0, V1]);
+}
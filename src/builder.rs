@@ -0,0 +1,184 @@
+//! Building a `Header` for writing
+
+use std::collections::HashMap;
+
+use {
+    TimescaleUnit,
+    IdCode,
+    ScopeType,
+    VarType,
+    Scope,
+    ScopeItem,
+    Var,
+    Header,
+};
+
+/// Builds a `Header` with fluent methods, giving each variable the next unused `IdCode`.
+///
+/// `build` returns the header along with the code of each variable, keyed by its dot-separated
+/// path from the top scope.
+///
+/// ```
+/// use vcd::{HeaderBuilder, TimescaleUnit, Value, Writer};
+///
+/// let (header, ids) = HeaderBuilder::new()
+///     .version("my sim 1.0")
+///     .timescale(1, TimescaleUnit::NS)
+///     .scope("top", |s| s.wire("clk", 1).scope("cpu", |s| s.reg("pc", 32)))
+///     .build();
+///
+/// let mut buf = Vec::new();
+/// let mut w = Writer::new(&mut buf);
+/// w.header(&header).unwrap();
+/// w.timestamp(0).unwrap();
+/// w.change_scalar(ids["top.clk"], Value::V1).unwrap();
+/// assert_eq!(ids["top.cpu.pc"].to_string(), "\"");
+/// ```
+#[derive(Debug, Default)]
+pub struct HeaderBuilder {
+    header: Header,
+    next_id: u32,
+    ids: HashMap<String, IdCode>,
+}
+
+impl HeaderBuilder {
+    pub fn new() -> HeaderBuilder {
+        HeaderBuilder::default()
+    }
+
+    /// Set the `$date`
+    pub fn date(mut self, date: &str) -> HeaderBuilder {
+        self.header.date = Some(date.to_string());
+        self
+    }
+
+    /// Set the `$date` to the current time in UTC, in the `ctime` format written by most
+    /// simulators (requires the `chrono` feature)
+    #[cfg(feature = "chrono")]
+    pub fn date_now(self) -> HeaderBuilder {
+        let now = ::chrono::DateTime::<::chrono::Utc>::from(::std::time::SystemTime::now());
+        let date = now.format("%a %b %e %H:%M:%S %Y").to_string();
+        self.date(&date)
+    }
+
+    /// Set the `$version`
+    pub fn version(mut self, version: &str) -> HeaderBuilder {
+        self.header.version = Some(version.to_string());
+        self
+    }
+
+    /// Set the `$comment`
+    pub fn comment(mut self, comment: &str) -> HeaderBuilder {
+        self.header.comment = Some(comment.to_string());
+        self
+    }
+
+    /// Set the `$timescale`
+    pub fn timescale(mut self, value: u32, unit: TimescaleUnit) -> HeaderBuilder {
+        self.header.timescale = Some((value, unit));
+        self
+    }
+
+    /// Set the top scope, a module named `identifier` whose contents are added by `f`
+    pub fn scope<F>(mut self, identifier: &str, f: F) -> HeaderBuilder
+        where F: FnOnce(ScopeBuilder) -> ScopeBuilder {
+        let s = f(ScopeBuilder {
+            scope: Scope { identifier: identifier.to_string(), ..Default::default() },
+            path: identifier.to_string(),
+            next_id: self.next_id,
+            ids: self.ids,
+        });
+        self.header.scope = s.scope;
+        self.next_id = s.next_id;
+        self.ids = s.ids;
+        self
+    }
+
+    /// Return the header and the `IdCode` of each variable by its path
+    pub fn build(self) -> (Header, HashMap<String, IdCode>) {
+        (self.header, self.ids)
+    }
+}
+
+/// Adds variables and child scopes to a scope, from `HeaderBuilder::scope`
+#[derive(Debug)]
+pub struct ScopeBuilder {
+    scope: Scope,
+    path: String,
+    next_id: u32,
+    ids: HashMap<String, IdCode>,
+}
+
+impl ScopeBuilder {
+    /// Set the type of the scope, which is a module by default
+    pub fn scope_type(mut self, scope_type: ScopeType) -> ScopeBuilder {
+        self.scope.scope_type = scope_type;
+        self
+    }
+
+    /// Add a variable with the next unused `IdCode`
+    pub fn var(mut self, var_type: VarType, size: u32, reference: &str) -> ScopeBuilder {
+        let code = IdCode::from(self.next_id);
+        self.next_id += 1;
+        self.ids.insert(format!("{}.{}", self.path, reference), code);
+        self.scope.children.push(ScopeItem::Var(Var {
+            var_type: var_type,
+            size: size,
+            code: code,
+            reference: reference.to_string(),
+            index: None,
+        }));
+        self
+    }
+
+    /// Add a wire of `size` bits
+    pub fn wire(self, reference: &str, size: u32) -> ScopeBuilder {
+        self.var(VarType::Wire, size, reference)
+    }
+
+    /// Add a reg of `size` bits
+    pub fn reg(self, reference: &str, size: u32) -> ScopeBuilder {
+        self.var(VarType::Reg, size, reference)
+    }
+
+    /// Add a child module named `identifier` whose contents are added by `f`
+    pub fn scope<F>(mut self, identifier: &str, f: F) -> ScopeBuilder
+        where F: FnOnce(ScopeBuilder) -> ScopeBuilder {
+        let s = f(ScopeBuilder {
+            scope: Scope { identifier: identifier.to_string(), ..Default::default() },
+            path: format!("{}.{}", self.path, identifier),
+            next_id: self.next_id,
+            ids: self.ids,
+        });
+        self.scope.children.push(ScopeItem::Scope(s.scope));
+        self.next_id = s.next_id;
+        self.ids = s.ids;
+        self
+    }
+}
+
+#[test]
+fn nested_scopes() {
+    use super::Parser;
+
+    let (header, ids) = HeaderBuilder::new()
+        .date("today")
+        .timescale(10, TimescaleUnit::PS)
+        .scope("tb", |s| s
+            .wire("clk", 1)
+            .scope("dut", |s| s.reg("state", 4).scope_type(ScopeType::Task))
+            .wire("rst", 1))
+        .build();
+
+    assert_eq!(ids.len(), 3);
+    assert_eq!(ids["tb.clk"], IdCode(0));
+    assert_eq!(ids["tb.dut.state"], IdCode(1));
+    assert_eq!(ids["tb.rst"], IdCode(2));
+
+    let mut buf = Vec::new();
+    ::Writer::new(&mut buf).header(&header).unwrap();
+    let parsed = Parser::new(&buf[..]).parse_header().unwrap();
+    assert_eq!(format!("{:?}", parsed), format!("{:?}", header));
+    assert_eq!(parsed.find_var(&["tb", "dut", "state"]).map(|v| v.size), Some(4));
+    assert_eq!(parsed.find_scope(&["tb", "dut"]).map(|s| &s.scope_type), Some(&ScopeType::Task));
+}
@@ -0,0 +1,329 @@
+use std::io;
+use std::path::Path;
+use std::collections::HashMap;
+
+use fst_writer::{
+    self,
+    FstInfo,
+    FstFileType,
+    FstHeaderWriter,
+    FstBodyWriter,
+    FstScopeType,
+    FstVarType,
+    FstVarDirection,
+    FstSignalType,
+    FstSignalId,
+    FstWriteError,
+};
+
+use {
+    Value,
+    TimescaleUnit,
+    IdCode,
+    ScopeType,
+    VarType,
+    Scope,
+    ScopeItem,
+    Var,
+    Header,
+    Command,
+    Error,
+    Parser,
+};
+
+/// Size of buffered value changes at which a block is written to the file
+const FLUSH_SIZE: usize = 64 * 1024 * 1024;
+
+impl From<FstWriteError> for Error {
+    fn from(err: FstWriteError) -> Error {
+        match err {
+            FstWriteError::Io(e) => Error::Io(e),
+            e => Error::Io(io::Error::new(io::ErrorKind::InvalidInput, e.to_string())),
+        }
+    }
+}
+
+/// The FST signal written for each VCD identifier code, with the width of a bit vector or `None`
+/// for a real
+type Signals = HashMap<IdCode, (FstSignalId, Option<u32>)>;
+
+/// Split a VCD `$timescale` into an FST power-of-ten exponent and a multiplier for timestamps
+fn timescale(ts: Option<(u32, TimescaleUnit)>) -> (i8, u64) {
+    use TimescaleUnit::*;
+    let (mut factor, unit) = ts.unwrap_or((1, S));
+    let mut exponent = match unit {
+        S => 0, MS => -3, US => -6, NS => -9, PS => -12, FS => -15,
+    };
+    while factor >= 10 && factor % 10 == 0 {
+        factor /= 10;
+        exponent += 1;
+    }
+    (exponent, factor as u64)
+}
+
+fn scope_type(t: &ScopeType) -> FstScopeType {
+    match *t {
+        ScopeType::Module => FstScopeType::Module,
+        ScopeType::Task => FstScopeType::Task,
+        ScopeType::Function => FstScopeType::Function,
+        ScopeType::Begin => FstScopeType::Begin,
+        ScopeType::Fork => FstScopeType::Fork,
+        ScopeType::Interface => FstScopeType::Interface,
+        ScopeType::Package => FstScopeType::Package,
+        ScopeType::Program => FstScopeType::Program,
+        ScopeType::Class => FstScopeType::Class,
+        ScopeType::Union => FstScopeType::Union,
+        ScopeType::Struct => FstScopeType::Struct,
+        ScopeType::Other(ref s) if s == "generate" => FstScopeType::Generate,
+        ScopeType::Other(_) => FstScopeType::Module,
+    }
+}
+
+fn write_scope<W: io::Write + io::Seek>(w: &mut FstHeaderWriter<W>, s: &Scope,
+    ids: &mut Signals) -> Result<(), Error> {
+    try!(w.scope(&s.identifier, "", scope_type(&s.scope_type)));
+    for item in &s.children {
+        match *item {
+            ScopeItem::Scope(ref s) => try!(write_scope(w, s, ids)),
+            ScopeItem::Var(ref v) => try!(write_var(w, v, ids)),
+        }
+    }
+    try!(w.up_scope());
+    Ok(())
+}
+
+fn write_var<W: io::Write + io::Seek>(w: &mut FstHeaderWriter<W>, v: &Var,
+    ids: &mut Signals) -> Result<(), Error> {
+    let tpe = match v.var_type {
+        VarType::Event => FstVarType::Event,
+        VarType::Integer => FstVarType::Integer,
+        VarType::Parameter => FstVarType::Parameter,
+        VarType::Reg => FstVarType::Reg,
+        VarType::Supply0 => FstVarType::Supply0,
+        VarType::Supply1 => FstVarType::Supply1,
+        VarType::Time => FstVarType::Time,
+        VarType::Tri => FstVarType::Tri,
+        VarType::Triand => FstVarType::TriAnd,
+        VarType::Trior => FstVarType::TriOr,
+        VarType::Trireg => FstVarType::TriReg,
+        VarType::Tri0 => FstVarType::Tri0,
+        VarType::Tri1 => FstVarType::Tri1,
+        VarType::Wand => FstVarType::Wand,
+        VarType::Wire | VarType::Other(_) => FstVarType::Wire,
+        VarType::Wor => FstVarType::Wor,
+        VarType::Logic => FstVarType::Logic,
+        VarType::Bit => FstVarType::Bit,
+        VarType::Enum => FstVarType::Enum,
+        VarType::Int => FstVarType::Int,
+        VarType::Real => FstVarType::Real,
+        VarType::Realtime => FstVarType::RealTime,
+        VarType::Shortreal => FstVarType::ShortReal,
+        VarType::String | VarType::Port => {
+            return Err(Error::Parse("FST output of string and port variables is not supported"));
+        }
+    };
+    let width = match v.var_type {
+        VarType::Real | VarType::Realtime | VarType::Shortreal => None,
+        _ => Some(v.size),
+    };
+    let signal_type = width.map_or(FstSignalType::real(), FstSignalType::bit_vec);
+    let alias = ids.get(&v.code).map(|&(id, _)| id);
+    let id = try!(w.var(&v.reference, signal_type, tpe, FstVarDirection::Implicit, alias));
+    ids.insert(v.code, (id, width));
+    Ok(())
+}
+
+fn write_value<W: io::Write + io::Seek>(w: &mut FstBodyWriter<W>, id: FstSignalId,
+    width: Option<u32>, chars: &[u8]) -> Result<(), Error> {
+    let size = try!(width.ok_or(Error::Parse("Scalar or vector value change to a real variable")));
+    if chars.is_empty() || chars.len() > size as usize {
+        return Err(Error::Parse("Vector value does not match variable width"));
+    }
+    try!(w.signal_change(id, chars));
+    Ok(())
+}
+
+/// Write a VCD's `header` and data section `commands` to an FST file at `path`
+///
+/// FST output supports scalar, vector and real variables. A string or extended VCD port variable
+/// in the header, or a string change in the data, returns an `Error::Parse`.
+pub fn write_fst<I, P>(header: &Header, commands: I, path: P) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>>, P: AsRef<Path> {
+    let (exponent, factor) = timescale(header.timescale);
+    let info = FstInfo {
+        start_time: 0,
+        timescale_exponent: exponent,
+        version: header.version.clone().unwrap_or_default(),
+        date: header.date.clone().unwrap_or_default(),
+        file_type: FstFileType::Verilog,
+    };
+
+    let mut ids = HashMap::new();
+    let mut w = try!(fst_writer::open_fst(path, &info));
+    for v in &header.vars {
+        try!(write_var(&mut w, v, &mut ids));
+    }
+    for s in header.top_scopes() {
+        try!(write_scope(&mut w, s, &mut ids));
+    }
+    let mut w = try!(w.finish());
+
+    let mut chars = Vec::new();
+    for c in commands {
+        match try!(c) {
+            Command::Timestamp(t) => {
+                if w.size() >= FLUSH_SIZE {
+                    try!(w.flush());
+                }
+                let t = try!(t.checked_mul(factor)
+                    .ok_or(Error::Parse("Timestamp too large for 64 bits in FST timescale")));
+                try!(w.time_change(t));
+            }
+            Command::ChangeScalar(i, v) => {
+                let &(id, width) = try!(ids.get(&i).ok_or(Error::Parse("Undeclared variable")));
+                chars.clear();
+                chars.push(v.to_byte());
+                try!(write_value(&mut w, id, width, &chars));
+            }
+            Command::ChangeVector(i, ref v) => {
+                let &(id, width) = try!(ids.get(&i).ok_or(Error::Parse("Undeclared variable")));
+                chars.clear();
+                chars.extend(v.iter().map(Value::to_byte));
+                try!(write_value(&mut w, id, width, &chars));
+            }
+            Command::ChangeReal(i, v) => {
+                let &(id, width) = try!(ids.get(&i).ok_or(Error::Parse("Undeclared variable")));
+                if width.is_some() {
+                    return Err(Error::Parse("Real value change to a scalar or vector variable"));
+                }
+                try!(w.signal_change(id, &v.to_le_bytes()));
+            }
+            Command::ChangeString(..) => {
+                return Err(Error::Parse("FST output of string values is not supported"));
+            }
+            _ => (),
+        }
+    }
+
+    try!(w.finish());
+    Ok(())
+}
+
+/// Convert the VCD read by `parser` to an FST file at `path`
+pub fn to_fst<R: io::Read, P: AsRef<Path>>(mut parser: Parser<R>, path: P) -> Result<(), Error> {
+    let header = try!(parser.parse_header());
+    write_fst(&header, parser, path)
+}
+
+#[test]
+fn roundtrip() {
+    use super::super::fst::FstParser;
+    use super::super::Value::*;
+
+    let sample = b"
+    $timescale 10 ns $end
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var reg 4 \" count $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! bx \"
+    #5 1! b0 \"
+    #10 0!
+    #15 1! b1 \"
+    ";
+
+    let path = ::std::env::temp_dir()
+        .join(format!("vcd-roundtrip-{}.fst", ::std::process::id()));
+    to_fst(Parser::new(&sample[..]), &path).unwrap();
+
+    let mut p = FstParser::open(&path).unwrap();
+    let header = p.parse_header().unwrap();
+    assert_eq!(header.timescale, Some((10, TimescaleUnit::NS)));
+    assert_eq!(header.scope.children.len(), 2);
+
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands, vec![
+        Command::Timestamp(0),
+        Command::ChangeScalar(IdCode(0), V0),
+        Command::ChangeVector(IdCode(1), vec![X, X, X, X].into()),
+        Command::Timestamp(5),
+        Command::ChangeVector(IdCode(1), vec![V0, V0, V0, V0].into()),
+        Command::ChangeScalar(IdCode(0), V1),
+        Command::Timestamp(10),
+        Command::ChangeScalar(IdCode(0), V0),
+        Command::Timestamp(15),
+        Command::ChangeScalar(IdCode(0), V1),
+        Command::ChangeVector(IdCode(1), vec![V0, V0, V0, V1].into()),
+    ]);
+
+    let large = format!("$timescale 3 ns $end $enddefinitions $end #{}\n", ::std::u64::MAX / 2);
+    assert!(to_fst(Parser::new(large.as_bytes()), &path).is_err());
+    ::std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn top_level_vars() {
+    use super::super::fst::FstParser;
+
+    let sample = b"$var wire 1 ! reset $end
+$scope module top $end $var wire 1 \" clk $end $upscope $end
+$scope module glbl $end $var wire 1 # GSR $end $upscope $end
+$enddefinitions $end #0 1! 0\" 1# #5 0!
+";
+    let path = ::std::env::temp_dir()
+        .join(format!("vcd-top-level-{}.fst", ::std::process::id()));
+    to_fst(Parser::new(&sample[..]), &path).unwrap();
+
+    let mut p = FstParser::open(&path).unwrap();
+    let header = p.parse_header().unwrap();
+    let paths: Vec<String> = header.iter_vars().map(|(p, _)| p.join(".")).collect();
+    assert_eq!(paths, ["reset", "top.clk", "glbl.GSR"]);
+    let reset = header.find_var(&["reset"]).unwrap().code;
+    let changes = p.map(Result::unwrap)
+        .filter(|c| c.change_value().map(|(id, _)| id) == Some(reset));
+    assert_eq!(changes.count(), 2);
+    ::std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn reals() {
+    use super::super::fst::FstParser;
+
+    let sample = b"
+    $scope module top $end
+    $var real 64 ! temp $end
+    $var realtime 64 \" t $end
+    $var wire 1 # clk $end
+    $upscope $end
+    $enddefinitions $end
+    #0 r1.5 ! r0 \" 0#
+    #5 r-2.25 ! 1#
+    ";
+
+    let path = ::std::env::temp_dir().join(format!("vcd-reals-{}.fst", ::std::process::id()));
+    to_fst(Parser::new(&sample[..]), &path).unwrap();
+
+    let mut p = FstParser::open(&path).unwrap();
+    let header = p.parse_header().unwrap();
+    let types: Vec<VarType> = header.iter_vars().map(|(_, v)| v.var_type.clone()).collect();
+    assert_eq!(types, [VarType::Real, VarType::Realtime, VarType::Wire]);
+    let temp = header.find_var(&["top", "temp"]).unwrap().code;
+    let temps: Vec<Command> = p.map(Result::unwrap)
+        .filter(|c| c.change_value().map(|(id, _)| id) == Some(temp)).collect();
+    assert_eq!(temps, [Command::ChangeReal(temp, 1.5), Command::ChangeReal(temp, -2.25)]);
+
+    // Unsupported variables and mismatched values are reported as parse errors
+    let unsupported: [&[u8]; 3] = [
+        b"$var string 1 ! msg $end $enddefinitions $end\n",
+        b"$var real 64 ! temp $end $enddefinitions $end #0 b1 !\n",
+        b"$var wire 1 ! clk $end $enddefinitions $end #0 r1.5 !\n",
+    ];
+    for sample in &unsupported {
+        match to_fst(Parser::new(&sample[..]), &path) {
+            Err(Error::Parse(_)) => (),
+            r => panic!("{:?}", r),
+        }
+    }
+    ::std::fs::remove_file(&path).unwrap();
+}
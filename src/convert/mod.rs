@@ -0,0 +1,6 @@
+//! Conversion of VCD data to other waveform formats
+
+#[cfg(feature = "fst")]
+mod fst;
+#[cfg(feature = "fst")]
+pub use self::fst::{to_fst, write_fst};
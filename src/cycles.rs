@@ -0,0 +1,264 @@
+//! Clock detection and cycle-aligned sampling of signals
+
+use std::collections::HashMap;
+
+use {
+    Value,
+    IdCode,
+    VarValue,
+    Header,
+    Command,
+    Error,
+};
+
+/// The clock edges on which signals are sampled
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+impl Edge {
+    fn matches(&self, from: Value, to: Value) -> bool {
+        match (*self, from, to) {
+            (Edge::Rising, Value::V0, Value::V1) => true,
+            (Edge::Falling, Value::V1, Value::V0) => true,
+            (Edge::Both, Value::V0, Value::V1) => true,
+            (Edge::Both, Value::V1, Value::V0) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The values of the sampled signals at one clock edge
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cycle {
+    /// Number of the edge, counting from 0
+    pub cycle: u64,
+
+    /// Timestamp of the edge
+    pub time: u64,
+
+    /// Value of each sampled signal just before the edge, in the order passed to
+    /// `CycleSampler::new`, or `None` if the signal has not been assigned yet.
+    pub values: Vec<Option<VarValue>>,
+}
+
+fn scalar_change(c: &Command) -> Option<(IdCode, Value)> {
+    match *c {
+        Command::ChangeScalar(i, v) => Some((i, v)),
+        Command::ChangeVector(i, ref v) if v.len() == 1 => Some((i, v[0])),
+        _ => None,
+    }
+}
+
+/// Find the variable most likely to be a clock: the 1-bit variable with the most rising edges
+/// among those whose rising edges are evenly spaced.
+///
+/// Reads at most `limit` commands from `commands`.
+pub fn detect_clock<I>(header: &Header, commands: I, limit: usize) -> Result<Option<IdCode>, Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+
+    struct Candidate {
+        level: Value,
+        last_rise: Option<u64>,
+        period: Option<u64>,
+        rises: usize,
+        regular: bool,
+    }
+
+    let mut candidates: HashMap<IdCode, Candidate> = header.iter_vars()
+        .filter(|&(_, v)| v.size == 1)
+        .map(|(_, v)| {
+            let c = Candidate { level: Value::X, last_rise: None, period: None, rises: 0,
+                regular: true };
+            (v.code, c)
+        }).collect();
+
+    let mut time = 0;
+    for c in commands.into_iter().take(limit) {
+        let c = try!(c);
+        if let Command::Timestamp(t) = c {
+            time = t;
+            continue;
+        }
+
+        if let Some((id, v)) = scalar_change(&c) {
+            if let Some(cand) = candidates.get_mut(&id) {
+                if Edge::Rising.matches(cand.level, v) {
+                    if let Some(last) = cand.last_rise {
+                        let period = time - last;
+                        match cand.period {
+                            Some(p) if p != period => cand.regular = false,
+                            _ => cand.period = Some(period),
+                        }
+                    }
+                    cand.last_rise = Some(time);
+                    cand.rises += 1;
+                }
+                cand.level = v;
+            }
+        }
+    }
+
+    Ok(candidates.into_iter()
+        .filter(|&(_, ref c)| c.regular && c.rises >= 2)
+        .max_by_key(|&(id, ref c)| (c.rises, ::std::cmp::Reverse(id.0)))
+        .map(|(id, _)| id))
+}
+
+/// Iterator adapter that samples a set of signals on the edges of a clock, producing one `Cycle`
+/// per edge.
+///
+/// Signals are sampled with the values they held before the timestamp of the clock edge, so
+/// changes caused by the edge itself are seen in the following cycle, as a flip-flop would.
+pub struct CycleSampler<I> {
+    commands: I,
+    clock: IdCode,
+    edge: Edge,
+    signals: HashMap<IdCode, usize>,
+    values: Vec<Option<VarValue>>,
+    pending: Vec<(usize, VarValue)>,
+    clock_level: Value,
+    edge_seen: bool,
+    time: u64,
+    cycle: u64,
+    done: bool,
+}
+
+impl<I> CycleSampler<I> where I: Iterator<Item=Result<Command, Error>> {
+    /// Create a sampler reading the data section of a VCD from `commands`, sampling `signals` on
+    /// each `edge` of `clock`.
+    pub fn new<C>(commands: C, clock: IdCode, edge: Edge, signals: &[IdCode]) -> CycleSampler<I>
+        where C: IntoIterator<Item=Result<Command, Error>, IntoIter=I> {
+        CycleSampler {
+            commands: commands.into_iter(),
+            clock: clock,
+            edge: edge,
+            signals: signals.iter().enumerate().map(|(i, &id)| (id, i)).collect(),
+            values: vec![None; signals.len()],
+            pending: Vec::new(),
+            clock_level: Value::X,
+            edge_seen: false,
+            time: 0,
+            cycle: 0,
+            done: false,
+        }
+    }
+
+    /// Finish the current timestamp, returning a `Cycle` if the clock had an edge during it.
+    fn flush(&mut self) -> Option<Cycle> {
+        let cycle = if self.edge_seen {
+            self.edge_seen = false;
+            self.cycle += 1;
+            Some(Cycle { cycle: self.cycle - 1, time: self.time, values: self.values.clone() })
+        } else {
+            None
+        };
+
+        for (i, v) in self.pending.drain(..) {
+            self.values[i] = Some(v);
+        }
+
+        cycle
+    }
+}
+
+impl<I> Iterator for CycleSampler<I> where I: Iterator<Item=Result<Command, Error>> {
+    type Item = Result<Cycle, Error>;
+
+    fn next(&mut self) -> Option<Result<Cycle, Error>> {
+        while !self.done {
+            let c = match self.commands.next() {
+                Some(Ok(c)) => c,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.done = true;
+                    return self.flush().map(Ok);
+                }
+            };
+
+            if let Command::Timestamp(t) = c {
+                let cycle = self.flush();
+                self.time = t;
+                if cycle.is_some() { return cycle.map(Ok); }
+                continue;
+            }
+
+            if let Some((id, v)) = scalar_change(&c) {
+                if id == self.clock {
+                    if self.edge.matches(self.clock_level, v) { self.edge_seen = true; }
+                    self.clock_level = v;
+                }
+            }
+
+            if let Some((id, v)) = c.change_value() {
+                if let Some(&i) = self.signals.get(&id) {
+                    self.pending.push((i, v));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[test]
+fn counter() {
+    use super::Parser;
+    use super::Value::*;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var wire 1 \" en $end
+    $var reg 2 # count $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! 0\" b00 #
+    #5 1! 1\"
+    #10 0!
+    #15 1! b01 #
+    #20 0!
+    #25 1! b10 #
+    #30 0!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+
+    let clk = IdCode::from(0);
+    let count = IdCode::from(2);
+    assert_eq!(detect_clock(&header, commands.iter().cloned().map(Ok), 1000).unwrap(), Some(clk));
+
+    let cycles: Vec<Cycle> = CycleSampler::new(commands.into_iter().map(Ok), clk, Edge::Rising, &[count])
+        .collect::<Result<_, _>>().unwrap();
+
+    assert_eq!(cycles.iter().map(|c| c.time).collect::<Vec<_>>(), vec![5, 15, 25]);
+    assert_eq!(cycles[0].values, vec![Some(VarValue::Vector(vec![V0, V0].into()))]);
+    assert_eq!(cycles[1].values, vec![Some(VarValue::Vector(vec![V0, V0].into()))]);
+    assert_eq!(cycles[2].values, vec![Some(VarValue::Vector(vec![V0, V1].into()))]);
+}
+
+#[test]
+fn top_level_clock() {
+    use super::Parser;
+
+    let sample = b"
+    $var wire 1 ! clk $end
+    $scope module top $end
+    $var wire 1 \" en $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! 0\"
+    #5 1!
+    #10 0!
+    #15 1!
+    #20 0!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    assert_eq!(detect_clock(&header, p, 1000).unwrap(), Some(IdCode::from(0)));
+}
@@ -0,0 +1,56 @@
+//! Parsing of the `$date` header (requires the `chrono` feature)
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone};
+
+use Header;
+
+/// Formats without a time zone, after runs of whitespace have been collapsed
+const FORMATS: &'static [&'static str] = &[
+    // ctime, as written by Icarus Verilog, Verilator, ModelSim and GTKWave
+    "%a %b %d %H:%M:%S %Y",
+    // Synopsys VCS
+    "%b %d, %Y %H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%d-%b-%Y %H:%M:%S",
+];
+
+impl Header {
+    /// Parse the `$date` header as a date and time.
+    ///
+    /// Understands the `ctime` format written by Icarus Verilog, Verilator, ModelSim and GTKWave,
+    /// the format written by VCS, and RFC 2822 and RFC 3339 dates. Dates without a time zone are
+    /// taken to be UTC. Returns `None` if there is no `$date` or it is not in a known format.
+    pub fn date_parsed(&self) -> Option<DateTime<FixedOffset>> {
+        let date = match self.date {
+            Some(ref d) => d.split_whitespace().collect::<Vec<_>>().join(" "),
+            None => return None,
+        };
+
+        if let Ok(d) = DateTime::parse_from_rfc3339(&date) { return Some(d); }
+        if let Ok(d) = DateTime::parse_from_rfc2822(&date) { return Some(d); }
+        let utc = FixedOffset::east_opt(0).unwrap();
+        FORMATS.iter()
+            .filter_map(|f| NaiveDateTime::parse_from_str(&date, f).ok())
+            .next()
+            .map(|d| utc.from_utc_datetime(&d))
+    }
+}
+
+#[test]
+fn formats() {
+    fn parse(date: &str) -> Option<String> {
+        let header = Header { date: Some(date.to_string()), ..Default::default() };
+        header.date_parsed().map(|d| d.to_rfc3339())
+    }
+
+    let expected = Some("2023-10-04T09:05:01+00:00".to_string());
+    assert_eq!(parse("\n\tWed Oct  4 09:05:01 2023\n"), expected);
+    assert_eq!(parse("Wed Oct 04 09:05:01 2023"), expected);
+    assert_eq!(parse("Oct 04, 2023  09:05:01"), expected);
+    assert_eq!(parse("2023-10-04 09:05:01"), expected);
+    assert_eq!(parse("Wed, 4 Oct 2023 11:05:01 +0200"),
+        Some("2023-10-04T11:05:01+02:00".to_string()));
+    assert_eq!(parse("today"), None);
+    assert_eq!(Header::default().date_parsed(), None);
+}
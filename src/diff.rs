@@ -0,0 +1,317 @@
+//! Comparison of two waveforms signal by signal
+
+use std::ops::Range;
+use std::collections::{HashMap, HashSet};
+use std::iter::Peekable;
+
+use {
+    Value,
+    BitVector,
+    VarValue,
+    VarType,
+    IdCode,
+    Header,
+    Command,
+    Error,
+};
+
+/// Options for `diff`
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Only compare values within this time range
+    pub window: Option<Range<u64>>,
+
+    /// Only compare the signals with these dot-separated paths, e.g. `top.cpu.pc`
+    pub signals: Option<HashSet<String>>,
+
+    /// Largest difference between real values that is still considered equal
+    pub tolerance: f64,
+}
+
+/// The first difference found in a signal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// Dot-separated path of the signal
+    pub path: String,
+
+    /// Time at which the values first differ
+    pub time: u64,
+
+    /// Value in the left waveform, or `None` if it had not been assigned
+    pub left: Option<VarValue>,
+
+    /// Value in the right waveform, or `None` if it had not been assigned
+    pub right: Option<VarValue>,
+}
+
+/// The result of `diff`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diff {
+    /// Signals whose values differ, in path order
+    pub divergences: Vec<Divergence>,
+
+    /// Paths of signals only in the left waveform
+    pub only_left: Vec<String>,
+
+    /// Paths of signals only in the right waveform
+    pub only_right: Vec<String>,
+}
+
+impl Diff {
+    /// Returns true if no differences were found
+    pub fn is_equal(&self) -> bool {
+        self.divergences.is_empty() && self.only_left.is_empty() && self.only_right.is_empty()
+    }
+}
+
+fn collect_vars(header: &Header) -> HashMap<String, (IdCode, u32, VarType)> {
+    header.iter_vars().map(|(path, v)| (path.join("."), (v.code, v.size, v.var_type.clone())))
+        .collect()
+}
+
+/// Extend a vector value to `size` bits as VCD does: `x` and `z` are extended, `0` and `1` are
+/// padded with `0`. Scalars become 1-bit vectors.
+fn normalize(v: VarValue, size: u32) -> VarValue {
+    let bits = match v {
+        VarValue::Scalar(b) => BitVector::from(vec![b]),
+        VarValue::Vector(b) => b,
+        v => return v,
+    };
+    let size = size as usize;
+    if bits.len() >= size {
+        return VarValue::Vector(bits);
+    }
+    let pad = match bits.get(0) {
+        Some(Value::X) => Value::X,
+        Some(Value::Z) => Value::Z,
+        _ => Value::V0,
+    };
+    let out = ::std::iter::repeat(pad).take(size - bits.len()).chain(bits.iter()).collect();
+    VarValue::Vector(out)
+}
+
+fn equal(a: &Option<VarValue>, b: &Option<VarValue>, tolerance: f64) -> bool {
+    match (a, b) {
+        (&Some(VarValue::Real(a)), &Some(VarValue::Real(b))) => {
+            a == b || (a - b).abs() <= tolerance
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// One side of the comparison: a command stream and the current values of the compared signals
+struct Side<I: Iterator> {
+    commands: Peekable<I>,
+    index: HashMap<IdCode, Vec<usize>>,
+    sizes: Vec<u32>,
+    values: Vec<Option<VarValue>>,
+}
+
+impl<I> Side<I> where I: Iterator<Item=Result<Command, Error>> {
+    /// Apply the changes up to the next timestamp, marking the changed signals in `changed`
+    fn apply(&mut self, changed: &mut HashSet<usize>) -> Result<(), Error> {
+        loop {
+            match self.commands.peek() {
+                Some(&Ok(Command::Timestamp(_))) | None => return Ok(()),
+                _ => (),
+            }
+            let c = try!(self.commands.next().unwrap());
+            if let Some((id, v)) = c.change_value() {
+                for &i in self.index.get(&id).map(|i| &i[..]).unwrap_or(&[]) {
+                    self.values[i] = Some(normalize(v.clone(), self.sizes[i]));
+                    changed.insert(i);
+                }
+            }
+        }
+    }
+
+    /// The time of the next timestamp, once `apply` has been called
+    fn next_time(&mut self) -> Option<u64> {
+        match self.commands.peek() {
+            Some(&Ok(Command::Timestamp(t))) => Some(t),
+            _ => None,
+        }
+    }
+
+    /// Move past the next timestamp if it is at time `t`
+    fn advance(&mut self, t: u64) {
+        if self.next_time() == Some(t) {
+            self.commands.next();
+        }
+    }
+}
+
+/// Compare two waveforms, matching signals by their paths, and find the first time at which each
+/// signal's values differ.
+///
+/// Values are compared after all changes at each timestamp, so the order of changes within a
+/// timestamp does not matter, and vectors are compared after extending them to their declared
+/// width. The waveforms must have the same `$timescale`.
+pub fn diff<A, B>(left: &Header, left_commands: A, right: &Header, right_commands: B,
+                  options: &DiffOptions) -> Result<Diff, Error>
+    where A: IntoIterator<Item=Result<Command, Error>>,
+          B: IntoIterator<Item=Result<Command, Error>> {
+    if left.timescale != right.timescale {
+        return Err(Error::Parse("Cannot compare waveforms with different timescales"));
+    }
+
+    let (left_vars, right_vars) = (collect_vars(left), collect_vars(right));
+    let selected = |p: &String| options.signals.as_ref().map_or(true, |s| s.contains(p));
+
+    let mut result = Diff::default();
+    result.only_left = left_vars.keys().filter(|p| selected(p) && !right_vars.contains_key(*p))
+        .cloned().collect();
+    result.only_right = right_vars.keys().filter(|p| selected(p) && !left_vars.contains_key(*p))
+        .cloned().collect();
+    result.only_left.sort();
+    result.only_right.sort();
+
+    let mut paths: Vec<&String> = left_vars.keys()
+        .filter(|p| selected(p) && right_vars.contains_key(*p)).collect();
+    paths.sort();
+
+    let mut sides = (
+        Side { commands: left_commands.into_iter().peekable(), index: HashMap::new(),
+            sizes: Vec::new(), values: vec![None; paths.len()] },
+        Side { commands: right_commands.into_iter().peekable(), index: HashMap::new(),
+            sizes: Vec::new(), values: vec![None; paths.len()] },
+    );
+    for (i, path) in paths.iter().enumerate() {
+        let (l, r) = (&left_vars[*path], &right_vars[*path]);
+        sides.0.index.entry(l.0).or_insert_with(Vec::new).push(i);
+        sides.1.index.entry(r.0).or_insert_with(Vec::new).push(i);
+        let size = ::std::cmp::max(l.1, r.1);
+        sides.0.sizes.push(size);
+        sides.1.sizes.push(size);
+    }
+
+    let window = options.window.clone().unwrap_or(0..::std::u64::MAX);
+    let mut first: Vec<Option<Divergence>> = vec![None; paths.len()];
+    let mut changed = HashSet::new();
+    let mut time = 0;
+    while time < window.end {
+        try!(sides.0.apply(&mut changed));
+        try!(sides.1.apply(&mut changed));
+        let next = match (sides.0.next_time(), sides.1.next_time()) {
+            (Some(a), Some(b)) => Some(::std::cmp::min(a, b)),
+            (a, b) => a.or(b),
+        };
+
+        // The values now hold until `next`. Changes made before the window are compared as of
+        // its start.
+        if next.map_or(true, |next| next > window.start) {
+            let at = ::std::cmp::max(time, window.start);
+            for i in changed.drain() {
+                if first[i].is_none() &&
+                   !equal(&sides.0.values[i], &sides.1.values[i], options.tolerance) {
+                    first[i] = Some(Divergence {
+                        path: paths[i].clone(),
+                        time: at,
+                        left: sides.0.values[i].clone(),
+                        right: sides.1.values[i].clone(),
+                    });
+                }
+            }
+        }
+
+        time = match next {
+            Some(t) => t,
+            None => break,
+        };
+        sides.0.advance(time);
+        sides.1.advance(time);
+    }
+
+    result.divergences = first.into_iter().filter_map(|d| d).collect();
+    Ok(result)
+}
+
+#[test]
+fn divergence() {
+    use super::Parser;
+    use super::Value::*;
+
+    let left = b"
+    $timescale 1 ns $end
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var wire 4 \" count $end
+    $var real 64 # v $end
+    $var wire 1 $ old $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! b0 \" r1.0 #
+    #10 1! b1 \"
+    #20 0! b10 \" r2.0 #
+    #30 1! b11 \"
+    ";
+    let right = b"
+    $timescale 1 ns $end
+    $scope module top $end
+    $var wire 4 ! count $end
+    $var wire 1 \" clk $end
+    $var real 64 # v $end
+    $upscope $end
+    $enddefinitions $end
+    #0 b0000 ! 0\" r1.0 #
+    #10 1\" b0001 !
+    #20 r2.0000001 # 0\" b0011 !
+    #30 1\"
+    ";
+
+    let run = |options: &DiffOptions| {
+        let mut l = Parser::new(&left[..]);
+        let mut r = Parser::new(&right[..]);
+        let (lh, rh) = (l.parse_header().unwrap(), r.parse_header().unwrap());
+        diff(&lh, l, &rh, r, options).unwrap()
+    };
+
+    let result = run(&DiffOptions { tolerance: 1e-3, ..Default::default() });
+    assert_eq!(result.only_left, vec!["top.old".to_string()]);
+    assert!(result.only_right.is_empty());
+    assert_eq!(result.divergences, vec![Divergence {
+        path: "top.count".to_string(),
+        time: 20,
+        left: Some(VarValue::Vector(vec![V0, V0, V1, V0].into())),
+        right: Some(VarValue::Vector(vec![V0, V0, V1, V1].into())),
+    }]);
+
+    let result = run(&DiffOptions { window: Some(25..40), ..Default::default() });
+    assert_eq!(result.divergences.iter().map(|d| (&d.path[..], d.time)).collect::<Vec<_>>(),
+        vec![("top.count", 25), ("top.v", 25)]);
+
+    let signals = Some(vec!["top.clk".to_string()].into_iter().collect());
+    assert!(run(&DiffOptions { signals: signals, ..Default::default() }).is_equal());
+}
+
+#[test]
+fn top_level_vars() {
+    use super::Parser;
+
+    let left = b"
+    $var wire 1 ! reset $end
+    $scope module top $end
+    $var wire 1 \" clk $end
+    $upscope $end
+    $enddefinitions $end
+    #0 1! 0\"
+    #10 0!
+    ";
+    let right = b"
+    $var wire 1 ! reset $end
+    $scope module top $end
+    $var wire 1 \" clk $end
+    $upscope $end
+    $enddefinitions $end
+    #0 1! 0\"
+    #20 0!
+    ";
+
+    let mut l = Parser::new(&left[..]);
+    let mut r = Parser::new(&right[..]);
+    let (lh, rh) = (l.parse_header().unwrap(), r.parse_header().unwrap());
+    let result = diff(&lh, l, &rh, r, &DiffOptions::default()).unwrap();
+    assert!(result.only_left.is_empty() && result.only_right.is_empty());
+    assert_eq!(result.divergences.iter().map(|d| (&d.path[..], d.time)).collect::<Vec<_>>(),
+        vec![("reset", 10)]);
+}
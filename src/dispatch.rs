@@ -0,0 +1,119 @@
+//! Routing of value changes to callbacks registered per variable
+
+use std::collections::HashMap;
+
+use {
+    IdCode,
+    VarValue,
+    Header,
+    Command,
+    Error,
+    VarPath,
+};
+
+/// Calls callbacks registered for particular variables with each of their value changes.
+///
+/// ```
+/// # let buf = b"$scope module top $end $var wire 1 ! clk $end $upscope $end
+/// #   $enddefinitions $end #0 0! #5 1!\n";
+/// let mut parser = vcd::Parser::new(&buf[..]);
+/// let header = parser.parse_header().unwrap();
+///
+/// let mut changes = 0;
+/// {
+///     let mut dispatcher = vcd::dispatch::Dispatcher::new();
+///     dispatcher.on_path(&header, "top.clk", |_time, _value| changes += 1).unwrap();
+///     dispatcher.run(parser).unwrap();
+/// }
+/// assert_eq!(changes, 2);
+/// ```
+pub struct Dispatcher<'a> {
+    handlers: HashMap<IdCode, Vec<Box<FnMut(u64, &VarValue) + 'a>>>,
+}
+
+impl<'a> Dispatcher<'a> {
+    pub fn new() -> Dispatcher<'a> {
+        Dispatcher { handlers: HashMap::new() }
+    }
+
+    /// Call `f` with the time and new value of each change to variable `id`. Callbacks for the
+    /// same variable are called in the order they were registered.
+    pub fn on<F>(&mut self, id: IdCode, f: F) where F: FnMut(u64, &VarValue) + 'a {
+        self.handlers.entry(id).or_insert_with(Vec::new).push(Box::new(f));
+    }
+
+    /// Call `f` with each change to the variable with dotted path `path` in `header`, such as
+    /// `top.cpu.pc`, parsed as a `VarPath`. Fails if there is no such variable.
+    pub fn on_path<F>(&mut self, header: &Header, path: &str, f: F) -> Result<(), Error>
+        where F: FnMut(u64, &VarValue) + 'a {
+        let path: VarPath = try!(path.parse());
+        match header.find_var(path.parts()) {
+            Some(v) => { self.on(v.code, f); Ok(()) }
+            None => Err(Error::Parse("No variable with that path")),
+        }
+    }
+
+    /// Read a VCD data section from `commands`, calling the registered callbacks for each change
+    /// until the end of the stream. Returns the last timestamp.
+    pub fn run<I>(&mut self, commands: I) -> Result<u64, Error>
+        where I: IntoIterator<Item=Result<Command, Error>> {
+        let mut time = 0;
+        for c in commands {
+            let c = try!(c);
+            let id = match c {
+                Command::Timestamp(t) => { time = t; continue; }
+                Command::ChangeScalar(id, _) | Command::ChangeVector(id, _) |
+                Command::ChangeReal(id, _) | Command::ChangeString(id, _) => id,
+                _ => continue,
+            };
+            if let Some(handlers) = self.handlers.get_mut(&id) {
+                let (_, value) = c.change_value().unwrap();
+                for h in handlers.iter_mut() {
+                    h(time, &value);
+                }
+            }
+        }
+        Ok(time)
+    }
+}
+
+#[test]
+fn routing() {
+    use super::Parser;
+    use super::Value::*;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $scope module cpu $end
+    $var reg 2 \" state $end
+    $upscope $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! b00 \"
+    #5 1! b01 \"
+    #10 0!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+
+    let mut clk = Vec::new();
+    let mut states = Vec::new();
+    let mut all = 0;
+    {
+        let mut d = Dispatcher::new();
+        d.on(IdCode::from(0), |t, v| clk.push((t, v.clone())));
+        d.on_path(&header, "top.cpu.state", |t, v| states.push((t, v.clone()))).unwrap();
+        d.on_path(&header, "top.cpu.state", |_, _| all += 1).unwrap();
+        assert!(d.on_path(&header, "top.state", |_, _| ()).is_err());
+        assert_eq!(d.run(p).unwrap(), 10);
+    }
+
+    assert_eq!(clk.iter().map(|&(t, _)| t).collect::<Vec<_>>(), vec![0, 5, 10]);
+    assert_eq!(states, vec![
+        (0, VarValue::Vector(vec![V0, V0].into())),
+        (5, VarValue::Vector(vec![V0, V1].into())),
+    ]);
+    assert_eq!(all, 2);
+}
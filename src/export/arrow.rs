@@ -0,0 +1,207 @@
+use std::io;
+use std::sync::Arc;
+use std::collections::HashMap;
+
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_array::builder::{UInt64Builder, Float64Builder, StringBuilder};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+
+use {
+    Value,
+    Radix,
+    VarValue,
+    IdCode,
+    Var,
+    Command,
+    Error,
+};
+use super::sample;
+
+impl From<ArrowError> for Error {
+    fn from(err: ArrowError) -> Error {
+        match err {
+            ArrowError::IoError(_, e) => Error::Io(e),
+            e => Error::Io(io::Error::new(io::ErrorKind::Other, e.to_string())),
+        }
+    }
+}
+
+impl From<ParquetError> for Error {
+    fn from(err: ParquetError) -> Error {
+        Error::Io(io::Error::new(io::ErrorKind::Other, err.to_string()))
+    }
+}
+
+/// Builder for the Arrow column holding the values of one variable.
+///
+/// Variables of up to 64 bits are stored as integers, with `x` or `z` bits giving a null.
+/// Wider variables are stored as binary strings, keeping `x` and `z`.
+enum Column {
+    Integer(UInt64Builder),
+    Real(Float64Builder),
+    Text(StringBuilder),
+}
+
+impl Column {
+    fn new(var: &Var) -> Column {
+        match var.var_type {
+            ref t if t.is_real() => Column::Real(Float64Builder::new()),
+            _ if var.size <= 64 => Column::Integer(UInt64Builder::new()),
+            _ => Column::Text(StringBuilder::new()),
+        }
+    }
+
+    fn data_type(&self) -> DataType {
+        match *self {
+            Column::Integer(_) => DataType::UInt64,
+            Column::Real(_) => DataType::Float64,
+            Column::Text(_) => DataType::Utf8,
+        }
+    }
+
+    fn append(&mut self, v: Option<&VarValue>) {
+        match *self {
+            Column::Integer(ref mut b) => b.append_option(v.and_then(|v| match *v {
+                VarValue::Scalar(v) => integer(Some(v).into_iter()),
+                VarValue::Vector(ref v) => integer(v.iter()),
+                _ => None,
+            })),
+            Column::Real(ref mut b) => b.append_option(v.and_then(|v| match *v {
+                VarValue::Real(r) => Some(r),
+                _ => None,
+            })),
+            Column::Text(ref mut b) => b.append_option(v.map(|v| v.format(Radix::Binary))),
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match *self {
+            Column::Integer(ref mut b) => Arc::new(b.finish()),
+            Column::Real(ref mut b) => Arc::new(b.finish()),
+            Column::Text(ref mut b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+/// The value of a vector of up to 64 bits, or `None` if it has `x` or `z` bits
+fn integer<I: ExactSizeIterator<Item=Value>>(v: I) -> Option<u64> {
+    if v.len() > 64 { return None; }
+    v.fold(Some(0), |acc, b| acc.and_then(|n| match b {
+        Value::V0 => Some(n << 1),
+        Value::V1 => Some(n << 1 | 1),
+        _ => None,
+    }))
+}
+
+/// Read the changes to each of `signals` into a record batch per signal, with a `time` column and
+/// a `value` column holding each value assigned at that time.
+///
+/// Values of up to 64 bits become unsigned integers, with null for values containing `x` or `z`.
+/// Real variables become floats, and wider vectors become binary strings.
+pub fn signal_batches<I>(commands: I, signals: &[&Var]) -> Result<Vec<RecordBatch>, Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let mut index: HashMap<IdCode, Vec<usize>> = HashMap::new();
+    for (i, v) in signals.iter().enumerate() {
+        index.entry(v.code).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut times: Vec<UInt64Builder> = signals.iter().map(|_| UInt64Builder::new()).collect();
+    let mut values: Vec<Column> = signals.iter().map(|v| Column::new(v)).collect();
+    let mut time = 0;
+    for c in commands {
+        let c = try!(c);
+        if let Command::Timestamp(t) = c {
+            time = t;
+        } else if let Some((id, v)) = c.change_value() {
+            for &i in index.get(&id).map(|i| &i[..]).unwrap_or(&[]) {
+                times[i].append_value(time);
+                values[i].append(Some(&v));
+            }
+        }
+    }
+
+    let mut batches = Vec::new();
+    for (t, v) in times.iter_mut().zip(values.iter_mut()) {
+        let schema = Schema::new(vec![
+            Field::new("time", DataType::UInt64, false),
+            Field::new("value", v.data_type(), true),
+        ]);
+        let columns = vec![Arc::new(t.finish()) as ArrayRef, v.finish()];
+        batches.push(try!(RecordBatch::try_new(Arc::new(schema), columns)));
+    }
+    Ok(batches)
+}
+
+/// Sample `signals` every `interval` time units into a single record batch, with a `time` column
+/// followed by a column per signal named by its reference.
+///
+/// Values are converted as in `signal_batches`, with null for signals not yet assigned.
+pub fn sampled_batch<I>(commands: I, signals: &[&Var], interval: u64) -> Result<RecordBatch, Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let ids: Vec<_> = signals.iter().map(|v| v.code).collect();
+    let mut times = UInt64Builder::new();
+    let mut columns: Vec<Column> = signals.iter().map(|v| Column::new(v)).collect();
+    try!(sample(commands, &ids, interval, |t, values| {
+        times.append_value(t);
+        for (c, v) in columns.iter_mut().zip(values) {
+            c.append(v.as_ref());
+        }
+        Ok(())
+    }));
+
+    let mut fields = vec![Field::new("time", DataType::UInt64, false)];
+    fields.extend(signals.iter().zip(&columns).map(|(v, c)| {
+        Field::new(&v.reference[..], c.data_type(), true)
+    }));
+    let mut arrays = vec![Arc::new(times.finish()) as ArrayRef];
+    arrays.extend(columns.iter_mut().map(|c| c.finish()));
+    Ok(try!(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)))
+}
+
+/// Write `batch` to `w` as a Parquet file
+pub fn write_parquet<W: io::Write + Send>(batch: &RecordBatch, w: W) -> Result<(), Error> {
+    let mut writer = try!(ArrowWriter::try_new(w, batch.schema(), None));
+    try!(writer.write(batch));
+    try!(writer.close());
+    Ok(())
+}
+
+#[test]
+fn batches() {
+    use arrow_array::{Array, UInt64Array, Float64Array};
+    use super::super::Parser;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var reg 4 \" count $end
+    $var real 64 # v $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! b0000 \" r0.5 #
+    #5 1! b1x01 \"
+    #10 0! b0011 \"
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let vars: Vec<&Var> = header.iter_vars().map(|(_, v)| v).collect();
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+
+    let batches = signal_batches(commands.iter().cloned().map(Ok), &vars).unwrap();
+    let count = batches[1].column(1).as_any().downcast_ref::<UInt64Array>().unwrap();
+    assert_eq!(count.iter().collect::<Vec<_>>(), vec![Some(0), None, Some(3)]);
+    let v = batches[2].column(1).as_any().downcast_ref::<Float64Array>().unwrap();
+    assert_eq!(v.value(0), 0.5);
+
+    let batch = sampled_batch(commands.iter().cloned().map(Ok), &vars, 5).unwrap();
+    assert_eq!(batch.schema().field(1).name(), "clk");
+    let clk = batch.column(1).as_any().downcast_ref::<UInt64Array>().unwrap();
+    assert_eq!(clk.iter().collect::<Vec<_>>(), vec![Some(0), Some(1), Some(0)]);
+
+    let mut out = Vec::new();
+    write_parquet(&batch, &mut out).unwrap();
+    assert_eq!(&out[..4], b"PAR1");
+}
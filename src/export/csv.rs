@@ -0,0 +1,142 @@
+use std::io;
+use std::collections::HashMap;
+
+use {
+    Radix,
+    IdCode,
+    VarValue,
+    Header,
+    Command,
+    Error,
+    ErrorKind,
+};
+use super::sample;
+
+/// Options for `csv` export
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    /// Base used for vector values
+    pub radix: Radix,
+
+    /// If set, write a row every `interval` time units rather than one per timestamp
+    pub interval: Option<u64>,
+
+    /// Column separator
+    pub separator: char,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions { radix: Radix::Hex, interval: None, separator: ',' }
+    }
+}
+
+fn write_field(w: &mut io::Write, s: &str, separator: char) -> io::Result<()> {
+    if s.contains(separator) || s.contains('"') || s.contains('\n') || s.contains('\r') {
+        write!(w, "\"{}\"", s.replace('"', "\"\""))
+    } else {
+        write!(w, "{}", s)
+    }
+}
+
+fn write_row(w: &mut io::Write, time: u64, values: &[Option<VarValue>], options: &CsvOptions)
+    -> io::Result<()> {
+    try!(write!(w, "{}", time));
+    for v in values {
+        try!(write!(w, "{}", options.separator));
+        if let Some(ref v) = *v {
+            try!(write_field(w, &v.format(options.radix), options.separator));
+        }
+    }
+    writeln!(w, "")
+}
+
+/// Write the values of the signals at `paths` from a VCD data section to `w` as CSV.
+///
+/// The paths are dotted, as from `Header::iter_vars`, and fail with an `Invalid` error if
+/// `header` has no such variable. The first column is the time, followed by one column per signal
+/// named by its path. By default there is one row per timestamp, holding the values after all
+/// changes at that time. Signals without a value yet are left empty.
+pub fn csv<I, S>(header: &Header, commands: I, paths: &[S], options: &CsvOptions,
+    w: &mut io::Write) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>>, S: AsRef<str> {
+    let codes: HashMap<String, IdCode> = header.iter_vars()
+        .map(|(path, v)| (path.join("."), v.code)).collect();
+    let mut ids = Vec::new();
+    for path in paths {
+        let id = try!(codes.get(path.as_ref())
+            .ok_or(Error::Invalid(ErrorKind::Other("Signal not found in header"))));
+        ids.push(*id);
+    }
+
+    try!(write!(w, "time"));
+    for path in paths {
+        try!(write!(w, "{}", options.separator));
+        try!(write_field(w, path.as_ref(), options.separator));
+    }
+    try!(writeln!(w, ""));
+
+    if let Some(interval) = options.interval {
+        return sample(commands, &ids, interval, |t, values| {
+            Ok(try!(write_row(w, t, values, options)))
+        });
+    }
+
+    let mut index: HashMap<IdCode, Vec<usize>> = HashMap::new();
+    for (i, &id) in ids.iter().enumerate() {
+        index.entry(id).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut values = vec![None; ids.len()];
+    let mut time = None;
+    for c in commands {
+        let c = try!(c);
+        if let Command::Timestamp(t) = c {
+            if let Some(prev) = time {
+                if prev != t { try!(write_row(w, prev, &values, options)); }
+            }
+            time = Some(t);
+        } else if let Some((id, v)) = c.change_value() {
+            if let Some(slots) = index.get(&id) {
+                for &i in slots {
+                    values[i] = Some(v.clone());
+                }
+            }
+        }
+    }
+    if let Some(t) = time {
+        try!(write_row(w, t, &values, options));
+    }
+    Ok(())
+}
+
+#[test]
+fn export() {
+    use super::super::Parser;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var wire 12 \" addr $end
+    $var real 64 # v,out $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! bx0001111z \"
+    #10 1! b1 \" r0.5 #
+    #20 0!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let paths = ["top.clk", "top.addr", "top.v,out", "top.clk"];
+
+    let mut out = Vec::new();
+    csv(&header, p, &paths, &CsvOptions::default(), &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "time,top.clk,top.addr,\"top.v,out\",top.clk
+0,0,x1Z,,0\n10,1,1,0.5,1\n20,0,1,0.5,0\n");
+
+    match csv(&header, Vec::new(), &["top.nope"], &CsvOptions::default(), &mut Vec::new()) {
+        Err(Error::Invalid(_)) => (),
+        r => panic!("{:?}", r),
+    }
+}
@@ -0,0 +1,254 @@
+use std::io;
+
+use {
+    Radix,
+    Scope,
+    ScopeItem,
+    Var,
+    Header,
+    Error,
+    ErrorKind,
+};
+
+/// Trace colors available in GTKWave
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Color {
+    Normal,
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Indigo,
+    Violet,
+}
+
+/// A signal to display, and how to display it
+#[derive(Debug, Clone)]
+pub struct GtkwSignal<'a> {
+    /// Dot-separated path to the variable, starting with its top-level scope, e.g. `top.cpu.pc`, or
+    /// only its reference for a variable declared outside any scope
+    pub path: &'a str,
+
+    /// Base used for vector values
+    pub radix: Radix,
+
+    /// Trace color, or `None` for GTKWave's default
+    pub color: Option<Color>,
+}
+
+impl<'a> GtkwSignal<'a> {
+    /// Display the signal at `path` in hex with the default color
+    pub fn new(path: &'a str) -> GtkwSignal<'a> {
+        GtkwSignal { path: path, radix: Radix::Hex, color: None }
+    }
+}
+
+/// Find the variable at `path` below `scope`, returning it along with the scopes containing it
+fn find<'h>(scope: &'h Scope, path: &[&str], scopes: &mut Vec<&'h str>) -> Option<&'h Var> {
+    scopes.push(&scope.identifier);
+    for item in &scope.children {
+        match (item, path.len()) {
+            (&ScopeItem::Var(ref v), 1) if v.reference == path[0] => return Some(v),
+            (&ScopeItem::Scope(ref s), n) if n > 1 && s.identifier == path[0] => {
+                return find(s, &path[1..], scopes);
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// GTKWave trace flags: right-justified, in the given base
+fn flags(radix: Radix) -> u32 {
+    match radix {
+        Radix::Hex => 0x22,
+        Radix::Decimal => 0x24,
+        Radix::Binary => 0x28,
+    }
+}
+
+/// GTKWave trace flags for the comment traces beginning and ending a group
+const GROUP_BEGIN: u32 = 0x800200;
+const GROUP_END: u32 = 0x1000200;
+
+/// Write a GTKWave save file (`.gtkw`) to `w` that opens `dumpfile` with `signals` displayed.
+///
+/// The signal paths are checked against `header`, failing with `Error::Invalid` for a path that is
+/// not found, and the scopes containing them are expanded in the signal tree. Traces are named by
+/// the path followed by the variable's bit index or range, if it was declared with one, or else
+/// `[size-1:0]` for a vector.
+pub fn gtkw(header: &Header, dumpfile: &str, signals: &[GtkwSignal], w: &mut io::Write)
+    -> Result<(), Error> {
+    write(header, dumpfile, signals, false, w)
+}
+
+/// Write a GTKWave save file as for `gtkw`, with the signals in a group for each scope
+/// containing any of them, named by the path of the scope. Groups are in the order their first
+/// signal appears in `signals`.
+pub fn gtkw_grouped(header: &Header, dumpfile: &str, signals: &[GtkwSignal], w: &mut io::Write)
+    -> Result<(), Error> {
+    write(header, dumpfile, signals, true, w)
+}
+
+fn write(header: &Header, dumpfile: &str, signals: &[GtkwSignal], grouped: bool,
+    w: &mut io::Write) -> Result<(), Error> {
+    let mut traces = Vec::new();
+    let mut open: Vec<String> = Vec::new();
+    for s in signals {
+        let path: Vec<&str> = s.path.split('.').collect();
+        let mut scopes = Vec::new();
+        let var = if path.len() == 1 {
+            header.vars.iter().find(|v| v.reference == path[0])
+        } else {
+            header.top_scopes().find(|scope| scope.identifier == path[0])
+                .and_then(|scope| find(scope, &path[1..], &mut scopes))
+        };
+        let var = try!(var.ok_or(Error::Invalid(ErrorKind::Other("Signal not found in header"))));
+
+        for i in 1..scopes.len() + 1 {
+            let prefix = scopes[..i].join(".") + ".";
+            if !open.contains(&prefix) { open.push(prefix); }
+        }
+        let name = match var.index {
+            Some(ref index) => format!("{}{}", s.path, index),
+            None if var.size > 1 => format!("{}[{}:0]", s.path, var.size - 1),
+            None => s.path.to_string(),
+        };
+        traces.push((s, name, scopes.join(".")));
+    }
+    if grouped {
+        let mut groups: Vec<String> = Vec::new();
+        for &(_, _, ref scope) in &traces {
+            if !groups.contains(scope) { groups.push(scope.clone()); }
+        }
+        traces.sort_by_key(|t| groups.iter().position(|g| *g == t.2));
+    }
+
+    try!(writeln!(w, "[dumpfile] \"{}\"", dumpfile));
+    for scope in &open {
+        try!(writeln!(w, "[treeopen] {}", scope));
+    }
+    if let Some(scope) = open.last() {
+        try!(writeln!(w, "[sst_expanded] 1\n[sst_select] {}", scope.trim_end_matches('.')));
+    }
+    for (i, &(s, ref name, ref scope)) in traces.iter().enumerate() {
+        let first = i == 0 || traces[i - 1].2 != *scope;
+        if grouped && first {
+            try!(writeln!(w, "@{:x}\n-{}", GROUP_BEGIN, scope));
+        }
+        if let Some(color) = s.color {
+            try!(writeln!(w, "[color] {}", color as u32));
+        }
+        try!(writeln!(w, "@{:x}\n{}", flags(s.radix), name));
+        let last = i + 1 == traces.len() || traces[i + 1].2 != *scope;
+        if grouped && last {
+            try!(writeln!(w, "@{:x}\n-{}", GROUP_END, scope));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn save_file() {
+    use super::super::Parser;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $scope module cpu $end
+    $var reg 16 \" pc $end
+    $var reg 8 # data [15:8] $end
+    $var wire 1 $ irq [3] $end
+    $upscope $end
+    $upscope $end
+    $enddefinitions $end
+    ";
+
+    let header = Parser::new(&sample[..]).parse_header().unwrap();
+    let signals = [GtkwSignal::new("top.cpu.data"), GtkwSignal::new("top.cpu.irq")];
+    let mut out = Vec::new();
+    gtkw(&header, "dump.vcd", &signals, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.ends_with("@22\ntop.cpu.data[15:8]\n@22\ntop.cpu.irq[3]\n"), "{}", out);
+
+    let signals = [
+        GtkwSignal::new("top.clk"),
+        GtkwSignal { path: "top.cpu.pc", radix: Radix::Decimal, color: Some(Color::Green) },
+    ];
+    let mut out = Vec::new();
+    gtkw(&header, "dump.vcd", &signals, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\
+[dumpfile] \"dump.vcd\"
+[treeopen] top.
+[treeopen] top.cpu.
+[sst_expanded] 1
+[sst_select] top.cpu
+@22
+top.clk
+[color] 4
+@24
+top.cpu.pc[15:0]
+");
+
+    match gtkw(&header, "dump.vcd", &[GtkwSignal::new("top.nope")], &mut Vec::new()) {
+        Err(Error::Invalid(ErrorKind::Other(_))) => (),
+        r => panic!("{:?}", r),
+    }
+
+    let signals = [
+        GtkwSignal::new("top.cpu.pc"),
+        GtkwSignal::new("top.clk"),
+        GtkwSignal { path: "top.cpu.pc", radix: Radix::Binary, color: None },
+    ];
+    let mut out = Vec::new();
+    gtkw_grouped(&header, "dump.vcd", &signals, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.ends_with("\
+@800200
+-top.cpu
+@22
+top.cpu.pc[15:0]
+@28
+top.cpu.pc[15:0]
+@1000200
+-top.cpu
+@800200
+-top
+@22
+top.clk
+@1000200
+-top
+"), "{}", out);
+}
+
+#[test]
+fn top_level_vars() {
+    use super::super::Parser;
+
+    let sample = b"
+    $var wire 1 ! reset $end
+    $scope module top $end
+    $var wire 1 \" clk $end
+    $upscope $end
+    $scope module glbl $end
+    $var wire 1 # GSR $end
+    $upscope $end
+    $enddefinitions $end
+    ";
+
+    let header = Parser::new(&sample[..]).parse_header().unwrap();
+    let signals = [GtkwSignal::new("reset"), GtkwSignal::new("glbl.GSR")];
+    let mut out = Vec::new();
+    gtkw(&header, "dump.vcd", &signals, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\
+[dumpfile] \"dump.vcd\"
+[treeopen] glbl.
+[sst_expanded] 1
+[sst_select] glbl
+@22
+reset
+@22
+glbl.GSR
+");
+}
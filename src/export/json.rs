@@ -0,0 +1,187 @@
+use std::io;
+
+use serde_json::{self, Value as Json};
+
+use {
+    Radix,
+    VarValue,
+    Scope,
+    ScopeItem,
+    Var,
+    Header,
+    Command,
+    Error,
+};
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Io(err.into())
+    }
+}
+
+fn var_json(v: &Var) -> Json {
+    json!({
+        "kind": "var",
+        "type": v.var_type.to_string(),
+        "size": v.size,
+        "id": v.code.to_string(),
+        "name": v.reference,
+    })
+}
+
+fn scope_json(scope: &Scope) -> Json {
+    let children: Vec<Json> = scope.children.iter().map(|item| match *item {
+        ScopeItem::Scope(ref s) => scope_json(s),
+        ScopeItem::Var(ref v) => var_json(v),
+    }).collect();
+    json!({
+        "kind": "scope",
+        "type": scope.scope_type.to_string(),
+        "name": scope.identifier,
+        "children": children,
+    })
+}
+
+/// Convert `header` to a JSON tree.
+///
+/// Scopes and variables are objects with a `kind` of `"scope"` or `"var"`, and scopes list their
+/// contents in `children`. The first top-level scope is under `scope`, and the other top-level
+/// scopes and the variables declared outside any scope are in the `other_scopes` and `vars` arrays.
+pub fn header_json(header: &Header) -> Json {
+    json!({
+        "comment": header.comment,
+        "date": header.date,
+        "version": header.version,
+        "timescale": header.timescale.map(|(v, unit)| format!("{} {}", v, unit)),
+        "scope": scope_json(&header.scope),
+        "other_scopes": header.other_scopes.iter().map(scope_json).collect::<Vec<_>>(),
+        "vars": header.vars.iter().map(var_json).collect::<Vec<_>>(),
+    })
+}
+
+/// Convert a change command to a `{"time", "id", "value"}` object, or `None` if `c` is not a
+/// change. Scalar and vector values are binary strings.
+pub fn change_json(time: u64, c: &Command) -> Option<Json> {
+    c.change_value().map(|(id, v)| {
+        let value = match v {
+            VarValue::Real(r) => json!(r),
+            VarValue::String(s) => json!(s),
+            v => json!(v.format(Radix::Binary)),
+        };
+        json!({ "time": time, "id": id.to_string(), "value": value })
+    })
+}
+
+/// Call `f` with the JSON object for each change in `commands`
+fn changes<I, F>(commands: I, mut f: F) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>>, F: FnMut(Json) -> Result<(), Error> {
+    let mut time = 0;
+    for c in commands {
+        let c = try!(c);
+        if let Command::Timestamp(t) = c {
+            time = t;
+        } else if let Some(change) = change_json(time, &c) {
+            try!(f(change));
+        }
+    }
+    Ok(())
+}
+
+/// Write a VCD to `w` as a single JSON object, with the header tree under `"header"` and an array
+/// of `{"time", "id", "value"}` objects under `"changes"`.
+///
+/// The changes are written as they are read, so the file need not fit in memory.
+pub fn json<I>(header: &Header, commands: I, w: &mut io::Write) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    try!(write!(w, "{{\"header\":"));
+    try!(serde_json::to_writer(&mut *w, &header_json(header)));
+    try!(write!(w, ",\"changes\":["));
+    let mut first = true;
+    try!(changes(commands, |change| {
+        if !first { try!(write!(w, ",")); }
+        first = false;
+        Ok(try!(serde_json::to_writer(&mut *w, &change)))
+    }));
+    try!(writeln!(w, "]}}"));
+    Ok(())
+}
+
+/// Write a VCD to `w` as JSON Lines: the header tree on the first line, followed by one
+/// `{"time", "id", "value"}` object per line for each change.
+pub fn json_lines<I>(header: &Header, commands: I, w: &mut io::Write) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    try!(serde_json::to_writer(&mut *w, &header_json(header)));
+    try!(writeln!(w, ""));
+    changes(commands, |change| {
+        try!(serde_json::to_writer(&mut *w, &change));
+        Ok(try!(writeln!(w, "")))
+    })
+}
+
+#[test]
+fn export() {
+    use super::super::Parser;
+
+    let sample = b"
+    $timescale 10 ns $end
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var reg 4 \" count $end
+    $var real 64 # v $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! b0000 \" r0.5 #
+    #5 1! b1x01 \"
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let mut out = Vec::new();
+    json_lines(&header, p, &mut out).unwrap();
+
+    let lines: Vec<Json> = out.split(|&b| b == b'\n').filter(|l| !l.is_empty())
+        .map(|l| serde_json::from_slice(l).unwrap()).collect();
+    assert_eq!(lines[0]["timescale"], json!("10 ns"));
+    assert_eq!(lines[0]["scope"]["children"][1], json!({
+        "kind": "var", "type": "reg", "size": 4, "id": "\"", "name": "count"
+    }));
+    assert_eq!(&lines[1..], &[
+        json!({ "time": 0, "id": "!", "value": "0" }),
+        json!({ "time": 0, "id": "\"", "value": "0000" }),
+        json!({ "time": 0, "id": "#", "value": 0.5 }),
+        json!({ "time": 5, "id": "!", "value": "1" }),
+        json!({ "time": 5, "id": "\"", "value": "1x01" }),
+    ][..]);
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let mut out = Vec::new();
+    json(&header, p, &mut out).unwrap();
+    let doc: Json = serde_json::from_slice(&out).unwrap();
+    assert_eq!(doc["header"], lines[0]);
+    assert_eq!(doc["changes"].as_array().unwrap()[..], lines[1..]);
+}
+
+#[test]
+fn top_level_vars() {
+    use super::super::Parser;
+
+    let sample = b"
+    $var wire 1 ! reset $end
+    $scope module top $end
+    $var wire 1 \" clk $end
+    $upscope $end
+    $scope module glbl $end
+    $var wire 1 # GSR $end
+    $upscope $end
+    $enddefinitions $end
+    ";
+
+    let header = Parser::new(&sample[..]).parse_header().unwrap();
+    let doc = header_json(&header);
+    assert_eq!(doc["vars"], json!([
+        { "kind": "var", "type": "wire", "size": 1, "id": "!", "name": "reset" }
+    ]));
+    assert_eq!(doc["scope"]["name"], json!("top"));
+    assert_eq!(doc["other_scopes"][0]["children"][0]["name"], json!("GSR"));
+}
@@ -0,0 +1,86 @@
+//! Export of VCD data to other file formats
+
+use std::collections::HashMap;
+
+use {
+    IdCode,
+    VarValue,
+    Command,
+    Error,
+};
+
+mod csv;
+pub use self::csv::{csv, CsvOptions};
+
+mod gtkw;
+pub use self::gtkw::{gtkw, gtkw_grouped, GtkwSignal, Color};
+
+mod npy;
+pub use self::npy::npy;
+
+mod verilog;
+pub use self::verilog::{stimulus, readmemb};
+
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "arrow")]
+pub use self::arrow::{signal_batches, sampled_batch, write_parquet};
+
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "json")]
+pub use self::json::{json, json_lines, header_json, change_json};
+#[cfg(feature = "json")]
+mod wavejson;
+#[cfg(feature = "json")]
+pub use self::wavejson::wavejson;
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::sqlite;
+
+#[cfg(feature = "sigrok")]
+mod sigrok;
+#[cfg(feature = "sigrok")]
+pub use self::sigrok::sigrok;
+
+/// Call `f` with the values of the variables `ids` every `interval` time units, from the first
+/// timestamp to the last one in `commands`.
+///
+/// Values that have not been assigned yet are passed as `None`.
+fn sample<I, F>(commands: I, ids: &[IdCode], interval: u64, mut f: F) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>>,
+          F: FnMut(u64, &[Option<VarValue>]) -> Result<(), Error> {
+    let interval = ::std::cmp::max(interval, 1);
+    let mut index: HashMap<IdCode, Vec<usize>> = HashMap::new();
+    for (i, &id) in ids.iter().enumerate() {
+        index.entry(id).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut values = vec![None; ids.len()];
+    let mut time = None;
+    let mut next_sample = 0;
+    for c in commands {
+        let c = try!(c);
+        if let Command::Timestamp(t) = c {
+            if time.is_none() { next_sample = t; }
+            while next_sample < t {
+                try!(f(next_sample, &values));
+                next_sample += interval;
+            }
+            time = Some(t);
+        } else if let Some((id, v)) = c.change_value() {
+            if let Some(slots) = index.get(&id) {
+                for &i in slots {
+                    values[i] = Some(v.clone());
+                }
+            }
+        }
+    }
+
+    if time == Some(next_sample) {
+        try!(f(next_sample, &values));
+    }
+    Ok(())
+}
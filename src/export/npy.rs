@@ -0,0 +1,108 @@
+use std::io;
+
+use {
+    Value,
+    VarValue,
+    Var,
+    Header,
+    Command,
+    Error,
+};
+use super::sample;
+
+/// The numeric value of `v`, with vectors read as unsigned integers. Unassigned values and
+/// vectors with `x` or `z` bits are NaN.
+fn to_f64(v: &Option<VarValue>) -> f64 {
+    let bit = |n: f64, b: Value| match b {
+        Value::V0 => n * 2.0,
+        Value::V1 => n * 2.0 + 1.0,
+        _ => ::std::f64::NAN,
+    };
+    match *v {
+        Some(VarValue::Real(r)) => r,
+        Some(VarValue::Scalar(b)) => bit(0.0, b),
+        Some(VarValue::Vector(ref b)) => b.iter().fold(0.0, bit),
+        _ => ::std::f64::NAN,
+    }
+}
+
+/// Write the header of a `.npy` file for a little-endian `f64` array of the given shape
+fn write_npy_header(w: &mut io::Write, rows: usize, cols: usize) -> io::Result<()> {
+    let mut dict = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}",
+        rows, cols);
+    // Magic, version and length take 10 bytes, and the data must start 64-byte aligned
+    let len = (10 + dict.len() + 1 + 63) / 64 * 64 - 10;
+    while dict.len() < len - 1 { dict.push(' '); }
+    dict.push('\n');
+
+    try!(w.write_all(b"\x93NUMPY\x01\x00"));
+    try!(w.write_all(&[(len & 0xff) as u8, (len >> 8) as u8]));
+    w.write_all(dict.as_bytes())
+}
+
+/// Sample `signals` every `interval` time units and write them to `w` as a NumPy `.npy` file.
+///
+/// The array has one row per sample and a column of times in seconds, converted using the
+/// header's `$timescale`, followed by a column per signal. Real variables keep their values,
+/// other variables are read as unsigned integers, and `x`, `z` or unassigned values become NaN.
+/// Load it with `numpy.load`.
+pub fn npy<I>(header: &Header, commands: I, signals: &[&Var], interval: u64, w: &mut io::Write)
+    -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let (ts, unit) = header.timescale.unwrap_or((1, ::TimescaleUnit::S));
+    let seconds = ts as f64 / unit.divisor() as f64;
+
+    let ids: Vec<_> = signals.iter().map(|v| v.code).collect();
+    let mut data = Vec::new();
+    try!(sample(commands, &ids, interval, |t, values| {
+        data.push(t as f64 * seconds);
+        data.extend(values.iter().map(to_f64));
+        Ok(())
+    }));
+
+    let cols = signals.len() + 1;
+    try!(write_npy_header(w, data.len() / cols, cols));
+    for v in data {
+        try!(w.write_all(&v.to_bits().to_le_bytes()));
+    }
+    Ok(())
+}
+
+#[test]
+fn analog() {
+    use super::super::Parser;
+
+    let sample = b"
+    $timescale 1 us $end
+    $scope module top $end
+    $var real 64 ! vout $end
+    $var reg 4 \" code $end
+    $upscope $end
+    $enddefinitions $end
+    #0 r0.25 ! b0011 \"
+    #2 r1.5 ! bx011 \"
+    #4 r-2 !
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let vars: Vec<&Var> = header.iter_vars().map(|(_, v)| v).collect();
+
+    let mut out = Vec::new();
+    npy(&header, p, &vars, 1, &mut out).unwrap();
+
+    assert_eq!(&out[..8], b"\x93NUMPY\x01\x00");
+    let len = out[8] as usize | (out[9] as usize) << 8;
+    let dict = ::std::str::from_utf8(&out[10..10 + len]).unwrap();
+    assert!(dict.contains("'shape': (5, 3)"));
+    assert_eq!((10 + len) % 64, 0);
+
+    let values: Vec<f64> = out[10 + len..].chunks(8).map(|c| {
+        let mut b = [0; 8];
+        b.copy_from_slice(c);
+        f64::from_bits(u64::from_le_bytes(b))
+    }).collect();
+    assert_eq!(&values[..6], &[0.0, 0.25, 3.0, 1e-6, 0.25, 3.0]);
+    assert_eq!(&values[12..14], &[4e-6, -2.0]);
+    assert!(values[14].is_nan());
+}
@@ -0,0 +1,143 @@
+use std::io::{self, Write, Seek};
+
+use zip::ZipWriter;
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+
+use {
+    Value,
+    VarValue,
+    Header,
+    Var,
+    Command,
+    Error,
+};
+use super::sample;
+
+/// Size of each `logic-1-N` sample file in the archive
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+impl From<ZipError> for Error {
+    fn from(err: ZipError) -> Error {
+        match err {
+            ZipError::Io(e) => Error::Io(e),
+            e => Error::Io(io::Error::new(io::ErrorKind::Other, e.to_string())),
+        }
+    }
+}
+
+fn samplerate_string(rate: u64) -> String {
+    match rate {
+        r if r >= 1_000_000_000 && r % 1_000_000_000 == 0 => format!("{} GHz", r / 1_000_000_000),
+        r if r >= 1_000_000 && r % 1_000_000 == 0 => format!("{} MHz", r / 1_000_000),
+        r if r >= 1_000 && r % 1_000 == 0 => format!("{} kHz", r / 1_000),
+        r => format!("{} Hz", r),
+    }
+}
+
+/// Write 1-bit `signals` from a VCD to `w` as a sigrok session (`.sr`) file, readable by PulseView
+/// and `sigrok-cli`.
+///
+/// The signals are sampled every `interval` time units, which together with the header's
+/// `$timescale` sets the session's sample rate. sigrok has no unknown or high-impedance logic
+/// level, so `x` and `z` are written as 0.
+pub fn sigrok<I, W>(header: &Header, commands: I, signals: &[&Var], interval: u64, w: W)
+    -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>>, W: Write + Seek {
+    if signals.iter().any(|v| v.size != 1) {
+        return Err(Error::Parse("sigrok export supports only 1-bit variables"));
+    }
+
+    let (ts, unit) = header.timescale.unwrap_or((1, ::TimescaleUnit::S));
+    let period = (ts as u64).saturating_mul(::std::cmp::max(interval, 1));
+    let samplerate = (unit.divisor() as f64 / period as f64).round() as u64;
+    let unitsize = (signals.len() + 7) / 8;
+
+    let mut zip = ZipWriter::new(w);
+    let options = SimpleFileOptions::default();
+
+    try!(zip.start_file("version", options));
+    try!(zip.write_all(b"2"));
+
+    try!(zip.start_file("metadata", options));
+    try!(writeln!(zip, "[global]\nsigrok version=0.5.2\n\n[device 1]"));
+    try!(writeln!(zip, "capturefile=logic-1"));
+    try!(writeln!(zip, "total probes={}", signals.len()));
+    try!(writeln!(zip, "samplerate={}", samplerate_string(samplerate)));
+    try!(writeln!(zip, "total analog=0"));
+    for (i, v) in signals.iter().enumerate() {
+        try!(writeln!(zip, "probe{}={}", i + 1, v.reference));
+    }
+    try!(writeln!(zip, "unitsize={}", unitsize));
+
+    let ids: Vec<_> = signals.iter().map(|v| v.code).collect();
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    let mut chunks = 0;
+    let mut sample_bytes = vec![0u8; unitsize];
+    try!(sample(commands, &ids, interval, |_, values| {
+        for b in sample_bytes.iter_mut() { *b = 0; }
+        for (i, v) in values.iter().enumerate() {
+            let high = match *v {
+                Some(VarValue::Scalar(Value::V1)) => true,
+                Some(VarValue::Vector(ref v)) => v.iter().next_back() == Some(Value::V1),
+                _ => false,
+            };
+            if high { sample_bytes[i / 8] |= 1 << (i % 8); }
+        }
+        chunk.extend_from_slice(&sample_bytes);
+
+        if chunk.len() + unitsize > CHUNK_SIZE {
+            chunks += 1;
+            try!(zip.start_file(format!("logic-1-{}", chunks), options));
+            try!(zip.write_all(&chunk));
+            chunk.clear();
+        }
+        Ok(())
+    }));
+
+    if !chunk.is_empty() || chunks == 0 {
+        chunks += 1;
+        try!(zip.start_file(format!("logic-1-{}", chunks), options));
+        try!(zip.write_all(&chunk));
+    }
+
+    try!(zip.finish());
+    Ok(())
+}
+
+#[test]
+fn session() {
+    use std::io::{Cursor, Read};
+    use zip::ZipArchive;
+    use super::super::Parser;
+
+    let sample = b"
+    $timescale 1 us $end
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var wire 1 \" data $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! 1\"
+    #2 1!
+    #4 0! 0\"
+    #6 1!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let vars: Vec<&Var> = header.iter_vars().map(|(_, v)| v).collect();
+
+    let mut buf = Cursor::new(Vec::new());
+    sigrok(&header, p, &vars, 1, &mut buf).unwrap();
+
+    let mut archive = ZipArchive::new(buf).unwrap();
+    let mut metadata = String::new();
+    archive.by_name("metadata").unwrap().read_to_string(&mut metadata).unwrap();
+    assert!(metadata.contains("samplerate=1 MHz\n"));
+    assert!(metadata.contains("probe2=data\n"));
+
+    let mut samples = Vec::new();
+    archive.by_name("logic-1-1").unwrap().read_to_end(&mut samples).unwrap();
+    assert_eq!(samples, vec![0b10, 0b10, 0b11, 0b11, 0b00, 0b00, 0b01]);
+}
@@ -0,0 +1,151 @@
+use rusqlite::{self, Connection};
+use rusqlite::types::Value as Sql;
+
+use {
+    Radix,
+    VarValue,
+    Header,
+    Command,
+    Error,
+};
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::Io(::std::io::Error::new(::std::io::ErrorKind::Other, err.to_string()))
+    }
+}
+
+const SCHEMA: &'static str = "
+    CREATE TABLE signals (
+        path TEXT PRIMARY KEY,
+        scope TEXT NOT NULL,
+        name TEXT NOT NULL,
+        type TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        code TEXT NOT NULL
+    );
+    CREATE INDEX signals_code ON signals (code);
+    CREATE TABLE changes (
+        time INTEGER NOT NULL,
+        code TEXT NOT NULL,
+        value
+    );
+";
+
+const INDEXES: &'static str = "
+    CREATE INDEX changes_code_time ON changes (code, time);
+    CREATE INDEX changes_time ON changes (time);
+";
+
+fn insert_vars(tx: &rusqlite::Transaction, header: &Header) -> Result<(), Error> {
+    for (path, v) in header.iter_vars() {
+        let scope = path[..path.len() - 1].join(".");
+        try!(tx.execute(
+            "INSERT OR REPLACE INTO signals VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (path.join("."), &scope, &v.reference, v.var_type.to_string(), v.size,
+             v.code.to_string())));
+    }
+    Ok(())
+}
+
+/// Write the variables in `header` and the changes in `commands` to tables in `conn`.
+///
+/// The `signals` table has a row per variable, with its dotted `path`, containing `scope`, `name`,
+/// `type`, `size` and identifier `code` as written in the file. Aliased variables share a `code`.
+/// Variables declared outside any scope have an empty `scope`.
+/// The `changes` table has a row per value change with its `time`, `code` and `value`. Real values
+/// are stored as SQLite reals, and other values as binary strings. The changes are indexed by code
+/// and by time.
+///
+/// Everything is written in a single transaction. The tables must not already exist.
+pub fn sqlite<I>(header: &Header, commands: I, conn: &mut Connection) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let tx = try!(conn.transaction());
+    try!(tx.execute_batch(SCHEMA));
+    try!(insert_vars(&tx, header));
+
+    {
+        let mut insert = try!(tx.prepare("INSERT INTO changes VALUES (?1, ?2, ?3)"));
+        let mut time = 0;
+        for c in commands {
+            let c = try!(c);
+            if let Command::Timestamp(t) = c {
+                time = t;
+            } else if let Some((id, v)) = c.change_value() {
+                let value = match v {
+                    VarValue::Real(r) => Sql::Real(r),
+                    v => Sql::Text(v.format(Radix::Binary)),
+                };
+                try!(insert.execute((time as i64, id.to_string(), value)));
+            }
+        }
+    }
+
+    try!(tx.execute_batch(INDEXES));
+    try!(tx.commit());
+    Ok(())
+}
+
+#[test]
+fn events() {
+    use super::super::Parser;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $scope module cpu $end
+    $var reg 4 \" pc $end
+    $var real 64 # temp $end
+    $upscope $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! b0000 \" r20.5 #
+    #5 1! b0001 \"
+    #10 0!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let mut conn = Connection::open_in_memory().unwrap();
+    sqlite(&header, p, &mut conn).unwrap();
+
+    let rows: Vec<(i64, String)> = conn.prepare("
+        SELECT time, value FROM changes JOIN signals USING (code)
+        WHERE path = 'top.cpu.pc' ORDER BY time
+    ").unwrap().query_map((), |r| Ok((try!(r.get(0)), try!(r.get(1))))).unwrap()
+        .map(Result::unwrap).collect();
+    assert_eq!(rows, vec![(0, "0000".to_string()), (5, "0001".to_string())]);
+
+    let temp: f64 = conn.query_row(
+        "SELECT value FROM changes JOIN signals USING (code) WHERE name = 'temp'",
+        (), |r| r.get(0)).unwrap();
+    assert_eq!(temp, 20.5);
+}
+
+#[test]
+fn top_level_vars() {
+    use super::super::Parser;
+
+    let sample = b"
+    $var wire 1 ! reset $end
+    $scope module top $end
+    $var wire 1 \" clk $end
+    $upscope $end
+    $enddefinitions $end
+    #0 1! 0\"
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let mut conn = Connection::open_in_memory().unwrap();
+    sqlite(&header, p, &mut conn).unwrap();
+
+    let rows: Vec<(String, String, String)> = conn.prepare(
+        "SELECT path, scope, name FROM signals ORDER BY path"
+    ).unwrap().query_map((), |r| Ok((try!(r.get(0)), try!(r.get(1)), try!(r.get(2))))).unwrap()
+        .map(Result::unwrap).collect();
+    assert_eq!(rows, vec![
+        ("reset".to_string(), "".to_string(), "reset".to_string()),
+        ("top.clk".to_string(), "top".to_string(), "clk".to_string()),
+    ]);
+}
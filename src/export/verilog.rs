@@ -0,0 +1,167 @@
+use std::io;
+use std::collections::HashMap;
+
+use {
+    Radix,
+    VarValue,
+    IdCode,
+    Var,
+    Command,
+    Error,
+};
+
+/// A Verilog literal for value `v` of `var`
+fn literal(var: &Var, v: &VarValue) -> String {
+    match *v {
+        VarValue::Real(r) => format!("{:?}", r),
+        VarValue::String(ref s) => format!("{:?}", s),
+        ref v => format!("{}'b{}", var.size, v.format(Radix::Binary)),
+    }
+}
+
+/// The changes at one timestamp, as `(time, [(signal index, value)])`
+type Step = (u64, Vec<(usize, VarValue)>);
+
+/// Group the changes to `signals` by timestamp
+fn changes_by_time<I>(commands: I, signals: &[&Var]) -> Result<Vec<Step>, Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let mut index: HashMap<IdCode, Vec<usize>> = HashMap::new();
+    for (i, v) in signals.iter().enumerate() {
+        index.entry(v.code).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut out: Vec<Step> = Vec::new();
+    let mut time = 0;
+    for c in commands {
+        let c = try!(c);
+        if let Command::Timestamp(t) = c {
+            time = t;
+        } else if let Some((id, v)) = c.change_value() {
+            for &i in index.get(&id).map(|i| &i[..]).unwrap_or(&[]) {
+                if out.last().map_or(true, |l| l.0 != time) {
+                    out.push((time, Vec::new()));
+                }
+                out.last_mut().unwrap().1.push((i, v.clone()));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Write the changes to `signals` to `w` as a Verilog `initial` block that replays them with
+/// blocking assignments and `#` delays.
+///
+/// Delays are in the time units of the dump, so the testbench's `` `timescale `` should match the
+/// dump's `$timescale`. The signals must be declared as `reg` (or `real`) in the testbench, with
+/// the same names as in the dump.
+pub fn stimulus<I>(commands: I, signals: &[&Var], w: &mut io::Write) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    try!(writeln!(w, "initial begin"));
+    let mut time = 0;
+    for (t, changes) in try!(changes_by_time(commands, signals)) {
+        if t > time {
+            try!(writeln!(w, "  #{};", t - time));
+            time = t;
+        }
+        for (i, v) in changes {
+            try!(writeln!(w, "  {} = {};", signals[i].reference, literal(signals[i], &v)));
+        }
+    }
+    try!(writeln!(w, "end"));
+    Ok(())
+}
+
+/// Write the changes to `signals` as `$readmemb` data to `data`, and a Verilog driver that loads
+/// the data from `file` and replays it to `driver`.
+///
+/// Each line of data holds a 64-bit delay followed by the values of all the signals, which
+/// unlike `stimulus` keeps the testbench source small for long captures. Signals that have not
+/// been assigned are `x`. Real and string variables are not supported. As for `stimulus`, delays
+/// are in the time units of the dump.
+pub fn readmemb<I>(commands: I, signals: &[&Var], file: &str, data: &mut io::Write,
+                   driver: &mut io::Write) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    if signals.iter().any(|v| v.var_type.is_real() || v.size == 0) {
+        return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+            "$readmemb stimulus supports only bit and vector variables")));
+    }
+
+    let mut values: Vec<String> = signals.iter().map(|v| "x".repeat(v.size as usize)).collect();
+    let mut time = 0;
+    let mut entries = 0;
+    for (t, changes) in try!(changes_by_time(commands, signals)) {
+        for (i, v) in changes {
+            let bits = v.format(Radix::Binary);
+            let size = signals[i].size as usize;
+            // Extend to the full width as VCD does, with x and z extended and 0 and 1 zero-padded
+            let pad = match bits.chars().next() {
+                Some(c) if c == 'x' || c == 'z' => c,
+                _ => '0',
+            };
+            let skip = bits.len().saturating_sub(size);
+            values[i] = ::std::iter::repeat(pad).take(size.saturating_sub(bits.len()))
+                .chain(bits.chars().skip(skip)).collect();
+        }
+        try!(writeln!(data, "{:064b}_{}", t - time, values.join("_")));
+        time = t;
+        entries += 1;
+    }
+
+    let width: u32 = signals.iter().map(|v| v.size).sum();
+    let names: Vec<&str> = signals.iter().map(|v| &v.reference[..]).collect();
+    try!(writeln!(driver, "reg [{}:0] stimulus [0:{}];", width + 63, entries.max(1) - 1));
+    try!(writeln!(driver, "integer stimulus_i;"));
+    try!(writeln!(driver, "initial begin"));
+    try!(writeln!(driver, "  $readmemb(\"{}\", stimulus);", file));
+    try!(writeln!(driver,
+        "  for (stimulus_i = 0; stimulus_i < {}; stimulus_i = stimulus_i + 1) begin", entries));
+    try!(writeln!(driver, "    #(stimulus[stimulus_i][{}:{}]);", width + 63, width));
+    try!(writeln!(driver, "    {{{}}} = stimulus[stimulus_i][{}:0];", names.join(", "), width - 1));
+    try!(writeln!(driver, "  end"));
+    try!(writeln!(driver, "end"));
+    Ok(())
+}
+
+#[test]
+fn testbench() {
+    use super::super::Parser;
+
+    let sample = b"
+    $scope module top $end
+    $var reg 1 ! rst $end
+    $var reg 4 \" din $end
+    $upscope $end
+    $enddefinitions $end
+    #0 1! bx \"
+    #10 0! b101 \"
+    #25 b1100 \"
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let vars: Vec<&Var> = header.iter_vars().map(|(_, v)| v).collect();
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+
+    let mut out = Vec::new();
+    stimulus(commands.iter().cloned().map(Ok), &vars, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "\
+initial begin
+  rst = 1'b1;
+  din = 4'bx;
+  #10;
+  rst = 1'b0;
+  din = 4'b101;
+  #15;
+  din = 4'b1100;
+end
+");
+
+    let (mut data, mut driver) = (Vec::new(), Vec::new());
+    readmemb(commands.iter().cloned().map(Ok), &vars, "stim.mem", &mut data, &mut driver).unwrap();
+    let data = String::from_utf8(data).unwrap();
+    let lines: Vec<&str> = data.lines().map(|l| &l[60..]).collect();
+    assert_eq!(lines, vec!["0000_1_xxxx", "1010_0_0101", "1111_0_1100"]);
+    let driver = String::from_utf8(driver).unwrap();
+    assert!(driver.starts_with("reg [68:0] stimulus [0:2];\n"));
+    assert!(driver.contains("    {rst, din} = stimulus[stimulus_i][4:0];\n"));
+}
@@ -0,0 +1,139 @@
+use std::ops::Range;
+
+use serde_json::Value as Json;
+
+use {
+    Value,
+    Radix,
+    VarValue,
+    Var,
+    Command,
+    Error,
+};
+use cycles::{CycleSampler, Edge};
+
+/// The WaveJSON character for a scalar value
+fn level(v: Value) -> char {
+    match v.to_four_state() {
+        Value::V1 => '1',
+        Value::X => 'x',
+        Value::Z => 'z',
+        _ => '0',
+    }
+}
+
+/// One row of a WaveJSON diagram being built
+struct Row {
+    wave: String,
+    data: Vec<String>,
+    last: Option<Option<VarValue>>,
+}
+
+impl Row {
+    fn push(&mut self, v: &Option<VarValue>, radix: Radix) {
+        if self.last.as_ref() == Some(v) {
+            self.wave.push('.');
+            return;
+        }
+        self.wave.push(match *v {
+            None => 'x',
+            Some(VarValue::Scalar(b)) => level(b),
+            Some(VarValue::Vector(ref b)) if b.len() == 1 => level(b[0]),
+            Some(VarValue::Vector(ref b)) if b.iter().all(|b| b == Value::X) => 'x',
+            Some(VarValue::Vector(ref b)) if b.iter().all(|b| b == Value::Z) => 'z',
+            Some(ref v) => { self.data.push(v.format(radix)); '=' }
+        });
+        self.last = Some(v.clone());
+    }
+}
+
+/// Render `signals` as a WaveDrom WaveJSON timing diagram, with one diagram cycle per `edge` of
+/// `clock` whose time falls within `window`.
+///
+/// Each cycle shows the value a signal held from one clock edge until the next, as sampled just
+/// before the next edge, so registered signals line up with the clock as in a textbook diagram.
+/// The clock itself is drawn as the first row. Vector values are labelled in base `radix`, and
+/// signals that have not been assigned are shown as `x`.
+pub fn wavejson<I>(commands: I, clock: &Var, signals: &[&Var], edge: Edge, radix: Radix,
+                   window: Range<u64>) -> Result<Json, Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let mut ids: Vec<_> = signals.iter().map(|v| v.code).collect();
+    ids.push(clock.code);
+
+    let mut rows: Vec<Row> = signals.iter().map(|_| {
+        Row { wave: String::new(), data: Vec::new(), last: None }
+    }).collect();
+    let mut clock_wave = String::new();
+    let mut started = false;
+
+    for cycle in CycleSampler::new(commands, clock.code, edge, &ids) {
+        let cycle = try!(cycle);
+        if cycle.time >= window.end { break; }
+        if cycle.time < window.start { continue; }
+
+        // The first edge in the window only marks the start of the first diagram cycle
+        if started {
+            for (row, v) in rows.iter_mut().zip(&cycle.values) {
+                row.push(v, radix);
+            }
+            clock_wave.push(match (edge, clock_wave.is_empty()) {
+                (Edge::Rising, true) => 'p',
+                (Edge::Falling, true) => 'n',
+                (Edge::Both, _) => match cycle.values[signals.len()] {
+                    Some(VarValue::Scalar(Value::V0)) => 'h',
+                    _ => 'l',
+                },
+                _ => '.',
+            });
+        }
+        started = true;
+    }
+
+    let mut rows_json = vec![json!({ "name": clock.reference, "wave": clock_wave })];
+    rows_json.extend(signals.iter().zip(rows).map(|(var, row)| {
+        if row.data.is_empty() {
+            json!({ "name": var.reference, "wave": row.wave })
+        } else {
+            json!({ "name": var.reference, "wave": row.wave, "data": row.data })
+        }
+    }));
+    Ok(json!({ "signal": rows_json }))
+}
+
+#[test]
+fn diagram() {
+    use super::super::Parser;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var wire 1 \" en $end
+    $var reg 4 # count $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! 0\" bxxxx #
+    #5 1!
+    #6 1\" b0000 #
+    #10 0!
+    #15 1!
+    #16 b0001 #
+    #20 0!
+    #25 1!
+    #26 0\" b0010 #
+    #30 0!
+    #35 1!
+    #40 0!
+    #45 1!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let vars: Vec<&Var> = header.iter_vars().map(|(_, v)| v).collect();
+
+    let diagram = wavejson(p, vars[0], &vars[1..], Edge::Rising, Radix::Hex, 0..40).unwrap();
+    assert_eq!(diagram, json!({ "signal": [
+        { "name": "clk", "wave": "p.." },
+        { "name": "en", "wave": "1.0" },
+        { "name": "count", "wave": "===", "data": ["0", "1", "2"] },
+    ]}));
+}
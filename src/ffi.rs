@@ -0,0 +1,497 @@
+//! C interface (requires the `ffi` feature)
+//!
+//! The crate builds as a `cdylib` exporting these functions, declared for C and C++ in
+//! `include/vcd.h`. Parsers and writers are opaque handles created by `vcd_parser_open`,
+//! `vcd_parser_from_memory` and `vcd_writer_create`. Every function that can fail returns a
+//! `VcdStatus`, or a null handle, and the message for the most recent failure on the calling
+//! thread is available from `vcd_last_error`. A panic is caught before it reaches C and reported
+//! as `VcdStatus::Panic`, or a null handle.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Write};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::str::FromStr;
+
+use {
+    Value,
+    IdCode,
+    VarValue,
+    Header,
+    ScopeType,
+    TimescaleUnit,
+    VarType,
+    Command,
+    Error,
+    Parser,
+    Writer,
+};
+
+/// Result of a C interface call
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VcdStatus {
+    Ok = 0,
+    /// `vcd_parser_next` reached the end of the file
+    End = 1,
+    IoError = -1,
+    ParseError = -2,
+    InvalidArgument = -3,
+    /// The call panicked. The handles passed to it should not be used again, except to free them.
+    Panic = -4,
+}
+
+/// Kind of item returned by `vcd_parser_next`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VcdCommandKind {
+    Timestamp = 0,
+    Change = 1,
+}
+
+/// A timestamp or value change returned by `vcd_parser_next`. Other commands in the data
+/// section, such as `$dumpvars` and `$comment`, have no representation here and are skipped.
+#[repr(C)]
+pub struct VcdCommand {
+    pub kind: VcdCommandKind,
+    /// Time of a timestamp
+    pub time: u64,
+    /// Variable changed by a value change
+    pub code: u64,
+    /// New value as text, with vectors in binary. Valid until the next call on the parser.
+    pub value: *const c_char,
+    /// New value of a `real` variable, otherwise NaN
+    pub real: f64,
+}
+
+/// A variable from the header, returned by `vcd_parser_var`
+#[repr(C)]
+pub struct VcdVar {
+    pub code: u64,
+    pub size: u32,
+    /// Variable type, as in the `$var` command. Valid until the parser is freed.
+    pub var_type: *const c_char,
+    /// Dotted path from the top scope. Valid until the parser is freed.
+    pub path: *const c_char,
+}
+
+struct ParserVar {
+    code: IdCode,
+    size: u32,
+    var_type: CString,
+    path: CString,
+}
+
+/// Opaque parser handle
+pub struct VcdParser {
+    parser: Parser<Box<io::Read>>,
+    vars: Vec<ParserVar>,
+    value: CString,
+}
+
+/// Opaque writer handle
+pub struct VcdWriter {
+    out: BufWriter<File>,
+    next_code: u64,
+}
+
+thread_local!(static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default()));
+
+fn set_error(msg: &str) {
+    let msg = CString::new(msg.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = msg);
+}
+
+fn status(r: Result<(), Error>) -> VcdStatus {
+    match r {
+        Ok(()) => VcdStatus::Ok,
+        Err(e) => {
+            set_error(&e.to_string());
+            match e {
+                Error::Io(_) => VcdStatus::IoError,
+                _ => VcdStatus::ParseError,
+            }
+        }
+    }
+}
+
+/// Run `f`, returning `fallback` with the panic message as the last error if it panics rather than
+/// unwinding into C
+fn guard<T, F: FnOnce() -> T>(fallback: T, f: F) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(r) => r,
+        Err(payload) => {
+            let msg = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            set_error(&format!("panic: {}", msg));
+            fallback
+        }
+    }
+}
+
+fn invalid(msg: &str) -> VcdStatus {
+    set_error(msg);
+    VcdStatus::InvalidArgument
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidInput, "null string")));
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| Error::Parse("string is not UTF-8"))
+}
+
+fn c_string(s: String) -> CString {
+    CString::new(s.replace('\0', "")).unwrap_or_default()
+}
+
+/// The code as passed to C, failing for codes of more than 9 characters
+fn c_code(id: IdCode) -> Result<u64, Error> {
+    if id.0 > ::std::u64::MAX as u128 {
+        return Err(Error::Parse("identifier code too long for the C API"));
+    }
+    Ok(id.0 as u64)
+}
+
+fn collect_vars(header: &Header) -> Vec<ParserVar> {
+    header.iter_vars().map(|(path, v)| ParserVar {
+        code: v.code,
+        size: v.size,
+        var_type: c_string(v.var_type.to_string()),
+        path: c_string(path.join(".")),
+    }).collect()
+}
+
+/// Returns the message for the most recent failed call on this thread. The string is valid until
+/// the next failing call.
+#[no_mangle]
+pub extern "C" fn vcd_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|e| e.borrow().as_ptr())
+    })
+}
+
+fn new_parser(r: Box<io::Read>) -> *mut VcdParser {
+    let p = VcdParser { parser: Parser::new(r), vars: Vec::new(), value: CString::default() };
+    Box::into_raw(Box::new(p))
+}
+
+/// Open the VCD file at `path` for parsing. Returns null on failure.
+#[no_mangle]
+pub unsafe extern "C" fn vcd_parser_open(path: *const c_char) -> *mut VcdParser {
+    guard(ptr::null_mut(), || {
+        let file = to_str(path).and_then(|path| File::open(path).map_err(Error::Io));
+        match file {
+            Ok(f) => new_parser(Box::new(BufReader::new(f))),
+            Err(e) => { status(Err(e)); ptr::null_mut() }
+        }
+    })
+}
+
+/// Create a parser for a VCD file held in memory. The `len` bytes at `data` are copied.
+#[no_mangle]
+pub unsafe extern "C" fn vcd_parser_from_memory(data: *const u8, len: usize) -> *mut VcdParser {
+    guard(ptr::null_mut(), || {
+        if data.is_null() && len > 0 {
+            invalid("null data");
+            return ptr::null_mut();
+        }
+        let data = if len == 0 { Vec::new() } else { slice::from_raw_parts(data, len).to_vec() };
+        new_parser(Box::new(Cursor::new(data)))
+    })
+}
+
+/// Free a parser. `parser` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn vcd_parser_free(parser: *mut VcdParser) {
+    guard((), || {
+        if !parser.is_null() {
+            drop(Box::from_raw(parser));
+        }
+    })
+}
+
+/// Parse the header. Must be called before `vcd_parser_var` and `vcd_parser_next`.
+#[no_mangle]
+pub unsafe extern "C" fn vcd_parser_header(parser: *mut VcdParser) -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        let p = match parser.as_mut() { Some(p) => p, None => return invalid("null parser") };
+        status(p.parser.parse_header().map(|header| {
+            p.vars = collect_vars(&header);
+        }))
+    })
+}
+
+/// Number of variables in the header
+#[no_mangle]
+pub unsafe extern "C" fn vcd_parser_var_count(parser: *const VcdParser) -> usize {
+    guard(0, || {
+        parser.as_ref().map_or(0, |p| p.vars.len())
+    })
+}
+
+/// Get variable number `index` from the header, in declaration order
+#[no_mangle]
+pub unsafe extern "C" fn vcd_parser_var(parser: *const VcdParser, index: usize, out: *mut VcdVar)
+    -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        let (p, out) = match (parser.as_ref(), out.as_mut()) {
+            (Some(p), Some(out)) => (p, out),
+            _ => return invalid("null argument"),
+        };
+        let v = match p.vars.get(index) {
+            Some(v) => v,
+            None => return invalid("index out of range"),
+        };
+        let code = match c_code(v.code) { Ok(c) => c, Err(e) => return status(Err(e)) };
+        *out = VcdVar {
+            code: code,
+            size: v.size,
+            var_type: v.var_type.as_ptr(),
+            path: v.path.as_ptr(),
+        };
+        VcdStatus::Ok
+    })
+}
+
+/// Read the next timestamp or value change into `out`, returning `VCD_END` at the end of the
+/// file. Other commands in the data section, such as `$dumpvars`, are skipped.
+#[no_mangle]
+pub unsafe extern "C" fn vcd_parser_next(parser: *mut VcdParser, out: *mut VcdCommand)
+    -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        let (p, out) = match (parser.as_mut(), out.as_mut()) {
+            (Some(p), Some(out)) => (p, out),
+            _ => return invalid("null argument"),
+        };
+        loop {
+            let c = match p.parser.next() {
+                Some(Ok(c)) => c,
+                Some(Err(e)) => return status(Err(e)),
+                None => return VcdStatus::End,
+            };
+            if let Command::Timestamp(t) = c {
+                *out = VcdCommand {
+                    kind: VcdCommandKind::Timestamp, time: t, code: 0, value: ptr::null(),
+                    real: 0.0,
+                };
+                return VcdStatus::Ok;
+            }
+            if let Some((id, v)) = c.change_value() {
+                let code = match c_code(id) { Ok(c) => c, Err(e) => return status(Err(e)) };
+                let real = match v { VarValue::Real(r) => r, _ => ::std::f64::NAN };
+                p.value = c_string(v.format(::Radix::Binary));
+                *out = VcdCommand {
+                    kind: VcdCommandKind::Change, time: 0, code: code, value: p.value.as_ptr(),
+                    real: real,
+                };
+                return VcdStatus::Ok;
+            }
+        }
+    })
+}
+
+/// Create a VCD file at `path`, returning a writer or null on failure.
+///
+/// The header is written with `vcd_writer_timescale`, `vcd_writer_scope`, `vcd_writer_var`,
+/// `vcd_writer_upscope` and `vcd_writer_enddefinitions`, followed by timestamps and changes.
+#[no_mangle]
+pub unsafe extern "C" fn vcd_writer_create(path: *const c_char) -> *mut VcdWriter {
+    guard(ptr::null_mut(), || {
+        let file = to_str(path).and_then(|path| File::create(path).map_err(Error::Io));
+        match file {
+            Ok(f) => Box::into_raw(Box::new(VcdWriter { out: BufWriter::new(f), next_code: 0 })),
+            Err(e) => { status(Err(e)); ptr::null_mut() }
+        }
+    })
+}
+
+unsafe fn with_writer<F>(writer: *mut VcdWriter, f: F) -> VcdStatus
+    where F: FnOnce(&mut Writer, &mut u64) -> Result<(), Error> {
+    let w = match writer.as_mut() { Some(w) => w, None => return invalid("null writer") };
+    let mut vcd = Writer::new(&mut w.out);
+    status(f(&mut vcd, &mut w.next_code))
+}
+
+/// Write a `$timescale` command, with `unit` one of `"s"`, `"ms"`, `"us"`, `"ns"`, `"ps"` or
+/// `"fs"`
+#[no_mangle]
+pub unsafe extern "C" fn vcd_writer_timescale(writer: *mut VcdWriter, ts: u32,
+    unit: *const c_char) -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        with_writer(writer, |w, _| {
+            let unit = try!(TimescaleUnit::from_str(try!(to_str(unit))));
+            Ok(try!(w.timescale(ts, unit)))
+        })
+    })
+}
+
+/// Begin a module scope named `name`
+#[no_mangle]
+pub unsafe extern "C" fn vcd_writer_scope(writer: *mut VcdWriter, name: *const c_char)
+    -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        with_writer(writer, |w, _| Ok(try!(w.scope_def(ScopeType::Module, try!(to_str(name))))))
+    })
+}
+
+/// End the current scope
+#[no_mangle]
+pub unsafe extern "C" fn vcd_writer_upscope(writer: *mut VcdWriter) -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        with_writer(writer, |w, _| Ok(try!(w.upscope())))
+    })
+}
+
+/// Declare a variable of type `var_type` (such as `"wire"`, `"reg"` or `"real"`) in the current
+/// scope, storing the code used to refer to it in `code`
+#[no_mangle]
+pub unsafe extern "C" fn vcd_writer_var(writer: *mut VcdWriter, var_type: *const c_char,
+    size: u32, reference: *const c_char, code: *mut u64) -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        if code.is_null() { return invalid("null code"); }
+        with_writer(writer, |w, next_code| {
+            let var_type = try!(VarType::from_str(try!(to_str(var_type))));
+            try!(w.var_def(var_type, size, IdCode(*next_code as u128), try!(to_str(reference))));
+            *code = *next_code;
+            *next_code += 1;
+            Ok(())
+        })
+    })
+}
+
+/// End the header
+#[no_mangle]
+pub unsafe extern "C" fn vcd_writer_enddefinitions(writer: *mut VcdWriter) -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        with_writer(writer, |w, _| Ok(try!(w.enddefinitions())))
+    })
+}
+
+/// Write a `#time` timestamp
+#[no_mangle]
+pub unsafe extern "C" fn vcd_writer_timestamp(writer: *mut VcdWriter, time: u64) -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        with_writer(writer, |w, _| Ok(try!(w.timestamp(time))))
+    })
+}
+
+/// Change a `wire` or `reg` variable to `value`, a string of `0`, `1`, `x` and `z` bits with the
+/// most significant first
+#[no_mangle]
+pub unsafe extern "C" fn vcd_writer_change(writer: *mut VcdWriter, code: u64,
+    value: *const c_char) -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        with_writer(writer, |w, _| {
+            let value = try!(to_str(value));
+            let bits = try!(value.bytes().map(Value::parse).collect::<Result<Vec<_>, _>>());
+            match bits.len() {
+                0 => Err(Error::Parse("empty value")),
+                1 => Ok(try!(w.change_scalar(IdCode(code as u128), bits[0]))),
+                _ => Ok(try!(w.change_vector(IdCode(code as u128), &bits))),
+            }
+        })
+    })
+}
+
+/// Change a `real` variable to `value`
+#[no_mangle]
+pub unsafe extern "C" fn vcd_writer_change_real(writer: *mut VcdWriter, code: u64, value: f64)
+    -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        with_writer(writer, |w, _| Ok(try!(w.change_real(IdCode(code as u128), value))))
+    })
+}
+
+/// Flush and close the file, freeing the writer. `writer` may be null.
+#[no_mangle]
+pub unsafe extern "C" fn vcd_writer_close(writer: *mut VcdWriter) -> VcdStatus {
+    guard(VcdStatus::Panic, || {
+        if writer.is_null() { return VcdStatus::Ok; }
+        let mut w = Box::from_raw(writer);
+        status(w.out.flush().map_err(Error::Io))
+    })
+}
+
+#[test]
+fn roundtrip() {
+    let path = ::std::env::temp_dir().join(format!("vcd-ffi-{}.vcd", ::std::process::id()));
+    let path = CString::new(path.to_str().unwrap()).unwrap();
+    let s = |s: &str| CString::new(s).unwrap();
+
+    unsafe {
+        let w = vcd_writer_create(path.as_ptr());
+        assert!(!w.is_null());
+        let (mut clk, mut data) = (0, 0);
+        assert_eq!(vcd_writer_timescale(w, 1, s("ns").as_ptr()), VcdStatus::Ok);
+        assert_eq!(vcd_writer_scope(w, s("top").as_ptr()), VcdStatus::Ok);
+        assert_eq!(vcd_writer_var(w, s("wire").as_ptr(), 1, s("clk").as_ptr(), &mut clk),
+            VcdStatus::Ok);
+        assert_eq!(vcd_writer_var(w, s("reg").as_ptr(), 4, s("data").as_ptr(), &mut data),
+            VcdStatus::Ok);
+        assert_eq!(vcd_writer_var(w, s("not a type").as_ptr(), 1, s("b").as_ptr(), &mut data),
+            VcdStatus::ParseError);
+        assert_eq!(vcd_writer_upscope(w), VcdStatus::Ok);
+        assert_eq!(vcd_writer_enddefinitions(w), VcdStatus::Ok);
+        assert_eq!(vcd_writer_timestamp(w, 10), VcdStatus::Ok);
+        assert_eq!(vcd_writer_change(w, clk, s("1").as_ptr()), VcdStatus::Ok);
+        assert_eq!(vcd_writer_change(w, data, s("10x1").as_ptr()), VcdStatus::Ok);
+        assert_eq!(vcd_writer_change(w, data, s("2").as_ptr()), VcdStatus::ParseError);
+        assert_eq!(vcd_writer_close(w), VcdStatus::Ok);
+
+        let p = vcd_parser_open(path.as_ptr());
+        assert!(!p.is_null());
+        assert_eq!(vcd_parser_header(p), VcdStatus::Ok);
+        assert_eq!(vcd_parser_var_count(p), 2);
+        let mut var: VcdVar = ::std::mem::zeroed();
+        assert_eq!(vcd_parser_var(p, 1, &mut var), VcdStatus::Ok);
+        assert_eq!((var.code, var.size), (data, 4));
+        assert_eq!(CStr::from_ptr(var.path).to_str().unwrap(), "top.data");
+        assert_eq!(vcd_parser_var(p, 2, &mut var), VcdStatus::InvalidArgument);
+
+        let mut c: VcdCommand = ::std::mem::zeroed();
+        assert_eq!(vcd_parser_next(p, &mut c), VcdStatus::Ok);
+        assert_eq!((c.kind, c.time), (VcdCommandKind::Timestamp, 10));
+        assert_eq!(vcd_parser_next(p, &mut c), VcdStatus::Ok);
+        assert_eq!((c.kind, c.code), (VcdCommandKind::Change, clk));
+        assert_eq!(vcd_parser_next(p, &mut c), VcdStatus::Ok);
+        assert_eq!(CStr::from_ptr(c.value).to_str().unwrap(), "10x1");
+        assert_eq!(vcd_parser_next(p, &mut c), VcdStatus::End);
+        vcd_parser_free(p);
+
+        assert!(vcd_parser_open(s("/nonexistent/file.vcd").as_ptr()).is_null());
+        assert!(!CStr::from_ptr(vcd_last_error()).to_bytes().is_empty());
+    }
+    ::std::fs::remove_file(path.to_str().unwrap()).unwrap();
+}
+
+#[test]
+fn top_level_vars() {
+    let sample = b"$var wire 1 ! reset $end
+$scope module top $end $var wire 1 \" clk $end $upscope $end
+$enddefinitions $end
+";
+    unsafe {
+        let p = vcd_parser_from_memory(sample.as_ptr(), sample.len());
+        assert_eq!(vcd_parser_header(p), VcdStatus::Ok);
+        assert_eq!(vcd_parser_var_count(p), 2);
+        let mut var: VcdVar = ::std::mem::zeroed();
+        assert_eq!(vcd_parser_var(p, 0, &mut var), VcdStatus::Ok);
+        assert_eq!(CStr::from_ptr(var.path).to_str().unwrap(), "reset");
+        assert_eq!(vcd_parser_var(p, 1, &mut var), VcdStatus::Ok);
+        assert_eq!(CStr::from_ptr(var.path).to_str().unwrap(), "top.clk");
+        vcd_parser_free(p);
+    }
+}
+
+#[test]
+fn panics() {
+    assert_eq!(guard(VcdStatus::Panic, || -> VcdStatus { panic!("boom") }), VcdStatus::Panic);
+    let msg = unsafe { CStr::from_ptr(vcd_last_error()) };
+    assert_eq!(msg.to_str().unwrap(), "panic: boom");
+}
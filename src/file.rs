@@ -0,0 +1,140 @@
+//! Opening and creating VCD files, which may be gzip-compressed (requires the `flate2` feature)
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom};
+use std::path::Path;
+
+#[cfg(feature = "flate2")]
+use flate2::{Compression, read::MultiGzDecoder, write::GzEncoder};
+
+use { Parser, Error };
+
+/// The first two bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+impl Parser<Box<Read>> {
+    /// Open the VCD file at `path`. A file starting with the gzip magic bytes, such as a
+    /// `.vcd.gz`, is decompressed as it is read, which requires the `flate2` feature.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Parser<Box<Read>>, Error> {
+        let mut f = try!(File::open(path));
+        let mut magic = [0; 2];
+        let n = try!(f.read(&mut magic));
+        try!(f.seek(SeekFrom::Start(0)));
+        if n == 2 && magic == GZIP_MAGIC {
+            return gzip(f);
+        }
+        Ok(Parser::new(Box::new(f)))
+    }
+}
+
+#[cfg(feature = "flate2")]
+fn gzip(f: File) -> Result<Parser<Box<Read>>, Error> {
+    Ok(Parser::new(Box::new(MultiGzDecoder::new(f))))
+}
+
+#[cfg(not(feature = "flate2"))]
+fn gzip(_: File) -> Result<Parser<Box<Read>>, Error> {
+    Err(Error::Parse("Reading gzip-compressed input requires the flate2 feature"))
+}
+
+enum Output {
+    Plain(BufWriter<File>),
+    #[cfg(feature = "flate2")]
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+/// A file being written, from `OutputFile::create`, to pass to `Writer::new`. Output is
+/// buffered, and gzip-compressed if the file name ends in `.gz`.
+///
+/// Call `finish` when done writing, to write the end of the compressed stream and see any error
+/// doing so.
+pub struct OutputFile {
+    output: Output,
+}
+
+impl OutputFile {
+    /// Create the file at `path`, compressing it if its name ends in `.gz`, which requires the
+    /// `flate2` feature
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<OutputFile> {
+        let gzip = path.as_ref().extension().map_or(false, |e| e == "gz");
+        let f = BufWriter::new(try!(File::create(path)));
+        let output = if gzip {
+            try!(gzip_output(f))
+        } else {
+            Output::Plain(f)
+        };
+        Ok(OutputFile { output: output })
+    }
+
+    /// Flush the output, ending the compressed stream if there is one
+    pub fn finish(self) -> io::Result<()> {
+        match self.output {
+            Output::Plain(mut f) => io::Write::flush(&mut f),
+            #[cfg(feature = "flate2")]
+            Output::Gzip(gz) => try!(gz.finish()).into_inner().map(|_| ()).map_err(io::Error::from),
+        }
+    }
+}
+
+#[cfg(feature = "flate2")]
+fn gzip_output(f: BufWriter<File>) -> io::Result<Output> {
+    Ok(Output::Gzip(GzEncoder::new(f, Compression::default())))
+}
+
+#[cfg(not(feature = "flate2"))]
+fn gzip_output(_: BufWriter<File>) -> io::Result<Output> {
+    Err(io::Error::new(io::ErrorKind::InvalidInput,
+        "Writing gzip-compressed output requires the flate2 feature"))
+}
+
+impl io::Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.output {
+            Output::Plain(ref mut f) => f.write(buf),
+            #[cfg(feature = "flate2")]
+            Output::Gzip(ref mut gz) => gz.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.output {
+            Output::Plain(ref mut f) => f.flush(),
+            #[cfg(feature = "flate2")]
+            Output::Gzip(ref mut gz) => gz.flush(),
+        }
+    }
+}
+
+#[cfg(feature = "flate2")]
+#[test]
+fn gzip_roundtrip() {
+    use super::{Command, Writer};
+
+    let sample = b"$scope module top $end $var wire 1 ! a $end $upscope $end
+$enddefinitions $end
+#0 0! #5 1!
+";
+    let dir = ::std::env::temp_dir();
+    for name in &["vcd-gzip-test.vcd.gz", "vcd-gzip-test.vcd"] {
+        let path = dir.join(name);
+        let mut p = Parser::new(&sample[..]);
+        let header = p.parse_header().unwrap();
+        let mut out = OutputFile::create(&path).unwrap();
+        {
+            let mut w = Writer::new(&mut out);
+            w.header(&header).unwrap();
+            for c in p { w.command(&c.unwrap()).unwrap(); }
+        }
+        out.finish().unwrap();
+
+        let mut magic = [0; 2];
+        File::open(&path).unwrap().read_exact(&mut magic).unwrap();
+        assert_eq!(magic == GZIP_MAGIC, name.ends_with(".gz"));
+
+        let mut p = Parser::open(&path).unwrap();
+        assert_eq!(p.parse_header().unwrap().scope.identifier, "top");
+        let commands: Vec<Command> = p.map(Result::unwrap).collect();
+        assert_eq!(commands.len(), 4);
+        ::std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+//! Reading VCD files while they are still being written
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use {
+    Parser,
+    Error,
+};
+use merge::Merge;
+
+/// Wraps an `io::Read` that may grow, such as a file a simulator is writing, so that reaching the
+/// end waits for more data instead of ending the stream.
+///
+/// The reader is polled every `poll` until data arrives. If an idle timeout is set, the stream
+/// ends once no data has arrived for that long.
+pub struct Follow<R> {
+    inner: R,
+    poll: Duration,
+    idle_timeout: Option<Duration>,
+    last_data: Instant,
+}
+
+impl<R: Read> Follow<R> {
+    pub fn new(inner: R, poll: Duration) -> Follow<R> {
+        Follow { inner: inner, poll: poll, idle_timeout: None, last_data: Instant::now() }
+    }
+
+    /// End the stream once no data has arrived for `timeout`
+    pub fn idle_timeout(mut self, timeout: Duration) -> Follow<R> {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+}
+
+impl<R: Read> Read for Follow<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = try!(self.inner.read(buf));
+            if n > 0 || buf.is_empty() {
+                self.last_data = Instant::now();
+                return Ok(n);
+            }
+            if let Some(timeout) = self.idle_timeout {
+                if self.last_data.elapsed() >= timeout { return Ok(0); }
+            }
+            thread::sleep(self.poll);
+        }
+    }
+}
+
+/// A parser following a growing file
+pub type FollowParser = Parser<BufReader<Follow<File>>>;
+
+/// Follow several VCD files being written at once, such as the shards of a distributed
+/// simulation, and merge them into a single time-ordered stream as described for `Merge`.
+///
+/// Each input is given as a path and the name of its scope in the output. Reading waits for every
+/// file to write its header, and the merged commands for a time are produced once every input has
+/// moved past that time, so a stalled writer holds back the whole stream. An input ends when it
+/// has not grown for `idle_timeout`.
+pub fn follow_merge<P: AsRef<Path>>(inputs: Vec<(P, String)>, top: &str, poll: Duration,
+    idle_timeout: Duration) -> Result<Merge<FollowParser>, Error> {
+    let mut parsed = Vec::new();
+    for (path, name) in inputs {
+        let f = Follow::new(try!(File::open(path)), poll).idle_timeout(idle_timeout);
+        let mut parser = Parser::new(BufReader::new(f));
+        let header = try!(parser.parse_header());
+        parsed.push((header, parser, name));
+    }
+    Ok(Merge::new(parsed, top))
+}
+
+#[test]
+fn growing_files() {
+    use std::fs;
+    use std::io::Write;
+    use super::{Command, IdCode};
+
+    let dir = ::std::env::temp_dir();
+    let paths: Vec<_> = (0..2).map(|i| {
+        dir.join(format!("vcd-follow-{}-{}.vcd", ::std::process::id(), i))
+    }).collect();
+    let mut files: Vec<File> = paths.iter().map(|p| File::create(p).unwrap()).collect();
+    for f in &mut files {
+        write!(f, "$scope module core $end\n$var wire 1 ! clk $end\n$upscope $end\n\
+                   $enddefinitions $end\n#0\n0!\n").unwrap();
+    }
+
+    let writer = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        write!(files[1], "#10\n1!\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        write!(files[0], "#5\n1!\n").unwrap();
+    });
+
+    let inputs = vec![(&paths[0], "a".to_string()), (&paths[1], "b".to_string())];
+    let merge = follow_merge(inputs, "top", Duration::from_millis(5), Duration::from_millis(200))
+        .unwrap();
+    let commands: Vec<Command> = merge.map(Result::unwrap).collect();
+    writer.join().unwrap();
+    for p in &paths { fs::remove_file(p).unwrap(); }
+
+    let times: Vec<u64> = commands.iter().filter_map(|c| match *c {
+        Command::Timestamp(t) => Some(t),
+        _ => None,
+    }).collect();
+    assert_eq!(times, vec![0, 5, 10]);
+    assert_eq!(commands.last(), Some(&Command::ChangeScalar(IdCode::from(1), ::Value::V1)));
+}
@@ -0,0 +1,374 @@
+//! Reading of GTKWave's FST format (requires the `fst` feature)
+//!
+//! `FstParser` mirrors `Parser`: `parse_header` returns the same `Header`, and iterating it yields
+//! the data section as `Command`s, so code written against VCD files can read FST files unchanged.
+//! Each FST signal handle becomes an `IdCode`, so aliased variables share an `IdCode` just as in a
+//! VCD file.
+
+use std::io::{self, BufRead, BufReader, Seek};
+use std::fs::File;
+use std::path::Path;
+use std::vec;
+
+use fst_reader::{
+    FstReader,
+    FstFilter,
+    FstHierarchyEntry,
+    FstScopeType,
+    FstVarType,
+    FstSignalValue,
+    ReaderError,
+    ReadSignalsError,
+};
+
+use {
+    TimescaleUnit,
+    Value,
+    IdCode,
+    ScopeType,
+    VarType,
+    Header,
+    Command,
+    Error,
+};
+use read;
+
+impl From<ReaderError> for Error {
+    fn from(err: ReaderError) -> Error {
+        match err {
+            ReaderError::Io(e) => Error::Io(e),
+            _ => Error::Parse("Invalid FST file"),
+        }
+    }
+}
+
+impl From<ReadSignalsError<Error>> for Error {
+    fn from(err: ReadSignalsError<Error>) -> Error {
+        match err {
+            ReadSignalsError::ReadError(e) => Error::from(e),
+            ReadSignalsError::CallbackError(e) => e,
+        }
+    }
+}
+
+/// Convert an FST timescale of 10^`exponent` seconds to a VCD `$timescale`
+fn timescale(exponent: i8) -> Result<(u32, TimescaleUnit), Error> {
+    use TimescaleUnit::*;
+    if exponent > 2 || exponent < -15 {
+        return Err(Error::Parse("Unsupported FST timescale"));
+    }
+    let unit = match (exponent + 15) / 3 {
+        0 => FS,
+        1 => PS,
+        2 => NS,
+        3 => US,
+        4 => MS,
+        _ => S,
+    };
+    let mult = match (exponent + 15) % 3 {
+        0 => 1,
+        1 => 10,
+        _ => 100,
+    };
+    Ok((mult, unit))
+}
+
+fn scope_type(t: FstScopeType) -> ScopeType {
+    match t {
+        FstScopeType::Task => ScopeType::Task,
+        FstScopeType::Function => ScopeType::Function,
+        FstScopeType::Begin => ScopeType::Begin,
+        FstScopeType::Fork => ScopeType::Fork,
+        FstScopeType::Interface => ScopeType::Interface,
+        FstScopeType::Package => ScopeType::Package,
+        FstScopeType::Program => ScopeType::Program,
+        FstScopeType::Class => ScopeType::Class,
+        FstScopeType::Union => ScopeType::Union,
+        FstScopeType::Struct => ScopeType::Struct,
+        FstScopeType::Generate => ScopeType::Other("generate".to_string()),
+        _ => ScopeType::Module,
+    }
+}
+
+fn var_type(t: FstVarType) -> VarType {
+    match t {
+        FstVarType::Event => VarType::Event,
+        FstVarType::Integer => VarType::Integer,
+        FstVarType::Parameter => VarType::Parameter,
+        FstVarType::RealTime => VarType::Realtime,
+        FstVarType::ShortReal => VarType::Shortreal,
+        t if t.is_real() => VarType::Real,
+        FstVarType::Reg => VarType::Reg,
+        FstVarType::Supply0 => VarType::Supply0,
+        FstVarType::Supply1 => VarType::Supply1,
+        FstVarType::Time => VarType::Time,
+        FstVarType::Tri => VarType::Tri,
+        FstVarType::TriAnd => VarType::Triand,
+        FstVarType::TriOr => VarType::Trior,
+        FstVarType::TriReg => VarType::Trireg,
+        FstVarType::Tri0 => VarType::Tri0,
+        FstVarType::Tri1 => VarType::Tri1,
+        FstVarType::Wand => VarType::Wand,
+        FstVarType::Wor => VarType::Wor,
+        FstVarType::GenericString => VarType::String,
+        FstVarType::Bit => VarType::Bit,
+        FstVarType::Logic => VarType::Logic,
+        FstVarType::Int => VarType::Int,
+        FstVarType::Enum => VarType::Enum,
+        FstVarType::ShortInt => VarType::Other("shortint".to_string()),
+        FstVarType::LongInt => VarType::Other("longint".to_string()),
+        FstVarType::Byte => VarType::Other("byte".to_string()),
+        _ => VarType::Wire,
+    }
+}
+
+/// Map a value character from the FST file, which may be one of the VHDL 9-state characters,
+/// onto a `Value`. Unknown characters are `X`.
+fn value(b: u8) -> Value {
+    Value::parse(b).unwrap_or(Value::X)
+}
+
+/// The kind of data carried by an FST signal
+#[derive(Debug, Copy, Clone)]
+enum Signal {
+    Scalar,
+    Vector,
+    Real,
+    String,
+}
+
+/// FST parser. Reads a file in GTKWave's FST format and acts as an iterator of `Command`s.
+///
+/// FST stores values grouped by signal rather than by time, so the data section is decoded into
+/// memory in full the first time the iterator is advanced.
+pub struct FstParser<R: BufRead + Seek> {
+    reader: FstReader<R>,
+    signals: Vec<Signal>,
+    commands: Option<vec::IntoIter<Command>>,
+}
+
+impl FstParser<BufReader<File>> {
+    /// Open the FST file at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FstParser<BufReader<File>>, Error> {
+        FstParser::new(BufReader::new(try!(File::open(path))))
+    }
+}
+
+impl<R: BufRead + Seek> FstParser<R> {
+    /// Create a parser wrapping an FST file
+    pub fn new(r: R) -> Result<FstParser<R>, Error> {
+        Ok(FstParser {
+            reader: try!(FstReader::open(r)),
+            signals: Vec::new(),
+            commands: None,
+        })
+    }
+
+    /// Read the FST header and hierarchy into a `Header` struct, as `Parser::parse_header` does
+    /// for a VCD file.
+    pub fn parse_header(&mut self) -> Result<Header, Error> {
+        let fst_header = self.reader.get_header();
+
+        let mut commands = vec![
+            Command::Date(fst_header.date.trim().to_string()),
+            Command::Version(fst_header.version.trim().to_string()),
+        ];
+        let (ts, unit) = try!(timescale(fst_header.timescale_exponent));
+        commands.push(Command::Timescale(ts, unit));
+
+        let signals = &mut self.signals;
+        try!(self.reader.read_hierarchy(|entry| {
+            match entry {
+                FstHierarchyEntry::Scope { tpe, name, .. } => {
+                    commands.push(Command::ScopeDef(scope_type(tpe), name));
+                }
+                FstHierarchyEntry::UpScope => commands.push(Command::Upscope),
+                FstHierarchyEntry::Var { tpe, name, length, handle, .. } => {
+                    let index = handle.get_index();
+                    if index >= signals.len() {
+                        signals.resize(index + 1, Signal::Vector);
+                    }
+                    signals[index] = match length {
+                        _ if tpe.is_real() => Signal::Real,
+                        _ if tpe == FstVarType::GenericString => Signal::String,
+                        0 => Signal::String,
+                        1 => Signal::Scalar,
+                        _ => Signal::Vector,
+                    };
+                    let code = IdCode::from(index as u32);
+                    commands.push(Command::VarDef(var_type(tpe), length, code, name, None));
+                }
+                _ => (),
+            }
+        }));
+        commands.push(Command::Enddefinitions);
+
+        read::parse_header(&mut commands.into_iter().map(Ok))
+    }
+
+    fn read_data(&mut self) -> Result<Vec<Command>, Error> {
+        if self.signals.is_empty() {
+            try!(self.parse_header());
+        }
+
+        let signals = &self.signals;
+        let mut commands = Vec::new();
+        let mut time = None;
+
+        try!(self.reader.read_signals(&FstFilter::all(), |t, handle, v| {
+            if time != Some(t) {
+                commands.push(Command::Timestamp(t));
+                time = Some(t);
+            }
+
+            let index = handle.get_index();
+            let id = IdCode::from(index as u32);
+            let kind = try!(signals.get(index).cloned()
+                .ok_or(Error::Parse("Undeclared FST signal")));
+            commands.push(match (kind, v) {
+                (_, FstSignalValue::Real(r)) => Command::ChangeReal(id, r),
+                (Signal::Real, FstSignalValue::String(s)) => {
+                    Command::ChangeReal(id, try!(try!(::std::str::from_utf8(s)).parse()))
+                }
+                (Signal::String, FstSignalValue::String(s)) => {
+                    Command::ChangeString(id, try!(String::from_utf8(s.to_vec())))
+                }
+                (Signal::Scalar, FstSignalValue::String(s)) if s.len() == 1 => {
+                    Command::ChangeScalar(id, value(s[0]))
+                }
+                (_, FstSignalValue::String(s)) => {
+                    Command::ChangeVector(id, s.iter().cloned().map(value).collect())
+                }
+            });
+            Ok(())
+        }));
+
+        Ok(commands)
+    }
+}
+
+impl<R: BufRead + Seek> Iterator for FstParser<R> {
+    type Item = Result<Command, Error>;
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        if self.commands.is_none() {
+            match self.read_data() {
+                Ok(c) => self.commands = Some(c.into_iter()),
+                Err(e) => {
+                    self.commands = Some(Vec::new().into_iter());
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.commands.as_mut().and_then(|c| c.next()).map(Ok)
+    }
+}
+
+/// Returns true if `r` contains an FST file rather than a VCD file. The stream is rewound to the
+/// start afterwards.
+pub fn is_fst<R: io::Read + Seek>(r: &mut R) -> bool {
+    ::fst_reader::is_fst_file(r)
+}
+
+/// Write an FST file with nested scopes, an alias, a real variable, 9-state values and two value
+/// change blocks, a layout that `convert::write_fst` does not produce
+#[cfg(test)]
+fn write_sample(path: &Path) {
+    use fst_writer::*;
+
+    let info = FstInfo {
+        start_time: 0,
+        timescale_exponent: -7,
+        version: "test".to_string(),
+        date: "today".to_string(),
+        file_type: FstFileType::VerilogVhdl,
+    };
+    let mut w = open_fst(path, &info).unwrap();
+    w.scope("top", "", FstScopeType::Module).unwrap();
+    let clk = w.var("clk", FstSignalType::bit_vec(1), FstVarType::Wire, FstVarDirection::Implicit,
+        None).unwrap();
+    w.scope("sub", "", FstScopeType::Generate).unwrap();
+    let state = w.var("state", FstSignalType::bit_vec(3), FstVarType::Logic,
+        FstVarDirection::Implicit, None).unwrap();
+    w.var("clk_alias", FstSignalType::bit_vec(1), FstVarType::Wire, FstVarDirection::Implicit,
+        Some(clk)).unwrap();
+    w.up_scope().unwrap();
+    let temp = w.var("temp", FstSignalType::real(), FstVarType::Real, FstVarDirection::Implicit,
+        None).unwrap();
+    w.up_scope().unwrap();
+
+    // Two value change blocks
+    let mut w = w.finish().unwrap();
+    w.time_change(0).unwrap();
+    w.signal_change(clk, b"0").unwrap();
+    w.signal_change(state, b"uzx").unwrap();
+    w.signal_change(temp, &1.5f64.to_le_bytes()).unwrap();
+    w.time_change(5).unwrap();
+    w.signal_change(clk, b"1").unwrap();
+    w.flush().unwrap();
+    w.time_change(10).unwrap();
+    w.signal_change(state, b"h01").unwrap();
+    w.signal_change(temp, &(-2.0f64).to_le_bytes()).unwrap();
+    w.finish().unwrap();
+}
+
+#[test]
+fn decode() {
+    use std::fs;
+    use {ScopeItem, VarValue};
+    use Value::*;
+
+    let path = ::std::env::temp_dir().join(format!("vcd-fst-decode-{}.fst", ::std::process::id()));
+    write_sample(&path);
+
+    let mut p = FstParser::open(&path).unwrap();
+    let header = p.parse_header().unwrap();
+    assert_eq!(header.timescale, Some((100, TimescaleUnit::NS)));
+    assert_eq!((&header.version.as_ref().unwrap()[..], &header.date.as_ref().unwrap()[..]),
+        ("test", "today"));
+    let clk = header.find_var(&["top", "clk"]).unwrap().code;
+    assert_eq!(header.find_var(&["top", "sub", "clk_alias"]).unwrap().code, clk);
+    let state = header.find_var(&["top", "sub", "state"]).unwrap();
+    assert_eq!((&state.var_type, state.size), (&VarType::Logic, 3));
+    let temp = header.find_var(&["top", "temp"]).unwrap();
+    assert_eq!(temp.var_type, VarType::Real);
+    match header.scope.children[1] {
+        ScopeItem::Scope(ref s) => assert_eq!(s.scope_type, ScopeType::Other("generate".into())),
+        ref i => panic!("{:?}", i),
+    }
+
+    let mut changes = Vec::new();
+    let mut time = 0;
+    for c in p {
+        match c.unwrap() {
+            Command::Timestamp(t) => time = t,
+            c => changes.push((time, c.change_value().unwrap())),
+        }
+    }
+    // The order of changes at the same time is not specified
+    changes.sort_by_key(|c| (c.0, format!("{:?}", c.1)));
+    let (clk, state, temp) = (clk, state.code, temp.code);
+    let mut expected = vec![
+        (0, (clk, VarValue::Scalar(V0))),
+        (0, (state, VarValue::Vector(vec![U, Z, X].into()))),
+        (0, (temp, VarValue::Real(1.5))),
+        (5, (clk, VarValue::Scalar(V1))),
+        (10, (state, VarValue::Vector(vec![H, V0, V1].into()))),
+        (10, (temp, VarValue::Real(-2.0))),
+    ];
+    expected.sort_by_key(|c| (c.0, format!("{:?}", c.1)));
+    assert_eq!(changes, expected);
+
+    // Truncated and corrupt input is an error rather than a panic
+    let data = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    for len in &[0, 16, data.len() / 2, data.len() - 8] {
+        let r = FstParser::new(io::Cursor::new(&data[..*len])).and_then(|mut p| {
+            try!(p.parse_header());
+            p.collect::<Result<Vec<_>, _>>()
+        });
+        assert!(r.is_err(), "truncated to {} bytes", len);
+    }
+    let mut vcd = io::Cursor::new(&b"$enddefinitions $end\n"[..]);
+    assert!(!is_fst(&mut vcd));
+    assert!(FstParser::new(vcd).is_err());
+}
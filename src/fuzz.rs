@@ -0,0 +1,303 @@
+//! `Arbitrary` implementations for generating structured random input (requires the `arbitrary`
+//! feature)
+//!
+//! Generated values stay within what `Writer` can write and `Parser` reads back unchanged, so a
+//! written `Header` or `Command` parses to an equal value. Names and string values are short ASCII
+//! words, which may contain `\`, text is words separated by spaces, tabs and newlines, and real
+//! values are multiples of 1/1024. An `End` command only parses after a matching `Begin`.
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use {
+    TimescaleUnit,
+    Value,
+    PortState,
+    ScopeType,
+    VarType,
+    IdCode,
+    Scope,
+    Var,
+    ReferenceIndex,
+    ScopeItem,
+    Command,
+    VarValue,
+    SimulationCommand,
+    Header,
+};
+
+const WORD_CHARS: &'static [u8] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789_\\";
+
+/// Separators between the words of text. The parser trims whitespace around text, but keeps it
+/// inside.
+const SEPARATORS: &'static [&'static str] = &[" ", "  ", "\t", "\n", " \r\n "];
+
+fn word(u: &mut Unstructured) -> Result<String> {
+    let len = try!(u.int_in_range(1..=8));
+    let mut s = String::new();
+    for _ in 0..len {
+        s.push(*try!(u.choose(WORD_CHARS)) as char);
+    }
+    Ok(s)
+}
+
+fn text(u: &mut Unstructured) -> Result<String> {
+    let len = try!(u.int_in_range(0..=4));
+    let mut s = String::new();
+    for i in 0..len {
+        if i > 0 { s.push_str(try!(u.choose(SEPARATORS))); }
+        s.push_str(&try!(word(u)));
+    }
+    Ok(s)
+}
+
+fn real(u: &mut Unstructured) -> Result<f64> {
+    Ok(try!(i32::arbitrary(u)) as f64 / 1024.0)
+}
+
+fn vector(u: &mut Unstructured) -> Result<Vec<Value>> {
+    let len = try!(u.int_in_range(1..=128));
+    let mut v = Vec::new();
+    for _ in 0..len {
+        v.push(try!(Value::arbitrary(u)));
+    }
+    Ok(v)
+}
+
+impl<'a> Arbitrary<'a> for TimescaleUnit {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        use self::TimescaleUnit::*;
+        Ok(*try!(u.choose(&[S, MS, US, NS, PS, FS])))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Value {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        use self::Value::*;
+        Ok(*try!(u.choose(&[V0, V1, X, Z, U, W, L, H, DontCare])))
+    }
+}
+
+impl<'a> Arbitrary<'a> for ScopeType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        use self::ScopeType::*;
+        Ok(try!(u.choose(&[Module, Task, Function, Begin, Fork, Interface, Package, Class,
+            Other("generate".to_string())])).clone())
+    }
+}
+
+impl<'a> Arbitrary<'a> for VarType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        use self::VarType::*;
+        Ok(try!(u.choose(&[Wire, Reg, Real, Port, Integer, Parameter, Event, Tri1, Logic, Bit,
+            Enum, Int, Other("vendor_net".to_string())])).clone())
+    }
+}
+
+impl<'a> Arbitrary<'a> for SimulationCommand {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        use self::SimulationCommand::*;
+        Ok(*try!(u.choose(&[Dumpall, Dumpoff, Dumpon, Dumpvars, Dumpports, Dumpportsall,
+            Dumpportsoff, Dumpportson])))
+    }
+}
+
+impl<'a> Arbitrary<'a> for PortState {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(PortState(*try!(u.choose(b"DUNZduLHXTlh01?FAaBbCcf"))))
+    }
+}
+
+impl<'a> Arbitrary<'a> for IdCode {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(IdCode(try!(u128::arbitrary(u))))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Var {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Var {
+            var_type: try!(VarType::arbitrary(u)),
+            size: try!(u.int_in_range(1..=64)),
+            code: try!(IdCode::arbitrary(u)),
+            reference: try!(word(u)),
+            index: match try!(u.int_in_range(0..=2)) {
+                0 => None,
+                1 => Some(ReferenceIndex::BitSelect(try!(u.int_in_range(-4..=64)))),
+                _ => Some(ReferenceIndex::Range(try!(u.int_in_range(0..=64)),
+                    try!(u.int_in_range(-4..=64)))),
+            },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for ScopeItem {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match try!(u.int_in_range(0..=3)) {
+            0 => ScopeItem::Scope(try!(Scope::arbitrary(u))),
+            _ => ScopeItem::Var(try!(Var::arbitrary(u))),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Scope {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let scope_type = try!(ScopeType::arbitrary(u));
+        let identifier = try!(word(u));
+        let mut children = Vec::new();
+        for _ in 0..try!(u.arbitrary_len::<ScopeItem>()) {
+            children.push(try!(ScopeItem::arbitrary(u)));
+        }
+        Ok(Scope { scope_type: scope_type, identifier: identifier, children: children })
+    }
+}
+
+impl<'a> Arbitrary<'a> for VarValue {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match try!(u.int_in_range(0..=3)) {
+            0 => VarValue::Scalar(try!(Value::arbitrary(u))),
+            1 => VarValue::Vector(try!(vector(u)).into()),
+            2 => VarValue::Real(try!(real(u))),
+            _ => VarValue::String(try!(word(u))),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Command {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        use self::Command::*;
+        Ok(match try!(u.int_in_range(0..=17)) {
+            0 => Comment(try!(text(u))),
+            1 => Date(try!(text(u))),
+            2 => Version(try!(text(u))),
+            3 => Timescale(*try!(u.choose(&[1, 10, 100])), try!(TimescaleUnit::arbitrary(u))),
+            4 => ScopeDef(try!(ScopeType::arbitrary(u)), try!(word(u))),
+            5 => Upscope,
+            6 => {
+                let v = try!(Var::arbitrary(u));
+                VarDef(v.var_type, v.size, v.code, v.reference, v.index)
+            }
+            7 => Enddefinitions,
+            8 => Timestamp(try!(u64::arbitrary(u))),
+            9 => ChangeScalar(try!(IdCode::arbitrary(u)), try!(Value::arbitrary(u))),
+            10 => ChangeVector(try!(IdCode::arbitrary(u)), try!(vector(u)).into()),
+            11 => ChangeReal(try!(IdCode::arbitrary(u)), try!(real(u))),
+            12 => ChangeString(try!(IdCode::arbitrary(u)), try!(word(u))),
+            13 => {
+                let mut v = Vec::new();
+                for _ in 0..try!(u.int_in_range(1..=128)) {
+                    v.push(try!(PortState::arbitrary(u)));
+                }
+                let (s0, s1) = (try!(u.int_in_range(0..=7)), try!(u.int_in_range(0..=7)));
+                ChangePort(try!(IdCode::arbitrary(u)), v, s0, s1)
+            }
+            14 => Attribute(try!(text(u))),
+            15 => AttributeEnd,
+            16 => Begin(try!(SimulationCommand::arbitrary(u))),
+            _ => End(try!(SimulationCommand::arbitrary(u))),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Header {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let timescale = if try!(bool::arbitrary(u)) {
+            Some((*try!(u.choose(&[1, 10, 100])), try!(TimescaleUnit::arbitrary(u))))
+        } else {
+            None
+        };
+        Ok(Header {
+            comment: if try!(bool::arbitrary(u)) { Some(try!(text(u))) } else { None },
+            date: if try!(bool::arbitrary(u)) { Some(try!(text(u))) } else { None },
+            version: if try!(bool::arbitrary(u)) { Some(try!(text(u))) } else { None },
+            timescale: timescale,
+            vars: Vec::new(),
+            scope: try!(Scope::arbitrary(u)),
+            other_scopes: Vec::new(),
+            unknown: Vec::new(),
+        })
+    }
+}
+
+/// Headers and data sections generated from deterministic pseudo-random input data, with `End`
+/// commands only where a simulation command is open
+#[cfg(test)]
+fn samples() -> Vec<(Header, Vec<Command>)> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    let data: Vec<u8> = (0..1 << 16).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    }).collect();
+
+    data.chunks(1024).map(|chunk| {
+        // A header takes up all the data it is given, so it gets its own part of the chunk
+        let header = Header::arbitrary(&mut Unstructured::new(&chunk[..256])).unwrap();
+        let mut u = Unstructured::new(&chunk[256..]);
+        let mut commands = Vec::new();
+        let mut open = None;
+        while !u.is_empty() {
+            match Command::arbitrary(&mut u).unwrap() {
+                Command::End(_) => commands.extend(open.take().map(Command::End)),
+                c => {
+                    if let Command::Begin(s) = c { open = Some(s); }
+                    commands.push(c);
+                }
+            }
+        }
+        (header, commands)
+    }).collect()
+}
+
+#[test]
+fn roundtrip() {
+    use super::{Parser, Writer};
+
+    // The samples have whitespace inside text and backslashes in names and string values
+    let all: Vec<Command> = samples().into_iter().flat_map(|(_, c)| c).collect();
+    assert!(all.iter().any(|c| match *c {
+        Command::Comment(ref s) => s.contains('\n'),
+        _ => false,
+    }));
+    assert!(all.iter().any(|c| match *c {
+        Command::VarDef(_, _, _, ref r, _) => r.contains('\\'),
+        _ => false,
+    }));
+    assert!(all.iter().any(|c| match *c {
+        Command::ChangeString(_, ref s) => s.contains('\\'),
+        _ => false,
+    }));
+
+    for (header, commands) in samples() {
+        let mut buf = Vec::new();
+        {
+            let mut w = Writer::new(&mut buf);
+            w.header(&header).unwrap();
+            for c in &commands { w.command(c).unwrap(); }
+        }
+
+        let mut p = Parser::new(&buf[..]);
+        let parsed = p.parse_header().unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", header));
+        let parsed: Vec<Command> = p.map(Result::unwrap).collect();
+        assert_eq!(parsed, commands);
+    }
+}
+
+#[test]
+fn write_to_roundtrip() {
+    use super::Parser;
+
+    for (_, commands) in samples() {
+        let mut buf = Vec::new();
+        for c in &commands { c.write_to(&mut buf).unwrap(); }
+        let parsed: Vec<Command> = Parser::new(&buf[..]).map(Result::unwrap).collect();
+        assert_eq!(parsed, commands);
+
+        // Writing the parsed commands again gives the same text
+        let mut again = Vec::new();
+        for c in &parsed { c.write_to(&mut again).unwrap(); }
+        assert_eq!(again, buf);
+    }
+}
@@ -0,0 +1,259 @@
+//! Generation of synthetic waveforms with random hierarchies and signal activity
+
+use std::io;
+
+use {
+    TimescaleUnit,
+    Value,
+    IdCode,
+    Scope,
+    ScopeItem,
+    ScopeType,
+    Var,
+    VarType,
+    VarValue,
+    Header,
+    SimulationCommand,
+    Error,
+    Writer,
+};
+
+/// Kind of activity of a generated signal
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Activity {
+    /// A 1-bit clock with a random period
+    Clock,
+    /// A vector counting up at a random rate, wrapping at its width
+    Counter,
+    /// A vector of random width taking random new values
+    Toggle,
+    /// A real value rising linearly and falling back to 0 with a random period
+    Ramp,
+}
+
+/// Options for `generate`
+#[derive(Debug, Clone)]
+pub struct GenOptions {
+    /// Seed for the random number generator. The same options always produce the same file.
+    pub seed: u64,
+
+    /// Number of levels of scopes below the top scope
+    pub depth: u32,
+
+    /// Number of child scopes in each scope above the deepest level
+    pub fanout: u32,
+
+    /// Number of signals in each scope
+    pub signals: u32,
+
+    /// Activities given to the signals, each chosen with equal probability
+    pub activities: Vec<Activity>,
+
+    /// Time of the last timestamp
+    pub duration: u64,
+
+    /// Time between simulation steps, at which signals may change
+    pub step: u64,
+
+    /// Probability that a `Toggle` signal changes at each step
+    pub toggle_rate: f64,
+
+    pub timescale: (u32, TimescaleUnit),
+}
+
+impl Default for GenOptions {
+    fn default() -> GenOptions {
+        GenOptions {
+            seed: 1,
+            depth: 2,
+            fanout: 2,
+            signals: 4,
+            activities: vec![Activity::Clock, Activity::Counter, Activity::Toggle, Activity::Ramp],
+            duration: 1000,
+            step: 1,
+            toggle_rate: 0.1,
+            timescale: (1, TimescaleUnit::NS),
+        }
+    }
+}
+
+/// xorshift64* generator, so that output is reproducible without depending on an RNG crate
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed ^ 0x9e37_79b9_7f4a_7c15 | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A random number in `lo..=hi`
+    fn range(&mut self, lo: u64, hi: u64) -> u64 {
+        lo + self.next() % (hi - lo + 1)
+    }
+
+    /// A random number in `0.0..1.0`
+    fn float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A generated signal. Periods are in steps.
+enum Signal {
+    Clock { half_period: u64 },
+    Counter { width: u32, every: u64 },
+    Toggle { width: u32 },
+    Ramp { period: u64, max: f64 },
+}
+
+impl Signal {
+    fn new(activity: Activity, rng: &mut Rng) -> Signal {
+        match activity {
+            Activity::Clock => Signal::Clock { half_period: rng.range(1, 8) },
+            Activity::Counter => Signal::Counter {
+                width: rng.range(2, 16) as u32,
+                every: rng.range(1, 16),
+            },
+            Activity::Toggle => Signal::Toggle { width: rng.range(1, 32) as u32 },
+            Activity::Ramp => Signal::Ramp {
+                period: rng.range(16, 256),
+                max: rng.range(1, 5) as f64,
+            },
+        }
+    }
+
+    fn var(&self, code: IdCode, index: usize) -> Var {
+        let (var_type, size, name) = match *self {
+            Signal::Clock { .. } => (VarType::Wire, 1, "clk"),
+            Signal::Counter { width, .. } => (VarType::Reg, width, "count"),
+            Signal::Toggle { width } => (VarType::Wire, width, "data"),
+            Signal::Ramp { .. } => (VarType::Real, 64, "level"),
+        };
+        Var { var_type: var_type, size: size, code: code, reference: format!("{}{}", name, index),
+            index: None }
+    }
+
+    /// The value at simulation step `n`, or `None` if it does not change at that step
+    fn value(&self, n: u64, rate: f64, rng: &mut Rng) -> Option<VarValue> {
+        match *self {
+            Signal::Clock { half_period } if n % half_period == 0 => {
+                let v = if (n / half_period) % 2 == 0 { Value::V0 } else { Value::V1 };
+                Some(VarValue::Scalar(v))
+            }
+            Signal::Counter { width, every } if n % every == 0 => {
+                Some(VarValue::Vector(bits(n / every, width).into()))
+            }
+            Signal::Toggle { width } if n == 0 || rng.float() < rate => {
+                Some(match width {
+                    1 => VarValue::Scalar(bits(rng.next(), 1)[0]),
+                    w => VarValue::Vector(bits(rng.next(), w).into()),
+                })
+            }
+            Signal::Ramp { period, max } => {
+                Some(VarValue::Real(max * (n % period) as f64 / period as f64))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The low `width` bits of `n`, most significant first
+fn bits(n: u64, width: u32) -> Vec<Value> {
+    (0..width).rev().map(|i| if n >> i & 1 == 1 { Value::V1 } else { Value::V0 }).collect()
+}
+
+fn build_scope(name: String, depth: u32, options: &GenOptions, rng: &mut Rng,
+    signals: &mut Vec<Signal>) -> Scope {
+    let mut children = Vec::new();
+    for i in 0..options.signals {
+        if options.activities.is_empty() { break; }
+        let n = options.activities.len() as u64;
+        let activity = options.activities[rng.range(0, n - 1) as usize];
+        let signal = Signal::new(activity, rng);
+        children.push(ScopeItem::Var(signal.var(IdCode::from(signals.len() as u32), i as usize)));
+        signals.push(signal);
+    }
+    if depth > 0 {
+        for i in 0..options.fanout {
+            let child = build_scope(format!("s{}", i), depth - 1, options, rng, signals);
+            children.push(ScopeItem::Scope(child));
+        }
+    }
+    Scope { scope_type: ScopeType::Module, identifier: name, children: children }
+}
+
+/// Write a randomly generated VCD to `w`, as configured by `options`.
+///
+/// The top scope `top` contains `options.depth` levels of scopes, each with `options.signals`
+/// signals. Every signal is assigned in a `$dumpvars` block at time 0, and timestamps are written
+/// only for steps where some signal changes.
+pub fn generate(options: &GenOptions, w: &mut io::Write) -> Result<(), Error> {
+    let mut rng = Rng::new(options.seed);
+    let mut signals = Vec::new();
+    let scope = build_scope("top".to_string(), options.depth, options, &mut rng, &mut signals);
+    let header = Header {
+        comment: None,
+        date: None,
+        version: Some("vcd::gen".to_string()),
+        timescale: Some(options.timescale),
+        vars: Vec::new(),
+        scope: scope,
+        other_scopes: Vec::new(),
+        unknown: Vec::new(),
+    };
+
+    let mut vcd = Writer::new(w);
+    try!(vcd.header(&header));
+
+    let step = ::std::cmp::max(options.step, 1);
+    let mut changes = Vec::new();
+    for n in 0..options.duration / step + 1 {
+        changes.clear();
+        for (i, s) in signals.iter().enumerate() {
+            if let Some(v) = s.value(n, options.toggle_rate, &mut rng) {
+                changes.push(v.to_command(IdCode::from(i as u32)));
+            }
+        }
+        if changes.is_empty() { continue; }
+
+        try!(vcd.timestamp(n * step));
+        if n == 0 { try!(vcd.begin(SimulationCommand::Dumpvars)); }
+        for c in &changes { try!(vcd.command(c)); }
+        if n == 0 { try!(vcd.end()); }
+    }
+    Ok(())
+}
+
+#[test]
+fn hierarchy() {
+    use super::Parser;
+
+    let options = GenOptions { duration: 100, step: 5, ..GenOptions::default() };
+    let mut buf = Vec::new();
+    generate(&options, &mut buf).unwrap();
+
+    let mut again = Vec::new();
+    generate(&options, &mut again).unwrap();
+    assert_eq!(buf, again);
+
+    let mut p = Parser::new(&buf[..]);
+    let header = p.parse_header().unwrap();
+    assert_eq!(header.scope.children.len(), 6);
+    match header.scope.children[5] {
+        ScopeItem::Scope(ref s) => assert_eq!(s.children.len(), 6),
+        ref i => panic!("expected scope, found {:?}", i),
+    }
+
+    let times: Vec<u64> = p.filter_map(|c| match c.unwrap() {
+        ::Command::Timestamp(t) => Some(t),
+        _ => None,
+    }).collect();
+    assert_eq!(times.first(), Some(&0));
+    assert!(times.last() <= Some(&100));
+    assert!(times.iter().all(|t| t % 5 == 0));
+}
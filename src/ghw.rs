@@ -0,0 +1,280 @@
+//! Reading of GHDL's GHW waveform format (requires the `ghw` feature)
+//!
+//! `GhwParser` mirrors `Parser`, returning a `Header` and then the data section as `Command`s.
+//! GHW carries VHDL types that have no VCD equivalent, which are mapped as follows:
+//!
+//! * `std_logic` values are kept as the matching 9-state `Value`.
+//! * Booleans, bits and enumerations are 1-bit or vector variables holding the enumeration
+//!   position in binary; the literal names are not preserved.
+//! * Records and arrays become scopes containing one variable per scalar element.
+//! * VHDL-specific scope kinds (processes, generates, blocks) become modules.
+
+use std::path::Path;
+use std::iter::Peekable;
+use std::vec;
+
+use wellen::{self, simple, FileFormat, Hierarchy, ItemRef, SignalRef, SignalValueRef,
+    WellenError};
+
+use {
+    TimescaleUnit,
+    Value,
+    IdCode,
+    ScopeType,
+    VarType,
+    Header,
+    Command,
+    Error,
+};
+use read;
+
+impl From<WellenError> for Error {
+    fn from(err: WellenError) -> Error {
+        match err {
+            WellenError::Io(e) => Error::Io(e),
+            _ => Error::Parse("Invalid GHW file"),
+        }
+    }
+}
+
+fn value(b: u8) -> Value {
+    Value::parse(b).unwrap_or(Value::X)
+}
+
+fn scope_type(t: wellen::ScopeType) -> ScopeType {
+    match t {
+        wellen::ScopeType::Task => ScopeType::Task,
+        wellen::ScopeType::Function => ScopeType::Function,
+        wellen::ScopeType::Begin => ScopeType::Begin,
+        wellen::ScopeType::Fork => ScopeType::Fork,
+        wellen::ScopeType::Interface => ScopeType::Interface,
+        wellen::ScopeType::Package => ScopeType::Package,
+        wellen::ScopeType::Program => ScopeType::Program,
+        wellen::ScopeType::Class => ScopeType::Class,
+        wellen::ScopeType::Union => ScopeType::Union,
+        wellen::ScopeType::Struct => ScopeType::Struct,
+        _ => ScopeType::Module,
+    }
+}
+
+fn timescale(ts: wellen::Timescale) -> Result<(u32, TimescaleUnit), Error> {
+    use wellen::TimescaleUnit::*;
+    let unit = match ts.unit {
+        FemtoSeconds => TimescaleUnit::FS,
+        PicoSeconds => TimescaleUnit::PS,
+        NanoSeconds => TimescaleUnit::NS,
+        MicroSeconds => TimescaleUnit::US,
+        MilliSeconds => TimescaleUnit::MS,
+        Seconds => TimescaleUnit::S,
+        _ => return Err(Error::Parse("Unsupported GHW timescale")),
+    };
+    Ok((ts.factor, unit))
+}
+
+fn id(s: SignalRef) -> IdCode {
+    IdCode::from(s.index() as u32)
+}
+
+fn push_item(h: &Hierarchy, item: ItemRef, commands: &mut Vec<Command>) {
+    match item {
+        ItemRef::Scope(s) => {
+            let scope = &h[s];
+            commands.push(Command::ScopeDef(scope_type(scope.scope_type()), scope.name(h).into()));
+            for child in scope.items(h) {
+                push_item(h, child, commands);
+            }
+            commands.push(Command::Upscope);
+        }
+        ItemRef::Var(v) => {
+            let var = &h[v];
+            let (var_type, size) = if var.is_real(h) {
+                (VarType::Real, 64)
+            } else {
+                (VarType::Wire, var.length(h).unwrap_or(0))
+            };
+            let name = var.name(h).into();
+            commands.push(Command::VarDef(var_type, size, id(var.signal_ref()), name, None));
+        }
+    }
+}
+
+/// GHW parser. Reads a file written by GHDL's `--wave` option and acts as an iterator of
+/// `Command`s.
+///
+/// The whole file is decoded when it is opened.
+pub struct GhwParser {
+    wave: simple::Waveform,
+    commands: Option<vec::IntoIter<Command>>,
+}
+
+impl GhwParser {
+    /// Open the GHW file at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<GhwParser, Error> {
+        let wave = try!(simple::read(path));
+        if wave.hierarchy().file_format() != FileFormat::Ghw {
+            return Err(Error::Parse("Not a GHW file"));
+        }
+        Ok(GhwParser { wave: wave, commands: None })
+    }
+
+    /// Read the GHW hierarchy into a `Header` struct, as `Parser::parse_header` does for a VCD
+    /// file.
+    pub fn parse_header(&mut self) -> Result<Header, Error> {
+        let h = self.wave.hierarchy();
+        let mut commands = Vec::new();
+        if !h.date().is_empty() { commands.push(Command::Date(h.date().into())); }
+        if !h.version().is_empty() { commands.push(Command::Version(h.version().into())); }
+        if let Some(ts) = h.timescale() {
+            let (v, unit) = try!(timescale(ts));
+            commands.push(Command::Timescale(v, unit));
+        }
+        for item in h.items() {
+            push_item(h, item, &mut commands);
+        }
+        commands.push(Command::Enddefinitions);
+
+        read::parse_header(&mut commands.into_iter().map(Ok))
+    }
+
+    fn read_data(&mut self) -> Vec<Command> {
+        let signals: Vec<SignalRef> = self.wave.hierarchy().signals().collect();
+        self.wave.load_signals(&signals);
+
+        let wave = &self.wave;
+        let mut changes: Vec<(IdCode, Peekable<_>)> = signals.iter().filter_map(|&s| {
+            wave.get_signal(s).map(|signal| (id(s), signal.iter_changes().peekable()))
+        }).collect();
+
+        let mut commands = Vec::new();
+        for (index, &time) in wave.time_table().iter().enumerate() {
+            commands.push(Command::Timestamp(time));
+            for &mut (id, ref mut iter) in &mut changes {
+                while iter.peek().map_or(false, |&(i, _)| i as usize == index) {
+                    let (_, v) = iter.next().unwrap();
+                    match v {
+                        SignalValueRef::Real(r) => commands.push(Command::ChangeReal(id, r)),
+                        SignalValueRef::String(s) => {
+                            commands.push(Command::ChangeString(id, s.into()))
+                        }
+                        SignalValueRef::BitVec(_) => {
+                            let bits = v.to_bit_string().unwrap_or_default();
+                            let values: Vec<Value> = bits.bytes().map(value).collect();
+                            commands.push(if values.len() == 1 {
+                                Command::ChangeScalar(id, values[0])
+                            } else {
+                                Command::ChangeVector(id, values.into())
+                            });
+                        }
+                        SignalValueRef::Event => (),
+                    }
+                }
+            }
+        }
+        commands
+    }
+}
+
+impl Iterator for GhwParser {
+    type Item = Result<Command, Error>;
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        if self.commands.is_none() {
+            self.commands = Some(self.read_data().into_iter());
+        }
+        self.commands.as_mut().and_then(|c| c.next()).map(Ok)
+    }
+}
+
+#[test]
+fn decode() {
+    use std::fs;
+    use {ScopeItem, VarValue};
+    use Value::*;
+
+    // A GHW file as GHDL would write it for a std_ulogic, a boolean, a three-value enumeration
+    // and a record of a std_ulogic and a boolean
+    let mut data = b"GHDLwave\n\x10\x00\x01\x01\x04\x01\x00".to_vec();
+
+    let strings = ["std_ulogic", "'U'", "'X'", "'0'", "'1'", "'Z'", "'W'", "'L'", "'H'", "'-'",
+        "boolean", "false", "true", "state_t", "idle", "run", "stop", "pair_t", "a", "b",
+        "top", "sig", "flag", "state", "p"];
+    data.extend_from_slice(b"STR\0\0\0\0\0");
+    data.extend_from_slice(&(strings.len() as u32 - 1).to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    for s in &strings {
+        data.extend_from_slice(s.as_bytes());
+        data.push(0);
+    }
+
+    // Types, numbered from 1, each with its kind and name, strings numbered from 1 as above
+    data.extend_from_slice(b"TYP\0\0\0\0\0\x04\0\0\0");
+    data.extend_from_slice(&[23, 1, 9, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+    data.extend_from_slice(&[22, 11, 2, 12, 13]);
+    data.extend_from_slice(&[23, 14, 3, 15, 16, 17]);
+    data.extend_from_slice(&[32, 18, 2, 19, 1, 20, 2]);
+    data.push(0);
+
+    // An instance containing signals 1 to 5, with their names and types
+    data.extend_from_slice(b"HIE\0\0\0\0\0\x01\0\0\0\x04\0\0\0\x05\0\0\0");
+    data.extend_from_slice(&[6, 21]);
+    data.extend_from_slice(&[16, 22, 1, 1, 16, 23, 2, 2, 16, 24, 3, 3, 16, 25, 4, 4, 5]);
+    data.extend_from_slice(&[15, 0]);
+    data.extend_from_slice(b"EOH\0");
+
+    // Initial values: U, false, idle, 0, true
+    data.extend_from_slice(b"SNP\0\0\0\0\0");
+    data.extend_from_slice(&0i64.to_le_bytes());
+    data.extend_from_slice(&[0, 0, 0, 2, 1]);
+    data.extend_from_slice(b"ESN\0");
+
+    // At 10fs sig is 1 and state is stop, and at 15fs p.a is H
+    data.extend_from_slice(b"CYC\0");
+    data.extend_from_slice(&10i64.to_le_bytes());
+    data.extend_from_slice(&[1, 3, 2, 2, 0, 5, 4, 7, 0, 0x7f]);
+    data.extend_from_slice(b"ECY\0");
+
+    let directory = data.len() as u32;
+    data.extend_from_slice(b"DIR\0\0\0\0\0\0\0\0\0EOD\0TAI\0\0\0\0\0");
+    data.extend_from_slice(&directory.to_le_bytes());
+
+    let path = ::std::env::temp_dir().join(format!("vcd-ghw-decode-{}.ghw", ::std::process::id()));
+    fs::write(&path, &data).unwrap();
+    let parsed = GhwParser::open(&path);
+    fs::remove_file(&path).unwrap();
+    let mut p = parsed.unwrap();
+
+    let header = p.parse_header().unwrap();
+    assert_eq!(header.timescale, Some((1, TimescaleUnit::FS)));
+    let var = |path: &[&str]| header.find_var(path).unwrap();
+    let sizes: Vec<u32> = [&["top", "sig"][..], &["top", "flag"], &["top", "state"],
+        &["top", "p", "a"], &["top", "p", "b"]].iter().map(|p| var(p).size).collect();
+    assert_eq!(sizes, vec![1, 1, 2, 1, 1]);
+    match header.scope.children[3] {
+        ScopeItem::Scope(ref s) => assert_eq!(s.identifier, "p"),
+        ref i => panic!("{:?}", i),
+    }
+
+    let (sig, flag, state) = (var(&["top", "sig"]).code, var(&["top", "flag"]).code,
+        var(&["top", "state"]).code);
+    let (a, b) = (var(&["top", "p", "a"]).code, var(&["top", "p", "b"]).code);
+    let mut changes = Vec::new();
+    let mut time = 0;
+    for c in p {
+        match c.unwrap() {
+            Command::Timestamp(t) => time = t,
+            c => changes.push((time, c.change_value().unwrap())),
+        }
+    }
+    changes.sort_by_key(|c| (c.0, format!("{:?}", c.1)));
+    let mut expected = vec![
+        (0, (sig, VarValue::Scalar(U))),
+        (0, (flag, VarValue::Scalar(V0))),
+        (0, (state, VarValue::Vector(vec![V0, V0].into()))),
+        (0, (a, VarValue::Scalar(V0))),
+        (0, (b, VarValue::Scalar(V1))),
+        (10, (sig, VarValue::Scalar(V1))),
+        (10, (state, VarValue::Vector(vec![V1, V0].into()))),
+        (15, (a, VarValue::Scalar(H))),
+    ];
+    expected.sort_by_key(|c| (c.0, format!("{:?}", c.1)));
+    assert_eq!(changes, expected);
+}
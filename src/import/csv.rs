@@ -0,0 +1,269 @@
+use std::io;
+use std::collections::HashMap;
+
+use {
+    TimescaleUnit,
+    Value,
+    IdCode,
+    ScopeType,
+    VarType,
+    Scope,
+    ScopeItem,
+    Var,
+    Header,
+    Error,
+    Writer,
+};
+
+/// The type of a CSV column, and of the variable it becomes
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ColumnType {
+    /// A 1-bit wire, from `0`/`1`, `true`/`false`, `x` or `z`
+    Bool,
+
+    /// A vector of the given width, from decimal, `0x` hex or `0b` binary integers. Negative
+    /// numbers are stored in two's complement.
+    Integer(u32),
+
+    /// A real variable, from floating point numbers
+    Real,
+}
+
+/// Options for `csv` import
+#[derive(Debug, Clone)]
+pub struct CsvImportOptions {
+    /// Column separator
+    pub separator: char,
+
+    /// Factor applied to the values in the time column, which are then rounded to give the
+    /// timestamps. For example, a CSV with times in seconds and a timescale of `1 us` needs
+    /// `1e6`.
+    pub time_multiplier: f64,
+
+    /// Added to the values in the time column before they are scaled by `time_multiplier`, to
+    /// shift captures that start at a negative time
+    pub time_offset: f64,
+
+    /// The `$timescale` of the output
+    pub timescale: (u32, TimescaleUnit),
+
+    /// Name of the `$scope` containing all variables
+    pub scope: String,
+
+    /// Column types by column name, overriding the inferred type
+    pub types: HashMap<String, ColumnType>,
+}
+
+impl Default for CsvImportOptions {
+    fn default() -> CsvImportOptions {
+        CsvImportOptions {
+            separator: ',',
+            time_multiplier: 1.0,
+            time_offset: 0.0,
+            timescale: (1, TimescaleUnit::NS),
+            scope: "csv".to_string(),
+            types: HashMap::new(),
+        }
+    }
+}
+
+/// Split a CSV line into fields, handling double-quoted fields
+fn split_line(line: &str, separator: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); field.push('"'); }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                fields.push(::std::mem::take(&mut field))
+            }
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn parse_bool(s: &str) -> Option<Value> {
+    match &s.to_lowercase()[..] {
+        "0" | "false" => Some(Value::V0),
+        "1" | "true" => Some(Value::V1),
+        "x" => Some(Value::X),
+        "z" => Some(Value::Z),
+        _ => None,
+    }
+}
+
+fn parse_int(s: &str) -> Option<i128> {
+    let (neg, s) = if s.starts_with('-') { (true, &s[1..]) } else { (false, s) };
+    let v = if s.starts_with("0x") || s.starts_with("0X") {
+        i128::from_str_radix(&s[2..], 16)
+    } else if s.starts_with("0b") || s.starts_with("0B") {
+        i128::from_str_radix(&s[2..], 2)
+    } else {
+        s.parse()
+    };
+    v.ok().map(|v| if neg { -v } else { v })
+}
+
+/// Number of bits needed to hold `v`, plus a sign bit if `signed`
+fn bits_needed(v: i128, signed: bool) -> u32 {
+    if v < 0 {
+        129 - (!v).leading_zeros()
+    } else {
+        ::std::cmp::max(128 - v.leading_zeros(), 1) + signed as u32
+    }
+}
+
+fn infer_type(values: &[&str]) -> ColumnType {
+    if values.iter().all(|v| parse_bool(v).is_some()) {
+        return ColumnType::Bool;
+    }
+    let ints: Option<Vec<i128>> = values.iter().map(|v| parse_int(v)).collect();
+    if let Some(ints) = ints {
+        let signed = ints.iter().any(|&v| v < 0);
+        return ColumnType::Integer(ints.iter().map(|&v| bits_needed(v, signed)).max().unwrap_or(1));
+    }
+    ColumnType::Real
+}
+
+fn to_bits(v: i128, width: u32) -> Vec<Value> {
+    (0..width).rev().map(|i| {
+        let bit = if i >= 128 { v < 0 } else { (v >> i) & 1 == 1 };
+        if bit { Value::V1 } else { Value::V0 }
+    }).collect()
+}
+
+/// Read a CSV file with a header row and write it to `w` as a VCD.
+///
+/// The first column holds the time of each row, and every other column becomes a variable named
+/// by its header. Column types are inferred from their contents unless given in `options`, with
+/// integer widths chosen to fit the largest value. Empty cells leave the variable unchanged.
+///
+/// The whole CSV is read before anything is written, since the widths must be known before the
+/// header is written.
+pub fn csv<R: io::BufRead>(r: R, options: &CsvImportOptions, w: &mut io::Write)
+    -> Result<(), Error> {
+    let mut lines = r.lines();
+    let names = match lines.next() {
+        Some(line) => split_line(try!(line).trim_end(), options.separator),
+        None => return Err(Error::Parse("Empty CSV file")),
+    };
+    if names.len() < 2 {
+        return Err(Error::Parse("CSV file needs a time column and at least one signal column"));
+    }
+
+    let mut rows = Vec::new();
+    for line in lines {
+        let line = try!(line);
+        if line.trim().is_empty() { continue; }
+        let mut fields = split_line(line.trim_end(), options.separator);
+        fields.resize(names.len(), String::new());
+        let time: f64 = try!(fields[0].trim().parse::<f64>()) + options.time_offset;
+        if time < 0.0 {
+            return Err(Error::Parse("Negative time in CSV file"));
+        }
+        rows.push(((time * options.time_multiplier).round() as u64, fields));
+    }
+
+    let types: Vec<ColumnType> = (1..names.len()).map(|col| {
+        options.types.get(names[col].trim()).cloned().unwrap_or_else(|| {
+            let values: Vec<&str> = rows.iter().map(|r| r.1[col].trim())
+                .filter(|v| !v.is_empty()).collect();
+            infer_type(&values)
+        })
+    }).collect();
+
+    let children = names[1..].iter().zip(types.iter()).enumerate().map(|(i, (name, t))| {
+        let (var_type, size) = match *t {
+            ColumnType::Bool => (VarType::Wire, 1),
+            ColumnType::Integer(width) => (VarType::Reg, width),
+            ColumnType::Real => (VarType::Real, 64),
+        };
+        ScopeItem::Var(Var { var_type: var_type, size: size, code: IdCode::from(i as u32),
+            reference: name.trim().replace(' ', "_"), index: None })
+    }).collect();
+
+    let header = Header {
+        timescale: Some(options.timescale),
+        scope: Scope { scope_type: ScopeType::Module, identifier: options.scope.clone(),
+            children: children },
+        ..Default::default()
+    };
+
+    let mut writer = Writer::new(w);
+    try!(writer.header(&header));
+
+    let mut last: Vec<Option<String>> = vec![None; types.len()];
+    let mut time = None;
+    for (t, fields) in rows {
+        if time.map_or(false, |prev| t < prev) {
+            return Err(Error::Parse("CSV times are not in increasing order"));
+        }
+        let mut wrote_time = false;
+        for (i, t_col) in types.iter().enumerate() {
+            let field = fields[i + 1].trim();
+            if field.is_empty() || last[i].as_ref().map(|s| &s[..]) == Some(field) { continue; }
+            if !wrote_time && time != Some(t) {
+                try!(writer.timestamp(t));
+            }
+            wrote_time = true;
+
+            let id = IdCode::from(i as u32);
+            match *t_col {
+                ColumnType::Bool => {
+                    let v = try!(parse_bool(field).ok_or(Error::Parse("Invalid boolean in CSV")));
+                    try!(writer.change_scalar(id, v));
+                }
+                ColumnType::Integer(width) => {
+                    let v = try!(parse_int(field).ok_or(Error::Parse("Invalid integer in CSV")));
+                    try!(writer.change_vector(id, &to_bits(v, width)));
+                }
+                ColumnType::Real => {
+                    try!(writer.change_real(id, try!(field.parse())));
+                }
+            }
+            last[i] = Some(field.to_string());
+        }
+        if wrote_time { time = Some(t); }
+    }
+    Ok(())
+}
+
+#[test]
+fn import() {
+    use super::super::{Parser, Command};
+
+    let data = "time,en,count,\"v in\"\n0,false,0,0.5\n1e-6,true,5,\n2e-6,true,-3,1.25\n";
+    let options = CsvImportOptions {
+        time_multiplier: 1e9,
+        ..Default::default()
+    };
+
+    let mut out = Vec::new();
+    csv(data.as_bytes(), &options, &mut out).unwrap();
+
+    let mut p = Parser::new(&out[..]);
+    let header = p.parse_header().unwrap();
+    match header.scope.children[1] {
+        ScopeItem::Var(ref v) => assert_eq!((&v.var_type, v.size), (&VarType::Reg, 4)),
+        _ => panic!(),
+    }
+
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands, vec![
+        Command::Timestamp(0),
+        Command::ChangeScalar(IdCode(0), Value::V0),
+        Command::ChangeVector(IdCode(1), to_bits(0, 4).into()),
+        Command::ChangeReal(IdCode(2), 0.5),
+        Command::Timestamp(1000),
+        Command::ChangeScalar(IdCode(0), Value::V1),
+        Command::ChangeVector(IdCode(1), to_bits(5, 4).into()),
+        Command::Timestamp(2000),
+        Command::ChangeVector(IdCode(1), to_bits(-3, 4).into()),
+        Command::ChangeReal(IdCode(2), 1.25),
+    ]);
+}
@@ -0,0 +1,7 @@
+//! Conversion of other file formats to VCD
+
+mod csv;
+pub use self::csv::{csv, ColumnType, CsvImportOptions};
+
+mod saleae;
+pub use self::saleae::{saleae_csv, saleae_binary};
@@ -0,0 +1,206 @@
+use std::io;
+
+use {
+    TimescaleUnit,
+    Value,
+    IdCode,
+    ScopeType,
+    VarType,
+    Scope,
+    ScopeItem,
+    Var,
+    Header,
+    Error,
+    Writer,
+};
+use super::csv::{csv, ColumnType, CsvImportOptions};
+
+/// Convert Saleae Logic 2's digital CSV export, with a time column in seconds and a column per
+/// channel, to a VCD with a 1-bit wire per channel in scope `saleae`, written to `w`.
+///
+/// As for `saleae_binary`, times are shifted so that a capture starting at a negative time
+/// starts at time 0.
+pub fn saleae_csv<R: io::BufRead>(mut r: R, timescale: (u32, TimescaleUnit), w: &mut io::Write)
+    -> Result<(), Error> {
+    // Read the header row to find the channel names, and force every channel to be a wire
+    let mut first = String::new();
+    try!(r.read_line(&mut first));
+    let types = first.trim_end().split(',').skip(1).map(|name| {
+        (name.trim().trim_matches('"').to_string(), ColumnType::Bool)
+    }).collect();
+
+    // The first row has the earliest time
+    let mut row = String::new();
+    try!(r.read_line(&mut row));
+    let start = row.split(',').next().and_then(|t| t.trim().parse::<f64>().ok()).unwrap_or(0.0);
+    first.push_str(&row);
+
+    let options = CsvImportOptions {
+        time_multiplier: timescale.1.divisor() as f64 / timescale.0 as f64,
+        time_offset: -f64::min(start, 0.0),
+        timescale: timescale,
+        scope: "saleae".to_string(),
+        types: types,
+        ..Default::default()
+    };
+    csv(io::Read::chain(io::Cursor::new(first), r), &options, w)
+}
+
+fn read_bytes<R: io::Read>(r: &mut R, n: usize) -> Result<Vec<u8>, Error> {
+    let mut buf = vec![0; n];
+    try!(r.read_exact(&mut buf));
+    Ok(buf)
+}
+
+fn read_u32<R: io::Read>(r: &mut R) -> Result<u32, Error> {
+    let mut buf = [0; 4];
+    try!(r.read_exact(&mut buf));
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: io::Read>(r: &mut R) -> Result<u64, Error> {
+    let mut buf = [0; 8];
+    try!(r.read_exact(&mut buf));
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64<R: io::Read>(r: &mut R) -> Result<f64, Error> {
+    read_u64(r).map(f64::from_bits)
+}
+
+/// A channel read from a Saleae binary export
+struct Channel {
+    initial: bool,
+    begin: f64,
+    end: f64,
+    transitions: Vec<f64>,
+}
+
+fn read_channel<R: io::Read>(r: &mut R) -> Result<Channel, Error> {
+    if try!(read_bytes(r, 8)) != b"<SALEAE>" {
+        return Err(Error::Parse("Not a Saleae binary export"));
+    }
+    if try!(read_u32(r)) != 0 {
+        return Err(Error::Parse("Unsupported Saleae binary export version"));
+    }
+    if try!(read_u32(r)) != 0 {
+        return Err(Error::Parse("Saleae binary export is not a digital channel"));
+    }
+    let initial = try!(read_u32(r)) != 0;
+    let begin = try!(read_f64(r));
+    let end = try!(read_f64(r));
+    let count = try!(read_u64(r));
+    let mut transitions = Vec::new();
+    for _ in 0..count {
+        transitions.push(try!(read_f64(r)));
+    }
+    Ok(Channel { initial: initial, begin: begin, end: end, transitions: transitions })
+}
+
+/// Convert Saleae Logic 2's binary export of digital channels (the `digital_N.bin` files, format
+/// version 0) to a VCD with a 1-bit wire per channel in scope `saleae`, written to `w`.
+///
+/// `channels` pairs each channel's name with a reader for its file. Times are shifted so that the
+/// earliest capture start is at time 0, since captures triggered mid-way have negative times,
+/// and the capture end is written as a final timestamp.
+pub fn saleae_binary<R: io::Read>(channels: Vec<(String, R)>, timescale: (u32, TimescaleUnit),
+                                  w: &mut io::Write) -> Result<(), Error> {
+    let mut names = Vec::new();
+    let mut data = Vec::new();
+    for (name, mut r) in channels {
+        data.push(try!(read_channel(&mut r)));
+        names.push(name);
+    }
+
+    let start = data.iter().map(|c| c.begin).fold(::std::f64::INFINITY, f64::min);
+    let ticks_per_second = timescale.1.divisor() as f64 / timescale.0 as f64;
+    let ticks = |t: f64| ((t - start) * ticks_per_second).round() as u64;
+
+    let mut changes = Vec::new();
+    for (i, c) in data.iter().enumerate() {
+        changes.push((ticks(c.begin), i, c.initial));
+        let mut level = c.initial;
+        for &t in &c.transitions {
+            level = !level;
+            changes.push((ticks(t), i, level));
+        }
+    }
+    changes.sort_by_key(|&(t, i, _)| (t, i));
+    let end = data.iter().map(|c| ticks(c.end)).max().unwrap_or(0);
+
+    let children = names.iter().enumerate().map(|(i, name)| {
+        ScopeItem::Var(Var { var_type: VarType::Wire, size: 1, code: IdCode::from(i as u32),
+            reference: name.replace(' ', "_"), index: None })
+    }).collect();
+    let header = Header {
+        timescale: Some(timescale),
+        scope: Scope { scope_type: ScopeType::Module, identifier: "saleae".to_string(),
+            children: children },
+        ..Default::default()
+    };
+
+    let mut writer = Writer::new(w);
+    try!(writer.header(&header));
+    let mut time = None;
+    for (t, i, level) in changes {
+        if time != Some(t) {
+            try!(writer.timestamp(t));
+            time = Some(t);
+        }
+        let v = if level { Value::V1 } else { Value::V0 };
+        try!(writer.change_scalar(IdCode::from(i as u32), v));
+    }
+    if time.map_or(true, |t| t < end) {
+        try!(writer.timestamp(end));
+    }
+    Ok(())
+}
+
+#[test]
+fn binary() {
+    use super::super::{Parser, Command};
+
+    fn channel(initial: u32, begin: f64, end: f64, transitions: &[f64]) -> Vec<u8> {
+        let mut data = b"<SALEAE>".to_vec();
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&initial.to_le_bytes());
+        data.extend_from_slice(&begin.to_bits().to_le_bytes());
+        data.extend_from_slice(&end.to_bits().to_le_bytes());
+        data.extend_from_slice(&(transitions.len() as u64).to_le_bytes());
+        for t in transitions {
+            data.extend_from_slice(&t.to_bits().to_le_bytes());
+        }
+        data
+    }
+
+    let clk = channel(0, -1e-6, 4e-6, &[0.0, 1e-6, 2e-6, 3e-6]);
+    let cs = channel(1, -1e-6, 4e-6, &[1e-6]);
+    let channels = vec![("clk".to_string(), &clk[..]), ("CS n".to_string(), &cs[..])];
+
+    let mut out = Vec::new();
+    saleae_binary(channels, (1, TimescaleUnit::US), &mut out).unwrap();
+
+    let mut p = Parser::new(&out[..]);
+    let header = p.parse_header().unwrap();
+    match header.scope.children[1] {
+        ScopeItem::Var(ref v) => assert_eq!(v.reference, "CS_n"),
+        _ => panic!(),
+    }
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands, vec![
+        Command::Timestamp(0),
+        Command::ChangeScalar(IdCode(0), Value::V0),
+        Command::ChangeScalar(IdCode(1), Value::V1),
+        Command::Timestamp(1),
+        Command::ChangeScalar(IdCode(0), Value::V1),
+        Command::Timestamp(2),
+        Command::ChangeScalar(IdCode(0), Value::V0),
+        Command::ChangeScalar(IdCode(1), Value::V0),
+        Command::Timestamp(3),
+        Command::ChangeScalar(IdCode(0), Value::V1),
+        Command::Timestamp(4),
+        Command::ChangeScalar(IdCode(0), Value::V0),
+        Command::Timestamp(5),
+    ]);
+}
@@ -0,0 +1,85 @@
+//! Parsing shared by the WebAssembly and Node.js bindings
+//!
+//! Headers and changes are converted to JSON in the formats used by `export::json`. Times are
+//! JSON numbers, which JavaScript reads exactly only up to 2^53.
+
+use std::io::Cursor;
+
+use serde_json::Value as Json;
+
+use {
+    Command,
+    Error,
+    Parser,
+};
+use export::{header_json, change_json};
+
+/// A parser over a complete file held in memory, returning its value changes in batches
+pub struct Batches {
+    parser: Parser<Cursor<Vec<u8>>>,
+    time: u64,
+}
+
+impl Batches {
+    pub fn new(data: Vec<u8>) -> Batches {
+        Batches { parser: Parser::new(Cursor::new(data)), time: 0 }
+    }
+
+    /// Parse the header, returning it as JSON
+    pub fn parse_header(&mut self) -> Result<Json, Error> {
+        Ok(header_json(&try!(self.parser.parse_header())))
+    }
+
+    /// Return up to `max` value changes as `{"time", "id", "value"}` objects, or none once the
+    /// file has been read to the end
+    pub fn next_changes(&mut self, max: usize) -> Result<Vec<Json>, Error> {
+        let mut changes = Vec::new();
+        while changes.len() < max {
+            let c = match self.parser.next() {
+                Some(c) => try!(c),
+                None => break,
+            };
+            if let Command::Timestamp(t) = c {
+                self.time = t;
+            } else if let Some(change) = change_json(self.time, &c) {
+                changes.push(change);
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// Extract the part of the VCD file in `data` between times `start` and `end` inclusive, as
+/// `transform::window` does
+pub fn extract(data: &[u8], start: u64, end: u64) -> Result<String, Error> {
+    let mut parser = Parser::new(data);
+    let header = try!(parser.parse_header());
+    let mut out = Vec::new();
+    try!(::transform::window(&header, parser, start, end, &mut out));
+    Ok(try!(String::from_utf8(out)))
+}
+
+#[test]
+fn batches() {
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0!
+    #5 1!
+    #10 0!
+    ";
+
+    let mut p = Batches::new(sample.to_vec());
+    assert_eq!(p.parse_header().unwrap()["scope"]["children"][0]["name"], "clk");
+    assert_eq!(p.next_changes(2).unwrap(), vec![
+        json!({ "time": 0, "id": "!", "value": "0" }),
+        json!({ "time": 5, "id": "!", "value": "1" }),
+    ]);
+    assert_eq!(p.next_changes(2).unwrap(), vec![json!({ "time": 10, "id": "!", "value": "0" })]);
+    assert!(p.next_changes(2).unwrap().is_empty());
+
+    let slice = extract(sample, 5, 7).unwrap();
+    assert!(slice.ends_with("#5\n$dumpvars\n1!\n$end\n#7\n"));
+}
@@ -1,18 +1,168 @@
+#[cfg(feature = "fst")]
+extern crate fst_reader;
+#[cfg(feature = "fst")]
+extern crate fst_writer;
+#[cfg(feature = "ghw")]
+extern crate wellen;
+#[cfg(feature = "sigrok")]
+extern crate zip;
+#[cfg(feature = "arrow")]
+extern crate arrow_array;
+#[cfg(feature = "arrow")]
+extern crate arrow_schema;
+#[cfg(feature = "arrow")]
+extern crate parquet;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+#[cfg(feature = "bitvec")]
+extern crate bitvec;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "python")]
+extern crate pyo3;
+// pyo3's and napi's generated code refers to `::core`, which the 2015 edition needs declared
+#[cfg(any(feature = "python", feature = "node"))]
+extern crate core;
+#[cfg(feature = "node")]
+extern crate napi;
+#[cfg(feature = "node")]
+extern crate napi_derive;
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+#[cfg(feature = "json")]
+#[macro_use]
+extern crate serde_json;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "flate2")]
+extern crate flate2;
+#[cfg(feature = "futures")]
+extern crate futures;
+
 use std::str::FromStr;
 use std::fmt::{self, Display};
+use std::slice::Iter;
+use std::iter::Chain;
+use std::option;
+use std::cmp::Ordering;
+use std::time::Duration;
+use std::io;
 
 mod read;
-pub use read::{Error, Parser};
+pub use read::{Error, ErrorKind, Position, Parser, ParserOptions, Diagnostics};
+
+mod slice;
+pub use slice::SliceParser;
+
+mod path;
+pub use path::{VarPath, PathStyle};
+
+mod file;
+pub use file::OutputFile;
+
+pub mod visit;
+pub use visit::Visitor;
 
 mod write;
-pub use write::Writer;
+pub use write::{Writer, CheckedWriter, BoolSignal, U32Signal, F64Signal, StringSignal};
+
+pub mod source;
+pub use source::WaveformSource;
+
+pub mod paged;
+pub use paged::PagedWaveform;
+
+pub mod vector;
+pub use vector::BitVector;
+
+pub mod builder;
+pub use builder::{HeaderBuilder, ScopeBuilder};
+
+pub mod waveform;
+pub use waveform::Waveform;
+
+pub mod measure;
+pub mod cycles;
+pub mod transform;
+pub mod diff;
+pub mod merge;
+pub mod follow;
+pub mod gen;
+pub mod stats;
+pub mod dispatch;
+
+#[cfg(feature = "fst")]
+pub mod fst;
+
+#[cfg(feature = "ghw")]
+pub mod ghw;
+
+#[cfg(feature = "bitvec")]
+pub mod bits;
+
+#[cfg(feature = "rayon")]
+pub mod parallel;
+
+#[cfg(feature = "futures")]
+mod stream;
+#[cfg(feature = "futures")]
+pub use stream::{AsyncParser, ParseHeader};
+
+#[cfg(feature = "chrono")]
+mod date;
+
+#[cfg(feature = "arbitrary")]
+mod fuzz;
+
+#[cfg(any(feature = "wasm", feature = "node"))]
+mod js;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(feature = "node")]
+pub mod node;
+
+pub mod convert;
+pub mod export;
+pub mod import;
+pub mod render;
 
 /// A unit of time for the `$timescale` command
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum TimescaleUnit {
     S, MS, US, NS, PS, FS,
 }
 
+impl TimescaleUnit {
+    /// The number of this unit in one second
+    pub fn divisor(&self) -> u64 {
+        use self::TimescaleUnit::*;
+        match *self {
+            S  => 1,
+            MS => 1_000,
+            US => 1_000_000,
+            NS => 1_000_000_000,
+            PS => 1_000_000_000_000,
+            FS => 1_000_000_000_000_000,
+        }
+    }
+}
+
 impl FromStr for TimescaleUnit {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -43,8 +193,101 @@ impl Display for TimescaleUnit {
     }
 }
 
+/// The length of a time step, as given by a `$timescale` command
+///
+/// Timescales compare by their length, so `1 ns` is equal to `1000 ps`.
+///
+/// ```
+/// use vcd::{Timescale, TimescaleUnit};
+/// use std::time::Duration;
+///
+/// let ns = Timescale::new(10, TimescaleUnit::NS);
+/// let ps = Timescale::new(100, TimescaleUnit::PS);
+/// assert!(ps < ns);
+/// assert_eq!(ns.to_duration(5), Duration::from_nanos(50));
+/// assert_eq!(ns.rescale(5, ps), 500);
+/// ```
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Timescale {
+    pub value: u32,
+    pub unit: TimescaleUnit,
+}
+
+impl Timescale {
+    pub fn new(value: u32, unit: TimescaleUnit) -> Timescale {
+        Timescale { value: value, unit: unit }
+    }
+
+    /// The length of one time step in femtoseconds
+    pub fn femtoseconds(&self) -> u128 {
+        self.value as u128 * (TimescaleUnit::FS.divisor() / self.unit.divisor()) as u128
+    }
+
+    /// The time of timestamp `t` in femtoseconds
+    pub fn to_femtoseconds(&self, t: u64) -> u128 {
+        t as u128 * self.femtoseconds()
+    }
+
+    /// The time of timestamp `t` as a `Duration`. Panics if it does not fit.
+    pub fn to_duration(&self, t: u64) -> Duration {
+        let fs = self.to_femtoseconds(t);
+        let secs = fs / TimescaleUnit::FS.divisor() as u128;
+        let nanos = fs % TimescaleUnit::FS.divisor() as u128 / 1_000_000;
+        assert!(secs <= u64::max_value() as u128, "timestamp too large for a Duration");
+        Duration::new(secs as u64, nanos as u32)
+    }
+
+    /// Convert timestamp `t` in this timescale to the timestamp in timescale `to`, rounding down
+    /// if `to` is coarser. Panics if the result overflows a `u64`.
+    pub fn rescale(&self, t: u64, to: Timescale) -> u64 {
+        let r = self.to_femtoseconds(t) / to.femtoseconds();
+        assert!(r <= u64::max_value() as u128, "rescaled timestamp overflows");
+        r as u64
+    }
+}
+
+impl From<(u32, TimescaleUnit)> for Timescale {
+    fn from((value, unit): (u32, TimescaleUnit)) -> Timescale {
+        Timescale::new(value, unit)
+    }
+}
+
+impl From<Timescale> for (u32, TimescaleUnit) {
+    fn from(ts: Timescale) -> (u32, TimescaleUnit) {
+        (ts.value, ts.unit)
+    }
+}
+
+impl PartialEq for Timescale {
+    fn eq(&self, other: &Timescale) -> bool {
+        self.femtoseconds() == other.femtoseconds()
+    }
+}
+
+impl Eq for Timescale {}
+
+impl PartialOrd for Timescale {
+    fn partial_cmp(&self, other: &Timescale) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timescale {
+    fn cmp(&self, other: &Timescale) -> Ordering {
+        self.femtoseconds().cmp(&other.femtoseconds())
+    }
+}
+
+impl Display for Timescale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
+    }
+}
+
 /// A VCD scalar value
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Value {
     /// Logic high (prefixed with `V` to make a valid Rust identifier
     V0,
@@ -57,6 +300,21 @@ pub enum Value {
 
     /// The "high-impedance" value
     Z,
+
+    /// Uninitialized, from the IEEE 1164 9-state logic used by VHDL simulators
+    U,
+
+    /// Weak unknown, from the IEEE 1164 9-state logic
+    W,
+
+    /// Weak logic low, from the IEEE 1164 9-state logic
+    L,
+
+    /// Weak logic high, from the IEEE 1164 9-state logic
+    H,
+
+    /// Don't care (`-`), from the IEEE 1164 9-state logic
+    DontCare,
 }
 
 impl Value {
@@ -67,7 +325,39 @@ impl Value {
             b'1' => Ok(V1),
             b'x' | b'X' => Ok(X),
             b'z' | b'Z' => Ok(Z),
-            _ => Err(Error::Parse("Invalid wire value"))
+            b'u' | b'U' => Ok(U),
+            b'w' | b'W' => Ok(W),
+            b'l' | b'L' => Ok(L),
+            b'h' | b'H' => Ok(H),
+            b'-' => Ok(DontCare),
+            _ => Err(Error::Invalid(ErrorKind::InvalidValueChar(v)))
+        }
+    }
+
+    /// The character for the value in a VCD file
+    fn to_byte(self) -> u8 {
+        use Value::*;
+        match self {
+            V0 => b'0',
+            V1 => b'1',
+            X => b'x',
+            Z => b'z',
+            U => b'u',
+            W => b'w',
+            L => b'l',
+            H => b'h',
+            DontCare => b'-',
+        }
+    }
+
+    /// The closest 4-state value: `L` and `H` are `0` and `1`, and `U`, `W` and `-` are `x`
+    pub fn to_four_state(self) -> Value {
+        use Value::*;
+        match self {
+            L => V0,
+            H => V1,
+            U | W | DontCare => X,
+            v => v,
         }
     }
 }
@@ -81,38 +371,134 @@ impl FromStr for Value {
 
 impl Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use Value::*;
-        write!(f, "{}", match *self {
-            V0  => "0",
-            V1 => "1",
-            X => "x",
-            Z => "z",
-        })
+        write!(f, "{}", self.to_byte() as char)
     }
 }
 
-/// A type of scope, as used in the `$scope` command
+/// The direction of a port in an extended VCD file, as seen from the device under test
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PortDirection {
+    /// Driven by the test fixture
+    Input,
+    /// Driven by the device under test
+    Output,
+    /// Driven by both or neither
+    Unknown,
+}
+
+/// The state of one bit of a port in an extended VCD `p` change, one of the characters defined by
+/// IEEE 1364 for the direction and value of the port's drivers
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PortState(u8);
+
+impl PortState {
+    fn parse(v: u8) -> Result<PortState, Error> {
+        match v {
+            b'D' | b'U' | b'N' | b'Z' | b'd' | b'u' |
+            b'L' | b'H' | b'X' | b'T' | b'l' | b'h' |
+            b'0' | b'1' | b'?' | b'F' | b'A' | b'a' | b'B' | b'b' | b'C' | b'c' | b'f' => {
+                Ok(PortState(v))
+            }
+            _ => Err(Error::Invalid(ErrorKind::InvalidValueChar(v)))
+        }
+    }
+
+    /// Which side is driving the port
+    pub fn direction(&self) -> PortDirection {
+        match self.0 {
+            b'D' | b'U' | b'N' | b'Z' | b'd' | b'u' => PortDirection::Input,
+            b'L' | b'H' | b'X' | b'T' | b'l' | b'h' => PortDirection::Output,
+            _ => PortDirection::Unknown,
+        }
+    }
+
+    /// The logic value of the port. Conflicting input and output values are `X`.
+    pub fn value(&self) -> Value {
+        match self.0 {
+            b'D' | b'd' | b'L' | b'l' | b'0' => Value::V0,
+            b'U' | b'u' | b'H' | b'h' | b'1' => Value::V1,
+            b'Z' | b'T' | b'F' | b'f' => Value::Z,
+            _ => Value::X,
+        }
+    }
+
+    /// Whether two or more drivers are active on the same side
+    pub fn multiple_drivers(&self) -> bool {
+        match self.0 {
+            b'd' | b'u' | b'l' | b'h' => true,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for PortState {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            &[c] => PortState::parse(c),
+            _ => Err(Error::Parse("Invalid port value")),
+        }
+    }
+}
+
+impl Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0 as char)
+    }
+}
+
+/// A type of scope, as used in the `$scope` command
+///
+/// Covers the scope types of IEEE 1364 and those added by SystemVerilog. Any other type is read
+/// as `Other` with its keyword.
+///
+/// ```
+/// use vcd::ScopeType;
+/// assert_eq!("interface".parse::<ScopeType>().unwrap(), ScopeType::Interface);
+/// assert_eq!("vhdl_architecture".parse::<ScopeType>().unwrap().to_string(), "vhdl_architecture");
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ScopeType {
     Module,
     Task,
     Function,
     Begin,
     Fork,
+    Interface,
+    Package,
+    Program,
+    Class,
+    Union,
+    Struct,
+
+    /// Any other type, with its keyword
+    Other(String),
 }
 
 impl FromStr for ScopeType {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         use self::ScopeType::*;
-        match s {
-            "module" => Ok(Module),
-            "task" => Ok(Task),
-            "function" => Ok(Function),
-            "begin" => Ok(Begin),
-            "fork" => Ok(Fork),
-            _ => Err(Error::Parse("Invalid scope type"))
-        }
+        Ok(match s {
+            "module" => Module,
+            "task" => Task,
+            "function" => Function,
+            "begin" => Begin,
+            "fork" => Fork,
+            "interface" => Interface,
+            "package" => Package,
+            "program" => Program,
+            "class" => Class,
+            "union" => Union,
+            "struct" => Struct,
+            s if s.is_empty() || s.starts_with('$') || s.contains(char::is_whitespace) => {
+                return Err(Error::Parse("Invalid scope type"));
+            }
+            s => Other(s.to_string()),
+        })
     }
 }
 
@@ -125,42 +511,115 @@ impl Display for ScopeType {
             Function => "function",
             Begin  => "begin",
             Fork  => "fork",
+            Interface => "interface",
+            Package => "package",
+            Program => "program",
+            Class => "class",
+            Union => "union",
+            Struct => "struct",
+            Other(ref s) => s,
         })
     }
 }
 
 /// A type of variable, as used in the `$var` command
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+///
+/// Covers the variable types of IEEE 1364 and those added by SystemVerilog simulators. Any other
+/// type, such as one written by a vendor tool, is read as `Other` with its keyword.
+///
+/// ```
+/// use vcd::VarType;
+/// assert_eq!("tri1".parse::<VarType>().unwrap(), VarType::Tri1);
+/// assert_eq!("xnet".parse::<VarType>().unwrap(), VarType::Other("xnet".to_string()));
+/// assert_eq!(VarType::Shortreal.to_string(), "shortreal");
+/// assert!(VarType::Realtime.is_real());
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum VarType {
-    //Event,
-    //Integer,
-    //Parameter,
+    Event,
+    Integer,
+    Parameter,
+    /// A port of the device under test in an extended VCD file
+    Port,
     Real,
+    Realtime,
     Reg,
-    //Supply0,
-    //Supply1,
-    //Time,
-    //Tri,
-    //Triant,
-    //Trior,
-    //Trireg,
-    //Tri0,
-    //Tri1,
-    //Wand,
+    Supply0,
+    Supply1,
+    Time,
+    Tri,
+    Triand,
+    Trior,
+    Trireg,
+    Tri0,
+    Tri1,
+    Wand,
     Wire,
-    //Wor,
+    Wor,
+
+    /// A SystemVerilog `logic` variable
+    Logic,
+    /// A SystemVerilog `bit` variable
+    Bit,
+    /// A `string` variable, from SystemVerilog and as a GTKWave extension
+    String,
+    /// A SystemVerilog enum variable
+    Enum,
+    /// A SystemVerilog `shortreal` variable
+    Shortreal,
+    /// A SystemVerilog `int` variable
+    Int,
+
+    /// Any other type, with its keyword
+    Other(std::string::String),
+}
+
+impl VarType {
+    /// Returns true for the types whose values are written as `r` changes
+    pub fn is_real(&self) -> bool {
+        match *self {
+            VarType::Real | VarType::Realtime | VarType::Shortreal => true,
+            _ => false,
+        }
+    }
 }
 
 impl FromStr for VarType {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         use self::VarType::*;
-        match s {
-            "wire" => Ok(Wire),
-            "reg" => Ok(Reg),
-            "real" => Ok(Real),
-            _ => Err(Error::Parse("Invalid var type"))
-        }
+        Ok(match s {
+            "event" => Event,
+            "integer" => Integer,
+            "parameter" => Parameter,
+            "port" => Port,
+            "real" => Real,
+            "realtime" => Realtime,
+            "reg" => Reg,
+            "supply0" => Supply0,
+            "supply1" => Supply1,
+            "time" => Time,
+            "tri" => Tri,
+            "triand" => Triand,
+            "trior" => Trior,
+            "trireg" => Trireg,
+            "tri0" => Tri0,
+            "tri1" => Tri1,
+            "wand" => Wand,
+            "wire" => Wire,
+            "wor" => Wor,
+            "logic" => Logic,
+            "bit" => Bit,
+            "string" => String,
+            "enum" => Enum,
+            "shortreal" => Shortreal,
+            "int" => Int,
+            s if s.is_empty() || s.starts_with('$') || s.contains(char::is_whitespace) => {
+                return Err(Error::Parse("Invalid var type"));
+            }
+            s => Other(s.to_string()),
+        })
     }
 }
 
@@ -168,24 +627,78 @@ impl Display for VarType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::VarType::*;
         write!(f, "{}", match *self {
-            Wire => "wire",
-            Reg => "reg",
+            Event => "event",
+            Integer => "integer",
+            Parameter => "parameter",
+            Port => "port",
             Real => "real",
+            Realtime => "realtime",
+            Reg => "reg",
+            Supply0 => "supply0",
+            Supply1 => "supply1",
+            Time => "time",
+            Tri => "tri",
+            Triand => "triand",
+            Trior => "trior",
+            Trireg => "trireg",
+            Tri0 => "tri0",
+            Tri1 => "tri1",
+            Wand => "wand",
+            Wire => "wire",
+            Wor => "wor",
+            Logic => "logic",
+            Bit => "bit",
+            String => "string",
+            Enum => "enum",
+            Shortreal => "shortreal",
+            Int => "int",
+            Other(ref s) => s,
         })
     }
 }
 
 /// An ID used within the file to refer to a particular variable.
+///
+/// In the file, an ID is a sequence of printable ASCII characters from `!` to `~`, read as a
+/// number in base 94 with the least significant digit first, so `!` is 0, `~` is 93 and `!!` is
+/// 94. `FromStr` and `Display` convert between the two, and any code of up to 19 characters can be
+/// read and written.
+///
+/// ```
+/// use vcd::IdCode;
+/// assert_eq!(IdCode::from(93).to_string(), "~");
+/// assert_eq!(IdCode::from(94).to_string(), "!!");
+/// assert_eq!("a!".parse::<IdCode>().unwrap().to_string(), "a!");
+/// for i in (0..1u64 << 32).step_by(9973) {
+///     let id = IdCode::from(i as u32);
+///     assert_eq!(id.to_string().parse::<IdCode>().unwrap(), id);
+/// }
+/// assert!("a b".parse::<IdCode>().is_err());
+/// ```
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-pub struct IdCode(u32);
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IdCode(u128);
+
+const ID_CHAR_MIN: u8 = b'!';
+const ID_CHAR_MAX: u8 = b'~';
+const NUM_ID_CHARS: u128 = (ID_CHAR_MAX - ID_CHAR_MIN + 1) as u128;
 
 impl IdCode {
     fn new(v: &[u8]) -> Result<IdCode, Error> {
-        let mut result = 0u32;
-        for &i in v {
-            result = result << 7 | ((i - b'!') as u32);
+        if v.is_empty() { return Err(Error::Invalid(ErrorKind::InvalidIdCode)); }
+        // Each digit is one more than its value, so that codes with trailing `!`s are distinct
+        let mut result = 0u128;
+        for &i in v.iter().rev() {
+            if i < ID_CHAR_MIN || i > ID_CHAR_MAX {
+                return Err(Error::Invalid(ErrorKind::InvalidIdCode));
+            }
+            result = match result.checked_mul(NUM_ID_CHARS)
+                .and_then(|r| r.checked_add((i - ID_CHAR_MIN) as u128 + 1)) {
+                Some(r) => r,
+                None => return Err(Error::Invalid(ErrorKind::InvalidIdCode)),
+            };
         }
-        Ok(IdCode(result))
+        Ok(IdCode(result - 1))
     }
 }
 
@@ -197,16 +710,16 @@ impl FromStr for IdCode {
 }
 
 impl From<u32> for IdCode {
-    fn from(i: u32) -> IdCode { IdCode(i) }
+    fn from(i: u32) -> IdCode { IdCode(i as u128) }
 }
 
 impl Display for IdCode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut i = self.0;
         loop {
-            try!(write!(f, "{}", ((i&0x7f) as u8 + b'!') as char));
-            i = i >> 7;
-            if i == 0 { break; }
+            try!(write!(f, "{}", ((i % NUM_ID_CHARS) as u8 + ID_CHAR_MIN) as char));
+            if i < NUM_ID_CHARS { break; }
+            i = i / NUM_ID_CHARS - 1;
         }
         Ok(())
     }
@@ -214,29 +727,163 @@ impl Display for IdCode {
 
 /// Information on a VCD scope as represented by a `$scope` command and its children
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Scope {
     pub scope_type: ScopeType,
     pub identifier: String,
     pub children: Vec<ScopeItem>
 }
 
+impl Scope {
+    /// Find a scope below this one by the identifiers of the scopes on the way to it
+    pub fn find_scope<S: AsRef<str>>(&self, path: &[S]) -> Option<&Scope> {
+        match path.split_first() {
+            None => Some(self),
+            Some((first, rest)) => self.children.iter().filter_map(|item| match *item {
+                ScopeItem::Scope(ref s) if s.identifier == first.as_ref() => s.find_scope(rest),
+                _ => None,
+            }).next(),
+        }
+    }
+
+    /// Find a variable below this scope by the identifiers of the scopes on the way to it,
+    /// followed by its reference
+    pub fn find_var<S: AsRef<str>>(&self, path: &[S]) -> Option<&Var> {
+        let (reference, scopes) = match path.split_last() {
+            Some(p) => p,
+            None => return None,
+        };
+        self.find_scope(scopes).and_then(|s| s.children.iter().filter_map(|item| match *item {
+            ScopeItem::Var(ref v) if v.reference == reference.as_ref() => Some(v),
+            _ => None,
+        }).next())
+    }
+
+    /// Iterate over the variables in this scope and all scopes below it, in the order they are
+    /// declared, with their paths from this scope. A path starts with the identifier of this scope
+    /// and ends with the reference of the variable.
+    ///
+    /// ```
+    /// let buf = b"$scope module top $end $var wire 1 ! clk $end $scope module cpu $end
+    ///     $var wire 8 \" pc $end $upscope $end $upscope $end $enddefinitions $end\n";
+    /// let header = vcd::Parser::new(&buf[..]).parse_header().unwrap();
+    /// let paths: Vec<String> = header.scope.iter_vars().map(|(p, _)| p.join(".")).collect();
+    /// assert_eq!(paths, vec!["top.clk", "top.cpu.pc"]);
+    /// ```
+    pub fn iter_vars(&self) -> Vars {
+        Vars {
+            root: [].iter(),
+            scopes: [].iter(),
+            path: vec![&self.identifier[..]],
+            stack: vec![self.children.iter()],
+        }
+    }
+}
+
+/// Iterator over the variables in a scope and its descendants, from `Scope::iter_vars`
+pub struct Vars<'a> {
+    root: Iter<'a, Var>,
+    /// Further top-level scopes, iterated after the first
+    scopes: Iter<'a, Scope>,
+    path: Vec<&'a str>,
+    stack: Vec<Iter<'a, ScopeItem>>,
+}
+
+impl<'a> Iterator for Vars<'a> {
+    type Item = (Vec<&'a str>, &'a Var);
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(v) = self.root.next() {
+            return Some((vec![&v.reference[..]], v));
+        }
+        loop {
+            let item = match self.stack.last_mut() {
+                Some(items) => items.next(),
+                None => match self.scopes.next() {
+                    Some(s) => {
+                        self.path.push(&s.identifier);
+                        self.stack.push(s.children.iter());
+                        continue;
+                    }
+                    None => return None,
+                },
+            };
+            match item {
+                Some(&ScopeItem::Var(ref v)) => {
+                    let mut path = self.path.clone();
+                    path.push(&v.reference);
+                    return Some((path, v));
+                }
+                Some(&ScopeItem::Scope(ref s)) => {
+                    self.path.push(&s.identifier);
+                    self.stack.push(s.children.iter());
+                }
+                None => {
+                    self.stack.pop();
+                    self.path.pop();
+                }
+            }
+        }
+    }
+}
+
 impl Default for Scope {
     fn default() -> Scope {
         Scope { scope_type: ScopeType::Module, identifier: "".to_string(), children: Vec::new() }
     }
 }
 
+/// The bit or range of bits given after the reference in a `$var` command, as in `data [7:0]`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ReferenceIndex {
+    /// A single bit, as in `[3]`
+    BitSelect(i32),
+
+    /// A range of bits from the most to the least significant, as in `[7:0]`
+    Range(i32, i32),
+}
+
+impl FromStr for ReferenceIndex {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with('[') || !s.ends_with(']') || s.len() < 2 {
+            return Err(Error::Parse("Invalid reference index"));
+        }
+        let mut parts = s[1..s.len() - 1].splitn(2, ':');
+        let msb = try!(parts.next().unwrap().trim().parse());
+        Ok(match parts.next() {
+            Some(lsb) => ReferenceIndex::Range(msb, try!(lsb.trim().parse())),
+            None => ReferenceIndex::BitSelect(msb),
+        })
+    }
+}
+
+impl Display for ReferenceIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ReferenceIndex::BitSelect(i) => write!(f, "[{}]", i),
+            ReferenceIndex::Range(msb, lsb) => write!(f, "[{}:{}]", msb, lsb),
+        }
+    }
+}
+
 /// Information on a VCD variable as represented by a `$var` command.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Var {
     pub var_type: VarType,
     pub size: u32,
     pub code: IdCode,
     pub reference: String,
+
+    /// The bit index or range following the reference, if any. An index written without a space
+    /// after the reference, as in `data[7:0]`, is part of the reference instead.
+    pub index: Option<ReferenceIndex>,
 }
 
 /// An item in a scope -- either a child scope or a variable
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ScopeItem {
     Scope(Scope),
     Var(Var),
@@ -244,8 +891,10 @@ pub enum ScopeItem {
 
 /// An element in a VCD file
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Command {
-    /// A `$comment` command
+    /// A `$comment` command. Comments may appear among the value changes in the data section as
+    /// well as in the header, and are returned where they appear.
     Comment(String),
 
     /// A `$date` command
@@ -264,19 +913,36 @@ pub enum Command {
     Upscope,
 
     /// A `$var` command
-    VarDef(VarType, u32, IdCode, String),
+    VarDef(VarType, u32, IdCode, String, Option<ReferenceIndex>),
 
     /// An `$enddefinitions` command
     Enddefinitions,
 
-    /// A `#xxx` timestamp
+    /// An `$attrbegin` command, a GTKWave extension attaching metadata such as an enum
+    /// translation table to the following definitions, with the text of its arguments
+    Attribute(String),
+
+    /// An `$attrend` command, ending the definitions an `$attrbegin` applies to
+    AttributeEnd,
+
+    /// A command with an unrecognized keyword, with the keyword and the text up to its `$end`,
+    /// returned by a parser that is not strict
+    Unknown(String, String),
+
+    /// A `#xxx` timestamp, in units of the `$timescale`. At a timescale of 1 fs a `u64` covers
+    /// just over five hours; a larger timescale covers proportionally longer.
     Timestamp(u64),
 
+    /// A `#xxx` timestamp too large for a `u64`, returned only by a parser with
+    /// `set_wide_timestamps` on. The adapters in this crate that follow the time, such as
+    /// `Waveform`, only follow `Timestamp`.
+    WideTimestamp(u128),
+
     /// A `0a` change to a scalar variable
     ChangeScalar(IdCode, Value),
 
     /// A `b0000 a` change to a vector variable
-    ChangeVector(IdCode, Vec<Value>),
+    ChangeVector(IdCode, BitVector),
 
     /// A `r1.234 a` change to a real variable
     ChangeReal(IdCode, f64),
@@ -284,6 +950,10 @@ pub enum Command {
     /// A `sSTART a` change to a (real?) variable
     ChangeString(IdCode, String),
 
+    /// A `pUD 0 6 <0` change to a port in an extended VCD file, with the state of each bit and the
+    /// strengths of the `0` and `1` components, from 0 (high impedance) to 7 (supply)
+    ChangePort(IdCode, Vec<PortState>, u8, u8),
+
     /// A beginning of a simulation command. Unlike header commands, which are parsed atomically,
     /// simulation commands emit a Begin, followed by the data changes within them, followed by
     /// End.
@@ -293,13 +963,181 @@ pub enum Command {
     End(SimulationCommand)
 }
 
+impl Command {
+    /// If this is one of the `Change*` commands other than `ChangePort`, return the variable it
+    /// changes and its new value
+    pub fn change_value(&self) -> Option<(IdCode, VarValue)> {
+        use self::Command::*;
+        match *self {
+            ChangeScalar(i, v) => Some((i, VarValue::Scalar(v))),
+            ChangeVector(i, ref v) => Some((i, VarValue::Vector(v.clone()))),
+            ChangeReal(i, v) => Some((i, VarValue::Real(v))),
+            ChangeString(i, ref v) => Some((i, VarValue::String(v.clone()))),
+            _ => None,
+        }
+    }
+}
+
+/// A `Command` whose strings and values borrow from the parser that returned it, from
+/// `Parser::next_event` or `SliceParser::next_event`
+#[derive(Debug, PartialEq, Clone)]
+pub enum CommandRef<'a> {
+    Comment(&'a str),
+    Date(&'a str),
+    Version(&'a str),
+    Timescale(u32, TimescaleUnit),
+    ScopeDef(ScopeType, &'a str),
+    Upscope,
+    VarDef(VarType, u32, IdCode, &'a str, Option<ReferenceIndex>),
+    Enddefinitions,
+    Attribute(&'a str),
+    AttributeEnd,
+    Unknown(&'a str, &'a str),
+    Timestamp(u64),
+    WideTimestamp(u128),
+    ChangeScalar(IdCode, Value),
+    ChangeVector(IdCode, &'a BitVector),
+    ChangeReal(IdCode, f64),
+    ChangeString(IdCode, &'a str),
+    ChangePort(IdCode, &'a [PortState], u8, u8),
+    Begin(SimulationCommand),
+    End(SimulationCommand),
+}
+
+impl<'a> CommandRef<'a> {
+    /// Copy the borrowed data into an owned `Command`
+    pub fn to_command(&self) -> Command {
+        use self::CommandRef::*;
+        match *self {
+            Comment(s) => Command::Comment(s.to_string()),
+            Date(s) => Command::Date(s.to_string()),
+            Version(s) => Command::Version(s.to_string()),
+            Timescale(n, unit) => Command::Timescale(n, unit),
+            ScopeDef(ref t, s) => Command::ScopeDef(t.clone(), s.to_string()),
+            Upscope => Command::Upscope,
+            VarDef(ref t, size, code, s, index) => {
+                Command::VarDef(t.clone(), size, code, s.to_string(), index)
+            }
+            Enddefinitions => Command::Enddefinitions,
+            Attribute(s) => Command::Attribute(s.to_string()),
+            AttributeEnd => Command::AttributeEnd,
+            Unknown(k, s) => Command::Unknown(k.to_string(), s.to_string()),
+            Timestamp(t) => Command::Timestamp(t),
+            WideTimestamp(t) => Command::WideTimestamp(t),
+            ChangeScalar(i, v) => Command::ChangeScalar(i, v),
+            ChangeVector(i, v) => Command::ChangeVector(i, v.clone()),
+            ChangeReal(i, v) => Command::ChangeReal(i, v),
+            ChangeString(i, s) => Command::ChangeString(i, s.to_string()),
+            ChangePort(i, v, s0, s1) => Command::ChangePort(i, v.to_vec(), s0, s1),
+            Begin(c) => Command::Begin(c),
+            End(c) => Command::End(c),
+        }
+    }
+}
+
+/// The value of a variable, as set by one of the `Change*` commands
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum VarValue {
+    Scalar(Value),
+    Vector(BitVector),
+    Real(f64),
+    String(String),
+}
+
+impl VarValue {
+    /// Create the `Change*` command that sets variable `id` to this value
+    pub fn to_command(&self, id: IdCode) -> Command {
+        match *self {
+            VarValue::Scalar(v) => Command::ChangeScalar(id, v),
+            VarValue::Vector(ref v) => Command::ChangeVector(id, v.clone()),
+            VarValue::Real(v) => Command::ChangeReal(id, v),
+            VarValue::String(ref v) => Command::ChangeString(id, v.clone()),
+        }
+    }
+
+    /// Format the value as text, showing scalar and vector values in base `radix`.
+    ///
+    /// Hex digits made entirely of `x` or `z` bits are shown as `x` or `z`, and digits with only
+    /// some unknown bits as `X` or `Z`. A decimal value with any unknown bits is shown as `x`.
+    pub fn format(&self, radix: Radix) -> String {
+        match *self {
+            VarValue::Scalar(v) => v.to_string(),
+            VarValue::Vector(ref v) => format_vector(&v.to_values(), radix),
+            VarValue::Real(v) => v.to_string(),
+            VarValue::String(ref v) => v.clone(),
+        }
+    }
+}
+
+/// A number base used to display vector values
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Radix {
+    Binary,
+    Decimal,
+    Hex,
+}
+
+fn format_vector(v: &[Value], radix: Radix) -> String {
+    match radix {
+        Radix::Binary => v.iter().map(|b| b.to_string()).collect(),
+        Radix::Hex => {
+            let mut digits = Vec::new();
+            for nibble in v.rchunks(4) {
+                let mut n = 0;
+                let (mut x, mut z) = (0, 0);
+                for (i, &b) in nibble.iter().rev().enumerate() {
+                    match b.to_four_state() {
+                        Value::V1 => n |= 1 << i,
+                        Value::X => x += 1,
+                        Value::Z => z += 1,
+                        _ => (),
+                    }
+                }
+                digits.push(match (x, z) {
+                    (0, 0) => ::std::char::from_digit(n, 16).unwrap(),
+                    (x, _) if x == nibble.len() => 'x',
+                    (_, z) if z == nibble.len() => 'z',
+                    (0, _) => 'Z',
+                    _ => 'X',
+                });
+            }
+            digits.iter().rev().collect()
+        }
+        Radix::Decimal => {
+            // Little-endian base 10 digits, doubled and incremented for each bit
+            let mut digits = vec![0u8];
+            for &b in v {
+                let bit = match b.to_four_state() {
+                    Value::V0 => 0,
+                    Value::V1 => 1,
+                    _ => return "x".to_string(),
+                };
+                let mut carry = bit;
+                for d in digits.iter_mut() {
+                    let n = *d * 2 + carry;
+                    *d = n % 10;
+                    carry = n / 10;
+                }
+                if carry > 0 { digits.push(carry); }
+            }
+            digits.iter().rev().map(|&d| (b'0' + d) as char).collect()
+        }
+    }
+}
+
 /// A simulation command type, used in Command::Begin and Command::End
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SimulationCommand {
     Dumpall,
     Dumpoff,
     Dumpon,
     Dumpvars,
+    Dumpports,
+    Dumpportsall,
+    Dumpportsoff,
+    Dumpportson,
 }
 
 impl Display for SimulationCommand {
@@ -310,16 +1148,126 @@ impl Display for SimulationCommand {
             Dumpoff  => "dumpoff",
             Dumpon   => "dumpon",
             Dumpvars => "dumpvars",
+            Dumpports => "dumpports",
+            Dumpportsall => "dumpportsall",
+            Dumpportsoff => "dumpportsoff",
+            Dumpportson => "dumpportson",
         })
     }
 }
 
 /// Structure containing the data from the header of a VCD file
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Header {
+    /// The text of the `$comment` commands in the header, joined by newlines if there are several
     pub comment: Option<String>,
     pub date: Option<String>,
     pub version: Option<String>,
     pub timescale: Option<(u32, TimescaleUnit)>,
+    /// Variables declared before the top scope, outside any scope, as some tools write them
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub vars: Vec<Var>,
+    /// The first top-level scope. A later `$scope` at the top level with the same identifier and
+    /// type adds to its children.
     pub scope: Scope,
+    /// The top-level scopes after the first with other identifiers, such as the `glbl` module
+    /// that Xilinx simulators write beside the design
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub other_scopes: Vec<Scope>,
+    /// Commands outside the top scope with unrecognized keywords, as `(keyword, text)`, which are
+    /// only accepted when the parser is not strict
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unknown: Vec<(String, String)>,
+}
+
+impl Header {
+    /// Write the header as VCD declarations, ending with `$enddefinitions`, as for
+    /// `Writer::header`
+    ///
+    /// ```
+    /// let buf = b"$version sim $end $scope module top $end $var wire 1 ! clk $end $upscope $end
+    ///     $enddefinitions $end\n";
+    /// let mut header = vcd::Parser::new(&buf[..]).parse_header().unwrap();
+    /// header.scope.identifier = "tb".to_string();
+    /// let mut out = Vec::new();
+    /// header.write_to(&mut out).unwrap();
+    /// let rewritten = vcd::Parser::new(&out[..]).parse_header().unwrap();
+    /// assert_eq!(rewritten.version.as_ref().unwrap(), "sim");
+    /// assert!(rewritten.find_var(&["tb", "clk"]).is_some());
+    /// ```
+    pub fn write_to(&self, w: &mut io::Write) -> io::Result<()> {
+        Writer::new(w).header(self)
+    }
+
+    /// Iterate over all variables with their paths, as for `Scope::iter_vars` on each top-level
+    /// scope, starting with the variables declared outside any scope, whose paths are only their
+    /// references
+    ///
+    /// ```
+    /// let buf = b"$var wire 1 ! reset $end $scope module top $end $var wire 1 \" clk $end
+    ///     $upscope $end $scope module glbl $end $var wire 1 # GSR $end $upscope $end
+    ///     $enddefinitions $end\n";
+    /// let header = vcd::Parser::new(&buf[..]).parse_header().unwrap();
+    /// let paths: Vec<String> = header.iter_vars().map(|(p, _)| p.join(".")).collect();
+    /// assert_eq!(paths, ["reset", "top.clk", "glbl.GSR"]);
+    /// ```
+    pub fn iter_vars(&self) -> Vars {
+        Vars { root: self.vars.iter(), scopes: self.other_scopes.iter(), ..self.scope.iter_vars() }
+    }
+
+    /// The top-level scopes: `scope` followed by `other_scopes`
+    pub fn top_scopes(&self) -> Chain<option::IntoIter<&Scope>, Iter<Scope>> {
+        Some(&self.scope).into_iter().chain(self.other_scopes.iter())
+    }
+
+    /// All variables declared with the code `id`, in the order they are declared. Several
+    /// variables share a code when they are aliases for the same net. This searches the whole
+    /// header; `Waveform::vars_for_id` looks them up in an index instead.
+    ///
+    /// ```
+    /// let buf = b"$scope module top $end $var wire 1 ! clk $end $scope module cpu $end
+    ///     $var wire 1 ! clk_in $end $upscope $end $upscope $end $enddefinitions $end\n";
+    /// let header = vcd::Parser::new(&buf[..]).parse_header().unwrap();
+    /// let names: Vec<&str> = header.vars_for_id(vcd::IdCode::from(0))
+    ///     .iter().map(|v| &v.reference[..]).collect();
+    /// assert_eq!(names, ["clk", "clk_in"]);
+    /// ```
+    pub fn vars_for_id(&self, id: IdCode) -> Vec<&Var> {
+        self.iter_vars().map(|(_, v)| v).filter(|v| v.code == id).collect()
+    }
+
+    /// Find a scope by its path, starting with the identifier of a top-level scope
+    pub fn find_scope<S: AsRef<str>>(&self, path: &[S]) -> Option<&Scope> {
+        match path.split_first() {
+            Some((first, rest)) => self.top_scopes()
+                .filter(|s| first.as_ref() == s.identifier)
+                .filter_map(|s| s.find_scope(rest)).next(),
+            None => None,
+        }
+    }
+
+    /// Find a variable by its path, starting with the identifier of a top-level scope and ending
+    /// with the variable's reference. The path of a variable declared outside any scope is just its
+    /// reference.
+    ///
+    /// ```
+    /// let buf = b"$scope module top $end $scope module cpu $end $var wire 8 ! pc $end
+    ///     $upscope $end $upscope $end $enddefinitions $end\n";
+    /// let header = vcd::Parser::new(&buf[..]).parse_header().unwrap();
+    /// assert_eq!(header.find_var(&["top", "cpu", "pc"]).unwrap().size, 8);
+    /// assert!(header.find_var(&["top", "pc"]).is_none());
+    /// assert_eq!(header.find_scope(&["top", "cpu"]).unwrap().children.len(), 1);
+    /// ```
+    pub fn find_var<S: AsRef<str>>(&self, path: &[S]) -> Option<&Var> {
+        match path.split_first() {
+            Some((reference, rest)) if rest.is_empty() => {
+                self.vars.iter().find(|v| v.reference == reference.as_ref())
+            }
+            Some((first, rest)) => self.top_scopes()
+                .filter(|s| first.as_ref() == s.identifier)
+                .filter_map(|s| s.find_var(rest)).next(),
+            None => None,
+        }
+    }
 }
@@ -0,0 +1,143 @@
+//! Frequency and duty-cycle measurement of periodic signals
+
+use std::ops::Range;
+
+use {
+    Value,
+    IdCode,
+    Header,
+    Command,
+    Error,
+};
+
+/// Results of measuring a periodic signal with `measure`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    /// Number of complete periods (rising edge to rising edge) observed in the window
+    pub periods: usize,
+
+    /// Mean period, in seconds
+    pub period: f64,
+
+    /// Mean frequency, in Hz
+    pub frequency: f64,
+
+    /// Fraction of each period spent high, averaged over all periods
+    pub duty_cycle: f64,
+
+    /// RMS deviation of the individual periods from the mean period, in seconds
+    pub jitter_rms: f64,
+
+    /// Difference between the longest and shortest period, in seconds
+    pub jitter_pp: f64,
+}
+
+/// Measure the frequency, duty cycle and period jitter of the 1-bit variable `id` over the
+/// timestamps in `window`.
+///
+/// The timestamps are converted to seconds using the `$timescale` in `header`, or 1s if the header
+/// has none. Edges to or from `x` or `z` are ignored, and a period containing one is skipped.
+/// Returns `None` if fewer than two rising edges were seen.
+pub fn measure<I>(header: &Header, commands: I, id: IdCode, window: Range<u64>)
+    -> Result<Option<Measurement>, Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+
+    let tick = match header.timescale {
+        Some((v, unit)) => v as f64 / unit.divisor() as f64,
+        None => 1.0,
+    };
+
+    let mut time = 0;
+    let mut level = Value::X;
+    let mut rise: Option<u64> = None;
+    let mut fall: Option<u64> = None;
+    let mut periods = Vec::new();
+    let mut high = 0u64;
+
+    for c in commands {
+        let value = match try!(c) {
+            Command::Timestamp(t) => {
+                if t >= window.end { break; }
+                time = t;
+                continue;
+            }
+            Command::ChangeScalar(i, v) if i == id => v,
+            Command::ChangeVector(i, ref v) if i == id && v.len() == 1 => v[0],
+            _ => continue,
+        };
+
+        if time < window.start {
+            level = value;
+            continue;
+        }
+
+        match (level, value) {
+            (Value::V0, Value::V1) => {
+                if let (Some(r), Some(f)) = (rise, fall) {
+                    periods.push(time - r);
+                    high += f - r;
+                }
+                rise = Some(time);
+                fall = None;
+            }
+            (Value::V1, Value::V0) => {
+                if rise.is_some() { fall = Some(time); }
+            }
+            (a, b) if a != b => {
+                rise = None;
+                fall = None;
+            }
+            _ => (),
+        }
+        level = value;
+    }
+
+    if periods.is_empty() {
+        return Ok(None);
+    }
+
+    let total: u64 = periods.iter().sum();
+    let mean = total as f64 / periods.len() as f64;
+    let variance = periods.iter().map(|&p| (p as f64 - mean).powi(2)).sum::<f64>() / periods.len() as f64;
+    let min = *periods.iter().min().unwrap();
+    let max = *periods.iter().max().unwrap();
+
+    Ok(Some(Measurement {
+        periods: periods.len(),
+        period: mean * tick,
+        frequency: 1.0 / (mean * tick),
+        duty_cycle: high as f64 / total as f64,
+        jitter_rms: variance.sqrt() * tick,
+        jitter_pp: (max - min) as f64 * tick,
+    }))
+}
+
+#[test]
+fn pwm() {
+    use super::Parser;
+
+    let sample = b"
+    $timescale 10 us $end
+    $scope module top $end
+    $var wire 1 ! pwm $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0!
+    #10 1! #13 0!
+    #20 1! #23 0!
+    #31 1! #34 0!
+    #40 1! #43 0!
+    #50 x!
+    #60 1! #63 0!
+    #70 1!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let m = measure(&header, p, IdCode::from(0), 0..55).unwrap().unwrap();
+
+    assert_eq!(m.periods, 3);
+    assert!((m.period - 10e-5).abs() < 1e-12);
+    assert!((m.duty_cycle - 0.3).abs() < 1e-9);
+    assert!((m.jitter_pp - 2e-5).abs() < 1e-12);
+}
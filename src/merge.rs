@@ -0,0 +1,418 @@
+//! Combining several waveforms into one, and splitting one into several
+
+use std::io;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use {
+    Timescale,
+    IdCode,
+    Scope,
+    ScopeItem,
+    Header,
+    Command,
+    Error,
+    ErrorKind,
+    Writer,
+};
+
+/// One waveform being merged
+struct Input<I> {
+    commands: I,
+    /// New `IdCode` for each of the input's codes
+    ids: HashMap<IdCode, IdCode>,
+    /// Number of output time units per input time unit
+    factor: u128,
+    /// Time of the input's next commands, or `None` once it has ended
+    next: Option<u64>,
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// The longest timescale that each of `timescales` is a whole number of steps of, in the finest of
+/// their units
+fn common_timescale<T: Iterator<Item=Timescale> + Clone>(timescales: T) -> Option<Timescale> {
+    let unit = match timescales.clone().map(|ts| ts.unit).max_by_key(|u| u.divisor()) {
+        Some(u) => u,
+        None => return None,
+    };
+    let step = timescales.fold(0, |g, ts| gcd(g, ts.femtoseconds()));
+    Some(Timescale::new((step / Timescale::new(1, unit).femtoseconds()) as u32, unit))
+}
+
+/// Give the variables in `scope` new `IdCode`s, numbered from `next`
+fn renumber(scope: &mut Scope, ids: &mut HashMap<IdCode, IdCode>, next: &mut u32) {
+    for item in &mut scope.children {
+        match *item {
+            ScopeItem::Var(ref mut v) => {
+                v.code = *ids.entry(v.code).or_insert_with(|| {
+                    *next += 1;
+                    IdCode::from(*next - 1)
+                });
+            }
+            ScopeItem::Scope(ref mut s) => renumber(s, ids, next),
+        }
+    }
+}
+
+/// Iterator that interleaves the data sections of several waveforms in time order, producing the
+/// data section for a combined header.
+///
+/// The combined header has a top scope with each input's top scope inside it, renamed as given.
+/// The variables an input declares outside any scope, and its other top-level scopes, are moved
+/// into its renamed scope.
+/// Variables are given new `IdCode`s so that the inputs cannot clash. The output timescale is the
+/// longest one that each input's timescale is a whole number of, such as `100 ps` for inputs in
+/// `1 ns` and `100 ps` or `1 ns` for inputs in `2 ns` and `3 ns`, so times are scaled exactly.
+/// Inputs without a `$timescale` are assumed to use the output timescale. A scaled time too large
+/// for a `u64` is an `ErrorKind::TimestampOverflow` error.
+///
+/// The combined header's `$date` and `$version` are the first given by any input, and its
+/// `$comment` joins the comments of all the inputs, one per line. Unrecognised header commands are
+/// kept from every input.
+///
+/// Commands at the same time are grouped under one timestamp, in the order the inputs were given.
+pub struct Merge<I> {
+    header: Header,
+    inputs: Vec<Input<I>>,
+    queue: VecDeque<Command>,
+}
+
+impl<I> Merge<I> where I: Iterator<Item=Result<Command, Error>> {
+    /// Combine `inputs`, each given as its header, its data section and the name of its scope in
+    /// the output. The output's top scope is named `top`.
+    pub fn new<C>(inputs: Vec<(Header, C, String)>, top: &str) -> Merge<I>
+        where C: IntoIterator<Item=Result<Command, Error>, IntoIter=I> {
+        let timescale = common_timescale(inputs.iter().filter_map(|i| i.0.timescale)
+            .map(Timescale::from));
+        let comments: Vec<&str> = inputs.iter().filter_map(|i| i.0.comment.as_ref())
+            .map(|c| &c[..]).collect();
+
+        let mut header = Header {
+            comment: if comments.is_empty() { None } else { Some(comments.join("\n")) },
+            date: inputs.iter().filter_map(|i| i.0.date.clone()).next(),
+            version: inputs.iter().filter_map(|i| i.0.version.clone()).next(),
+            timescale: timescale.map(Timescale::into),
+            scope: Scope { identifier: top.to_string(), ..Default::default() },
+            ..Default::default()
+        };
+        let mut merged = Vec::new();
+        let mut next_id = 0;
+        for (h, commands, name) in inputs {
+            let mut ids = HashMap::new();
+            let mut scope = h.scope;
+            scope.identifier = name;
+            let vars = h.vars.into_iter().map(ScopeItem::Var);
+            scope.children = vars.chain(scope.children)
+                .chain(h.other_scopes.into_iter().map(ScopeItem::Scope)).collect();
+            renumber(&mut scope, &mut ids, &mut next_id);
+            header.scope.children.push(ScopeItem::Scope(scope));
+            header.unknown.extend(h.unknown);
+
+            let factor = match (h.timescale, timescale) {
+                (Some(ts), Some(out)) => {
+                    Timescale::from(ts).femtoseconds() / out.femtoseconds()
+                }
+                _ => 1,
+            };
+            merged.push(Input { commands: commands.into_iter(), ids: ids, factor: factor,
+                next: Some(0) });
+        }
+
+        Merge { header: header, inputs: merged, queue: VecDeque::new() }
+    }
+
+    /// The combined header
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Queue the commands from every input at the earliest pending time
+    fn step(&mut self) -> Result<bool, Error> {
+        let time = match self.inputs.iter().filter_map(|i| i.next).min() {
+            Some(t) => t,
+            None => return Ok(false),
+        };
+
+        let mut wrote_time = false;
+        for input in &mut self.inputs {
+            if input.next != Some(time) { continue; }
+            input.next = None;
+            for c in &mut input.commands {
+                let c = match try!(c) {
+                    Command::Timestamp(t) => {
+                        let t = try!((t as u128).checked_mul(input.factor)
+                            .filter(|&t| t <= u64::max_value() as u128)
+                            .ok_or(Error::Invalid(ErrorKind::TimestampOverflow))) as u64;
+                        if t == time { continue; }
+                        input.next = Some(t);
+                        break;
+                    }
+                    c => c,
+                };
+                let c = match c.change_value() {
+                    Some((id, v)) => match input.ids.get(&id) {
+                        Some(&id) => v.to_command(id),
+                        None => continue,
+                    },
+                    None => c,
+                };
+                if !wrote_time {
+                    self.queue.push_back(Command::Timestamp(time));
+                    wrote_time = true;
+                }
+                self.queue.push_back(c);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<I> Iterator for Merge<I> where I: Iterator<Item=Result<Command, Error>> {
+    type Item = Result<Command, Error>;
+
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        while self.queue.is_empty() {
+            match self.step() {
+                Ok(true) => (),
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.queue.pop_front().map(Ok)
+    }
+}
+
+/// Write the combination of `inputs` to `w`, as described for `Merge`
+pub fn merge<I>(inputs: Vec<(Header, I, String)>, top: &str, w: &mut io::Write)
+    -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let merge = Merge::new(inputs, top);
+    let mut writer = Writer::new(w);
+    try!(writer.header(merge.header()));
+    for c in merge {
+        try!(writer.command(&try!(c)));
+    }
+    Ok(())
+}
+
+/// One output of `split`
+struct Part<'w> {
+    writer: Writer<'w>,
+    ids: HashSet<IdCode>,
+    timestamp: Option<Command>,
+    begin: Option<Command>,
+}
+
+impl<'w> Part<'w> {
+    /// Write `c` if it applies to this part, along with any timestamp or simulation command
+    /// held back until a change to one of the part's variables
+    fn push(&mut self, c: &Command) -> Result<(), Error> {
+        match *c {
+            Command::Timestamp(_) => self.timestamp = Some(c.clone()),
+            Command::Begin(_) => self.begin = Some(c.clone()),
+            Command::End(_) if self.begin.take().is_some() => (),
+            _ => {
+                if c.change_value().map_or(true, |(id, _)| self.ids.contains(&id)) {
+                    if let Some(t) = self.timestamp.take() { try!(self.writer.command(&t)); }
+                    if let Some(b) = self.begin.take() { try!(self.writer.command(&b)); }
+                    try!(self.writer.command(c));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write each of the scopes in `header` at the paths in `outputs` to the matching writer, as a
+/// standalone VCD with that scope as its top scope. This is the reverse of `merge`.
+///
+/// Paths start with the identifier of the top scope, as for `Header::find_scope`. Each output
+/// keeps the `IdCode`s of the input and gets only the changes to its own variables, with
+/// timestamps and simulation commands that contain none of them dropped.
+pub fn split<I>(header: &Header, commands: I, outputs: Vec<(&[&str], &mut io::Write)>)
+    -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let mut parts = Vec::new();
+    for (path, w) in outputs {
+        let scope = match header.find_scope(path) {
+            Some(s) => s.clone(),
+            None => return Err(Error::Parse("Scope not found in header")),
+        };
+        let ids = scope.iter_vars().map(|(_, v)| v.code).collect();
+        let mut writer = Writer::new(w);
+        try!(writer.header(&Header {
+            comment: header.comment.clone(),
+            date: header.date.clone(),
+            version: header.version.clone(),
+            timescale: header.timescale,
+            vars: Vec::new(),
+            scope: scope,
+            other_scopes: Vec::new(),
+            unknown: header.unknown.clone(),
+        }));
+        parts.push(Part { writer: writer, ids: ids, timestamp: None, begin: None });
+    }
+
+    for c in commands {
+        let c = try!(c);
+        for part in &mut parts {
+            try!(part.push(&c));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn interleave() {
+    use super::{Parser, TimescaleUnit};
+    use super::Value::*;
+    use super::Command::*;
+
+    let tb = b"
+    $timescale 1 ns $end
+    $scope module tb $end
+    $var wire 1 ! clk $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0!
+    #5 1!
+    #10 0!
+    ";
+    let fw = b"
+    $timescale 100 ps $end
+    $scope module cpu $end
+    $var wire 8 ! pc $end
+    $upscope $end
+    $enddefinitions $end
+    #0 b0 !
+    #50 b1 !
+    #75 b10 !
+    ";
+
+    let mut inputs = Vec::new();
+    for &(data, name) in &[(&tb[..], "tb"), (&fw[..], "fw")] {
+        let mut p = Parser::new(data);
+        let header = p.parse_header().unwrap();
+        inputs.push((header, p, name.to_string()));
+    }
+    let merge = Merge::new(inputs, "top");
+    assert_eq!(merge.header().timescale, Some((100, TimescaleUnit::PS)));
+    match merge.header().scope.children[1] {
+        ScopeItem::Scope(ref s) => assert_eq!(s.identifier, "fw"),
+        _ => panic!(),
+    }
+
+    let cmds: Vec<Command> = merge.map(Result::unwrap).collect();
+    assert_eq!(cmds, vec![
+        Timestamp(0),
+        ChangeScalar(IdCode(0), V0),
+        ChangeVector(IdCode(1), vec![V0].into()),
+        Timestamp(50),
+        ChangeScalar(IdCode(0), V1),
+        ChangeVector(IdCode(1), vec![V1].into()),
+        Timestamp(75),
+        ChangeVector(IdCode(1), vec![V1, V0].into()),
+        Timestamp(100),
+        ChangeScalar(IdCode(0), V0),
+    ]);
+}
+
+#[test]
+fn split_merged() {
+    use super::{Parser, Value};
+
+    let sample = b"$scope module top $end
+$scope module tb $end $var wire 1 ! clk $end $upscope $end
+$scope module fw $end $var wire 8 \" pc $end $upscope $end
+$upscope $end $enddefinitions $end
+$dumpvars 0! b0 \" $end
+#5 1!
+#10 b1 \"
+";
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let (mut tb, mut fw) = (Vec::new(), Vec::new());
+    split(&header, p, vec![(&["top", "tb"][..], &mut tb), (&["top", "fw"][..], &mut fw)])
+        .unwrap();
+
+    let mut p = Parser::new(&tb[..]);
+    assert_eq!(p.parse_header().unwrap().scope.identifier, "tb");
+    assert_eq!(p.map(Result::unwrap).collect::<Vec<_>>(), vec![
+        Command::Begin(::SimulationCommand::Dumpvars),
+        Command::ChangeScalar(IdCode(0), Value::V0),
+        Command::End(::SimulationCommand::Dumpvars),
+        Command::Timestamp(5),
+        Command::ChangeScalar(IdCode(0), Value::V1),
+    ]);
+    let mut p = Parser::new(&fw[..]);
+    assert_eq!(p.parse_header().unwrap().find_var(&["fw", "pc"]).map(|v| v.size), Some(8));
+    assert_eq!(p.filter(|c| c.as_ref().unwrap().change_value().is_some()).count(), 2);
+}
+
+#[test]
+fn top_level_vars() {
+    use super::Parser;
+    use super::Value::*;
+    use super::Command::*;
+
+    let sample = b"
+    $var wire 1 ! reset $end
+    $scope module cpu $end
+    $var wire 1 \" clk $end
+    $upscope $end
+    $scope module glbl $end
+    $var wire 1 # GSR $end
+    $upscope $end
+    $enddefinitions $end
+    #0 1! 0\" 0#
+    #5 0!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let merge = Merge::new(vec![(header, p, "fw".to_string())], "top");
+    let paths: Vec<String> = merge.header().iter_vars().map(|(p, _)| p.join(".")).collect();
+    assert_eq!(paths, ["top.fw.reset", "top.fw.clk", "top.fw.glbl.GSR"]);
+    let reset = merge.header().find_var(&["top", "fw", "reset"]).unwrap().code;
+
+    let cmds: Vec<Command> = merge.map(Result::unwrap).collect();
+    assert_eq!(cmds.iter().filter(|c| c.change_value().map(|(id, _)| id) == Some(reset))
+        .cloned().collect::<Vec<_>>(),
+        vec![ChangeScalar(reset, V1), ChangeScalar(reset, V0)]);
+}
+
+#[test]
+fn common_timescales() {
+    use super::{Parser, TimescaleUnit};
+    use super::Command::*;
+
+    let input = |ts: &str, comment: &str, data: &str| {
+        let sample = format!("$timescale {} $end $comment {} $end $version v1 $end
+            $scope module m $end $var wire 1 ! a $end $upscope $end $enddefinitions $end
+            {}\n", ts, comment, data);
+        let mut p = Parser::new(::std::io::Cursor::new(sample.into_bytes()));
+        let header = p.parse_header().unwrap();
+        (header, p, ts.replace(' ', "_"))
+    };
+
+    let merge = Merge::new(vec![input("2 ns", "a", "#3 1!"), input("3 ns", "b", "#2 0!")], "top");
+    assert_eq!(merge.header().timescale, Some((1, TimescaleUnit::NS)));
+    assert_eq!(merge.header().comment.as_ref().map(|c| &c[..]), Some("a\nb"));
+    assert_eq!(merge.header().version.as_ref().map(|v| &v[..]), Some("v1"));
+    let times: Vec<u64> = merge.filter_map(|c| match c.unwrap() {
+        Timestamp(t) => Some(t),
+        _ => None,
+    }).collect();
+    assert_eq!(times, [6]);
+
+    let merge = Merge::new(vec![input("1 s", "a", "#18446744073709551615 1!"),
+        input("1 fs", "b", "#1 0!")], "top");
+    assert_eq!(merge.header().timescale, Some((1, TimescaleUnit::FS)));
+    let result: Result<Vec<Command>, Error> = merge.collect();
+    match result {
+        Err(Error::Invalid(ErrorKind::TimestampOverflow)) => (),
+        r => panic!("{:?}", r),
+    }
+}
@@ -0,0 +1,55 @@
+//! Node.js bindings using N-API (requires the `node` feature)
+//!
+//! Built with `napi build --features node`, the addon exports a `VcdParser` class and an
+//! `extractWindow` function. Headers and changes are returned as plain objects in the formats used
+//! by `export::json`. Times are JavaScript numbers, so are exact only up to 2^53.
+
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+use serde_json::Value as Json;
+
+use Error;
+use js::{self, Batches};
+
+impl From<Error> for napi::Error {
+    fn from(err: Error) -> napi::Error {
+        napi::Error::from_reason(err.to_string())
+    }
+}
+
+/// A VCD parser over a complete file held in a `Buffer`, returning its contents in batches
+#[napi]
+pub struct VcdParser {
+    batches: Batches,
+}
+
+#[napi]
+impl VcdParser {
+    #[napi(constructor)]
+    pub fn new(data: Buffer) -> VcdParser {
+        VcdParser { batches: Batches::new(data.to_vec()) }
+    }
+
+    /// Parse the header, returning it as an object. Must be called before `nextChanges`.
+    #[napi]
+    pub fn parse_header(&mut self) -> napi::Result<Json> {
+        Ok(try!(self.batches.parse_header()))
+    }
+
+    /// Return up to `max` value changes as an array of `{time, id, value}` objects, which is empty
+    /// once the file has been read to the end.
+    #[napi]
+    pub fn next_changes(&mut self, max: u32) -> napi::Result<Vec<Json>> {
+        Ok(try!(self.batches.next_changes(max as usize)))
+    }
+}
+
+/// Extract the part of the VCD file in `data` between times `start` and `end` inclusive, as a
+/// standalone VCD file starting with the value of every signal at `start`.
+#[napi]
+pub fn extract_window(data: Buffer, start: i64, end: i64) -> napi::Result<String> {
+    if start < 0 || end < start {
+        return Err(napi::Error::from_reason("invalid time range"));
+    }
+    Ok(try!(js::extract(&data, start as u64, end as u64)))
+}
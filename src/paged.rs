@@ -0,0 +1,435 @@
+//! Loading a waveform within a memory budget, spilling changes to a temporary file
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::fs::{self, File, OpenOptions};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::mem;
+
+use {
+    IdCode,
+    Value,
+    VarValue,
+    Header,
+    Command,
+    Error,
+    WaveformSource,
+};
+
+/// Counter making the names of spill files unique within the process
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Approximate number of bytes of memory used by each buffered change besides its heap data
+const CHANGE_OVERHEAD: usize = 40;
+
+/// The value changes of every variable in a waveform, holding no more than a given number of
+/// bytes of changes in memory.
+///
+/// While loading, whenever the changes held in memory exceed the budget, those of the variable
+/// with the most are appended to a temporary file as a page and dropped from memory. The
+/// variables given as hot are never written out, so looking them up stays fast. Looking up a
+/// variable that was written out reads its pages back, which is why the lookups return a
+/// `Result`. The file is deleted when the `PagedWaveform` is dropped.
+///
+/// A `PagedWaveform` is `Send` and `Sync`: lookups take `&self` and share the spill file through
+/// a lock, so threads can read one behind an `Arc` without copying it.
+///
+/// ```
+/// use vcd::{Parser, Value, VarValue, PagedWaveform};
+///
+/// let data = b"$scope module top $end $var wire 1 ! clk $end $var wire 8 \" data $end
+/// $upscope $end $enddefinitions $end #0 0! b0 \" #5 1! b1010 \" #10 0!
+/// ";
+/// let clk = vcd::IdCode::from(0);
+/// let wave = PagedWaveform::load(Parser::new(&data[..]), 0, &[clk]).unwrap();
+/// assert!(!wave.is_spilled(clk));
+/// assert!(wave.is_spilled(vcd::IdCode::from(1)));
+/// assert_eq!(wave.value_at(clk, 7).unwrap(), Some(VarValue::Scalar(Value::V1)));
+/// assert_eq!(wave.changes(vcd::IdCode::from(1)).unwrap().len(), 2);
+/// ```
+pub struct PagedWaveform {
+    header: Header,
+    signals: Vec<Signal>,
+    index: HashMap<IdCode, usize>,
+    spill: Option<Spill>,
+    end_time: u64,
+}
+
+/// The changes to one variable: pages in the spill file, followed by those still in memory
+struct Signal {
+    hot: bool,
+    /// `(first time, offset, length)` of each page, in time order
+    pages: Vec<(u64, u64, usize)>,
+    changes: Vec<(u64, VarValue)>,
+    /// Approximate memory used by `changes`
+    bytes: usize,
+}
+
+struct Spill {
+    path: PathBuf,
+    file: Mutex<File>,
+    len: u64,
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl PagedWaveform {
+    /// Read the header and data section of `source`, keeping up to about `budget` bytes of
+    /// changes in memory. The changes to the variables in `hot` stay in memory whatever the
+    /// budget.
+    ///
+    /// Changes to undeclared variables are ignored, and only the last of several changes to a
+    /// variable at the same time is kept.
+    pub fn load<S: WaveformSource>(mut source: S, budget: usize, hot: &[IdCode])
+        -> Result<PagedWaveform, Error> {
+        let header = try!(source.parse_header());
+        let hot: HashSet<IdCode> = hot.iter().cloned().collect();
+        let mut wave = PagedWaveform {
+            header: header,
+            signals: Vec::new(),
+            index: HashMap::new(),
+            spill: None,
+            end_time: 0,
+        };
+        let codes: Vec<IdCode> = wave.header.iter_vars().map(|(_, v)| v.code).collect();
+        for code in codes {
+            if !wave.index.contains_key(&code) {
+                wave.index.insert(code, wave.signals.len());
+                wave.signals.push(Signal {
+                    hot: hot.contains(&code),
+                    pages: Vec::new(),
+                    changes: Vec::new(),
+                    bytes: 0,
+                });
+            }
+        }
+
+        let mut time = 0;
+        let mut used = 0;
+        for c in source {
+            let c = try!(c);
+            if let Command::Timestamp(t) = c {
+                time = t;
+                continue;
+            }
+            let (id, value) = match c.change_value() {
+                Some(change) => change,
+                None => continue,
+            };
+            let i = match wave.index.get(&id) {
+                Some(&i) => i,
+                None => continue,
+            };
+            {
+                let signal = &mut wave.signals[i];
+                if signal.changes.last().map_or(false, |&(t, _)| t == time) {
+                    let (_, old) = signal.changes.pop().unwrap();
+                    signal.bytes -= change_size(&old);
+                    used -= change_size(&old);
+                }
+                signal.bytes += change_size(&value);
+                used += change_size(&value);
+                signal.changes.push((time, value));
+            }
+            while used > budget {
+                match try!(wave.spill_largest()) {
+                    0 => break,
+                    n => used -= n,
+                }
+            }
+        }
+        wave.end_time = time;
+        Ok(wave)
+    }
+
+    /// Write the in-memory changes of the variable with the most to the spill file, returning
+    /// the number of bytes freed, or 0 if every variable with changes in memory is hot
+    fn spill_largest(&mut self) -> Result<usize, Error> {
+        let i = match (0..self.signals.len()).filter(|&i| !self.signals[i].hot)
+            .max_by_key(|&i| self.signals[i].bytes) {
+            Some(i) if self.signals[i].bytes > 0 => i,
+            _ => return Ok(0),
+        };
+        if self.spill.is_none() {
+            self.spill = Some(try!(Spill::create()));
+        }
+        let spill = self.spill.as_mut().unwrap();
+        let signal = &mut self.signals[i];
+
+        let mut page = Vec::new();
+        for &(t, ref v) in &signal.changes {
+            encode_change(t, v, &mut page);
+        }
+        try!(try!(spill.file.get_mut().map_err(|_| poisoned())).write_all(&page));
+        signal.pages.push((signal.changes[0].0, spill.len, page.len()));
+        spill.len += page.len() as u64;
+        signal.changes = Vec::new();
+        Ok(mem::replace(&mut signal.bytes, 0))
+    }
+
+    /// The header of the waveform
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The time of the last timestamp
+    pub fn end_time(&self) -> u64 {
+        self.end_time
+    }
+
+    /// Whether any changes to the variable `id` were written to the spill file
+    pub fn is_spilled(&self, id: IdCode) -> bool {
+        self.index.get(&id).map_or(false, |&i| !self.signals[i].pages.is_empty())
+    }
+
+    /// All changes to the variable `id` as `(time, value)` in time order, or none if there is no
+    /// such variable
+    pub fn changes(&self, id: IdCode) -> Result<Vec<(u64, VarValue)>, Error> {
+        let signal = match self.index.get(&id) {
+            Some(&i) => &self.signals[i],
+            None => return Ok(Vec::new()),
+        };
+        let mut changes = Vec::new();
+        for page in &signal.pages {
+            try!(self.read_page(page, &mut changes));
+        }
+        for &(t, ref v) in &signal.changes {
+            push_change(&mut changes, t, v.clone());
+        }
+        Ok(changes)
+    }
+
+    /// The value of the variable `id` at `time`, which is its last change at or before `time`,
+    /// or `None` if it had not yet been given a value. At most one page is read.
+    pub fn value_at(&self, id: IdCode, time: u64) -> Result<Option<VarValue>, Error> {
+        let signal = match self.index.get(&id) {
+            Some(&i) => &self.signals[i],
+            None => return Ok(None),
+        };
+        let last = |changes: &[(u64, VarValue)]| {
+            changes.iter().take_while(|&&(t, _)| t <= time).last().map(|&(_, ref v)| v.clone())
+        };
+        if signal.changes.first().map_or(false, |&(t, _)| t <= time) {
+            return Ok(last(&signal.changes));
+        }
+        let n = signal.pages.iter().take_while(|&&(t, _, _)| t <= time).count();
+        if n == 0 {
+            return Ok(None);
+        }
+        let mut changes = Vec::new();
+        try!(self.read_page(&signal.pages[n - 1], &mut changes));
+        Ok(last(&changes))
+    }
+
+    fn read_page(&self, &(_, offset, len): &(u64, u64, usize), out: &mut Vec<(u64, VarValue)>)
+        -> Result<(), Error> {
+        let spill = self.spill.as_ref().expect("pages are only written to a spill file");
+        let mut page = vec![0; len];
+        {
+            let mut file = try!(spill.file.lock().map_err(|_| poisoned()));
+            try!(file.seek(SeekFrom::Start(offset)));
+            try!(file.read_exact(&mut page));
+        }
+        let mut rest = &page[..];
+        while !rest.is_empty() {
+            let (t, v) = try!(decode_change(&mut rest));
+            push_change(out, t, v);
+        }
+        Ok(())
+    }
+}
+
+impl Spill {
+    fn create() -> Result<Spill, Error> {
+        let n = SPILL_FILES.fetch_add(1, Ordering::SeqCst);
+        let path = ::std::env::temp_dir()
+            .join(format!("vcd-spill-{}-{}.bin", ::std::process::id(), n));
+        let file = try!(OpenOptions::new().read(true).write(true).create_new(true).open(&path));
+        Ok(Spill { path: path, file: Mutex::new(file), len: 0 })
+    }
+}
+
+fn poisoned() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::Other, "spill file lock poisoned"))
+}
+
+/// Append a change, replacing the last one if it is at the same time, as happens where a page
+/// ends and the next begins
+fn push_change(changes: &mut Vec<(u64, VarValue)>, t: u64, v: VarValue) {
+    if changes.last().map_or(false, |&(last, _)| last == t) {
+        changes.pop();
+    }
+    changes.push((t, v));
+}
+
+fn change_size(v: &VarValue) -> usize {
+    CHANGE_OVERHEAD + match *v {
+        VarValue::Vector(ref v) => v.len() / 4,
+        VarValue::String(ref s) => s.len(),
+        VarValue::Scalar(_) | VarValue::Real(_) => 0,
+    }
+}
+
+// A change is written as its time, a tag byte for the type of value, and the value: one value
+// character, a length and value characters, the bits of a real, or a length and UTF-8 bytes
+
+fn encode_change(t: u64, v: &VarValue, out: &mut Vec<u8>) {
+    out.extend_from_slice(&t.to_le_bytes());
+    match *v {
+        VarValue::Scalar(v) => {
+            out.push(0);
+            out.push(v.to_byte());
+        }
+        VarValue::Vector(ref v) => {
+            out.push(1);
+            out.extend_from_slice(&(v.len() as u64).to_le_bytes());
+            out.extend(v.iter().map(|v| v.to_byte()));
+        }
+        VarValue::Real(r) => {
+            out.push(2);
+            out.extend_from_slice(&r.to_bits().to_le_bytes());
+        }
+        VarValue::String(ref s) => {
+            out.push(3);
+            out.extend_from_slice(&(s.len() as u64).to_le_bytes());
+            out.extend_from_slice(s.as_bytes());
+        }
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
+    if data.len() < n {
+        return Err(Error::Parse("Truncated page in spill file"));
+    }
+    let (head, rest) = data.split_at(n);
+    *data = rest;
+    Ok(head)
+}
+
+fn take_u64(data: &mut &[u8]) -> Result<u64, Error> {
+    let mut b = [0; 8];
+    b.copy_from_slice(try!(take(data, 8)));
+    Ok(u64::from_le_bytes(b))
+}
+
+fn decode_change(data: &mut &[u8]) -> Result<(u64, VarValue), Error> {
+    let t = try!(take_u64(data));
+    let v = match try!(take(data, 1))[0] {
+        0 => VarValue::Scalar(try!(Value::parse(try!(take(data, 1))[0]))),
+        1 => {
+            let n = try!(take_u64(data)) as usize;
+            let bits = try!(take(data, n));
+            VarValue::Vector(try!(bits.iter().map(|&b| Value::parse(b)).collect()))
+        }
+        2 => VarValue::Real(f64::from_bits(try!(take_u64(data)))),
+        3 => {
+            let n = try!(take_u64(data)) as usize;
+            VarValue::String(try!(String::from_utf8(try!(take(data, n)).to_vec())))
+        }
+        _ => return Err(Error::Parse("Invalid value tag in spill file")),
+    };
+    Ok((t, v))
+}
+
+#[test]
+fn spill() {
+    use Parser;
+
+    let mut sample = String::from("$scope module top $end $var wire 1 ! clk $end
+$var wire 4 \" data $end $var real 64 # temp $end $var wire 1 $ msg $end
+$upscope $end $enddefinitions $end
+");
+    for t in 0..200 {
+        sample.push_str(&format!("#{} {}! b{:b} \" r{}.5 # s{} $\n", t * 5, t % 2, t % 16, t, t));
+        if t % 7 == 0 {
+            // A second change at the same time replaces the first, even across pages
+            sample.push_str(&format!("b{:b} \"\n", (t + 1) % 16));
+        }
+    }
+
+    // The changes to each variable, kept in memory
+    let mut expected: HashMap<IdCode, Vec<(u64, VarValue)>> = HashMap::new();
+    let mut time = 0;
+    for c in Parser::new(sample.as_bytes()) {
+        let c = c.unwrap();
+        if let Command::Timestamp(t) = c {
+            time = t;
+        } else if let Some((id, v)) = c.change_value() {
+            push_change(expected.entry(id).or_insert_with(Vec::new), time, v);
+        }
+    }
+    let expected_at = |id: IdCode, t: u64| {
+        expected[&id].iter().take_while(|&&(ct, _)| ct <= t).last().map(|&(_, ref v)| v.clone())
+    };
+    let clk = IdCode::from(0);
+
+    for &budget in &[0, 1000, 10000, ::std::usize::MAX] {
+        let wave = PagedWaveform::load(Parser::new(sample.as_bytes()), budget, &[clk]).unwrap();
+        assert_eq!(wave.end_time(), 995);
+        assert!(!wave.is_spilled(clk));
+        assert_eq!(wave.is_spilled(IdCode::from(1)), budget < ::std::usize::MAX);
+        assert_eq!(wave.spill.is_some(), budget < ::std::usize::MAX);
+        for id in (0..4).map(IdCode::from) {
+            assert_eq!(wave.changes(id).unwrap(), expected[&id]);
+            for &t in &[0, 2, 35, 500, 997, 2000] {
+                assert_eq!(wave.value_at(id, t).unwrap(), expected_at(id, t));
+            }
+        }
+        assert!(wave.changes(IdCode::from(4)).unwrap().is_empty());
+        assert_eq!(wave.value_at(IdCode::from(4), 10).unwrap(), None);
+    }
+
+    let path = {
+        let wave = PagedWaveform::load(Parser::new(sample.as_bytes()), 0, &[]).unwrap();
+        let path = wave.spill.as_ref().unwrap().path.clone();
+        assert!(path.exists());
+        path
+    };
+    assert!(!path.exists());
+}
+
+#[test]
+fn send_sync() {
+    use std::sync::Arc;
+    use std::thread;
+    use Parser;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<PagedWaveform>();
+    assert_send_sync::<Parser<&[u8]>>();
+    assert_send_sync::<Parser<File>>();
+
+    let sample = b"$scope module top $end $var wire 1 ! clk $end $upscope $end
+$enddefinitions $end #0 0! #5 1!
+";
+    let wave = Arc::new(PagedWaveform::load(Parser::new(&sample[..]), 0, &[]).unwrap());
+    assert!(wave.is_spilled(IdCode::from(0)));
+    let readers: Vec<_> = (0..2).map(|_| {
+        let wave = wave.clone();
+        thread::spawn(move || wave.changes(IdCode::from(0)).unwrap().len())
+    }).collect();
+    for r in readers {
+        assert_eq!(r.join().unwrap(), 2);
+    }
+}
+
+#[test]
+fn top_level_vars() {
+    use Parser;
+
+    let sample = b"$var wire 1 ! reset $end
+$scope module top $end $var wire 1 \" clk $end $upscope $end
+$scope module glbl $end $var wire 1 # GSR $end $upscope $end
+$enddefinitions $end #0 1! 0\" 0# #5 0!
+";
+    let wave = PagedWaveform::load(Parser::new(&sample[..]), 0, &[]).unwrap();
+    assert_eq!(wave.changes(IdCode::from(0)).unwrap(),
+        vec![(0, VarValue::Scalar(Value::V1)), (5, VarValue::Scalar(Value::V0))]);
+    assert_eq!(wave.value_at(IdCode::from(2), 5).unwrap(), Some(VarValue::Scalar(Value::V0)));
+}
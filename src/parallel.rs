@@ -0,0 +1,128 @@
+//! Parsing the data section of a VCD held in memory on several threads (requires the `rayon`
+//! feature)
+//!
+//! The data section is split into chunks at lines starting with a timestamp, which are parsed in
+//! parallel by `SliceParser`s. This assumes that such a line always starts a new command, which
+//! is not the case if a `$comment` or other multi-line command contains a line starting with
+//! `#` and a number. A file like that must be parsed sequentially.
+
+use rayon::prelude::*;
+
+use {
+    Header,
+    Command,
+    Error,
+    Position,
+    SliceParser,
+};
+
+/// Whether a chunk of the data section can start at `data[i]`, the start of a line
+fn timestamp_line(data: &[u8], i: usize) -> bool {
+    if data.get(i) != Some(&b'#') { return false; }
+    let digits = data[i + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+    digits > 0 && data.get(i + 1 + digits).map_or(true, |&b| b.is_ascii_whitespace())
+}
+
+/// A chunk of the data section, with its offset and line number in the whole input
+struct Chunk<'a> {
+    data: &'a [u8],
+    offset: usize,
+    line: u64,
+}
+
+impl<'a> Chunk<'a> {
+    /// Convert a position in the chunk to a position in the whole input
+    fn locate(&self, err: Error) -> Error {
+        match err {
+            Error::Syntax { kind, position } => Error::Syntax {
+                kind: kind,
+                position: Position {
+                    line: position.line + self.line - 1,
+                    column: position.column,
+                    offset: position.offset + self.offset as u64,
+                },
+            },
+            err => err,
+        }
+    }
+
+    fn parse(&self) -> Result<Vec<Command>, Error> {
+        SliceParser::new(self.data).collect::<Result<Vec<_>, _>>().map_err(|e| self.locate(e))
+    }
+}
+
+/// Parse the header of `data`, then split the data section into at most `n` chunks of similar
+/// size, each but the first starting at a line with a timestamp
+fn split(data: &[u8], n: usize) -> Result<(Header, Vec<Chunk>), Error> {
+    let mut parser = SliceParser::new(data);
+    let header = try!(parser.parse_header());
+    let start = parser.bytes_read() as usize;
+    let size = (data.len() - start) / n.max(1) + 1;
+
+    let mut chunks = Vec::new();
+    let (mut offset, mut line) = (start, parser.current_position().line);
+    while offset < data.len() {
+        let mut end = (offset + size).min(data.len());
+        while end < data.len() && !(data[end - 1] == b'\n' && timestamp_line(data, end)) {
+            end += 1;
+        }
+        let chunk = &data[offset..end];
+        chunks.push(Chunk { data: chunk, offset: offset, line: line });
+        line += chunk.iter().filter(|&&b| b == b'\n').count() as u64;
+        offset = end;
+    }
+    Ok((header, chunks))
+}
+
+/// Parse `data` into its header and the commands of its data section, in order, parsing the data
+/// section on the threads of the current rayon pool
+///
+/// ```
+/// let data = b"$scope module top $end $var wire 1 ! clk $end $upscope $end
+/// $enddefinitions $end
+/// #0 0!
+/// #5 1!
+/// ";
+/// let (header, commands) = vcd::parallel::parse(&data[..]).unwrap();
+/// assert_eq!(header.scope.identifier, "top");
+/// assert_eq!(commands.len(), 4);
+/// ```
+pub fn parse(data: &[u8]) -> Result<(Header, Vec<Command>), Error> {
+    let (header, chunks) = try!(split(data, rayon::current_num_threads() * 4));
+    let parsed: Vec<Vec<Command>> = try!(chunks.par_iter().map(Chunk::parse).collect());
+    Ok((header, parsed.into_iter().flat_map(|c| c).collect()))
+}
+
+#[test]
+fn chunks_in_order() {
+    use super::Parser;
+
+    let mut sample = b"$scope module top $end $var wire 8 # count $end $var wire 1 ! clk $end
+$upscope $end $enddefinitions $end
+".to_vec();
+    for t in 0..1000 {
+        sample.extend(format!("#{}\n{}!\nb{:b} #\n", t * 5, t % 2, t % 256).bytes());
+    }
+
+    let mut parser = Parser::new(&sample[..]);
+    parser.parse_header().unwrap();
+    let expected: Vec<Command> = parser.map(Result::unwrap).collect();
+
+    let (_, chunks) = split(&sample, 7).unwrap();
+    assert_eq!(chunks.len(), 7);
+    assert!(chunks[1..].iter().all(|c| timestamp_line(c.data, 0)));
+    assert_eq!(parse(&sample).unwrap().1, expected);
+
+    let bad = sample.len() - 8;
+    sample[bad] = b'q';
+    let mut sequential = SliceParser::new(&sample);
+    sequential.parse_header().unwrap();
+    let expected = sequential.find(Result::is_err).unwrap().unwrap_err();
+    match (parse(&sample), expected) {
+        (Err(Error::Syntax { position, .. }), Error::Syntax { position: expected, .. }) => {
+            assert_eq!(position, expected);
+            assert_eq!(position.line, 3002);
+        }
+        (other, _) => panic!("{:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,214 @@
+//! Hierarchical names of scopes and variables, formatted as text
+
+use std::fmt;
+use std::str::FromStr;
+
+use Error;
+
+/// How the identifiers of a `VarPath` are joined and escaped as text
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PathStyle {
+    /// Identifiers separated by `.`, as in `top.cpu.pc`. A `.`, `/`, `\`, bracket or whitespace
+    /// character in an identifier is escaped with a `\`.
+    Dot,
+
+    /// Identifiers separated by `/`, as in `top/cpu/pc`, escaped as for `Dot`
+    Slash,
+
+    /// Identifiers separated by `.`, with an identifier containing special characters written as
+    /// a Verilog escaped identifier, which starts with `\` and ends with a space, as GTKWave shows
+    /// it: `top.\cpu[0] .pc`. Whitespace or `\` in an escaped identifier is preceded by a `\`.
+    Gtkwave,
+}
+
+impl PathStyle {
+    fn separator(self) -> char {
+        match self {
+            PathStyle::Dot | PathStyle::Gtkwave => '.',
+            PathStyle::Slash => '/',
+        }
+    }
+}
+
+/// Whether `c` cannot appear unescaped in an identifier in a path
+fn special(c: char) -> bool {
+    match c {
+        '.' | '/' | '\\' | '[' | ']' => true,
+        c => c.is_whitespace(),
+    }
+}
+
+/// The path to a scope or variable: the identifiers of the scopes from the top scope down,
+/// followed by the reference of the variable if it is a variable.
+///
+/// A `VarPath` can be passed to lookup methods such as `Header::find_var` with `parts`. It is
+/// displayed and parsed in `PathStyle::Dot`, which is also how it is serialized with the `serde`
+/// feature.
+///
+/// ```
+/// use vcd::{PathStyle, VarPath};
+///
+/// let path: VarPath = r"top.mem\[3\].q".parse().unwrap();
+/// assert_eq!(path.parts(), &["top", "mem[3]", "q"]);
+/// assert_eq!(path.format(PathStyle::Slash), r"top/mem\[3\]/q");
+/// assert_eq!(path.format(PathStyle::Gtkwave), r"top.\mem[3] .q");
+/// ```
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct VarPath {
+    parts: Vec<String>,
+}
+
+impl VarPath {
+    /// Create a path from its identifiers
+    pub fn new(parts: Vec<String>) -> VarPath {
+        VarPath { parts: parts }
+    }
+
+    /// The identifiers of the path, starting with the top scope
+    pub fn parts(&self) -> &[String] {
+        &self.parts
+    }
+
+    /// Add an identifier to the end of the path
+    pub fn push(&mut self, identifier: &str) {
+        self.parts.push(identifier.to_string());
+    }
+
+    /// The path to the scope containing this scope or variable, or `None` for an empty path
+    pub fn parent(&self) -> Option<VarPath> {
+        self.parts.split_last().map(|(_, rest)| VarPath::new(rest.to_vec()))
+    }
+
+    /// Format the path as text in `style`
+    pub fn format(&self, style: PathStyle) -> String {
+        let mut s = String::new();
+        for (i, part) in self.parts.iter().enumerate() {
+            if i > 0 { s.push(style.separator()); }
+            if style == PathStyle::Gtkwave {
+                if part.is_empty() || part.chars().any(special) {
+                    s.push('\\');
+                    for c in part.chars() {
+                        if c == '\\' || c.is_whitespace() { s.push('\\'); }
+                        s.push(c);
+                    }
+                    s.push(' ');
+                } else {
+                    s.push_str(part);
+                }
+            } else {
+                for c in part.chars() {
+                    if special(c) { s.push('\\'); }
+                    s.push(c);
+                }
+            }
+        }
+        s
+    }
+
+    /// Parse a path formatted in `style`
+    pub fn parse(s: &str, style: PathStyle) -> Result<VarPath, Error> {
+        if s.is_empty() {
+            return Err(Error::Parse("Empty path"));
+        }
+        let separator = style.separator();
+        let mut parts = Vec::new();
+        let mut part = String::new();
+        let mut chars = s.chars();
+        loop {
+            let c = chars.next();
+            match c {
+                None => break,
+                Some(c) if c == separator => parts.push(part.split_off(0)),
+                Some('\\') if style == PathStyle::Gtkwave && part.is_empty() => {
+                    loop {
+                        match chars.next() {
+                            Some(' ') => break,
+                            Some('\\') => match chars.next() {
+                                Some(c) => part.push(c),
+                                None => return Err(Error::Parse("Unterminated escape in path")),
+                            },
+                            Some(c) => part.push(c),
+                            None => return Err(Error::Parse("Unterminated escaped identifier")),
+                        }
+                    }
+                    match chars.next() {
+                        None => break,
+                        Some(c) if c == separator => parts.push(part.split_off(0)),
+                        Some(_) => return Err(Error::Parse("Expected separator in path")),
+                    }
+                }
+                Some('\\') => match chars.next() {
+                    Some(c) => part.push(c),
+                    None => return Err(Error::Parse("Unterminated escape in path")),
+                },
+                Some(c) => part.push(c),
+            }
+        }
+        parts.push(part);
+        Ok(VarPath::new(parts))
+    }
+}
+
+impl<'a> From<Vec<&'a str>> for VarPath {
+    fn from(parts: Vec<&'a str>) -> VarPath {
+        VarPath::new(parts.into_iter().map(str::to_string).collect())
+    }
+}
+
+impl fmt::Display for VarPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(PathStyle::Dot))
+    }
+}
+
+impl FromStr for VarPath {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        VarPath::parse(s, PathStyle::Dot)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for VarPath {
+    fn serialize<S: ::serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for VarPath {
+    fn deserialize<D: ::serde::Deserializer<'de>>(d: D) -> Result<VarPath, D::Error> {
+        let s: String = try!(::serde::Deserialize::deserialize(d));
+        s.parse().map_err(::serde::de::Error::custom)
+    }
+}
+
+#[test]
+fn styles() {
+    use Header;
+
+    let paths = [
+        vec!["top", "cpu", "pc"],
+        vec!["top", "a.b", "c d", r"e\f", "g/h", ""],
+    ];
+    for parts in &paths {
+        let path = VarPath::from(parts.clone());
+        for &style in &[PathStyle::Dot, PathStyle::Slash, PathStyle::Gtkwave] {
+            assert_eq!(VarPath::parse(&path.format(style), style).unwrap(), path);
+        }
+    }
+    assert_eq!(VarPath::from(paths[1].clone()).format(PathStyle::Gtkwave),
+        r"top.\a.b .\c\ d .\e\\f .\g/h .\ ");
+    assert_eq!("top/cpu".parse::<VarPath>().unwrap().parts(), &["top/cpu"]);
+    assert!(VarPath::parse(r"top.\cpu", PathStyle::Gtkwave).is_err());
+    assert!(VarPath::parse(r"top.\cpu x", PathStyle::Gtkwave).is_err());
+    assert!("top\\".parse::<VarPath>().is_err());
+
+    let buf = b"$scope module top $end $scope module gen[3] $end $var wire 8 ! q $end $upscope $end
+$upscope $end $enddefinitions $end
+";
+    let header: Header = ::Parser::new(&buf[..]).parse_header().unwrap();
+    let path: VarPath = r"top.gen\[3\].q".parse().unwrap();
+    assert_eq!(header.find_var(path.parts()).unwrap().size, 8);
+    assert_eq!(path.parent().unwrap().to_string(), r"top.gen\[3\]");
+}
@@ -0,0 +1,397 @@
+//! Python bindings (requires the `python` feature)
+//!
+//! Build the `vcd` extension module with `maturin build --features python`. It provides `Parser`,
+//! `Header`, `Var` and `Waveform` classes, and functions running the exporters and transforms on
+//! files:
+//!
+//! ```python
+//! import vcd
+//! p = vcd.Parser.open("dump.vcd")
+//! header = p.parse_header()
+//! for time, code, value in p:
+//!     ...
+//! wave = vcd.Waveform.load("dump.vcd")
+//! pc = wave.value_at("top.cpu.pc", 1000)
+//! vcd.export_csv("dump.vcd", "dump.csv", signals=["top.clk", "top.count"])
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::collections::HashMap;
+
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyIOError, PyKeyError, PyValueError};
+use pyo3::IntoPyObjectExt;
+
+use {
+    Radix,
+    IdCode,
+    VarValue,
+    Header,
+    Var,
+    Command,
+    Error,
+    Parser,
+    Waveform,
+};
+use export::{self, CsvOptions};
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> PyErr {
+        match err {
+            Error::Io(e) => PyIOError::new_err(e.to_string()),
+            e => PyValueError::new_err(e.to_string()),
+        }
+    }
+}
+
+/// A variable declared in the header
+#[pyclass(name = "Var", frozen)]
+#[derive(Clone)]
+pub struct PyVar {
+    /// Dotted path from a top-level scope, or just the reference of a variable outside any scope
+    #[pyo3(get)]
+    pub path: String,
+
+    /// Identifier code used in value changes
+    #[pyo3(get)]
+    pub code: String,
+
+    /// Width in bits
+    #[pyo3(get)]
+    pub size: u32,
+
+    /// Variable type, as in the `$var` command
+    #[pyo3(get)]
+    pub var_type: String,
+}
+
+#[pymethods]
+impl PyVar {
+    fn __repr__(&self) -> String {
+        format!("Var({:?}, code={:?}, size={}, type={:?})",
+            self.path, self.code, self.size, self.var_type)
+    }
+}
+
+impl<'a> From<&'a (String, Var)> for PyVar {
+    fn from(&(ref path, ref v): &'a (String, Var)) -> PyVar {
+        PyVar {
+            path: path.clone(),
+            code: v.code.to_string(),
+            size: v.size,
+            var_type: v.var_type.to_string(),
+        }
+    }
+}
+
+/// The header of a VCD file, returned by `Parser.parse_header`
+#[pyclass(name = "Header", frozen)]
+pub struct PyHeader {
+    header: Header,
+    vars: Vec<(String, Var)>,
+}
+
+impl PyHeader {
+    fn new(header: Header) -> PyHeader {
+        let vars = header.iter_vars().map(|(p, v)| (p.join("."), v.clone())).collect();
+        PyHeader { header: header, vars: vars }
+    }
+
+    /// Look up the variables at `paths`, or all variables if `None`
+    fn select(&self, paths: Option<Vec<String>>) -> PyResult<Vec<&Var>> {
+        match paths {
+            None => Ok(self.vars.iter().map(|&(_, ref v)| v).collect()),
+            Some(paths) => paths.iter().map(|p| {
+                self.vars.iter().find(|&&(ref path, _)| path == p).map(|&(_, ref v)| v)
+                    .ok_or_else(|| PyKeyError::new_err(format!("no signal {}", p)))
+            }).collect(),
+        }
+    }
+}
+
+#[pymethods]
+impl PyHeader {
+    #[getter]
+    fn date(&self) -> Option<String> { self.header.date.clone() }
+
+    #[getter]
+    fn version(&self) -> Option<String> { self.header.version.clone() }
+
+    #[getter]
+    fn comment(&self) -> Option<String> { self.header.comment.clone() }
+
+    /// The `$timescale` as a `(number, unit)` tuple such as `(1, "ns")`
+    #[getter]
+    fn timescale(&self) -> Option<(u32, String)> {
+        self.header.timescale.map(|(ts, unit)| (ts, unit.to_string()))
+    }
+
+    /// All variables, in declaration order
+    #[getter]
+    fn vars(&self) -> Vec<PyVar> {
+        self.vars.iter().map(PyVar::from).collect()
+    }
+
+    /// Find the variable with dotted path `path`
+    fn find(&self, path: &str) -> Option<PyVar> {
+        self.vars.iter().find(|&&(ref p, _)| p == path).map(PyVar::from)
+    }
+}
+
+/// A VCD parser. After `parse_header`, iterating yields a `(time, code, value)` tuple for each
+/// value change, with scalar and vector values as strings of bits.
+#[pyclass(name = "Parser", unsendable)]
+pub struct PyParser {
+    parser: Parser<Box<io::Read>>,
+    time: u64,
+}
+
+#[pymethods]
+impl PyParser {
+    /// Create a parser for VCD data in a `bytes` object
+    #[new]
+    fn new(data: &[u8]) -> PyParser {
+        PyParser { parser: Parser::new(Box::new(io::Cursor::new(data.to_vec()))), time: 0 }
+    }
+
+    /// Create a parser reading the file at `path`
+    #[staticmethod]
+    fn open(path: &str) -> PyResult<PyParser> {
+        let f = try!(File::open(path));
+        Ok(PyParser { parser: Parser::new(Box::new(BufReader::new(f))), time: 0 })
+    }
+
+    fn parse_header(&mut self) -> PyResult<PyHeader> {
+        Ok(PyHeader::new(try!(self.parser.parse_header())))
+    }
+
+    fn __iter__(slf: PyRefMut<Self>) -> PyRefMut<Self> { slf }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<(u64, String, Py<PyAny>)>> {
+        while let Some(c) = self.parser.next() {
+            let c = try!(c);
+            if let Command::Timestamp(t) = c {
+                self.time = t;
+            } else if let Some((id, v)) = c.change_value() {
+                return Ok(Some((self.time, id.to_string(), try!(value(py, &v)))));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A real value as a `float`, and any other value as a string of bits
+fn value(py: Python, v: &VarValue) -> PyResult<Py<PyAny>> {
+    match *v {
+        VarValue::Real(r) => r.into_py_any(py),
+        ref v => v.format(Radix::Binary).into_py_any(py),
+    }
+}
+
+/// A whole VCD file loaded into memory, for looking up the value of any signal at any time.
+/// Signals are named by their dotted paths, and values are given as by `Parser`.
+#[pyclass(name = "Waveform", frozen)]
+pub struct PyWaveform {
+    wave: Waveform,
+    codes: HashMap<String, IdCode>,
+}
+
+impl PyWaveform {
+    fn new(wave: Waveform) -> PyWaveform {
+        let codes = wave.header().iter_vars().map(|(p, v)| (p.join("."), v.code)).collect();
+        PyWaveform { wave: wave, codes: codes }
+    }
+
+    fn code(&self, path: &str) -> PyResult<IdCode> {
+        self.codes.get(path).cloned()
+            .ok_or_else(|| PyKeyError::new_err(format!("no signal {}", path)))
+    }
+
+    fn changes_list(py: Python, changes: &[(u64, VarValue)]) -> PyResult<Vec<(u64, Py<PyAny>)>> {
+        changes.iter().map(|&(t, ref v)| Ok((t, try!(value(py, v))))).collect()
+    }
+}
+
+#[pymethods]
+impl PyWaveform {
+    /// Load VCD data in a `bytes` object
+    #[new]
+    fn from_bytes(data: &[u8]) -> PyResult<PyWaveform> {
+        Ok(PyWaveform::new(try!(Waveform::load(Parser::new(data)))))
+    }
+
+    /// Load the VCD file at `path`
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<PyWaveform> {
+        let parser = Parser::new(BufReader::new(try!(File::open(path))));
+        Ok(PyWaveform::new(try!(Waveform::load(parser))))
+    }
+
+    /// The time of the last timestamp
+    #[getter]
+    fn end_time(&self) -> u64 {
+        self.wave.end_time()
+    }
+
+    /// The value of the signal at `path` at `time`, or `None` if it had not yet been given one
+    fn value_at(&self, py: Python, path: &str, time: u64) -> PyResult<Option<Py<PyAny>>> {
+        match self.wave.value_at(try!(self.code(path)), time) {
+            Some(v) => Ok(Some(try!(value(py, v)))),
+            None => Ok(None),
+        }
+    }
+
+    /// All changes to the signal at `path` as `(time, value)` tuples in time order
+    fn changes(&self, py: Python, path: &str) -> PyResult<Vec<(u64, Py<PyAny>)>> {
+        PyWaveform::changes_list(py, self.wave.changes(try!(self.code(path))))
+    }
+
+    /// The changes to the signal at `path` at times from `start` up to but not including `end`
+    fn changes_between(&self, py: Python, path: &str, start: u64, end: u64)
+        -> PyResult<Vec<(u64, Py<PyAny>)>> {
+        let id = try!(self.code(path));
+        PyWaveform::changes_list(py, self.wave.changes_between(id, start, end))
+    }
+}
+
+fn open(path: &str) -> PyResult<(PyHeader, Parser<BufReader<File>>)> {
+    let mut parser = Parser::new(BufReader::new(try!(File::open(path))));
+    let header = PyHeader::new(try!(parser.parse_header()));
+    Ok((header, parser))
+}
+
+fn parse_radix(radix: &str) -> PyResult<Radix> {
+    match radix {
+        "bin" => Ok(Radix::Binary),
+        "dec" => Ok(Radix::Decimal),
+        "hex" => Ok(Radix::Hex),
+        _ => Err(PyValueError::new_err("radix must be \"bin\", \"dec\" or \"hex\"")),
+    }
+}
+
+/// Write `signals` (all signals if `None`) from the VCD file `input` to `output` as CSV, with one
+/// row per timestamp or, if set, every `interval` time units
+#[pyfunction]
+#[pyo3(signature = (input, output, signals=None, interval=None, radix="hex"))]
+fn export_csv(input: &str, output: &str, signals: Option<Vec<String>>, interval: Option<u64>,
+    radix: &str) -> PyResult<()> {
+    let (header, parser) = try!(open(input));
+    let paths = signals.unwrap_or_else(|| header.vars.iter().map(|v| v.0.clone()).collect());
+    let radix = try!(parse_radix(radix));
+    let options = CsvOptions { radix: radix, interval: interval, separator: ',' };
+    let mut w = BufWriter::new(try!(File::create(output)));
+    Ok(try!(export::csv(&header.header, parser, &paths, &options, &mut w)))
+}
+
+/// Write `signals` (all signals if `None`) from the VCD file `input` to `output` as a NumPy
+/// `.npy` array sampled every `interval` time units
+#[pyfunction]
+#[pyo3(signature = (input, output, interval, signals=None))]
+fn export_npy(input: &str, output: &str, interval: u64, signals: Option<Vec<String>>)
+    -> PyResult<()> {
+    let (header, parser) = try!(open(input));
+    let vars = try!(header.select(signals));
+    let mut w = BufWriter::new(try!(File::create(output)));
+    Ok(try!(export::npy(&header.header, parser, &vars, interval, &mut w)))
+}
+
+/// Write the part of the VCD file `input` between times `start` and `end` to `output`
+#[pyfunction]
+fn window(input: &str, output: &str, start: u64, end: u64) -> PyResult<()> {
+    let (header, parser) = try!(open(input));
+    let mut w = BufWriter::new(try!(File::create(output)));
+    Ok(try!(::transform::window(&header.header, parser, start, end, &mut w)))
+}
+
+#[pymodule]
+fn vcd(m: &Bound<PyModule>) -> PyResult<()> {
+    try!(m.add_class::<PyParser>());
+    try!(m.add_class::<PyHeader>());
+    try!(m.add_class::<PyVar>());
+    try!(m.add_class::<PyWaveform>());
+    try!(m.add_function(try!(wrap_pyfunction!(self::export_csv, m))));
+    try!(m.add_function(try!(wrap_pyfunction!(self::export_npy, m))));
+    try!(m.add_function(try!(wrap_pyfunction!(self::window, m))));
+    Ok(())
+}
+
+#[test]
+fn parser() {
+    let sample = b"
+    $timescale 1 ns $end
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var real 64 \" temp $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! r20.5 \"
+    #5 1!
+    ";
+
+    Python::initialize();
+    Python::attach(|py| {
+        let mut p = PyParser::new(sample);
+        let header = p.parse_header().unwrap();
+        assert_eq!(header.timescale(), Some((1, "ns".to_string())));
+        assert_eq!(header.find("top.temp").unwrap().code, "\"");
+        assert!(header.select(Some(vec!["top.nope".to_string()])).is_err());
+
+        let (time, code, value) = p.__next__(py).unwrap().unwrap();
+        assert_eq!((time, &code[..]), (0, "!"));
+        assert_eq!(value.extract::<String>(py).unwrap(), "0");
+        let (_, _, value) = p.__next__(py).unwrap().unwrap();
+        assert_eq!(value.extract::<f64>(py).unwrap(), 20.5);
+        assert_eq!(p.__next__(py).unwrap().unwrap().0, 5);
+        assert!(p.__next__(py).unwrap().is_none());
+    });
+}
+
+#[test]
+fn top_level_vars() {
+    let sample = b"$var wire 1 ! reset $end
+$scope module top $end $var wire 1 \" clk $end $upscope $end
+$enddefinitions $end
+";
+
+    Python::initialize();
+    let mut p = PyParser::new(sample);
+    let header = p.parse_header().unwrap();
+    let paths: Vec<String> = header.vars().into_iter().map(|v| v.path).collect();
+    assert_eq!(paths, ["reset", "top.clk"]);
+    assert_eq!(header.find("reset").unwrap().code, "!");
+}
+
+#[test]
+fn waveform() {
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var real 64 \" temp $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! r20.5 \"
+    #5 1!
+    #10 0! r21 \"
+    ";
+
+    Python::initialize();
+    Python::attach(|py| {
+        let wave = PyWaveform::from_bytes(sample).unwrap();
+        assert_eq!(wave.end_time(), 10);
+
+        let at = |path: &str, t: u64| wave.value_at(py, path, t).unwrap()
+            .map(|v| v.to_string());
+        assert_eq!(at("top.clk", 7), Some("1".to_string()));
+        assert_eq!(at("top.temp", 9), Some("20.5".to_string()));
+        assert!(wave.value_at(py, "top.nope", 0).is_err());
+
+        let times = |changes: Vec<(u64, Py<PyAny>)>| -> Vec<u64> {
+            changes.into_iter().map(|c| c.0).collect()
+        };
+        assert_eq!(times(wave.changes(py, "top.clk").unwrap()), [0, 5, 10]);
+        assert_eq!(times(wave.changes_between(py, "top.clk", 5, 10).unwrap()), [5]);
+        let temps = wave.changes(py, "top.temp").unwrap();
+        assert_eq!(temps[1].1.extract::<f64>(py).unwrap(), 21.0);
+    });
+}
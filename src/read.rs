@@ -1,24 +1,165 @@
 use std::io;
 use std::fmt;
+use std::mem;
 use std::error;
 use std::str::{ FromStr, from_utf8 };
 use std::num;
+use std::collections::HashSet;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
 
 use {
     Value,
+    PortState,
     ScopeType,
     Scope,
     Var,
     ScopeItem,
     SimulationCommand,
     Header,
-    Command
+    Command,
+    CommandRef,
+    BitVector,
+    IdCode,
+    VarType,
+    ReferenceIndex,
 };
+use transform::{Window, TakeUntil};
+
+/// A position in the input of a `Parser`
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Position {
+    /// Line number, starting at 1
+    pub line: u64,
+
+    /// Column in bytes, starting at 1
+    pub column: u64,
+
+    /// Number of bytes before the position
+    pub offset: u64,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// The kind of a syntax error found by `Parser`, or of an invalid value
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The input ended in the middle of a command
+    UnexpectedEof,
+
+    /// A byte that cannot begin a command was found where a command was expected. Iterating the
+    /// parser again continues after the byte.
+    UnexpectedCharacter(u8),
+
+    /// A token was longer than the parser allows for its position
+    TokenTooLong,
+
+    /// A command had more arguments than expected before its `$end`
+    ExpectedEnd,
+
+    /// A command ended with `$end` before all of its arguments
+    UnexpectedEnd,
+
+    /// An `$end` was found outside of a simulation command
+    UnmatchedEnd,
+
+    /// A `$` was followed by an unknown keyword, which is given lossily converted to UTF-8
+    InvalidKeyword(String),
+
+    /// A byte that is not a valid bit value in a scalar, vector or port value change
+    InvalidValueChar(u8),
+
+    /// A token that should be a number could not be parsed as one of the expected type
+    InvalidNumber,
+
+    /// Text that is not valid UTF-8
+    InvalidUtf8,
+
+    /// An identifier code that is empty, contains a character outside `!` to `~`, or is too long
+    /// to fit in an `IdCode`
+    InvalidIdCode,
+
+    /// A timestamp was earlier than the one before it, found when checking the order of
+    /// timestamps with `Parser::set_check_time_order`
+    TimestampOutOfOrder { previous: u128, found: u128 },
+
+    /// A timestamp with a `-` sign
+    NegativeTimestamp,
+
+    /// A timestamp too large for a `u64`, or for a `u128` with `Parser::set_wide_timestamps`
+    TimestampOverflow,
+
+    /// Any other error, such as an invalid number or value, described by the message
+    Other(&'static str),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::UnexpectedEof => write!(f, "Unexpected EOF"),
+            ErrorKind::UnexpectedCharacter(b) => write!(f, "Unexpected character {:?}", b as char),
+            ErrorKind::TokenTooLong => write!(f, "Token too long"),
+            ErrorKind::ExpectedEnd => write!(f, "Expected $end"),
+            ErrorKind::UnexpectedEnd => write!(f, "Unexpected $end"),
+            ErrorKind::UnmatchedEnd => write!(f, "Unmatched $end"),
+            ErrorKind::InvalidKeyword(ref k) => write!(f, "Invalid keyword ${}", k),
+            ErrorKind::InvalidValueChar(b) => write!(f, "Invalid value {:?}", b as char),
+            ErrorKind::InvalidNumber => write!(f, "Invalid number"),
+            ErrorKind::InvalidUtf8 => write!(f, "Invalid UTF8"),
+            ErrorKind::InvalidIdCode => write!(f, "Invalid identifier code"),
+            ErrorKind::TimestampOutOfOrder { previous, found } => {
+                write!(f, "Timestamp #{} is earlier than the previous #{}", found, previous)
+            }
+            ErrorKind::NegativeTimestamp => write!(f, "Negative timestamp"),
+            ErrorKind::TimestampOverflow => write!(f, "Timestamp too large"),
+            ErrorKind::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     Io(io::Error),
     Parse(&'static str),
+
+    /// A syntax error found by `Parser`, with the position where it was detected
+    Syntax { kind: ErrorKind, position: Position },
+
+    /// An invalid value, such as from parsing a `Value` or `IdCode` from a string. A parser
+    /// reports these as `Syntax` errors with the position attached.
+    Invalid(ErrorKind),
+}
+
+impl Error {
+    /// The position in the input where the error was detected, if known
+    pub fn position(&self) -> Option<Position> {
+        match *self {
+            Error::Syntax { position, .. } => Some(position),
+            _ => None,
+        }
+    }
+
+    /// The kind of the error, if it is a syntax error or invalid value
+    ///
+    /// ```
+    /// use vcd::ErrorKind;
+    ///
+    /// let mut parser = vcd::Parser::new(&b"#0 b10q1 !\n"[..]);
+    /// let err = parser.nth(1).unwrap().unwrap_err();
+    /// assert_eq!(err.kind(), Some(&ErrorKind::InvalidValueChar(b'q')));
+    /// ```
+    pub fn kind(&self) -> Option<&ErrorKind> {
+        match *self {
+            Error::Syntax { ref kind, .. } | Error::Invalid(ref kind) => Some(kind),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Error {
@@ -26,6 +167,8 @@ impl fmt::Display for Error {
         match *self {
             Error::Io(ref err) => write!(f, "{}", err),
             Error::Parse(ref msg) => write!(f, "{}", msg),
+            Error::Syntax { ref kind, ref position } => write!(f, "{}: {}", position, kind),
+            Error::Invalid(ref kind) => write!(f, "{}", kind),
         }
     }
 }
@@ -34,7 +177,7 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::Io(..) => "VCD IO error",
-            Error::Parse(..) => "VCD parse error",
+            Error::Parse(..) | Error::Syntax { .. } | Error::Invalid(..) => "VCD parse error",
         }
     }
 
@@ -51,32 +194,188 @@ impl From<io::Error> for Error {
 }
 
 impl From<num::ParseIntError> for Error {
-    fn from(_: num::ParseIntError) -> Error { Error::Parse("Invalid number") }
+    fn from(_: num::ParseIntError) -> Error { Error::Invalid(ErrorKind::InvalidNumber) }
 }
 
 impl From<num::ParseFloatError> for Error {
-    fn from(_: num::ParseFloatError) -> Error { Error::Parse("Invalid number") }
+    fn from(_: num::ParseFloatError) -> Error { Error::Invalid(ErrorKind::InvalidNumber) }
 }
 
 impl From<::std::str::Utf8Error> for Error {
-    fn from(_: ::std::str::Utf8Error) -> Error { Error::Parse("Invalid UTF8") }
+    fn from(_: ::std::str::Utf8Error) -> Error { Error::Invalid(ErrorKind::InvalidUtf8) }
 }
 
 impl From<::std::string::FromUtf8Error> for Error {
-    fn from(_: ::std::string::FromUtf8Error) -> Error { Error::Parse("Invalid UTF8") }
+    fn from(_: ::std::string::FromUtf8Error) -> Error {
+        Error::Invalid(ErrorKind::InvalidUtf8)
+    }
+}
+
+/// The problems a `Parser` has worked around so far, from `Parser::diagnostics`, so that a
+/// pipeline reading untidy input can report how far it departed from the standard.
+///
+/// ```
+/// let buf = b"$enddefinitions $end #0 $vendor x $end 1! #1x 0! #2\n";
+/// let mut parser = vcd::Parser::new(&buf[..]);
+/// parser.set_strict(false);
+/// parser.parse_header().unwrap();
+/// while let Some(c) = parser.next() {
+///     if c.is_err() && !parser.resync().unwrap() { break; }
+/// }
+/// let report = parser.diagnostics();
+/// assert_eq!(report.unknown_commands[0].0, "vendor");
+/// assert_eq!(report.recovered_errors[0].0, vcd::ErrorKind::InvalidNumber);
+/// assert_eq!(report.to_string(), "1 unknown command, 1 error recovered from");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnostics {
+    /// Commands with unrecognized keywords that were accepted when not strict, as the keyword and
+    /// the position of the command
+    pub unknown_commands: Vec<(String, Position)>,
+
+    /// Errors after which `Parser::resync` skipped ahead, with their positions
+    pub recovered_errors: Vec<(ErrorKind, Position)>,
+}
+
+impl Diagnostics {
+    /// Whether nothing had to be worked around
+    pub fn is_clean(&self) -> bool {
+        self.unknown_commands.is_empty() && self.recovered_errors.is_empty()
+    }
 }
 
-fn whitespace_byte(b: u8) -> bool {
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let (unknown, recovered) = (self.unknown_commands.len(), self.recovered_errors.len());
+        write!(f, "{} unknown command{}, {} error{} recovered from",
+            unknown, plural(unknown), recovered, plural(recovered))
+    }
+}
+
+pub fn whitespace_byte(b: u8) -> bool {
     match b {
         b' ' | b'\n' | b'\r' | b'\t' => true,
         _ => false,
     }
 }
 
+/// Size of the buffer input is read into
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Number of bytes between `tracing` progress events
+#[cfg(feature = "tracing")]
+const PROGRESS_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// Throughput in MB/s below which a progress event is logged as a warning
+#[cfg(feature = "tracing")]
+const SLOW_MB_PER_S: f64 = 5.0;
+
+/// Limits on the size of the values the parser accepts, from `Parser::set_options`, so that input
+/// from an untrusted source cannot make it use unbounded memory. A value over a limit is a
+/// `TokenTooLong` error. Every token the parser keeps in memory is covered by one of the limits.
+/// By default there are no limits.
+///
+/// ```
+/// let mut parser = vcd::Parser::new(&b"#0 b10101010 !\n"[..]);
+/// parser.set_options(vcd::ParserOptions { max_vector_width: 4, ..Default::default() });
+/// assert!(parser.next().unwrap().is_ok());
+/// assert!(parser.next().unwrap().is_err());
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct ParserOptions {
+    /// Maximum length in bytes of a scope identifier or variable reference
+    pub max_identifier_len: usize,
+
+    /// Maximum length in bytes of a variable's identifier code
+    pub max_id_len: usize,
+
+    /// Maximum length in bytes of any other token: a `$keyword`, a timestamp, a number in a
+    /// `$timescale` or `$var` command including the index after a reference, or a scope or variable
+    /// type
+    pub max_token_len: usize,
+
+    /// Maximum number of bits in a vector or port value change
+    pub max_vector_width: usize,
+
+    /// Maximum length in bytes of a string or real value change, or of the text of a command
+    /// such as `$comment` including the whitespace around it
+    pub max_string_len: usize,
+}
+
+impl Default for ParserOptions {
+    fn default() -> ParserOptions {
+        ParserOptions {
+            max_identifier_len: usize::MAX,
+            max_id_len: usize::MAX,
+            max_token_len: usize::MAX,
+            max_vector_width: usize::MAX,
+            max_string_len: usize::MAX,
+        }
+    }
+}
+
 /// VCD parser. Wraps an `io::Read` and acts as an iterator of `Command`s.
+///
+/// Input is read in blocks into an internal buffer, so there is no need to wrap the reader in an
+/// `io::BufReader`.
+///
+/// With the `tracing` feature, the parser emits `tracing` events when the header has been parsed,
+/// every 64MB of input with the throughput since the last event (as a warning if below 5MB/s), and
+/// for each error.
 pub struct Parser<R: io::Read> {
-    bytes_iter: io::Bytes<R>,
+    reader: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
     simulation_command: Option<SimulationCommand>,
+    strict: bool,
+    unescape_strings: bool,
+    check_time_order: bool,
+    last_time: Option<u128>,
+    wide_timestamps: bool,
+    options: ParserOptions,
+    resumable: bool,
+    /// Start of the command being parsed in resumable mode, which is kept in the buffer
+    mark: Option<usize>,
+    filter: Option<HashSet<IdCode>>,
+    diagnostics: Diagnostics,
+    /// The last error returned, which `resync` records in `diagnostics`
+    last_error: Option<(ErrorKind, Position)>,
+    in_header: bool,
+    bytes_read: u64,
+    line: u64,
+    line_start: u64,
+    command_position: Position,
+    #[cfg(feature = "tracing")]
+    progress_time: Instant,
+
+    // Buffers holding the strings and values of the last command, reused for each command
+    token: Vec<u8>,
+    word: Vec<u8>,
+    keyword: String,
+    text: String,
+    vector: BitVector,
+    ports: Vec<PortState>,
+}
+
+/// A parsed command. The strings and values of commands other than `Parsed::Command` are in the
+/// parser's buffers, and are borrowed by `Parser::borrow_buffers`.
+enum Parsed {
+    Command(CommandRef<'static>),
+    Comment,
+    Date,
+    Version,
+    Attribute,
+    Unknown,
+    ScopeDef(ScopeType),
+    VarDef(VarType, u32, IdCode, Option<ReferenceIndex>),
+    ChangeVector(IdCode),
+    ChangeString(IdCode),
+    ChangePort(IdCode, u8, u8),
+
+    /// A value change excluded by `Parser::set_filter`, which is not returned
+    Skip,
 }
 
 impl<R: io::Read> Parser<R> {
@@ -88,122 +387,494 @@ impl<R: io::Read> Parser<R> {
     /// ```
     pub fn new(r: R) -> Parser<R> {
         Parser {
-            bytes_iter: r.bytes(),
+            reader: r,
+            buf: vec![0; BUFFER_SIZE].into_boxed_slice(),
+            pos: 0,
+            len: 0,
             simulation_command: None,
+            strict: true,
+            unescape_strings: false,
+            check_time_order: false,
+            last_time: None,
+            wide_timestamps: false,
+            options: ParserOptions::default(),
+            resumable: false,
+            mark: None,
+            filter: None,
+            diagnostics: Diagnostics::default(),
+            last_error: None,
+            in_header: true,
+            bytes_read: 0,
+            line: 1,
+            line_start: 0,
+            command_position: Position::default(),
+            #[cfg(feature = "tracing")]
+            progress_time: Instant::now(),
+            token: Vec::new(),
+            word: Vec::new(),
+            keyword: String::new(),
+            text: String::new(),
+            vector: BitVector::new(),
+            ports: Vec::new(),
         }
     }
 
-    fn read_byte(&mut self) -> Result<u8, Error> {
-        match self.bytes_iter.next() {
-            Some(Ok(b)) => Ok(b),
-            Some(Err(e)) => return Err(Error::from(e)),
-            None => return Err(Error::Parse("Unexpected EOF")),
+    /// Set whether an unrecognized `$keyword` is an error, as it is by default. When not strict,
+    /// the parser skips to the command's `$end` and returns it as a `Command::Unknown`.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Set whether to decode the escapes that `Writer::set_escape_strings` turns on in string value
+    /// changes, as described for `unescape_string`. This is off by default, so that strings from
+    /// other tools containing backslashes are returned as they are.
+    pub fn set_unescape_strings(&mut self, unescape: bool) {
+        self.unescape_strings = unescape;
+    }
+
+    /// Set whether to check that timestamps never decrease. When checking, a timestamp earlier
+    /// than the one before it is a `TimestampOutOfOrder` error at the position of the `#`, and
+    /// later timestamps are compared to the latest one before it.
+    pub fn set_check_time_order(&mut self, check: bool) {
+        self.check_time_order = check;
+    }
+
+    /// Set whether a timestamp too large for a `u64` is returned as a `Command::WideTimestamp`, for
+    /// traces too long to count in `u64`s at their timescale. This is off by default, and such a
+    /// timestamp is a `TimestampOverflow` error.
+    pub fn set_wide_timestamps(&mut self, wide: bool) {
+        self.wide_timestamps = wide;
+    }
+
+    /// Set limits on the size of the input accepted, as described for `ParserOptions`
+    pub fn set_options(&mut self, options: ParserOptions) {
+        self.options = options;
+    }
+
+    /// Set whether the end of the input partway through a command means that more input may
+    /// follow, as when reading a file that a simulator is still writing. When resumable, the
+    /// incomplete command is left unread and the end of the input is returned instead of an
+    /// `UnexpectedEof` error, so that iterating again once more input is available continues
+    /// from the start of that command. The header must be complete before it is parsed.
+    pub fn set_resumable(&mut self, resumable: bool) {
+        self.resumable = resumable;
+    }
+
+    /// Get a mutable reference to the underlying reader
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Return only the value changes of the variables in `ids`. Changes to other variables are
+    /// skipped without decoding their values; other commands are returned as usual.
+    pub fn set_filter(&mut self, ids: &[IdCode]) {
+        self.filter = Some(ids.iter().cloned().collect());
+    }
+
+    /// Set the filter to the variables at `paths` in `header`, each given as for
+    /// `Header::find_var`, so that only their value changes are returned
+    pub fn set_filter_paths(&mut self, header: &Header, paths: &[&[&str]]) -> Result<(), Error> {
+        let mut ids = HashSet::new();
+        for path in paths {
+            match header.find_var(path) {
+                Some(v) => { ids.insert(v.code); }
+                None => return Err(Error::Parse("Variable not found in header")),
+            }
         }
+        self.filter = Some(ids);
+        Ok(())
     }
 
-    fn read_token<'a>(&mut self, buf: &'a mut [u8]) -> Result<&'a [u8], Error> {
-        let mut len = 0;
-        loop {
-            let b = try!(self.read_byte());
-            if whitespace_byte(b) {
-                if len > 0 { break; } else { continue; }
+    /// Return the value changes of all variables again, undoing `set_filter`
+    pub fn clear_filter(&mut self) {
+        self.filter = None;
+    }
+
+    /// Skip the data section up to timestamp `start`, as for `transform::Window`, returning the
+    /// commands from then on. They begin with a `$dumpvars` block holding the value of every
+    /// variable at `start`.
+    pub fn seek_to_time(self, start: u64) -> Window<Self> {
+        Window::new(self, start, ::std::u64::MAX)
+    }
+
+    /// Stop reading the data section at the first timestamp after `end`. Only the commands read
+    /// before it are returned.
+    pub fn take_until(self, end: u64) -> TakeUntil<Self> {
+        TakeUntil::new(self, end)
+    }
+
+    /// Whether changes to `id` are returned, according to the filter
+    fn wanted(&self, id: IdCode) -> bool {
+        self.filter.as_ref().map_or(true, |f| f.contains(&id))
+    }
+
+    /// Return the buffered input, reading more if the buffer is empty. An empty slice means the
+    /// end of the input.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.len {
+            self.compact();
+            if self.len == self.buf.len() {
+                // The marked command fills the buffer
+                let mut buf = mem::replace(&mut self.buf, Box::new([])).into_vec();
+                let len = buf.len();
+                buf.resize(len * 2, 0);
+                self.buf = buf.into_boxed_slice();
             }
+            loop {
+                match self.reader.read(&mut self.buf[self.len..]) {
+                    Ok(n) => { self.len += n; break; }
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(&self.buf[self.pos..self.len])
+    }
+
+    /// Move the unread input, or the input from the mark if there is one, to the start of the
+    /// buffer
+    fn compact(&mut self) {
+        let start = self.mark.unwrap_or(self.pos);
+        if start == 0 { return; }
+        self.buf.copy_within(start..self.len, 0);
+        self.len -= start;
+        self.pos -= start;
+        self.mark = self.mark.map(|_| 0);
+    }
 
-            if let Some(p) = buf.get_mut(len) {
-                *p = b;
-            } else {
-                return Err(Error::Parse("Token too long"));
+    /// Mark `n` bytes returned by `fill_buf` as read
+    fn consume(&mut self, n: usize) {
+        for (i, &b) in self.buf[self.pos..self.pos + n].iter().enumerate() {
+            if b == b'\n' {
+                self.line += 1;
+                self.line_start = self.bytes_read + i as u64 + 1;
             }
+        }
+        self.pos += n;
+        self.bytes_read += n as u64;
+        #[cfg(feature = "tracing")]
+        {
+            let before = self.bytes_read - n as u64;
+            if before / PROGRESS_INTERVAL != self.bytes_read / PROGRESS_INTERVAL {
+                self.progress();
+            }
+        }
+    }
+
+    /// Return at least `n` buffered bytes without consuming them, or fewer at the end of the input
+    fn peek(&mut self, n: usize) -> io::Result<&[u8]> {
+        while self.len - self.pos < n {
+            self.compact();
+            match self.reader.read(&mut self.buf[self.len..]) {
+                Ok(0) => break,
+                Ok(m) => self.len += m,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(&self.buf[self.pos..self.len])
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let b = try!(self.fill_buf()).first().cloned();
+        if b.is_some() { self.consume(1); }
+        Ok(b)
+    }
+
+    /// Skip whitespace up to the start of the next token. Returns `false` at the end of the input.
+    fn skip_whitespace(&mut self) -> io::Result<bool> {
+        loop {
+            let (n, found) = {
+                let buf = try!(self.fill_buf());
+                if buf.is_empty() { return Ok(false); }
+                match buf.iter().position(|&b| !whitespace_byte(b)) {
+                    Some(i) => (i, true),
+                    None => (buf.len(), false),
+                }
+            };
+            self.consume(n);
+            if found { return Ok(true); }
+        }
+    }
 
-            len += 1;
+    #[cfg(feature = "tracing")]
+    fn progress(&mut self) {
+        let now = Instant::now();
+        let secs = now.duration_since(self.progress_time).as_secs_f64();
+        let mb_per_s = PROGRESS_INTERVAL as f64 / 1e6 / secs;
+        if mb_per_s < SLOW_MB_PER_S {
+            tracing::warn!(bytes = self.bytes_read, mb_per_s, "slow VCD input");
+        } else {
+            tracing::debug!(bytes = self.bytes_read, mb_per_s, "VCD parse progress");
         }
-        Ok(&buf[..len])
+        self.progress_time = now;
     }
 
-    fn read_token_string(&mut self) -> Result<String, Error> {
-        let mut r = Vec::new();
+    /// Skip ahead after an error to a point where parsing can continue, so that the rest of a
+    /// partly corrupt file can be read. Skips to the first token on the next line, or to an
+    /// earlier timestamp or command keyword other than `$end`. Returns `false` if the end of the
+    /// input was reached instead.
+    pub fn resync(&mut self) -> Result<bool, Error> {
+        if let Some(e) = self.last_error.take() {
+            self.diagnostics.recovered_errors.push(e);
+        }
+        let mut newline = false;
         loop {
-            let b = try!(self.read_byte());
-            if whitespace_byte(b) {
-                if r.len() > 0 { break; } else { continue; }
+            loop {
+                let (n, found, nl) = {
+                    let buf = try!(self.fill_buf());
+                    if buf.is_empty() { return Ok(false); }
+                    let n = buf.iter().position(|&b| !whitespace_byte(b)).unwrap_or(buf.len());
+                    (n, n < buf.len(), buf[..n].contains(&b'\n'))
+                };
+                newline |= nl;
+                self.consume(n);
+                if found { break; }
+            }
+
+            {
+                let start = try!(self.peek(5));
+                let end = start.starts_with(b"$end")
+                    && start.get(4).map_or(true, |&b| whitespace_byte(b));
+                if newline || start[0] == b'#' || (start[0] == b'$' && !end) { return Ok(true); }
+            }
+
+            match self.read_token_with(|_| true) {
+                Ok(()) => (),
+                Err(Error::Syntax { kind: ErrorKind::UnexpectedEof, .. }) => return Ok(false),
+                Err(e) => return Err(e),
             }
-            r.push(b);
         }
-        Ok(try!(String::from_utf8(r)))
+    }
+
+    /// The unknown commands accepted and the errors recovered from so far
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// The number of bytes of input parsed so far, for showing progress through a file of
+    /// known size. Input read ahead into the parser's buffer is not counted.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Returns true until the `$enddefinitions` command has been parsed
+    pub fn in_header(&self) -> bool {
+        self.in_header
+    }
+
+    /// The position of the next byte to be read
+    pub fn current_position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.bytes_read - self.line_start + 1,
+            offset: self.bytes_read,
+        }
+    }
+
+    /// The position of the start of the last command returned by the iterator
+    pub fn command_position(&self) -> Position {
+        self.command_position
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::Syntax { kind: kind, position: self.current_position() }
+    }
+
+    /// Attach the current position to an error without one, such as an invalid number
+    fn locate(&self, err: Error) -> Error {
+        match err {
+            Error::Parse(msg) => self.error(ErrorKind::Other(msg)),
+            Error::Invalid(kind) => self.error(kind),
+            err => err,
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<u8, Error> {
+        match try!(self.next_byte()) {
+            Some(b) => Ok(b),
+            None => Err(self.error(ErrorKind::UnexpectedEof)),
+        }
+    }
+
+    /// Read the next token, up to the whitespace ending it, passing its bytes to `f` as they are
+    /// found in the buffer, which may be in several pieces. `f` returns `false` if the token is
+    /// too long.
+    fn read_token_with<F>(&mut self, mut f: F) -> Result<(), Error> where F: FnMut(&[u8]) -> bool {
+        if !try!(self.skip_whitespace()) { return Err(self.error(ErrorKind::UnexpectedEof)); }
+        loop {
+            let (n, end, ok) = {
+                let buf = try!(self.fill_buf());
+                if buf.is_empty() { (0, false, true) } else {
+                    let (n, end) = match buf.iter().position(|&b| whitespace_byte(b)) {
+                        Some(i) => (i, true),
+                        None => (buf.len(), false),
+                    };
+                    (n, end, f(&buf[..n]))
+                }
+            };
+            if n == 0 && !end { return Err(self.error(ErrorKind::UnexpectedEof)); }
+            if !ok { return Err(self.error(ErrorKind::TokenTooLong)); }
+            self.consume(n);
+            if end { return Ok(()); }
+        }
+    }
+
+    /// Read a token of up to `max` bytes into `buf`, replacing its contents
+    fn read_token_vec(&mut self, buf: &mut Vec<u8>, max: usize) -> Result<(), Error> {
+        buf.clear();
+        self.read_token_with(|piece| {
+            if piece.len() > max - buf.len() { return false; }
+            buf.extend_from_slice(piece);
+            true
+        })
+    }
+
+    /// Read a token of up to `max` bytes into `self.token`
+    fn read_token_bytes(&mut self, max: usize) -> Result<(), Error> {
+        let mut r = mem::replace(&mut self.token, Vec::new());
+        let res = self.read_token_vec(&mut r, max);
+        self.token = r;
+        res
+    }
+
+    /// Read a short token, such as a number, keyword or identifier code, of up to `max` bytes
+    /// into `self.word`. This is separate from `self.token` so that the identifier code of a value
+    /// change can be read after its value.
+    fn read_word(&mut self, max: usize) -> Result<(), Error> {
+        let mut r = mem::replace(&mut self.word, Vec::new());
+        let res = self.read_token_vec(&mut r, max);
+        self.word = r;
+        res
+    }
+
+    /// Read an identifier into `self.text`
+    fn read_token_string(&mut self) -> Result<(), Error> {
+        let max = self.options.max_identifier_len;
+        try!(self.read_token_bytes(max));
+        self.text.clear();
+        self.text.push_str(try!(from_utf8(&self.token)));
+        Ok(())
     }
 
     fn read_token_parse<E, T>(&mut self) -> Result<T, Error> where Error: From<E>, T: FromStr<Err=E> {
-        let mut buf = [0; 32];
-        let tok = try!(self.read_token(&mut buf));
+        let max = self.options.max_token_len;
+        try!(self.read_word(max));
 
-        if tok == b"$end" {
-            return Err(Error::Parse("Unexpected $end"));
+        if self.word == b"$end" {
+            return Err(self.error(ErrorKind::UnexpectedEnd));
         }
 
-        let s = try!(from_utf8(tok));
+        let s = try!(from_utf8(&self.word));
         Ok(try!(s.parse()))
     }
 
+    /// Read the identifier code of a variable
+    fn read_id(&mut self) -> Result<IdCode, Error> {
+        let max = self.options.max_id_len;
+        try!(self.read_word(max));
+        if self.word == b"$end" {
+            return Err(self.error(ErrorKind::UnexpectedEnd));
+        }
+        IdCode::new(&self.word)
+    }
+
     fn read_command_end(&mut self) -> Result<(), Error> {
-        let mut buf = [0; 8];
-        let tok = try!(self.read_token(&mut buf));
-        if tok == b"$end" { Ok(()) } else { Err(Error::Parse("Expected $end")) }
+        // Compared as it is read, so that a long token is not buffered
+        let (mut len, mut end) = (0, true);
+        try!(self.read_token_with(|piece| {
+            end = end && b"$end".get(len..len + piece.len()) == Some(piece);
+            len += piece.len();
+            true
+        }));
+        if end && len == 4 { Ok(()) } else { Err(self.error(ErrorKind::ExpectedEnd)) }
     }
 
-    fn read_string_command(&mut self) -> Result<String, Error> {
-        let mut r = Vec::new();
-        loop {
-            r.push(try!(self.read_byte()));
-            if r.ends_with(b"$end") { break; }
-        }
-        let len = r.len() - 4;
-        r.truncate(len);
-        Ok(try!(String::from_utf8(r)).trim().to_string()) // TODO: don't reallocate
+    /// Read the text of a command up to its `$end` into `self.text`
+    fn read_string_command(&mut self) -> Result<(), Error> {
+        let mut r = mem::replace(&mut self.token, Vec::new());
+        r.clear();
+        let max = self.options.max_string_len.saturating_add(4);
+        let res = loop {
+            match self.read_byte() {
+                Ok(b) => r.push(b),
+                Err(e) => break Err(e),
+            }
+            if r.ends_with(b"$end") { break Ok(()); }
+            if r.len() >= max { break Err(self.error(ErrorKind::TokenTooLong)); }
+        };
+        self.token = r;
+        try!(res);
+        let len = self.token.len() - 4;
+        self.text.clear();
+        self.text.push_str(try!(from_utf8(&self.token[..len])).trim());
+        Ok(())
     }
 
-    fn parse_command(&mut self) -> Result<Command, Error> {
-        use super::Command::*;
-        use super::SimulationCommand::*;
+    fn parse_command(&mut self) -> Result<Parsed, Error> {
+        let max = self.options.max_token_len;
+        try!(self.read_word(max));
+        let cmd = mem::replace(&mut self.word, Vec::new());
+        let r = self.parse_keyword(&cmd);
+        self.word = cmd;
+        r
+    }
 
-        let mut cmdbuf = [0; 16];
-        let cmd = try!(self.read_token(&mut cmdbuf));
+    /// Parse the rest of a command after its `$keyword`
+    fn parse_keyword(&mut self, cmd: &[u8]) -> Result<Parsed, Error> {
+        use super::CommandRef::*;
+        use super::SimulationCommand::*;
 
         match cmd {
-            b"comment" => Ok(Comment(try!(self.read_string_command()))),
-            b"date"    => Ok(Date(try!(self.read_string_command()))),
-            b"version" => Ok(Version(try!(self.read_string_command()))),
+            b"comment" => { try!(self.read_string_command()); Ok(Parsed::Comment) }
+            b"date"    => { try!(self.read_string_command()); Ok(Parsed::Date) }
+            b"version" => { try!(self.read_string_command()); Ok(Parsed::Version) }
+            b"attrbegin" => { try!(self.read_string_command()); Ok(Parsed::Attribute) }
+            b"attrend" => {
+                try!(self.read_command_end());
+                Ok(Parsed::Command(AttributeEnd))
+            }
             b"timescale" => {
-                let (mut buf, mut buf2) = ([0; 8], [0; 8]);
-                let tok = try!(from_utf8(try!(self.read_token(&mut buf))));
+                let max = self.options.max_token_len;
+                try!(self.read_word(max));
+                let tok = try!(String::from_utf8(self.word.clone()));
                 // Support both "1ps" and "1 ps"
-                let (num_str, unit_str) = match tok.find(|c: char| !c.is_numeric()) {
-                    Some(idx) => (&tok[0..idx], &tok[idx..]),
-                    None => (tok, try!(from_utf8(try!(self.read_token(&mut buf2)))))
+                let (num, unit) = match tok.find(|c: char| !c.is_numeric()) {
+                    Some(idx) => (try!(tok[0..idx].parse()), try!(tok[idx..].parse())),
+                    None => {
+                        try!(self.read_word(max));
+                        (try!(tok.parse()), try!(try!(from_utf8(&self.word)).parse()))
+                    }
                 };
                 try!(self.read_command_end());
-                Ok(Timescale(try!(num_str.parse()), try!(unit_str.parse())))
+                Ok(Parsed::Command(Timescale(num, unit)))
             }
             b"scope" => {
                 let scope_type = try!(self.read_token_parse());
-                let identifier = try!(self.read_token_string());
+                try!(self.read_token_string());
                 try!(self.read_command_end());
-                Ok(ScopeDef(scope_type, identifier))
+                Ok(Parsed::ScopeDef(scope_type))
             }
             b"upscope" => {
                 try!(self.read_command_end());
-                Ok(Upscope)
+                Ok(Parsed::Command(Upscope))
             }
             b"var" => {
                 let var_type = try!(self.read_token_parse());
-                let size = try!(self.read_token_parse());
-                let code = try!(self.read_token_parse());
-                let reference = try!(self.read_token_string());
+                let size = try!(self.read_var_size());
+                let code = try!(self.read_id());
+                try!(self.read_token_string());
+                let max = self.options.max_token_len;
+                try!(self.read_word(max));
+                if self.word == b"$end" { return Ok(Parsed::VarDef(var_type, size, code, None)); }
+                let index = try!(try!(from_utf8(&self.word)).parse());
                 try!(self.read_command_end());
-                Ok(VarDef(var_type, size, code, reference))
+                Ok(Parsed::VarDef(var_type, size, code, Some(index)))
             }
             b"enddefinitions" => {
                 try!(self.read_command_end());
-                Ok(Enddefinitions)
+                self.in_header = false;
+                Ok(Parsed::Command(Enddefinitions))
             }
 
             // Simulation commands
@@ -211,123 +882,421 @@ impl<R: io::Read> Parser<R> {
             b"dumpoff" => self.begin_simulation_command(Dumpoff),
             b"dumpon" => self.begin_simulation_command(Dumpon),
             b"dumpvars" => self.begin_simulation_command(Dumpvars),
+            b"dumpports" => self.begin_simulation_command(Dumpports),
+            b"dumpportsall" => self.begin_simulation_command(Dumpportsall),
+            b"dumpportsoff" => self.begin_simulation_command(Dumpportsoff),
+            b"dumpportson" => self.begin_simulation_command(Dumpportson),
 
             b"end" => {
                 if let Some(c) = self.simulation_command.take() {
-                    Ok(End(c))
+                    Ok(Parsed::Command(End(c)))
                 } else {
-                    Err(Error::Parse("Unmatched $end"))
+                    Err(self.error(ErrorKind::UnmatchedEnd))
                 }
             }
 
-            _ => Err(Error::Parse("Invalid keyword"))
+            _ if self.strict => {
+                let keyword = String::from_utf8_lossy(cmd).into_owned();
+                Err(self.error(ErrorKind::InvalidKeyword(keyword)))
+            }
+            _ => {
+                self.keyword.clear();
+                self.keyword.push_str(try!(from_utf8(cmd)));
+                try!(self.read_string_command());
+                let position = self.command_position;
+                self.diagnostics.unknown_commands.push((self.keyword.clone(), position));
+                Ok(Parsed::Unknown)
+            }
         }
     }
 
-    fn begin_simulation_command(&mut self, c: SimulationCommand) -> Result<Command, Error> {
+    fn begin_simulation_command(&mut self, c: SimulationCommand) -> Result<Parsed, Error> {
         self.simulation_command = Some(c);
-        Ok(Command::Begin(c))
+        Ok(Parsed::Command(CommandRef::Begin(c)))
     }
 
-    fn parse_timestamp(&mut self) -> Result<Command, Error> {
-        Ok(Command::Timestamp(try!(self.read_token_parse())))
+    fn read_var_size(&mut self) -> Result<u32, Error> {
+        let max = self.options.max_token_len;
+        try!(self.read_word(max));
+        parse_var_size(try!(from_utf8(&self.word)))
     }
 
-    fn parse_scalar(&mut self, initial: u8) ->Result<Command, Error> {
-        let id = try!(self.read_token_parse());
-        let val = try!(Value::parse(initial));
-        Ok(Command::ChangeScalar(id, val))
+    fn parse_timestamp(&mut self) -> Result<Parsed, Error> {
+        let max = self.options.max_token_len;
+        try!(self.read_word(max));
+        let t = try!(parse_timestamp(&self.word));
+        let command = try!(timestamp_command(t, self.wide_timestamps));
+        if self.check_time_order {
+            match self.last_time {
+                Some(previous) if t < previous => {
+                    return Err(Error::Syntax {
+                        kind: ErrorKind::TimestampOutOfOrder { previous: previous, found: t },
+                        position: self.command_position,
+                    });
+                }
+                _ => self.last_time = Some(t),
+            }
+        }
+        Ok(Parsed::Command(command))
     }
 
-    fn parse_vector(&mut self) -> Result<Command, Error> {
-        let mut buf = [0; 32];
-        let val = try!(try!(self.read_token(&mut buf)).iter().cloned().map(Value::parse).collect());
-        let id = try!(self.read_token_parse());
-        Ok(Command::ChangeVector(id, val))
+    // The value of a change is decoded only after its `IdCode` is read and checked against the
+    // filter
+
+    fn parse_scalar(&mut self, initial: u8) ->Result<Parsed, Error> {
+        let id = try!(self.read_id());
+        if !self.wanted(id) { return Ok(Parsed::Skip); }
+        let val = try!(Value::parse(initial));
+        Ok(Parsed::Command(CommandRef::ChangeScalar(id, val)))
     }
 
-    fn parse_real(&mut self) -> Result<Command, Error> {
-        let val = try!(self.read_token_parse());
-        let id = try!(self.read_token_parse());
-        Ok(Command::ChangeReal(id, val))
+    fn parse_vector(&mut self) -> Result<Parsed, Error> {
+        let max = self.options.max_vector_width;
+        try!(self.read_token_bytes(max));
+        let id = try!(self.read_id());
+        if !self.wanted(id) { return Ok(Parsed::Skip); }
+        self.vector.clear();
+        for &b in &self.token {
+            self.vector.push(try!(Value::parse(b)));
+        }
+        Ok(Parsed::ChangeVector(id))
     }
 
-    fn parse_string(&mut self) -> Result<Command, Error> {
-        let val = try!(self.read_token_string());
-        let id = try!(self.read_token_parse());
-        Ok(Command::ChangeString(id, val))
+    fn parse_real(&mut self) -> Result<Parsed, Error> {
+        // Not limited in length like other tokens, as simulators may write every digit of the
+        // mantissa. Scientific notation, `inf` and `nan` are accepted in any case.
+        let max = self.options.max_string_len;
+        try!(self.read_token_bytes(max));
+        let id = try!(self.read_id());
+        if !self.wanted(id) { return Ok(Parsed::Skip); }
+        let val = try!(try!(from_utf8(&self.token)).parse());
+        Ok(Parsed::Command(CommandRef::ChangeReal(id, val)))
     }
 
-    fn parse_scope(&mut self, scope_type: ScopeType, reference: String) -> Result<Scope, Error> {
-        use super::Command::*;
-        let mut children = Vec::new();
+    fn parse_port(&mut self) -> Result<Parsed, Error> {
+        let max = self.options.max_vector_width;
+        try!(self.read_token_bytes(max));
+        let strength0 = try!(self.read_token_parse());
+        let strength1 = try!(self.read_token_parse());
+        if strength0 > 7 || strength1 > 7 { return Err(Error::Parse("Invalid port strength")); }
+        let id = try!(self.read_id());
+        if !self.wanted(id) { return Ok(Parsed::Skip); }
+        self.ports.clear();
+        for &b in &self.token {
+            self.ports.push(try!(PortState::parse(b)));
+        }
+        Ok(Parsed::ChangePort(id, strength0, strength1))
+    }
 
-        loop {
-            match self.next() {
-                Some(Ok(Upscope)) => break,
-                Some(Ok(ScopeDef(tp, id))) => {
-                    children.push(ScopeItem::Scope(try!(self.parse_scope(tp, id))));
-                }
-                Some(Ok(VarDef(tp, size, id, r))) => {
-                    children.push(ScopeItem::Var(
-                        Var { var_type: tp, size: size, code: id, reference: r }
-                    ));
-                }
-                Some(Ok(_)) => return Err(Error::Parse("Unexpected command in $scope")),
-                Some(Err(e)) => return Err(Error::from(e)),
-                None => return Err(Error::Parse("Unexpected EOF in $scope"))
-            }
+    fn parse_string(&mut self) -> Result<Parsed, Error> {
+        let max = self.options.max_string_len;
+        try!(self.read_token_bytes(max));
+        let id = try!(self.read_id());
+        if !self.wanted(id) { return Ok(Parsed::Skip); }
+        self.text.clear();
+        if self.unescape_strings && self.token.contains(&b'\\') {
+            let mut s = Vec::new();
+            unescape_string(&self.token, &mut s);
+            self.text.push_str(try!(from_utf8(&s)));
+        } else {
+            self.text.push_str(try!(from_utf8(&self.token)));
         }
+        Ok(Parsed::ChangeString(id))
+    }
 
-        Ok(Scope { scope_type: scope_type, identifier: reference, children: children })
+    /// Build the `CommandRef` for a parsed command, borrowing its data from the buffers
+    fn borrow_buffers(&self, p: Parsed) -> CommandRef {
+        use super::CommandRef::*;
+        match p {
+            Parsed::Command(c) => c,
+            Parsed::Comment => Comment(&self.text),
+            Parsed::Date => Date(&self.text),
+            Parsed::Version => Version(&self.text),
+            Parsed::Attribute => Attribute(&self.text),
+            Parsed::Unknown => Unknown(&self.keyword, &self.text),
+            Parsed::ScopeDef(t) => ScopeDef(t, &self.text),
+            Parsed::VarDef(t, size, code, index) => VarDef(t, size, code, &self.text, index),
+            Parsed::ChangeVector(id) => ChangeVector(id, &self.vector),
+            Parsed::ChangeString(id) => ChangeString(id, &self.text),
+            Parsed::ChangePort(id, s0, s1) => ChangePort(id, &self.ports, s0, s1),
+            Parsed::Skip => unreachable!("skipped changes are not returned"),
+        }
     }
 
     /// Parse the header of a VCD file into a `Header` struct. After returning, the stream has been
     /// read just past the `$enddefinitions` command and can be iterated to obtain the data.
     pub fn parse_header(&mut self) -> Result<Header, Error> {
-        use super::Command::*;
-        let mut header: Header = Default::default();
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("parse_header").entered();
+        let header = parse_header(self).map_err(|e| self.locate(e));
+        #[cfg(feature = "tracing")]
+        {
+            if let Ok(ref h) = header {
+                tracing::info!(bytes = self.bytes_read, timescale = ?h.timescale,
+                    "VCD header parsed");
+            }
+        }
+        header
+    }
+}
+
+/// Parse the size in a `$var` command, either as a number of bits or as a `[msb:lsb]` range as
+/// used by extended VCD ports
+pub fn parse_var_size(tok: &str) -> Result<u32, Error> {
+    if tok.starts_with('[') && tok.ends_with(']') {
+        let mut range = tok[1..tok.len() - 1].splitn(2, ':');
+        let msb: u32 = try!(range.next().unwrap().parse());
+        let lsb: u32 = try!(range.next().unwrap_or("0").parse());
+        Ok(if msb > lsb { msb - lsb } else { lsb - msb } + 1)
+    } else {
+        Ok(try!(tok.parse()))
+    }
+}
+
+/// Parse the number in a `#` timestamp, which must be a non-negative integer that fits in a `u128`
+pub fn parse_timestamp(tok: &[u8]) -> Result<u128, Error> {
+    if tok.first() == Some(&b'-') {
+        return Err(Error::Invalid(ErrorKind::NegativeTimestamp));
+    }
+    match try!(from_utf8(tok)).parse() {
+        Ok(t) => Ok(t),
+        Err(_) if !tok.is_empty() && tok.iter().all(u8::is_ascii_digit) => {
+            Err(Error::Invalid(ErrorKind::TimestampOverflow))
+        }
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+/// The command for the timestamp `t`: a `Timestamp` if it fits in a `u64`, or otherwise a
+/// `WideTimestamp` if `wide` is set
+pub fn timestamp_command(t: u128, wide: bool) -> Result<CommandRef<'static>, Error> {
+    if t <= u64::MAX as u128 {
+        Ok(CommandRef::Timestamp(t as u64))
+    } else if wide {
+        Ok(CommandRef::WideTimestamp(t))
+    } else {
+        Err(Error::Invalid(ErrorKind::TimestampOverflow))
+    }
+}
+
+fn hex_digit(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decode the escapes in the value of a string value change, as written by
+/// `Writer::change_string` with `set_escape_strings` on, appending the result to `out`: `\\` is a
+/// backslash and `\xHH` the byte with hex value `HH`. A `\` followed by anything else is kept as
+/// it is.
+pub fn unescape_string(tok: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < tok.len() {
+        if tok[i] == b'\\' {
+            if tok.get(i + 1) == Some(&b'\\') {
+                out.push(b'\\');
+                i += 2;
+                continue;
+            }
+            if tok.get(i + 1) == Some(&b'x') && i + 3 < tok.len() {
+                if let (Some(h), Some(l)) = (hex_digit(tok[i + 2]), hex_digit(tok[i + 3])) {
+                    out.push(h << 4 | l);
+                    i += 4;
+                    continue;
+                }
+            }
+        }
+        out.push(tok[i]);
+        i += 1;
+    }
+}
+
+fn parse_scope<I>(commands: &mut I, scope_type: ScopeType, reference: String) -> Result<Scope, Error>
+    where I: Iterator<Item=Result<Command, Error>> {
+    use super::Command::*;
+    let mut children = Vec::new();
+
+    loop {
+        match commands.next() {
+            Some(Ok(Upscope)) => break,
+            Some(Ok(ScopeDef(tp, id))) => {
+                children.push(ScopeItem::Scope(try!(parse_scope(commands, tp, id))));
+            }
+            Some(Ok(VarDef(tp, size, id, r, index))) => {
+                children.push(ScopeItem::Var(
+                    Var { var_type: tp, size: size, code: id, reference: r, index: index }
+                ));
+            }
+            Some(Ok(Attribute(_))) | Some(Ok(AttributeEnd)) | Some(Ok(Unknown(..))) => (),
+            Some(Ok(_)) => return Err(Error::Parse("Unexpected command in $scope")),
+            Some(Err(e)) => return Err(Error::from(e)),
+            None => return Err(Error::Parse("Unexpected EOF in $scope"))
+        }
+    }
+
+    Ok(Scope { scope_type: scope_type, identifier: reference, children: children })
+}
+
+/// Build a `Header` from a stream of header commands, consuming them up to and including the
+/// `$enddefinitions` command. `$attrbegin` and `$attrend` commands are skipped, as are unknown
+/// commands inside a scope.
+pub fn parse_header<I>(commands: &mut I) -> Result<Header, Error>
+    where I: Iterator<Item=Result<Command, Error>> {
+    use super::Command::*;
+    let mut header: Header = Default::default();
+    let mut has_scope = false;
+    loop {
+        match commands.next() {
+            Some(Ok(Enddefinitions)) => break,
+            Some(Ok(Comment(s))) => {
+                header.comment = Some(match header.comment.take() {
+                    Some(c) => c + "\n" + &s,
+                    None => s,
+                });
+            }
+            Some(Ok(Date(s)))    => { header.date    = Some(s); }
+            Some(Ok(Version(s))) => { header.version = Some(s); }
+            Some(Ok(Timescale(val, unit))) => { header.timescale = Some((val, unit)); }
+            Some(Ok(ScopeDef(tp, id))) => {
+                let scope = try!(parse_scope(commands, tp, id));
+                if !has_scope {
+                    header.scope = scope;
+                    has_scope = true;
+                } else {
+                    // A tool that reopens a top-level scope continues it
+                    let same = |s: &Scope| {
+                        s.identifier == scope.identifier && s.scope_type == scope.scope_type
+                    };
+                    if same(&header.scope) {
+                        header.scope.children.extend(scope.children);
+                    } else if let Some(s) = header.other_scopes.iter_mut().find(|s| same(s)) {
+                        s.children.extend(scope.children);
+                    } else {
+                        header.other_scopes.push(scope);
+                    }
+                }
+            }
+            Some(Ok(VarDef(tp, size, id, r, index))) => {
+                header.vars.push(
+                    Var { var_type: tp, size: size, code: id, reference: r, index: index }
+                );
+            }
+            Some(Ok(Unknown(k, s))) => header.unknown.push((k, s)),
+            Some(Ok(Attribute(_))) | Some(Ok(AttributeEnd)) => (),
+            Some(Ok(_)) => {
+                return Err(Error::Parse("Unexpected command in header"))
+            }
+            Some(Err(e)) => return Err(Error::from(e)),
+            None => return Err(Error::Parse("Unexpected EOF in header"))
+        }
+    }
+    Ok(header)
+}
+
+impl<P: io::Read> Parser<P> {
+    fn next_command(&mut self) -> Option<Result<Parsed, Error>> {
         loop {
-            match self.next() {
-                Some(Ok(Enddefinitions)) => break,
-                Some(Ok(Comment(s))) => { header.comment = Some(s); }
-                Some(Ok(Date(s)))    => { header.date    = Some(s); }
-                Some(Ok(Version(s))) => { header.version = Some(s); }
-                Some(Ok(Timescale(val, unit))) => { header.timescale = Some((val, unit)); }
-                Some(Ok(ScopeDef(tp, id))) => {
-                    header.scope = try!(self.parse_scope(tp, id));
+            if !self.resumable {
+                match self.parse_next() {
+                    Some(Ok(Parsed::Skip)) => continue,
+                    r => return r,
                 }
-                Some(Ok(_)) => {
-                    return Err(Error::Parse("Unexpected command in header"))
+            }
+
+            self.mark = Some(self.pos);
+            let state = (self.bytes_read, self.line, self.line_start, self.simulation_command,
+                self.in_header);
+            let r = self.parse_next();
+            if let Some(Err(Error::Syntax { kind: ErrorKind::UnexpectedEof, .. })) = r {
+                self.pos = self.mark.unwrap();
+                let (bytes_read, line, line_start, simulation_command, in_header) = state;
+                self.bytes_read = bytes_read;
+                self.line = line;
+                self.line_start = line_start;
+                self.simulation_command = simulation_command;
+                self.in_header = in_header;
+                self.mark = None;
+                return None;
+            }
+            self.mark = None;
+            match r {
+                Some(Ok(Parsed::Skip)) => continue,
+                r => return r,
+            }
+        }
+    }
+
+    fn parse_next(&mut self) -> Option<Result<Parsed, Error>> {
+        match self.skip_whitespace() {
+            Ok(true) => (),
+            Ok(false) => return None,
+            Err(e) => return Some(Err(Error::from(e))),
+        }
+        self.command_position = self.current_position();
+        let b = self.buf[self.pos];
+        self.consume(1);
+        Some(match b {
+            b'$' => self.parse_command(),
+            b'#' => self.parse_timestamp(),
+            b'0' | b'1' | b'z' | b'Z' | b'x' | b'X' |
+            b'u' | b'U' | b'w' | b'W' | b'l' | b'L' | b'h' | b'H' | b'-' => self.parse_scalar(b),
+            b'b' | b'B' => self.parse_vector(),
+            b'r' | b'R' => self.parse_real(),
+            b's' | b'S' => self.parse_string(),
+            b'p' => self.parse_port(),
+            _ => Err(Error::Syntax {
+                kind: ErrorKind::UnexpectedCharacter(b),
+                position: self.command_position,
+            }),
+        }.map_err(|e| self.locate(e)))
+    }
+
+    /// Parse the next command without allocating, returning `None` at the end of the input.
+    ///
+    /// Strings and values in the command borrow from buffers in the parser that are reused for
+    /// each command, so the command must be dropped before the parser is advanced again. Iterating
+    /// the parser is equivalent to calling `CommandRef::to_command` on each result.
+    ///
+    /// ```
+    /// use vcd::CommandRef;
+    ///
+    /// let mut parser = vcd::Parser::new(&b"#10 b1010 ! b11 ! "[..]);
+    /// let mut total = 0;
+    /// while let Some(c) = parser.next_event().unwrap() {
+    ///     if let CommandRef::ChangeVector(_, v) = c { total += v.len(); }
+    /// }
+    /// assert_eq!(total, 6);
+    /// ```
+    pub fn next_event(&mut self) -> Result<Option<CommandRef>, Error> {
+        match self.next_command() {
+            Some(Ok(p)) => {
+                self.last_error = None;
+                Ok(Some(self.borrow_buffers(p)))
+            }
+            Some(Err(e)) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(bytes = self.bytes_read, error = %e, "VCD parse error");
+                if let Error::Syntax { ref kind, position } = e {
+                    self.last_error = Some((kind.clone(), position));
                 }
-                Some(Err(e)) => return Err(Error::from(e)),
-                None => return Err(Error::Parse("Unexpected EOF in header"))
+                Err(e)
             }
+            None => Ok(None),
         }
-        Ok(header)
     }
 }
 
 impl<P: io::Read> Iterator for Parser<P> {
     type Item = Result<Command, Error>;
     fn next(&mut self) -> Option<Result<Command, Error>> {
-        while let Some(b) = self.bytes_iter.next() {
-            let b = match b {
-                Ok(b) => b,
-                Err(e) => return Some(Err(Error::from(e)))
-            };
-            match b {
-                b' ' | b'\n' | b'\r' | b'\t' => (),
-                b'$' => return Some(self.parse_command()),
-                b'#' => return Some(self.parse_timestamp()),
-                b'0' | b'1' | b'z' | b'Z' | b'x' | b'X' => return Some(self.parse_scalar(b)),
-                b'b' | b'B' => return Some(self.parse_vector()),
-                b'r' | b'R' => return Some(self.parse_real()),
-                b's' | b'S' => return Some(self.parse_string()),
-                _ => panic!("Unexpected character {}", b)
-            }
+        match self.next_event() {
+            Ok(Some(c)) => Some(Ok(c.to_command())),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
         }
-        None
     }
 }
 
@@ -403,7 +1372,7 @@ fn wikipedia_sample() {
 
     let expected = &[
         Begin(Dumpvars),
-        ChangeVector(IdCode(2), vec![X, X, X, X, X, X, X, X]),
+        ChangeVector(IdCode(2), vec![X, X, X, X, X, X, X, X].into()),
         ChangeScalar(IdCode(3), X),
         ChangeScalar(IdCode(4), V0),
         ChangeScalar(IdCode(5), X),
@@ -412,13 +1381,13 @@ fn wikipedia_sample() {
         ChangeScalar(IdCode(8), V0),
         End(Dumpvars),
         Timestamp(0),
-        ChangeVector(IdCode(2), vec![V1, V0, V0, V0, V0, V0, V0, V1]),
+        ChangeVector(IdCode(2), vec![V1, V0, V0, V0, V0, V0, V0, V1].into()),
         ChangeScalar(IdCode(3), V0),
         ChangeScalar(IdCode(4), V1),
         Timestamp(2211),
         ChangeScalar(IdCode(6), V0),
         Timestamp(2296),
-        ChangeVector(IdCode(2), vec![V0]),
+        ChangeVector(IdCode(2), vec![V0].into()),
         ChangeScalar(IdCode(3), V1),
         Timestamp(2302),
         ChangeScalar(IdCode(3), V0),
@@ -429,3 +1398,719 @@ fn wikipedia_sample() {
         assert_eq!(&i.unwrap(), e);
     }
 }
+
+#[test]
+fn small_reads() {
+    use super::IdCode;
+    use super::Value::*;
+
+    /// Returns at most 3 bytes per read, so that tokens are split across refills of the buffer
+    struct Trickle<'a>(&'a [u8]);
+    impl<'a> io::Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = ::std::cmp::min(3, ::std::cmp::min(buf.len(), self.0.len()));
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    let sample = b"$scope module top $end $var wire 4 ! counter_value $end $upscope $end
+        $enddefinitions $end #0 b0000 ! #12345 b1010 ! $comment long comment text $end\n";
+
+    let mut p = Parser::new(Trickle(&sample[..]));
+    let header = p.parse_header().unwrap();
+    match header.scope.children[0] {
+        ScopeItem::Var(ref v) => assert_eq!(v.reference, "counter_value"),
+        ref i => panic!("expected var, found {:?}", i),
+    }
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands, vec![
+        Command::Timestamp(0),
+        Command::ChangeVector(IdCode::from(0), vec![V0, V0, V0, V0].into()),
+        Command::Timestamp(12345),
+        Command::ChangeVector(IdCode::from(0), vec![V1, V0, V1, V0].into()),
+        Command::Comment("long comment text".to_string()),
+    ]);
+}
+
+#[test]
+fn unexpected_character() {
+    use super::IdCode;
+
+    let sample = b"$enddefinitions $end\n#0\n1!\n?\n0!\n";
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    assert_eq!(p.next().unwrap().unwrap(), Command::Timestamp(0));
+    assert_eq!(p.next().unwrap().unwrap(), Command::ChangeScalar(IdCode::from(0), Value::V1));
+    match p.next() {
+        Some(Err(Error::Syntax { kind: ErrorKind::UnexpectedCharacter(b'?'), position })) => {
+            assert_eq!(position, Position { line: 4, column: 1, offset: 9 + 18 });
+        }
+        e => panic!("expected unexpected character error, found {:?}", e),
+    }
+    assert_eq!(p.next().unwrap().unwrap(), Command::ChangeScalar(IdCode::from(0), Value::V0));
+    assert!(p.next().is_none());
+}
+
+#[test]
+fn error_position() {
+    let sample = b"$scope module top $end\n$var wire 1 ! clk [0] extra $end\n";
+    let mut p = Parser::new(&sample[..]);
+    let err = p.parse_header().unwrap_err();
+    match err {
+        Error::Syntax { kind: ErrorKind::ExpectedEnd, .. } => (),
+        ref e => panic!("expected syntax error, found {:?}", e),
+    }
+    assert_eq!(err.position(), Some(Position { line: 2, column: 28, offset: 50 }));
+    assert_eq!(err.to_string(), "line 2, column 28: Expected $end");
+
+    let mut p = Parser::new(&b"$enddefinitions $end\n#0\n#12x\n"[..]);
+    p.parse_header().unwrap();
+    p.next().unwrap().unwrap();
+    let err = p.next().unwrap().unwrap_err();
+    assert_eq!(err.to_string(), "line 3, column 5: Invalid number");
+    assert_eq!(p.command_position(), Position { line: 3, column: 1, offset: 24 });
+}
+
+#[test]
+fn extended_vcd() {
+    use super::{IdCode, VarType, PortDirection};
+
+    let sample = b"
+    $timescale 1 ns $end
+    $scope module top.dut $end
+    $var port [3:0] <0 data $end
+    $var port 1 <1 clk $end
+    $upscope $end
+    $enddefinitions $end
+    #0
+    $dumpports
+    pDDLL 6 6 <0
+    pN 0 0 <1
+    $end
+    #10
+    pUdhA 6 0 <0
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    match header.scope.children[0] {
+        ScopeItem::Var(ref v) => assert_eq!((&v.var_type, v.size), (&VarType::Port, 4)),
+        ref i => panic!("expected var, found {:?}", i),
+    }
+
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands[1], Command::Begin(SimulationCommand::Dumpports));
+    let data = "<0".parse::<IdCode>().unwrap();
+    match commands[6] {
+        Command::ChangePort(id, ref v, 6, 0) if id == data => {
+            assert_eq!(v.iter().map(|s| s.to_string()).collect::<String>(), "UdhA");
+            assert_eq!(v.iter().map(|s| s.direction()).collect::<Vec<_>>(), vec![
+                PortDirection::Input, PortDirection::Input, PortDirection::Output,
+                PortDirection::Unknown]);
+            assert_eq!(v.iter().map(|s| s.value()).collect::<Vec<_>>(),
+                vec![Value::V1, Value::V0, Value::V1, Value::X]);
+            assert!(v[1].multiple_drivers() && !v[0].multiple_drivers());
+        }
+        ref c => panic!("expected port change, found {:?}", c),
+    }
+}
+
+#[test]
+fn real_values() {
+    use super::IdCode;
+
+    // As written by Questa, Verilator and Icarus
+    let sample = b"$enddefinitions $end
+    r3.141592653589793 !
+    r1e-09 !
+    R-2.5E+03 !
+    r0.1000000000000000055511151231257827021181583404541015625 !
+    rinf ! r-Inf ! rNaN !
+    ";
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    let values: Vec<f64> = p.map(|c| match c.unwrap() {
+        Command::ChangeReal(id, v) if id == IdCode::from(0) => v,
+        c => panic!("expected real change, found {:?}", c),
+    }).collect();
+    assert_eq!(&values[..6], &[::std::f64::consts::PI, 1e-9, -2500.0, 0.1, ::std::f64::INFINITY,
+        ::std::f64::NEG_INFINITY]);
+    assert!(values[6].is_nan());
+}
+
+#[test]
+fn wide_vectors() {
+    use super::IdCode;
+
+    let mut sample = b"$enddefinitions $end\nb".to_vec();
+    sample.extend(::std::iter::repeat(b'1').take(512));
+    sample.extend_from_slice(b" !\n");
+
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    assert_eq!(p.next().unwrap().unwrap(),
+        Command::ChangeVector(IdCode::from(0), vec![Value::V1; 512].into()));
+}
+
+#[test]
+fn nine_state() {
+    use super::{IdCode, Writer};
+    use super::Value::*;
+
+    let sample = b"$enddefinitions $end\nU!\nbUXZWLH-01 \"\nh!\n";
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands, vec![
+        Command::ChangeScalar(IdCode::from(0), U),
+        Command::ChangeVector(IdCode::from(1), vec![U, X, Z, W, L, H, DontCare, V0, V1].into()),
+        Command::ChangeScalar(IdCode::from(0), H),
+    ]);
+
+    let mut buf = Vec::new();
+    {
+        let mut w = Writer::new(&mut buf);
+        for c in &commands { w.command(c).unwrap(); }
+    }
+    assert_eq!(&buf[..], &b"u!\nbuxzwlh-01 \"\nh!\n"[..]);
+}
+
+#[test]
+fn borrowed_events() {
+    use super::{IdCode, PortState};
+    use super::Value::*;
+
+    let sample = b"$comment  hello $end $var wire 2 ! data $end\nb01 !\nb1 !\nsab \"\npU0 0 6 #\n";
+    let mut p = Parser::new(&sample[..]);
+    assert_eq!(p.next_event().unwrap(), Some(CommandRef::Comment("hello")));
+    assert_eq!(p.next_event().unwrap(),
+        Some(CommandRef::VarDef(VarType::Wire, 2, IdCode::from(0), "data", None)));
+    assert_eq!(p.next_event().unwrap(),
+        Some(CommandRef::ChangeVector(IdCode::from(0), &vec![V0, V1].into())));
+    assert_eq!(p.next_event().unwrap(),
+        Some(CommandRef::ChangeVector(IdCode::from(0), &vec![V1].into())));
+    assert_eq!(p.next_event().unwrap(), Some(CommandRef::ChangeString(IdCode::from(1), "ab")));
+    let port = p.next_event().unwrap().unwrap();
+    assert_eq!(port.to_command(),
+        Command::ChangePort(IdCode::from(2), vec!["U".parse::<PortState>().unwrap(),
+            "0".parse().unwrap()], 0, 6));
+    assert_eq!(p.next_event().unwrap(), None);
+}
+
+#[test]
+fn attributes() {
+    use super::{IdCode, VarType, Writer};
+
+    let sample = b"
+    $scope module top $end
+    $attrbegin misc 07 state_names 2 $end
+    $var reg 2 ! state $end
+    $attrend $end
+    $upscope $end
+    $enddefinitions $end
+    #0
+    $attrbegin misc 03 extra $end
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    assert_eq!(header.find_var(&["top", "state"]).map(|v| (&v.var_type, v.code)),
+        Some((&VarType::Reg, IdCode::from(0))));
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands, vec![
+        Command::Timestamp(0),
+        Command::Attribute("misc 03 extra".to_string()),
+    ]);
+
+    let mut buf = Vec::new();
+    {
+        let mut w = Writer::new(&mut buf);
+        w.command(&commands[1]).unwrap();
+        w.command(&Command::AttributeEnd).unwrap();
+    }
+    assert_eq!(&buf[..], &b"$attrbegin misc 03 extra $end\n$attrend $end\n"[..]);
+}
+
+#[test]
+fn tolerant() {
+    use super::{IdCode, Value};
+
+    let sample = b"$scope module top $end $vendor_extension_header a b $end
+    $var wire 1 ! clk $end $upscope $end $enddefinitions $end
+    #1 $probe clk rising $end 1!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    assert!(p.parse_header().is_err());
+
+    let mut p = Parser::new(&sample[..]);
+    p.set_strict(false);
+    let header = p.parse_header().unwrap();
+    assert!(header.find_var(&["top", "clk"]).is_some());
+    let commands: Vec<Command> = p.by_ref().map(Result::unwrap).collect();
+    assert_eq!(commands, vec![
+        Command::Timestamp(1),
+        Command::Unknown("probe".to_string(), "clk rising".to_string()),
+        Command::ChangeScalar(IdCode::from(0), Value::V1),
+    ]);
+    let unknown: Vec<(&str, u64)> = p.diagnostics().unknown_commands.iter()
+        .map(|&(ref k, pos)| (&k[..], pos.line)).collect();
+    assert_eq!(unknown, vec![("vendor_extension_header", 1), ("probe", 3)]);
+}
+
+#[test]
+fn body_comments() {
+    use super::{IdCode, Writer};
+
+    let sample = b"$comment first $end $comment second $end
+    $scope module top $end $var wire 1 ! rst $end $upscope $end $enddefinitions $end
+    #0 1! $comment reset released $end #10 0!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    assert_eq!(header.comment, Some("first\nsecond".to_string()));
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands[2], Command::Comment("reset released".to_string()));
+
+    let mut buf = Vec::new();
+    {
+        let mut w = Writer::new(&mut buf);
+        w.header(&header).unwrap();
+        for c in &commands { w.command(c).unwrap(); }
+        assert!(w.comment("a $end b").is_err());
+    }
+    let mut p = Parser::new(&buf[..]);
+    assert_eq!(p.parse_header().unwrap().comment, header.comment);
+    assert_eq!(p.map(Result::unwrap).collect::<Vec<_>>(), commands);
+    assert_eq!(commands[1], Command::ChangeScalar(IdCode::from(0), Value::V1));
+}
+
+#[test]
+fn reference_index() {
+    use super::{ReferenceIndex, Writer};
+
+    let sample = b"$scope module top $end
+    $var wire 8 # data [7:0] $end
+    $var wire 1 $ data [8] $end
+    $var wire 1 % en $end
+    $upscope $end $enddefinitions $end
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let indexes: Vec<_> = header.scope.iter_vars().map(|(_, v)| v.index).collect();
+    assert_eq!(indexes, vec![Some(ReferenceIndex::Range(7, 0)),
+        Some(ReferenceIndex::BitSelect(8)), None]);
+    assert!("[7:".parse::<ReferenceIndex>().is_err());
+
+    let mut buf = Vec::new();
+    Writer::new(&mut buf).header(&header).unwrap();
+    let text = String::from_utf8(buf).unwrap();
+    assert!(text.contains("$var wire 8 # data [7:0] $end\n$var wire 1 $ data [8] $end\n"));
+}
+
+#[test]
+fn systemverilog_scopes() {
+    use super::ScopeType;
+
+    let sample = b"$scope module tb $end $scope interface bus_if $end $var logic 1 ! valid $end
+    $upscope $end $scope vhdl_process p0 $end $upscope $end $upscope $end $enddefinitions $end
+    ";
+    let header = Parser::new(&sample[..]).parse_header().unwrap();
+    assert_eq!(header.find_scope(&["tb", "bus_if"]).map(|s| &s.scope_type),
+        Some(&ScopeType::Interface));
+    assert_eq!(header.find_scope(&["tb", "p0"]).map(|s| &s.scope_type),
+        Some(&ScopeType::Other("vhdl_process".to_string())));
+}
+
+#[test]
+fn timestamp_range() {
+    let sample = b"#18446744073709551615\n#-5\n#18446744073709551616\n#1e3\n";
+    let mut p = Parser::new(&sample[..]);
+    assert_eq!(p.next().unwrap().unwrap(), Command::Timestamp(u64::max_value()));
+    let kinds = [
+        ErrorKind::NegativeTimestamp,
+        ErrorKind::TimestampOverflow,
+        ErrorKind::InvalidNumber,
+    ];
+    for (line, kind) in (2..).zip(&kinds) {
+        match p.next() {
+            Some(Err(Error::Syntax { kind: ref k, position })) => {
+                assert_eq!((k, position.line), (kind, line));
+            }
+            e => panic!("expected {}, found {:?}", kind, e),
+        }
+    }
+
+    let wide = b"#18446744073709551616\n#340282366920938463463374607431768211456\n#5\n";
+    let mut p = Parser::new(&wide[..]);
+    p.set_wide_timestamps(true);
+    p.set_check_time_order(true);
+    assert_eq!(p.next().unwrap().unwrap(), Command::WideTimestamp(1 << 64));
+    assert_eq!(p.next().unwrap().unwrap_err().kind(), Some(&ErrorKind::TimestampOverflow));
+    assert_eq!(p.next().unwrap().unwrap_err().kind(),
+        Some(&ErrorKind::TimestampOutOfOrder { previous: 1 << 64, found: 5 }));
+
+    let mut p = ::SliceParser::new(wide);
+    p.set_wide_timestamps(true);
+    assert_eq!(p.next().unwrap().unwrap(), Command::WideTimestamp(1 << 64));
+    assert_eq!(p.next().unwrap().unwrap_err().kind(), Some(&ErrorKind::TimestampOverflow));
+
+    let mut out = Vec::new();
+    Command::WideTimestamp(1 << 64).write_to(&mut out).unwrap();
+    assert_eq!(out, &wide[..22]);
+}
+
+#[test]
+fn resync_after_error() {
+    use super::IdCode;
+
+    let sample = b"$enddefinitions $end\n#0 1!\n#1x 0!\n0\"\n#2 $end $comment ok $end\n";
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    assert_eq!(p.next().unwrap().unwrap(), Command::Timestamp(0));
+    assert_eq!(p.next().unwrap().unwrap(), Command::ChangeScalar(IdCode::from(0), Value::V1));
+    assert!(p.next().unwrap().is_err());
+    assert!(p.resync().unwrap());
+    assert_eq!(p.next().unwrap().unwrap(), Command::ChangeScalar(IdCode::from(1), Value::V0));
+    assert_eq!(p.next().unwrap().unwrap(), Command::Timestamp(2));
+    assert!(p.next().unwrap().is_err());
+    assert!(p.resync().unwrap());
+    assert_eq!(p.next().unwrap().unwrap(), Command::Comment("ok".to_string()));
+    assert!(!p.resync().unwrap());
+    assert!(p.next().is_none());
+    let lines: Vec<u64> = p.diagnostics().recovered_errors.iter().map(|e| e.1.line).collect();
+    assert_eq!(lines, vec![3, 5]);
+    assert!(p.diagnostics().unknown_commands.is_empty());
+}
+
+#[test]
+fn progress() {
+    let sample = b"$scope module top $end $var wire 1 ! a $end $upscope $end
+$enddefinitions $end
+#0 1!
+";
+    let mut p = Parser::new(&sample[..]);
+    assert_eq!((p.bytes_read(), p.in_header()), (0, true));
+    p.parse_header().unwrap();
+    assert_eq!((p.bytes_read(), p.in_header()), (78, false));
+    assert_eq!(p.count(), 2);
+}
+
+#[cfg(all(feature = "serde", feature = "json"))]
+#[test]
+fn serde_roundtrip() {
+    let sample = b"$timescale 1 ns $end $scope module top $end $var wire 4 ! a [3:0] $end
+$upscope $end $enddefinitions $end
+#0 b10xz ! #1 $dumpoff b0 ! $end
+";
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+
+    let json = ::serde_json::to_string(&(&header, &commands)).unwrap();
+    let (h, c): (Header, Vec<Command>) = ::serde_json::from_str(&json).unwrap();
+    assert_eq!(format!("{:?}", h), format!("{:?}", header));
+    assert_eq!(c, commands);
+}
+
+#[test]
+fn filter() {
+    use super::{IdCode, Value};
+
+    let sample = b"$scope module top $end $var wire 1 ! a $end $var wire 4 \" b $end
+$var real 64 # r $end $upscope $end $enddefinitions $end
+#0 0! b0000 \" r0 # #1 b10q1 \" rnan? # #2 1! b1 \"
+";
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    p.set_filter_paths(&header, &[&["top", "a"]]).unwrap();
+    let commands: Vec<Command> = p.by_ref().map(Result::unwrap).collect();
+    assert_eq!(commands, vec![
+        Command::Timestamp(0),
+        Command::ChangeScalar(IdCode(0), Value::V0),
+        Command::Timestamp(1),
+        Command::Timestamp(2),
+        Command::ChangeScalar(IdCode(0), Value::V1),
+    ]);
+    assert!(p.set_filter_paths(&header, &[&["top", "c"]]).is_err());
+}
+
+#[test]
+fn time_window() {
+    use super::Value::*;
+    use super::Command::*;
+    use super::SimulationCommand::*;
+
+    let sample = b"$scope module top $end $var wire 1 ! a $end $upscope $end $enddefinitions $end
+#0 0! #10 1! #20 0! #30 1!
+";
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    let cmds: Vec<Command> = p.seek_to_time(15).map(Result::unwrap).collect();
+    assert_eq!(cmds, vec![
+        Timestamp(15), Begin(Dumpvars), ChangeScalar(IdCode(0), V1), End(Dumpvars),
+        Timestamp(20), ChangeScalar(IdCode(0), V0), Timestamp(30), ChangeScalar(IdCode(0), V1)]);
+
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    let cmds: Vec<Command> = p.take_until(15).map(Result::unwrap).collect();
+    assert_eq!(cmds, vec![
+        Timestamp(0), ChangeScalar(IdCode(0), V0), Timestamp(10), ChangeScalar(IdCode(0), V1)]);
+}
+
+#[test]
+fn resumable() {
+    use std::rc::Rc;
+    use std::cell::Cell;
+    use super::{IdCode, Value};
+
+    /// Input of which only the first `available` bytes have been written so far
+    struct Growing { data: Vec<u8>, pos: usize, available: Rc<Cell<usize>> }
+
+    impl io::Read for Growing {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.available.get() - self.pos).min(3);
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    let sample = b"$scope module top $end $var wire 1 ! a $end $upscope $end $enddefinitions $end
+#0 0! $comment written
+in pieces $end
+#15 1!
+";
+    let header_len = 80;
+    let available = Rc::new(Cell::new(header_len));
+    let input = Growing { data: sample.to_vec(), pos: 0, available: available.clone() };
+    let mut p = Parser::new(input);
+    p.set_resumable(true);
+    p.parse_header().unwrap();
+
+    let mut commands = Vec::new();
+    for n in header_len..sample.len() + 1 {
+        available.set(n);
+        commands.extend(p.by_ref().map(Result::unwrap));
+    }
+    assert_eq!(commands, vec![
+        Command::Timestamp(0),
+        Command::ChangeScalar(IdCode(0), Value::V0),
+        Command::Comment("written\nin pieces".to_string()),
+        Command::Timestamp(15),
+        Command::ChangeScalar(IdCode(0), Value::V1),
+    ]);
+    assert_eq!(p.current_position().line, 5);
+}
+
+#[test]
+fn header_unknown() {
+    let sample = b"$version sim $end $probe clk rising $end
+$scope module top $end $var wire 1 ! clk $end $vendor inside $end $upscope $end
+$enddefinitions $end
+";
+    let mut p = Parser::new(&sample[..]);
+    p.set_strict(false);
+    let header = p.parse_header().unwrap();
+    assert_eq!(header.unknown, vec![("probe".to_string(), "clk rising".to_string())]);
+
+    let mut buf = Vec::new();
+    header.write_to(&mut buf).unwrap();
+    let mut p = Parser::new(&buf[..]);
+    p.set_strict(false);
+    let rewritten = p.parse_header().unwrap();
+    assert_eq!(rewritten.unknown, header.unknown);
+    assert_eq!(rewritten.scope.children.len(), 1);
+}
+
+#[test]
+fn vars_outside_scope() {
+    let sample = b"$var wire 1 ! reset $end
+$scope module top $end $var wire 1 \" clk $end $upscope $end $enddefinitions $end
+#0 1! 0\"
+";
+    let header = Parser::new(&sample[..]).parse_header().unwrap();
+    assert_eq!(header.vars.len(), 1);
+    assert_eq!(header.find_var(&["reset"]).unwrap().code, IdCode(0));
+    let paths: Vec<Vec<&str>> = header.iter_vars().map(|(p, _)| p).collect();
+    assert_eq!(paths, vec![vec!["reset"], vec!["top", "clk"]]);
+
+    let mut buf = Vec::new();
+    header.write_to(&mut buf).unwrap();
+    assert!(buf.starts_with(b"$var wire 1 ! reset $end\n$scope module top $end"));
+    let rewritten = Parser::new(&buf[..]).parse_header().unwrap();
+    assert_eq!(rewritten.vars[0].reference, "reset");
+}
+
+#[test]
+fn repeated_top_scope() {
+    let sample = b"$scope module top $end $var wire 1 ! a $end $upscope $end
+$scope module top $end $var wire 1 \" b $end $upscope $end $enddefinitions $end
+";
+    let header = Parser::new(&sample[..]).parse_header().unwrap();
+    let paths: Vec<Vec<&str>> = header.iter_vars().map(|(p, _)| p).collect();
+    assert_eq!(paths, vec![vec!["top", "a"], vec!["top", "b"]]);
+
+    let sample = b"$scope module top $end $var wire 1 ! a $end $upscope $end
+$scope module glbl $end $var wire 1 \" GSR $end $upscope $end
+$scope module top $end $var wire 1 # b $end $upscope $end
+$scope module glbl $end $var wire 1 $ GTS $end $upscope $end $enddefinitions $end
+";
+    let header = Parser::new(&sample[..]).parse_header().unwrap();
+    let paths: Vec<Vec<&str>> = header.iter_vars().map(|(p, _)| p).collect();
+    assert_eq!(paths, vec![
+        vec!["top", "a"], vec!["top", "b"], vec!["glbl", "GSR"], vec!["glbl", "GTS"]]);
+    assert_eq!(header.find_var(&["glbl", "GTS"]).unwrap().code, IdCode::from(3));
+    assert_eq!(header.find_scope(&["glbl"]).unwrap().children.len(), 2);
+
+    let mut out = Vec::new();
+    header.write_to(&mut out).unwrap();
+    let rewritten = Parser::new(&out[..]).parse_header().unwrap();
+    assert_eq!(rewritten.other_scopes.len(), 1);
+    assert_eq!(rewritten.iter_vars().count(), 4);
+}
+
+#[test]
+fn limits() {
+    let sample = b"$comment 0123456789 $end
+$scope module top $end $var wire 4 ! abcdefgh $end $var string 1 \" s $end $upscope $end
+$enddefinitions $end
+#0 b1010 ! sabcdefghijklmn \"
+";
+    let limited = |options: ParserOptions| {
+        let mut p = Parser::new(&sample[..]);
+        p.set_options(options);
+        match p.find(Result::is_err) {
+            Some(Err(Error::Syntax { kind: ErrorKind::TokenTooLong, position })) => {
+                Some(position.line)
+            }
+            Some(other) => panic!("{:?}", other),
+            None => None,
+        }
+    };
+    let defaults = ParserOptions::default();
+    assert_eq!(limited(defaults), None);
+    let fits = ParserOptions {
+        max_identifier_len: 8,
+        max_id_len: 1,
+        max_token_len: 14,
+        max_vector_width: 4,
+        max_string_len: 14,
+    };
+    assert_eq!(limited(fits), None);
+    assert_eq!(limited(ParserOptions { max_token_len: 13, ..fits }), Some(3));
+    assert_eq!(limited(ParserOptions { max_id_len: 0, ..defaults }), Some(2));
+    assert_eq!(limited(ParserOptions { max_token_len: 6, ..defaults }), Some(1));
+    assert_eq!(limited(ParserOptions { max_string_len: 11, ..fits }), Some(1));
+    assert_eq!(limited(ParserOptions { max_identifier_len: 7, ..fits }), Some(2));
+    assert_eq!(limited(ParserOptions { max_vector_width: 3, ..fits }), Some(4));
+    assert_eq!(limited(ParserOptions { max_string_len: 13, ..defaults }), Some(4));
+}
+
+#[test]
+fn error_kinds() {
+    use super::IdCode;
+
+    let cases: [(&[u8], ErrorKind); 5] = [
+        (b"$dumpnothing $end ", ErrorKind::InvalidKeyword("dumpnothing".to_string())),
+        (b"q! ", ErrorKind::UnexpectedCharacter(b'q')),
+        (b"b01q ! ", ErrorKind::InvalidValueChar(b'q')),
+        (b"pUq 0 6 ! ", ErrorKind::InvalidValueChar(b'q')),
+        (b"#1 s\xff ! ", ErrorKind::InvalidUtf8),
+    ];
+    for &(input, ref kind) in &cases {
+        let err = Parser::new(input).find(Result::is_err).unwrap().unwrap_err();
+        assert_eq!(err.kind(), Some(kind), "{:?}", err);
+        assert!(err.position().is_some());
+    }
+
+    let err = "\x01".parse::<IdCode>().unwrap_err();
+    assert_eq!((err.kind(), err.position()), (Some(&ErrorKind::InvalidIdCode), None));
+    assert_eq!(err.to_string(), "Invalid identifier code");
+}
+
+#[test]
+fn time_order() {
+    let sample = b"#0 #10 #10
+#5 #20 #15
+";
+    let mut p = Parser::new(&sample[..]);
+    assert_eq!(p.by_ref().filter(Result::is_err).count(), 0);
+
+    let mut p = Parser::new(&sample[..]);
+    p.set_check_time_order(true);
+    let results: Vec<_> = p.collect();
+    assert_eq!(results.len(), 6);
+    match results[3] {
+        Err(Error::Syntax { kind: ErrorKind::TimestampOutOfOrder { previous: 10, found: 5 },
+            position }) => assert_eq!((position.line, position.column), (2, 1)),
+        ref other => panic!("{:?}", other),
+    }
+    assert_eq!(results[4].as_ref().unwrap(), &Command::Timestamp(20));
+    assert_eq!(results[5].as_ref().unwrap_err().kind(),
+        Some(&ErrorKind::TimestampOutOfOrder { previous: 20, found: 15 }));
+}
+
+#[test]
+fn long_tokens() {
+    use super::{IdCode, TimescaleUnit, Value};
+
+    let zeros = "0".repeat(40);
+    let sample = format!("$timescale {z}1 ns $end $scope module top $end
+$var wire {z}8 ! data $end $upscope $end $enddefinitions $end
+#{z}5 b{z}1 !
+", z = zeros);
+    let mut p = Parser::new(sample.as_bytes());
+    let header = p.parse_header().unwrap();
+    assert_eq!(header.timescale, Some((1, TimescaleUnit::NS)));
+    assert_eq!(header.find_var(&["top", "data"]).unwrap().size, 8);
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands, vec![Command::Timestamp(5),
+        Command::ChangeVector(IdCode::from(0), {
+            let mut v = vec![Value::V0; 41];
+            v[40] = Value::V1;
+            v.into()
+        })]);
+
+    // Too long to fit in an `IdCode`, but read in full before it is rejected
+    let id = format!("$var wire 1 {} x $end ", "~".repeat(40));
+    let err = Parser::new(id.as_bytes()).next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), Some(&ErrorKind::InvalidIdCode));
+
+    let keyword = format!("$dump{} $end ", "x".repeat(100));
+    let err = Parser::new(keyword.as_bytes()).next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), Some(&ErrorKind::InvalidKeyword(keyword[1..105].to_string())));
+    let err = Parser::new(&b"$upscope $endx "[..]).next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), Some(&ErrorKind::ExpectedEnd));
+}
+
+#[test]
+fn long_id_codes() {
+    use super::IdCode;
+
+    // Codes of six or more characters do not fit in 32 bits
+    let long = "abcdef";
+    let longest = "~".repeat(19);
+    let sample = format!("$scope module top $end $var wire 1 {} a $end $var wire 1 {} b $end
+$upscope $end $enddefinitions $end #0 1{} 0{}
+", long, longest, long, longest);
+    let mut p = Parser::new(sample.as_bytes());
+    let header = p.parse_header().unwrap();
+    let a = header.find_var(&["top", "a"]).unwrap().code;
+    let b = header.find_var(&["top", "b"]).unwrap().code;
+    assert_eq!((a.to_string(), b.to_string()), (long.to_string(), longest.clone()));
+    assert_eq!(long.parse::<IdCode>().unwrap(), a);
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands, vec![Command::Timestamp(0),
+        Command::ChangeScalar(a, Value::V1), Command::ChangeScalar(b, Value::V0)]);
+
+    let too_long = "~".repeat(20);
+    assert!(too_long.parse::<IdCode>().is_err());
+    let sample = format!("$var wire 1 {} a $end ", too_long);
+    let err = Parser::new(sample.as_bytes()).next().unwrap().unwrap_err();
+    assert_eq!(err.kind(), Some(&ErrorKind::InvalidIdCode));
+}
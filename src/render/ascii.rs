@@ -0,0 +1,120 @@
+use std::fmt;
+use std::ops::Range;
+
+use {
+    Value,
+    Radix,
+    VarValue,
+    Var,
+    Command,
+    Error,
+};
+use super::changes;
+
+/// The characters for one column of a 1-bit signal, given its previous value
+fn scalar_char(prev: Option<Value>, v: Value) -> char {
+    match (prev.map(Value::to_four_state), v.to_four_state()) {
+        (Some(Value::V0), Value::V1) => '/',
+        (Some(Value::V1), Value::V0) => '\\',
+        (_, Value::V1) => '‾',
+        (_, Value::X) => 'x',
+        (_, Value::Z) => '-',
+        _ => '_',
+    }
+}
+
+fn scalar(v: &VarValue) -> Option<Value> {
+    match *v {
+        VarValue::Scalar(b) => Some(b),
+        VarValue::Vector(ref b) if b.len() == 1 => Some(b[0]),
+        _ => None,
+    }
+}
+
+/// Draw the values of a signal for each column as a line of text
+fn lane(var: &Var, columns: &[Option<&VarValue>]) -> String {
+    let mut line = String::new();
+    let mut i = 0;
+    while i < columns.len() {
+        let v = match columns[i] {
+            Some(v) => v,
+            None => { line.push(' '); i += 1; continue; }
+        };
+
+        if let (1, Some(b)) = (var.size, scalar(v)) {
+            let prev = if i > 0 { columns[i - 1].and_then(scalar) } else { None };
+            line.push(scalar_char(prev, b));
+            i += 1;
+            continue;
+        }
+
+        // A bus segment: a transition marker, then the value for as long as it fits
+        let len = columns[i..].iter().take_while(|c| **c == Some(v)).count();
+        let text = v.format(Radix::Hex);
+        let mut segment: Vec<char> = if i > 0 { vec!['X'] } else { vec![] };
+        segment.extend(text.chars());
+        segment.resize(::std::cmp::max(segment.len(), len), ' ');
+        line.extend(segment.into_iter().take(len));
+        i += len;
+    }
+    line
+}
+
+/// Draw `signals` within `window` as an ASCII timing diagram, one column per `step` time units
+/// and one line per signal.
+///
+/// 1-bit signals are drawn as `‾` and `_`, with `/` and `\` marking edges, `x` for unknown and
+/// `-` for high impedance. Wider signals show their value in hex after an `X` at each change,
+/// cut short if the value changes again before it fits. Each column shows the value at the start
+/// of its time step, so pulses shorter than `step` may not appear.
+pub fn ascii<I>(commands: I, signals: &[&Var], window: Range<u64>, step: u64,
+                out: &mut fmt::Write) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let step = ::std::cmp::max(step, 1);
+    let ids: Vec<_> = signals.iter().map(|v| v.code).collect();
+    let changes = try!(changes(commands, &ids, &window));
+    let width = signals.iter().map(|v| v.reference.chars().count()).max().unwrap_or(0);
+
+    for (var, changes) in signals.iter().zip(&changes) {
+        let columns: Vec<Option<&VarValue>> = (window.start..window.end).step_by(step as usize)
+            .map(|t| changes.iter().take_while(|c| c.0 <= t).last().map(|c| &c.1))
+            .collect();
+        try!(writeln!(out, "{:width$}  {}", var.reference, lane(var, &columns).trim_end(),
+            width = width));
+    }
+    Ok(())
+}
+
+#[test]
+fn diagram() {
+    use super::super::Parser;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var reg 8 \" data $end
+    $var wire 1 # oe $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! b0 \" z#
+    #10 1!
+    #20 0! b10100101 \" 1#
+    #30 1!
+    #40 0!
+    #50 1! b1011 \" x#
+    #60 0!
+    #70 1!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let vars: Vec<&Var> = header.iter_vars().map(|(_, v)| v).collect();
+
+    let mut out = String::new();
+    ascii(p, &vars, 10..80, 5, &mut out).unwrap();
+    assert_eq!(out, "\
+clk   ‾‾\\_/‾\\_/‾\\_/‾
+data  0 Xa5   Xb
+oe    --‾‾‾‾‾‾xxxxxx
+");
+}
@@ -0,0 +1,59 @@
+//! Rendering of timing diagrams
+
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::collections::HashMap;
+
+use {
+    IdCode,
+    VarValue,
+    Command,
+    Error,
+};
+
+mod ascii;
+pub use self::ascii::ascii;
+
+mod svg;
+pub use self::svg::{svg, SvgOptions, AnalogStyle};
+
+impl From<fmt::Error> for Error {
+    fn from(_: fmt::Error) -> Error {
+        Error::Io(io::Error::new(io::ErrorKind::Other, "formatter error"))
+    }
+}
+
+/// The changes to a signal within a window, starting with its value at the start of the window
+/// if it had one
+type Changes = Vec<(u64, VarValue)>;
+
+/// Collect the changes to the variables `ids` within `window`.
+fn changes<I>(commands: I, ids: &[IdCode], window: &Range<u64>) -> Result<Vec<Changes>, Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let mut index: HashMap<IdCode, Vec<usize>> = HashMap::new();
+    for (i, &id) in ids.iter().enumerate() {
+        index.entry(id).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut out: Vec<Changes> = vec![Vec::new(); ids.len()];
+    let mut time = 0;
+    for c in commands {
+        let c = try!(c);
+        if let Command::Timestamp(t) = c {
+            if t >= window.end { break; }
+            time = t;
+        } else if let Some((id, v)) = c.change_value() {
+            for &i in index.get(&id).map(|i| &i[..]).unwrap_or(&[]) {
+                let changes = &mut out[i];
+                let t = ::std::cmp::max(time, window.start);
+                // Changes before the window, or at the same time, replace the previous value
+                if changes.last().map_or(false, |&(prev, _)| prev == t) {
+                    changes.pop();
+                }
+                changes.push((t, v.clone()));
+            }
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,234 @@
+use std::io;
+use std::ops::Range;
+
+use {
+    Value,
+    Radix,
+    VarValue,
+    Var,
+    Command,
+    Error,
+};
+use super::changes;
+
+/// How real variables are drawn
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AnalogStyle {
+    /// Hold each value until the next change
+    Stepped,
+
+    /// Join successive values with straight lines
+    Linear,
+}
+
+/// Options for `svg` rendering
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    /// Width of the plot area in pixels, not counting the signal names
+    pub width: f64,
+
+    /// Height of each signal's lane in pixels
+    pub lane_height: f64,
+
+    /// Width reserved for signal names in pixels
+    pub label_width: f64,
+
+    /// How real variables are drawn
+    pub analog: AnalogStyle,
+}
+
+impl Default for SvgOptions {
+    fn default() -> SvgOptions {
+        SvgOptions { width: 800.0, lane_height: 30.0, label_width: 120.0,
+            analog: AnalogStyle::Stepped }
+    }
+}
+
+const GAP: f64 = 10.0;
+const MARGIN: f64 = 4.0;
+const SLANT: f64 = 3.0;
+const CHAR_WIDTH: f64 = 7.0;
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Pixel coordinates of a lane
+struct Lane {
+    top: f64,
+    bottom: f64,
+    x0: f64,
+    scale: f64,
+    start: u64,
+    end: u64,
+}
+
+impl Lane {
+    fn x(&self, t: u64) -> f64 {
+        self.x0 + (t - self.start) as f64 * self.scale
+    }
+
+    fn mid(&self) -> f64 {
+        (self.top + self.bottom) / 2.0
+    }
+
+    /// The end time of the change at `i`
+    fn until(&self, changes: &[(u64, VarValue)], i: usize) -> u64 {
+        changes.get(i + 1).map_or(self.end, |c| c.0)
+    }
+}
+
+fn digital(w: &mut io::Write, lane: &Lane, changes: &[(u64, VarValue)]) -> io::Result<()> {
+    let y = |v: &VarValue| match *v {
+        VarValue::Scalar(Value::V1) => lane.top + MARGIN,
+        VarValue::Scalar(Value::V0) => lane.bottom - MARGIN,
+        VarValue::Vector(ref b) if b.len() == 1 && b[0] == Value::V1 => lane.top + MARGIN,
+        VarValue::Vector(ref b) if b.len() == 1 && b[0] == Value::V0 => lane.bottom - MARGIN,
+        _ => lane.mid(),
+    };
+    let mut d = String::new();
+    for (i, &(t, ref v)) in changes.iter().enumerate() {
+        if i == 0 {
+            d.push_str(&format!("M{:.1} {:.1}", lane.x(t), y(v)));
+        } else {
+            d.push_str(&format!(" V{:.1}", y(v)));
+        }
+        d.push_str(&format!(" H{:.1}", lane.x(lane.until(changes, i))));
+    }
+    writeln!(w, r#"<path class="digital" d="{}"/>"#, d)
+}
+
+fn bus(w: &mut io::Write, lane: &Lane, changes: &[(u64, VarValue)]) -> io::Result<()> {
+    let (top, bottom, mid) = (lane.top + MARGIN, lane.bottom - MARGIN, lane.mid());
+    for (i, &(t, ref v)) in changes.iter().enumerate() {
+        let (x0, x1) = (lane.x(t), lane.x(lane.until(changes, i)));
+        let slant = f64::min(SLANT, (x1 - x0) / 2.0);
+        let text = v.format(Radix::Hex);
+        let class = if text.contains(|c| c == 'x' || c == 'X') { "bus unknown" } else { "bus" };
+        try!(writeln!(w, r#"<path class="{}" d="M{:.1} {:.1} L{:.1} {:.1} H{:.1} L{:.1} {:.1} "#,
+            class, x0, mid, x0 + slant, top, x1 - slant, x1, mid));
+        try!(writeln!(w, r#"    L{:.1} {:.1} H{:.1} Z"/>"#, x1 - slant, bottom, x0 + slant));
+        if (text.len() as f64) * CHAR_WIDTH + 2.0 * SLANT < x1 - x0 {
+            try!(writeln!(w, r#"<text class="value" x="{:.1}" y="{:.1}">{}</text>"#,
+                (x0 + x1) / 2.0, mid, escape(&text)));
+        }
+    }
+    Ok(())
+}
+
+fn analog(w: &mut io::Write, lane: &Lane, changes: &[(u64, VarValue)], style: AnalogStyle)
+    -> io::Result<()> {
+    let points: Vec<(u64, f64)> = changes.iter().filter_map(|&(t, ref v)| match *v {
+        VarValue::Real(r) if r.is_finite() => Some((t, r)),
+        _ => None,
+    }).collect();
+    if points.is_empty() { return Ok(()); }
+
+    let min = points.iter().map(|p| p.1).fold(::std::f64::INFINITY, f64::min);
+    let max = points.iter().map(|p| p.1).fold(::std::f64::NEG_INFINITY, f64::max);
+    let range = lane.bottom - lane.top - 2.0 * MARGIN;
+    let y = |v: f64| if max > min {
+        lane.bottom - MARGIN - (v - min) / (max - min) * range
+    } else {
+        lane.mid()
+    };
+
+    let mut d = format!("M{:.1} {:.1}", lane.x(points[0].0), y(points[0].1));
+    for &(t, v) in &points[1..] {
+        match style {
+            AnalogStyle::Stepped => d.push_str(&format!(" H{:.1} V{:.1}", lane.x(t), y(v))),
+            AnalogStyle::Linear => d.push_str(&format!(" L{:.1} {:.1}", lane.x(t), y(v))),
+        }
+    }
+    if style == AnalogStyle::Stepped {
+        d.push_str(&format!(" H{:.1}", lane.x(lane.end)));
+    }
+    writeln!(w, r#"<path class="analog" d="{}"/>"#, d)
+}
+
+/// Draw `signals` within `window` as an SVG timing diagram, with one lane per signal.
+///
+/// 1-bit signals are drawn as digital waveforms, with `x` and `z` at mid level. Wider signals are
+/// drawn as bus segments labelled with their value in hex where it fits. Real variables are drawn
+/// as analog traces scaled to their range within the window, stepped or linear as set by
+/// `options`. Nothing is drawn for a signal before its first value.
+pub fn svg<I>(commands: I, signals: &[&Var], window: Range<u64>, options: &SvgOptions,
+              w: &mut io::Write) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    if window.end <= window.start {
+        return Err(Error::Io(io::Error::new(io::ErrorKind::InvalidInput, "empty time window")));
+    }
+    let ids: Vec<_> = signals.iter().map(|v| v.code).collect();
+    let changes = try!(changes(commands, &ids, &window));
+
+    let width = options.label_width + options.width;
+    let height = signals.len() as f64 * (options.lane_height + GAP) + GAP;
+    try!(writeln!(w, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" "#,
+        width, height));
+    try!(writeln!(w, r#"    viewBox="0 0 {} {}">"#, width, height));
+    try!(writeln!(w, "<style>\
+        path {{ fill: none; stroke: #080; stroke-width: 1.5 }} \
+        path.bus {{ fill: #efe }} path.unknown {{ stroke: #c00; fill: #fee }} \
+        path.analog {{ stroke: #00c }} \
+        text {{ font: 12px monospace; dominant-baseline: middle }} \
+        text.value {{ text-anchor: middle }}\
+        </style>"));
+
+    for (i, (var, changes)) in signals.iter().zip(&changes).enumerate() {
+        let top = GAP + i as f64 * (options.lane_height + GAP);
+        let lane = Lane {
+            top: top,
+            bottom: top + options.lane_height,
+            x0: options.label_width,
+            scale: options.width / (window.end - window.start) as f64,
+            start: window.start,
+            end: window.end,
+        };
+        try!(writeln!(w, r#"<text x="4" y="{:.1}">{}</text>"#, lane.mid(), escape(&var.reference)));
+        if changes.is_empty() { continue; }
+        try!(match var.var_type {
+            ref t if t.is_real() => analog(w, &lane, changes, options.analog),
+            _ if var.size == 1 => digital(w, &lane, changes),
+            _ => bus(w, &lane, changes),
+        });
+    }
+
+    try!(writeln!(w, "</svg>"));
+    Ok(())
+}
+
+#[test]
+fn diagram() {
+    use super::super::Parser;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $var reg 8 \" data $end
+    $var real 64 # vout $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! b0 \" r0 #
+    #10 1! r1 #
+    #20 0! b10100101 \" r0.5 #
+    #30 1! bx \"
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let vars: Vec<&Var> = header.iter_vars().map(|(_, v)| v).collect();
+
+    let options = SvgOptions { width: 400.0, label_width: 100.0, ..Default::default() };
+    let mut out = Vec::new();
+    svg(p, &vars, 0..40, &options, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.starts_with("<svg "));
+    let clk = "M100.0 36.0 H200.0 V14.0 H300.0 V36.0 H400.0 V14.0 H500.0";
+    assert!(out.contains(&format!(r#"<path class="digital" d="{}"/>"#, clk)));
+    assert!(out.contains(r#"<text class="value" x="350.0" y="65.0">a5</text>"#));
+    assert!(out.contains(r#"<path class="bus unknown" "#));
+    let vout = "M100.0 116.0 H200.0 V94.0 H300.0 V105.0 H500.0";
+    assert!(out.contains(&format!(r#"<path class="analog" d="{}"/>"#, vout)));
+    assert!(out.trim_end().ends_with("</svg>"));
+}
@@ -0,0 +1,416 @@
+//! Parsing a VCD file that is already in memory
+
+use std::str::from_utf8;
+
+use {
+    Value,
+    PortState,
+    IdCode,
+    SimulationCommand,
+    Header,
+    Command,
+    CommandRef,
+    Error,
+    ErrorKind,
+    Position,
+};
+use read::{self, whitespace_byte, parse_timestamp, timestamp_command, parse_var_size};
+
+/// VCD parser for input held in a byte slice, such as a memory-mapped file. Acts as an iterator
+/// of `Command`s like `Parser`, and has the same `next_event` method for reading without
+/// allocating.
+///
+/// Tokens are read directly from the slice rather than copied through a buffer, and the strings
+/// in commands returned by `next_event` point into the input instead of being copied. Unlike
+/// `Parser`, the input does not need to end with whitespace.
+///
+/// `cargo bench --bench slice` compares the two parsers. `next_event` reads scalar changes in
+/// about half the time `Parser` takes, but gains less on vectors and reals, whose values cost the
+/// same to decode in both.
+///
+/// ```
+/// use vcd::{CommandRef, SliceParser};
+///
+/// let data = b"$scope module top $end $var wire 1 ! clk $end $upscope $end
+/// $enddefinitions $end #0 1! $comment rising $end";
+/// let mut parser = SliceParser::new(&data[..]);
+/// let header = parser.parse_header().unwrap();
+/// assert_eq!(header.scope.identifier, "top");
+///
+/// let mut comment = None;
+/// while let Some(c) = parser.next_event().unwrap() {
+///     if let CommandRef::Comment(s) = c { comment = Some(s.len()); }
+/// }
+/// assert_eq!(comment, Some(6));
+/// ```
+pub struct SliceParser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    simulation_command: Option<SimulationCommand>,
+    strict: bool,
+    unescape_strings: bool,
+    wide_timestamps: bool,
+    command_start: usize,
+
+    // Buffers holding the values of the last command, reused for each command
+    vector: ::BitVector,
+    ports: Vec<PortState>,
+    text: String,
+}
+
+/// A parsed command. Vector and port values, and string values that contained escapes, are in
+/// the parser's buffers.
+enum Parsed<'a> {
+    Command(CommandRef<'a>),
+    ChangeVector(IdCode),
+    ChangePort(IdCode, u8, u8),
+    ChangeString(IdCode),
+}
+
+impl<'a> SliceParser<'a> {
+    /// Create a parser reading `data`
+    pub fn new(data: &'a [u8]) -> SliceParser<'a> {
+        SliceParser {
+            data: data,
+            pos: 0,
+            simulation_command: None,
+            strict: true,
+            unescape_strings: false,
+            wide_timestamps: false,
+            command_start: 0,
+            vector: ::BitVector::new(),
+            ports: Vec::new(),
+            text: String::new(),
+        }
+    }
+
+    /// Set whether an unrecognized `$keyword` is an error, as for `Parser::set_strict`
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Set whether to decode escapes in string value changes, as for
+    /// `Parser::set_unescape_strings`
+    pub fn set_unescape_strings(&mut self, unescape: bool) {
+        self.unescape_strings = unescape;
+    }
+
+    /// Set whether a timestamp too large for a `u64` is returned as a `Command::WideTimestamp`, as
+    /// for `Parser::set_wide_timestamps`
+    pub fn set_wide_timestamps(&mut self, wide: bool) {
+        self.wide_timestamps = wide;
+    }
+
+    /// The number of bytes of input parsed so far
+    pub fn bytes_read(&self) -> u64 {
+        self.pos as u64
+    }
+
+    fn offset_position(&self, offset: usize) -> Position {
+        let before = &self.data[..offset];
+        let line_start = before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        Position {
+            line: before.iter().filter(|&&b| b == b'\n').count() as u64 + 1,
+            column: (offset - line_start) as u64 + 1,
+            offset: offset as u64,
+        }
+    }
+
+    /// The position of the next byte to be read
+    pub fn current_position(&self) -> Position {
+        self.offset_position(self.pos)
+    }
+
+    /// The position of the start of the last command returned
+    pub fn command_position(&self) -> Position {
+        self.offset_position(self.command_start)
+    }
+
+    fn error(&self, kind: ErrorKind) -> Error {
+        Error::Syntax { kind: kind, position: self.current_position() }
+    }
+
+    /// Skip whitespace up to the start of the next token. Returns `false` at the end of the input.
+    fn skip_whitespace(&mut self) -> bool {
+        while self.pos < self.data.len() && whitespace_byte(self.data[self.pos]) {
+            self.pos += 1;
+        }
+        self.pos < self.data.len()
+    }
+
+    fn read_token(&mut self) -> Result<&'a [u8], Error> {
+        if !self.skip_whitespace() { return Err(self.error(ErrorKind::UnexpectedEof)); }
+        let data = self.data;
+        let start = self.pos;
+        while self.pos < data.len() && !whitespace_byte(data[self.pos]) {
+            self.pos += 1;
+        }
+        Ok(&data[start..self.pos])
+    }
+
+    fn read_token_str(&mut self) -> Result<&'a str, Error> {
+        Ok(try!(from_utf8(try!(self.read_token()))))
+    }
+
+    fn parse_token<T>(&self, tok: &[u8]) -> Result<T, Error>
+        where T: ::std::str::FromStr, Error: From<T::Err> {
+        if tok == b"$end" {
+            return Err(self.error(ErrorKind::UnexpectedEnd));
+        }
+        Ok(try!(try!(from_utf8(tok)).parse()))
+    }
+
+    fn read_token_parse<T>(&mut self) -> Result<T, Error>
+        where T: ::std::str::FromStr, Error: From<T::Err> {
+        let tok = try!(self.read_token());
+        self.parse_token(tok)
+    }
+
+    fn read_command_end(&mut self) -> Result<(), Error> {
+        let tok = try!(self.read_token());
+        if tok == b"$end" { Ok(()) } else { Err(self.error(ErrorKind::ExpectedEnd)) }
+    }
+
+    /// Read the text of a command up to its `$end`
+    fn read_string_command(&mut self) -> Result<&'a str, Error> {
+        let data = self.data;
+        match data[self.pos..].windows(4).position(|w| w == b"$end") {
+            Some(i) => {
+                let text = &data[self.pos..self.pos + i];
+                self.pos += i + 4;
+                Ok(try!(from_utf8(text)).trim())
+            }
+            None => {
+                self.pos = data.len();
+                Err(self.error(ErrorKind::UnexpectedEof))
+            }
+        }
+    }
+
+    fn parse_command(&mut self) -> Result<Parsed<'a>, Error> {
+        use super::CommandRef::*;
+        use super::SimulationCommand::*;
+
+        let cmd = try!(self.read_token());
+        Ok(Parsed::Command(match cmd {
+            b"comment" => Comment(try!(self.read_string_command())),
+            b"date" => Date(try!(self.read_string_command())),
+            b"version" => Version(try!(self.read_string_command())),
+            b"attrbegin" => Attribute(try!(self.read_string_command())),
+            b"attrend" => { try!(self.read_command_end()); AttributeEnd }
+            b"timescale" => {
+                let tok = try!(self.read_token_str());
+                // Support both "1ps" and "1 ps"
+                let (num_str, unit_str) = match tok.find(|c: char| !c.is_numeric()) {
+                    Some(idx) => (&tok[0..idx], &tok[idx..]),
+                    None => (tok, try!(self.read_token_str())),
+                };
+                try!(self.read_command_end());
+                Timescale(try!(num_str.parse()), try!(unit_str.parse()))
+            }
+            b"scope" => {
+                let scope_type = try!(self.read_token_parse());
+                let identifier = try!(self.read_token_str());
+                try!(self.read_command_end());
+                ScopeDef(scope_type, identifier)
+            }
+            b"upscope" => { try!(self.read_command_end()); Upscope }
+            b"var" => {
+                let var_type = try!(self.read_token_parse());
+                let size = try!(parse_var_size(try!(self.read_token_str())));
+                let code = try!(self.read_token_parse());
+                let reference = try!(self.read_token_str());
+                let tok = try!(self.read_token());
+                if tok == b"$end" {
+                    VarDef(var_type, size, code, reference, None)
+                } else {
+                    let index = try!(try!(from_utf8(tok)).parse());
+                    try!(self.read_command_end());
+                    VarDef(var_type, size, code, reference, Some(index))
+                }
+            }
+            b"enddefinitions" => { try!(self.read_command_end()); Enddefinitions }
+
+            b"dumpall" => self.begin_simulation_command(Dumpall),
+            b"dumpoff" => self.begin_simulation_command(Dumpoff),
+            b"dumpon" => self.begin_simulation_command(Dumpon),
+            b"dumpvars" => self.begin_simulation_command(Dumpvars),
+            b"dumpports" => self.begin_simulation_command(Dumpports),
+            b"dumpportsall" => self.begin_simulation_command(Dumpportsall),
+            b"dumpportsoff" => self.begin_simulation_command(Dumpportsoff),
+            b"dumpportson" => self.begin_simulation_command(Dumpportson),
+
+            b"end" => match self.simulation_command.take() {
+                Some(c) => End(c),
+                None => return Err(self.error(ErrorKind::UnmatchedEnd)),
+            },
+
+            _ if self.strict => {
+                let keyword = String::from_utf8_lossy(cmd).into_owned();
+                return Err(self.error(ErrorKind::InvalidKeyword(keyword)));
+            }
+            _ => {
+                let keyword = try!(from_utf8(cmd));
+                Unknown(keyword, try!(self.read_string_command()))
+            }
+        }))
+    }
+
+    fn begin_simulation_command(&mut self, c: SimulationCommand) -> CommandRef<'a> {
+        self.simulation_command = Some(c);
+        CommandRef::Begin(c)
+    }
+
+    /// Read the `IdCode` following a value, either in the rest of the value's token or in the
+    /// next token
+    fn read_id(&mut self, rest: &[u8]) -> Result<IdCode, Error> {
+        if rest.is_empty() { self.read_token_parse() } else { self.parse_token(rest) }
+    }
+
+    fn next_command(&mut self) -> Option<Result<Parsed<'a>, Error>> {
+        if !self.skip_whitespace() { return None; }
+        self.command_start = self.pos;
+        let b = self.data[self.pos];
+        self.pos += 1;
+        Some(match b {
+            b'$' => self.parse_command(),
+            b'#' => self.read_token().and_then(parse_timestamp)
+                .and_then(|t| timestamp_command(t, self.wide_timestamps)).map(Parsed::Command),
+            b'0' | b'1' | b'z' | b'Z' | b'x' | b'X' |
+            b'u' | b'U' | b'w' | b'W' | b'l' | b'L' | b'h' | b'H' | b'-' => {
+                let data = self.data;
+                let end = data[self.pos..].iter().position(|&b| whitespace_byte(b))
+                    .map_or(data.len(), |i| self.pos + i);
+                let rest = &data[self.pos..end];
+                self.pos = end;
+                Value::parse(b).and_then(|v| {
+                    let id = try!(self.read_id(rest));
+                    Ok(Parsed::Command(CommandRef::ChangeScalar(id, v)))
+                })
+            }
+            b'b' | b'B' => self.parse_vector(),
+            b'r' | b'R' => self.read_token_str().and_then(|tok| {
+                let val = try!(tok.parse());
+                let id = try!(self.read_token_parse());
+                Ok(Parsed::Command(CommandRef::ChangeReal(id, val)))
+            }),
+            b's' | b'S' => self.parse_string(),
+            b'p' => self.parse_port(),
+            _ => Err(Error::Syntax {
+                kind: ErrorKind::UnexpectedCharacter(b),
+                position: self.offset_position(self.command_start),
+            }),
+        }.map_err(|e| match e {
+            Error::Parse(msg) => self.error(ErrorKind::Other(msg)),
+            Error::Invalid(kind) => self.error(kind),
+            e => e,
+        }))
+    }
+
+    fn parse_vector(&mut self) -> Result<Parsed<'a>, Error> {
+        let tok = try!(self.read_token());
+        self.vector.clear();
+        for &b in tok {
+            self.vector.push(try!(Value::parse(b)));
+        }
+        let id = try!(self.read_token_parse());
+        Ok(Parsed::ChangeVector(id))
+    }
+
+    fn parse_string(&mut self) -> Result<Parsed<'a>, Error> {
+        let tok = try!(self.read_token());
+        let id = try!(self.read_token_parse());
+        if !self.unescape_strings || !tok.contains(&b'\\') {
+            return Ok(Parsed::Command(CommandRef::ChangeString(id, try!(from_utf8(tok)))));
+        }
+        let mut s = Vec::new();
+        read::unescape_string(tok, &mut s);
+        self.text.clear();
+        self.text.push_str(try!(from_utf8(&s)));
+        Ok(Parsed::ChangeString(id))
+    }
+
+    fn parse_port(&mut self) -> Result<Parsed<'a>, Error> {
+        let tok = try!(self.read_token());
+        self.ports.clear();
+        for &b in tok {
+            self.ports.push(try!(PortState::parse(b)));
+        }
+        let strength0 = try!(self.read_token_parse());
+        let strength1 = try!(self.read_token_parse());
+        if strength0 > 7 || strength1 > 7 { return Err(Error::Parse("Invalid port strength")); }
+        let id = try!(self.read_token_parse());
+        Ok(Parsed::ChangePort(id, strength0, strength1))
+    }
+
+    /// Parse the next command without allocating, returning `None` at the end of the input.
+    ///
+    /// Vector and port values are in buffers in the parser that are reused for each command, so
+    /// the command must be dropped before the parser is advanced again.
+    pub fn next_event(&mut self) -> Result<Option<CommandRef>, Error> {
+        Ok(match self.next_command() {
+            Some(Ok(Parsed::Command(c))) => Some(c),
+            Some(Ok(Parsed::ChangeVector(id))) => Some(CommandRef::ChangeVector(id, &self.vector)),
+            Some(Ok(Parsed::ChangePort(id, s0, s1))) => {
+                Some(CommandRef::ChangePort(id, &self.ports, s0, s1))
+            }
+            Some(Ok(Parsed::ChangeString(id))) => Some(CommandRef::ChangeString(id, &self.text)),
+            Some(Err(e)) => return Err(e),
+            None => None,
+        })
+    }
+
+    /// Parse the header into a `Header` struct, leaving the parser at the start of the data
+    /// section, as for `Parser::parse_header`
+    pub fn parse_header(&mut self) -> Result<Header, Error> {
+        read::parse_header(self).map_err(|e| match e {
+            Error::Parse(msg) => self.error(ErrorKind::Other(msg)),
+            Error::Invalid(kind) => self.error(kind),
+            e => e,
+        })
+    }
+}
+
+impl<'a> Iterator for SliceParser<'a> {
+    type Item = Result<Command, Error>;
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        match self.next_event() {
+            Ok(Some(c)) => Some(Ok(c.to_command())),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[test]
+fn same_as_parser() {
+    use super::Parser;
+
+    let sample = b"$date today $end $timescale 10ps $end
+$scope module top $end
+$var wire 8 # data [7:0] $end $var real 64 r x $end $var string 1 s msg $end
+$var port [3:0] <0 p $end
+$upscope $end $enddefinitions $end
+$dumpvars bxxxxxxxx # 0! r0 r $end
+#10 b10000001 # 1 ! r1.5e3 r shello s pUD10 6 0 <0 $comment two
+lines $end
+#20 z!
+";
+    let mut parser = Parser::new(&sample[..]);
+    let mut slice = SliceParser::new(&sample[..]);
+    assert_eq!(format!("{:?}", slice.parse_header().unwrap()),
+        format!("{:?}", parser.parse_header().unwrap()));
+    let expected: Vec<Command> = parser.map(Result::unwrap).collect();
+    assert_eq!(slice.map(Result::unwrap).collect::<Vec<_>>(), expected);
+
+    // No trailing whitespace is needed, and errors have positions
+    let mut slice = SliceParser::new(&b"#1 0!\n#2 1!"[..]);
+    assert_eq!(slice.by_ref().map(Result::unwrap).count(), 4);
+    let mut slice = SliceParser::new(&b"#1 0!\n#2 b12 !"[..]);
+    match slice.nth(3) {
+        Some(Err(Error::Syntax { kind: ErrorKind::InvalidValueChar(b'2'), position })) => {
+            assert_eq!((position.line, position.column), (2, 7));
+        }
+        other => panic!("{:?}", other),
+    }
+}
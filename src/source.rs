@@ -0,0 +1,109 @@
+//! A common interface to the waveform file readers
+
+use std::io;
+use std::collections::HashSet;
+
+use {
+    IdCode,
+    VarValue,
+    Header,
+    Command,
+    Error,
+    Parser,
+};
+
+/// A reader of waveform data: a header describing the variable hierarchy, followed by a stream
+/// of `Command`s.
+///
+/// `Parser` implements this for VCD files, as do `FstParser` and `GhwParser` when their features
+/// are enabled, so code written against this trait works with any of them.
+pub trait WaveformSource: Iterator<Item=Result<Command, Error>> + Sized {
+    /// Read the header, containing the variable hierarchy. This must be called before iterating.
+    fn parse_header(&mut self) -> Result<Header, Error>;
+
+    /// Iterate over the value changes of all variables, as `(time, id, value)`
+    fn changes(self) -> Changes<Self> {
+        Changes { source: self, filter: None, time: 0 }
+    }
+
+    /// Iterate over the value changes of the variables in `ids`, as `(time, id, value)`
+    fn signal_changes(self, ids: &[IdCode]) -> Changes<Self> {
+        Changes { source: self, filter: Some(ids.iter().cloned().collect()), time: 0 }
+    }
+}
+
+impl<R: io::Read> WaveformSource for Parser<R> {
+    fn parse_header(&mut self) -> Result<Header, Error> {
+        Parser::parse_header(self)
+    }
+}
+
+#[cfg(feature = "fst")]
+impl<R: io::BufRead + io::Seek> WaveformSource for ::fst::FstParser<R> {
+    fn parse_header(&mut self) -> Result<Header, Error> {
+        ::fst::FstParser::parse_header(self)
+    }
+}
+
+#[cfg(feature = "ghw")]
+impl WaveformSource for ::ghw::GhwParser {
+    fn parse_header(&mut self) -> Result<Header, Error> {
+        ::ghw::GhwParser::parse_header(self)
+    }
+}
+
+/// Iterator over the value changes from a `WaveformSource`, returned by
+/// `WaveformSource::changes` and `WaveformSource::signal_changes`.
+pub struct Changes<S> {
+    source: S,
+    filter: Option<HashSet<IdCode>>,
+    time: u64,
+}
+
+impl<S: WaveformSource> Iterator for Changes<S> {
+    type Item = Result<(u64, IdCode, VarValue), Error>;
+
+    fn next(&mut self) -> Option<Result<(u64, IdCode, VarValue), Error>> {
+        for c in &mut self.source {
+            let c = match c {
+                Ok(c) => c,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Command::Timestamp(t) = c {
+                self.time = t;
+            } else if let Some((id, v)) = c.change_value() {
+                if self.filter.as_ref().map_or(true, |f| f.contains(&id)) {
+                    return Some(Ok((self.time, id, v)));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[test]
+fn parser_changes() {
+    use super::Value::*;
+
+    fn changes_of_b<S: WaveformSource>(mut source: S) -> Vec<(u64, IdCode, VarValue)> {
+        let header = source.parse_header().unwrap();
+        let id = header.find_var(&["top", "b"]).unwrap().code;
+        source.signal_changes(&[id]).map(Result::unwrap).collect()
+    }
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! a $end
+    $var wire 2 \" b $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! b00 \"
+    #5 1!
+    #10 b01 \"
+    ";
+
+    assert_eq!(changes_of_b(Parser::new(&sample[..])), vec![
+        (0, IdCode(1), VarValue::Vector(vec![V0, V0].into())),
+        (10, IdCode(1), VarValue::Vector(vec![V0, V1].into())),
+    ]);
+}
@@ -0,0 +1,296 @@
+//! Per-signal activity statistics: toggle counts, `x`/`z` occupancy and value histograms
+//!
+//! `stats` first collects the changes to the selected signals from the command stream, then
+//! analyses each signal independently. With the `rayon` feature, signals are analysed in
+//! parallel. `Collector` computes the same statistics in a single pass as commands are read,
+//! without keeping the changes.
+
+use std::collections::{BTreeMap, HashMap};
+
+use {
+    Radix,
+    Value,
+    IdCode,
+    VarValue,
+    Var,
+    Command,
+    Error,
+};
+
+/// Activity statistics for one signal, from `stats`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SignalStats {
+    /// Time from 0 to the end of the data
+    pub time: u64,
+
+    /// Number of value changes, not counting the first assignment
+    pub changes: u64,
+
+    /// Number of `0`-`1` and `1`-`0` transitions, summed over all bits
+    pub toggles: u64,
+
+    /// Time of the first assignment, if any
+    pub first_change: Option<u64>,
+
+    /// Time of the last assignment that changed the value, or of the first assignment
+    pub last_change: Option<u64>,
+
+    /// Time during which any bit was `x` or `z`, including any time before the first assignment
+    pub unknown_time: u64,
+
+    /// Total time spent at each value, keyed by the value as text with vectors in binary
+    pub histogram: BTreeMap<String, u64>,
+}
+
+impl SignalStats {
+    /// The fraction of the total time during which any bit was `x` or `z`
+    pub fn unknown_fraction(&self) -> f64 {
+        if self.time == 0 { 0.0 } else { self.unknown_time as f64 / self.time as f64 }
+    }
+}
+
+fn unknown_bit(b: Value) -> bool {
+    match b.to_four_state() {
+        Value::X | Value::Z => true,
+        _ => false,
+    }
+}
+
+fn is_unknown(v: &VarValue) -> bool {
+    match *v {
+        VarValue::Scalar(b) => unknown_bit(b),
+        VarValue::Vector(ref v) => v.iter().any(unknown_bit),
+        _ => false,
+    }
+}
+
+fn bit_toggles(from: &VarValue, to: &VarValue) -> u64 {
+    fn toggle(a: Value, b: Value) -> bool {
+        match (a.to_four_state(), b.to_four_state()) {
+            (Value::V0, Value::V1) | (Value::V1, Value::V0) => true,
+            _ => false,
+        }
+    }
+    match (from, to) {
+        (&VarValue::Scalar(a), &VarValue::Scalar(b)) => toggle(a, b) as u64,
+        (&VarValue::Vector(ref a), &VarValue::Vector(ref b)) => {
+            // Compare from the least significant bit, as shorter vectors are missing high bits
+            a.iter().rev().zip(b.iter().rev()).filter(|&(a, b)| toggle(a, b)).count() as u64
+        }
+        _ => 0,
+    }
+}
+
+/// Statistics for one signal, built up from its changes in time order
+#[derive(Debug, Clone, Default)]
+struct Accumulator {
+    stats: SignalStats,
+    /// The value and the time it was assigned
+    current: Option<(u64, VarValue)>,
+}
+
+impl Accumulator {
+    /// Account for the time spent at the current value, up to `until`
+    fn close(&mut self, until: u64) {
+        if let Some((time, ref v)) = self.current {
+            let duration = until.saturating_sub(time);
+            *self.stats.histogram.entry(v.format(Radix::Binary)).or_insert(0) += duration;
+            if is_unknown(v) { self.stats.unknown_time += duration; }
+        }
+    }
+
+    fn change(&mut self, time: u64, v: VarValue) {
+        self.close(time);
+        match self.current {
+            None => {
+                self.stats.unknown_time = time;
+                self.stats.first_change = Some(time);
+                self.stats.last_change = Some(time);
+            }
+            Some((_, ref prev)) => {
+                if *prev != v {
+                    self.stats.changes += 1;
+                    self.stats.last_change = Some(time);
+                }
+                self.stats.toggles += bit_toggles(prev, &v);
+            }
+        }
+        self.current = Some((time, v));
+    }
+
+    fn finish(mut self, end: u64) -> SignalStats {
+        self.close(end);
+        if self.current.is_none() { self.stats.unknown_time = end; }
+        self.stats.time = end;
+        self.stats
+    }
+}
+
+/// Compute the statistics for one signal from its changes, up to time `end`
+fn analyze(changes: &[(u64, VarValue)], end: u64) -> SignalStats {
+    let mut acc = Accumulator::default();
+    for &(time, ref v) in changes {
+        acc.change(time, v.clone());
+    }
+    acc.finish(end)
+}
+
+#[cfg(feature = "rayon")]
+fn analyze_all(changes: &[Vec<(u64, VarValue)>], end: u64) -> Vec<SignalStats> {
+    use rayon::prelude::*;
+    changes.par_iter().map(|c| analyze(c, end)).collect()
+}
+
+#[cfg(not(feature = "rayon"))]
+fn analyze_all(changes: &[Vec<(u64, VarValue)>], end: u64) -> Vec<SignalStats> {
+    changes.iter().map(|c| analyze(c, end)).collect()
+}
+
+/// Compute activity statistics for each of `signals` over a VCD data section, ending at its last
+/// timestamp.
+///
+/// A repeated assignment of the same value is not counted as a change. Time before a signal is
+/// first assigned counts as unknown but does not appear in its histogram.
+pub fn stats<I>(commands: I, signals: &[&Var]) -> Result<Vec<SignalStats>, Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let mut index: HashMap<IdCode, Vec<usize>> = HashMap::new();
+    for (i, v) in signals.iter().enumerate() {
+        index.entry(v.code).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut changes: Vec<Vec<(u64, VarValue)>> = vec![Vec::new(); signals.len()];
+    let mut time = 0;
+    for c in commands {
+        let c = try!(c);
+        if let Command::Timestamp(t) = c {
+            time = t;
+        } else if let Some((id, v)) = c.change_value() {
+            for &i in index.get(&id).map(|i| &i[..]).unwrap_or(&[]) {
+                // A later change at the same time replaces the earlier one
+                if changes[i].last().map_or(false, |&(t, _)| t == time) {
+                    changes[i].pop();
+                }
+                changes[i].push((time, v.clone()));
+            }
+        }
+    }
+
+    Ok(analyze_all(&changes, time))
+}
+
+/// Computes the same statistics as `stats` in a single pass, as each command of the data section
+/// is passed to `push`, keeping only the current value of each signal rather than all of its
+/// changes.
+///
+/// ```
+/// use vcd::{Parser, Var};
+/// use vcd::stats::Collector;
+///
+/// let data = b"$scope module top $end $var wire 1 ! clk $end $upscope $end
+/// $enddefinitions $end #0 0! #5 1! #10 0! #20
+/// ";
+/// let mut parser = Parser::new(&data[..]);
+/// let header = parser.parse_header().unwrap();
+/// let vars: Vec<&Var> = header.iter_vars().map(|(_, v)| v).collect();
+/// let mut collector = Collector::new(&vars);
+/// for c in parser {
+///     collector.push(&c.unwrap());
+/// }
+/// let stats = collector.finish();
+/// assert_eq!((stats[0].toggles, stats[0].histogram["0"]), (2, 15));
+/// ```
+pub struct Collector {
+    index: HashMap<IdCode, Vec<usize>>,
+    signals: Vec<Accumulator>,
+    /// The change to each signal at the current time, applied once the time advances, so that
+    /// only the last of several changes at the same time counts
+    pending: Vec<Option<VarValue>>,
+    /// The signals with a pending change
+    dirty: Vec<usize>,
+    time: u64,
+}
+
+impl Collector {
+    /// Collect statistics for each of `signals`, in the same order
+    pub fn new(signals: &[&Var]) -> Collector {
+        let mut index: HashMap<IdCode, Vec<usize>> = HashMap::new();
+        for (i, v) in signals.iter().enumerate() {
+            index.entry(v.code).or_insert_with(Vec::new).push(i);
+        }
+        Collector {
+            index: index,
+            signals: vec![Accumulator::default(); signals.len()],
+            pending: vec![None; signals.len()],
+            dirty: Vec::new(),
+            time: 0,
+        }
+    }
+
+    fn apply_pending(&mut self) {
+        for i in self.dirty.drain(..) {
+            if let Some(v) = self.pending[i].take() { self.signals[i].change(self.time, v); }
+        }
+    }
+
+    /// Account for a command of the data section
+    pub fn push(&mut self, c: &Command) {
+        if let Command::Timestamp(t) = *c {
+            if t != self.time {
+                self.apply_pending();
+                self.time = t;
+            }
+        } else if let Some((id, v)) = c.change_value() {
+            for &i in self.index.get(&id).map(|i| &i[..]).unwrap_or(&[]) {
+                if self.pending[i].is_none() { self.dirty.push(i); }
+                self.pending[i] = Some(v.clone());
+            }
+        }
+    }
+
+    /// The statistics for each signal, ending at the last timestamp
+    pub fn finish(mut self) -> Vec<SignalStats> {
+        self.apply_pending();
+        let end = self.time;
+        self.signals.into_iter().map(|acc| acc.finish(end)).collect()
+    }
+}
+
+#[test]
+fn activity() {
+    use super::Parser;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! en $end
+    $var reg 4 \" count $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0! bxxxx \"
+    #10 1! b0000 \"
+    #20 b0011 \"
+    #30 0! b0011 \"
+    #40 b1100 \"
+    #50 1!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let vars: Vec<&Var> = header.iter_vars().map(|(_, v)| v).collect();
+
+    let stats = stats(p, &vars).unwrap();
+    assert_eq!((stats[0].changes, stats[0].toggles, stats[0].unknown_time), (3, 3, 0));
+    assert_eq!(stats[0].histogram["0"], 30);
+    assert_eq!(stats[0].histogram["1"], 20);
+
+    assert_eq!((stats[1].changes, stats[1].toggles, stats[1].unknown_time), (3, 6, 10));
+    assert_eq!(stats[1].histogram["0011"], 20);
+    assert_eq!(stats[1].histogram["1100"], 10);
+    assert_eq!(stats[1].unknown_fraction(), 0.2);
+    assert_eq!((stats[1].first_change, stats[1].last_change), (Some(0), Some(40)));
+
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    let mut collector = Collector::new(&vars);
+    for c in p { collector.push(&c.unwrap()); }
+    assert_eq!(collector.finish(), stats);
+}
@@ -0,0 +1,203 @@
+//! Parsing a VCD from an asynchronous reader (requires the `futures` feature)
+
+use std::io::{self, Read};
+use std::pin::Pin;
+
+use futures::io::AsyncRead;
+use futures::stream::Stream;
+use futures::task::{Context, Poll};
+use futures::future::Future;
+
+use {
+    Header,
+    Command,
+    Error,
+    Parser,
+};
+use read::parse_header;
+
+/// Size of the reads from the asynchronous reader
+const READ_SIZE: usize = 32 * 1024;
+
+/// The bytes received from the asynchronous reader that the parser has not yet read
+#[derive(Default)]
+struct Received {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for Received {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!((&self.data[self.pos..]).read(buf));
+        self.pos += n;
+        if self.pos == self.data.len() {
+            self.data.clear();
+            self.pos = 0;
+        }
+        Ok(n)
+    }
+}
+
+/// VCD parser wrapping a `futures::io::AsyncRead`, such as a socket, which decodes commands as
+/// their bytes arrive without blocking the thread while waiting for more.
+///
+/// The header is parsed with `parse_header`, then the commands of the data section are read as a
+/// `Stream`. Input received so far is held by a `Parser` in resumable mode, so a command split
+/// across reads is parsed once the rest of it arrives.
+///
+/// ```
+/// extern crate futures;
+/// # extern crate vcd;
+/// use futures::executor::block_on;
+/// use futures::stream::StreamExt;
+///
+/// # fn main() {
+/// let data = b"$scope module top $end $var wire 1 ! clk $end $upscope $end
+/// $enddefinitions $end #0 0! #5 1!
+/// ";
+/// let mut parser = vcd::AsyncParser::new(&data[..]);
+/// let header = block_on(parser.parse_header()).unwrap();
+/// assert_eq!(header.scope.identifier, "top");
+/// let commands: Vec<_> = block_on(parser.collect());
+/// assert_eq!(commands.len(), 4);
+/// # }
+/// ```
+pub struct AsyncParser<R> {
+    reader: R,
+    parser: Parser<Received>,
+    buf: Box<[u8]>,
+    eof: bool,
+    header: Vec<Command>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncParser<R> {
+    /// Create a parser wrapping an asynchronous reader
+    pub fn new(reader: R) -> AsyncParser<R> {
+        let mut parser = Parser::new(Received::default());
+        parser.set_resumable(true);
+        AsyncParser {
+            reader: reader,
+            parser: parser,
+            buf: vec![0; READ_SIZE].into_boxed_slice(),
+            eof: false,
+            header: Vec::new(),
+        }
+    }
+
+    /// Set whether an unrecognized `$keyword` is an error, as described for `Parser::set_strict`
+    pub fn set_strict(&mut self, strict: bool) {
+        self.parser.set_strict(strict);
+    }
+
+    /// Parse the header of the VCD into a `Header` struct. Once the returned future completes,
+    /// the rest of the input can be read as a `Stream` of commands.
+    pub fn parse_header(&mut self) -> ParseHeader<R> {
+        ParseHeader { parser: self }
+    }
+
+    /// Parse the next command, reading from the reader until the input received so far holds a
+    /// complete command or the input ends
+    fn poll_command(&mut self, cx: &mut Context) -> Poll<Option<Result<Command, Error>>> {
+        loop {
+            if let Some(c) = self.parser.next() {
+                return Poll::Ready(Some(c));
+            }
+            if self.eof {
+                return Poll::Ready(None);
+            }
+            match Pin::new(&mut self.reader).poll_read(cx, &mut self.buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::Interrupted => (),
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(Error::from(e)))),
+                Poll::Ready(Ok(0)) => {
+                    // An incomplete command at the end of the input is now an error
+                    self.eof = true;
+                    self.parser.set_resumable(false);
+                }
+                Poll::Ready(Ok(n)) => {
+                    self.parser.get_mut().data.extend_from_slice(&self.buf[..n]);
+                }
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for AsyncParser<R> {
+    type Item = Result<Command, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_command(cx)
+    }
+}
+
+/// Future parsing the header of an `AsyncParser`, from `AsyncParser::parse_header`
+pub struct ParseHeader<'a, R: 'a> {
+    parser: &'a mut AsyncParser<R>,
+}
+
+impl<'a, R: AsyncRead + Unpin> Future for ParseHeader<'a, R> {
+    type Output = Result<Header, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let parser = &mut *self.get_mut().parser;
+        loop {
+            match parser.poll_command(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(Ok(Command::Enddefinitions))) => {
+                    parser.header.push(Command::Enddefinitions);
+                    break;
+                }
+                Poll::Ready(Some(Ok(c))) => parser.header.push(c),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => break,
+            }
+        }
+        let mut commands = parser.header.drain(..).map(Ok);
+        Poll::Ready(parse_header(&mut commands))
+    }
+}
+
+#[test]
+fn trickle() {
+    use futures::executor::block_on;
+    use futures::stream::StreamExt;
+    use {IdCode, Value, ErrorKind};
+
+    /// Returns a few bytes at a time, and is not ready on every other poll
+    struct Trickle { data: &'static [u8], ready: bool }
+
+    impl AsyncRead for Trickle {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8])
+            -> Poll<io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = buf.len().min(self.data.len()).min(5);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    let sample = b"$scope module top $end $var wire 1 ! a $end $var wire 8 \" b $end
+$upscope $end $enddefinitions $end
+#0 0! b10101010 \" $comment split across reads $end
+#123456 1!
+";
+    let mut p = AsyncParser::new(Trickle { data: sample, ready: false });
+    let header = block_on(p.parse_header()).unwrap();
+    assert_eq!(header.find_var(&["top", "b"]).unwrap().size, 8);
+    let commands: Vec<Command> = block_on(p.map(Result::unwrap).collect());
+    assert_eq!(commands.len(), 6);
+    assert_eq!(commands[4], Command::Timestamp(123456));
+    assert_eq!(commands[5], Command::ChangeScalar(IdCode(0), Value::V1));
+
+    let mut p = AsyncParser::new(Trickle { data: &sample[..sample.len() - 4], ready: false });
+    block_on(p.parse_header()).unwrap();
+    match block_on(p.collect::<Vec<_>>()).pop() {
+        Some(Err(Error::Syntax { kind: ErrorKind::UnexpectedEof, .. })) => (),
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,883 @@
+//! Adapters that transform a stream of `Command`s
+
+use std::io;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use {
+    Timescale,
+    Value,
+    IdCode,
+    VarValue,
+    Scope,
+    ScopeItem,
+    Var,
+    Header,
+    SimulationCommand,
+    Command,
+    Error,
+    Writer,
+};
+
+/// The current value of every variable seen in a command stream, in order of first assignment.
+#[derive(Debug, Default, Clone)]
+struct State {
+    index: HashMap<IdCode, usize>,
+    values: Vec<(IdCode, VarValue)>,
+}
+
+impl State {
+    fn update(&mut self, c: &Command) {
+        if let Some((id, v)) = c.change_value() {
+            if let Some(&i) = self.index.get(&id) {
+                self.values[i].1 = v;
+            } else {
+                self.index.insert(id, self.values.len());
+                self.values.push((id, v));
+            }
+        }
+    }
+
+    fn get(&self, id: IdCode) -> Option<&VarValue> {
+        self.index.get(&id).map(|&i| &self.values[i].1)
+    }
+
+    /// Queue a `$dumpall` or `$dumpvars` block containing the value of every variable
+    fn dump(&self, kind: SimulationCommand, out: &mut VecDeque<Command>) {
+        out.push_back(Command::Begin(kind));
+        out.extend(self.values.iter().map(|&(id, ref v)| v.to_command(id)));
+        out.push_back(Command::End(kind));
+    }
+}
+
+/// Iterator adapter that ends a command stream at a given time, followed by a `$dumpall` of the
+/// state of all variables at that time.
+///
+/// Changes at exactly the end time are kept. If the stream is cut inside a simulation command,
+/// that command is ended first.
+pub struct Truncate<I> {
+    commands: I,
+    end: u64,
+    time: Option<u64>,
+    open: Option<SimulationCommand>,
+    state: State,
+    queue: VecDeque<Command>,
+    done: bool,
+}
+
+impl<I> Truncate<I> where I: Iterator<Item=Result<Command, Error>> {
+    /// Wrap the data section of a VCD, cutting it at timestamp `end`
+    pub fn new<C>(commands: C, end: u64) -> Truncate<I>
+        where C: IntoIterator<Item=Result<Command, Error>, IntoIter=I> {
+        Truncate {
+            commands: commands.into_iter(),
+            end: end,
+            time: None,
+            open: None,
+            state: State::default(),
+            queue: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn finish(&mut self, cut: bool) {
+        self.done = true;
+        if let Some(c) = self.open.take() {
+            self.queue.push_back(Command::End(c));
+        }
+        if cut && self.time.map_or(true, |t| t < self.end) {
+            self.queue.push_back(Command::Timestamp(self.end));
+        }
+        self.state.dump(SimulationCommand::Dumpall, &mut self.queue);
+    }
+}
+
+impl<I> Iterator for Truncate<I> where I: Iterator<Item=Result<Command, Error>> {
+    type Item = Result<Command, Error>;
+
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        loop {
+            if let Some(c) = self.queue.pop_front() {
+                return Some(Ok(c));
+            }
+            if self.done {
+                return None;
+            }
+
+            let c = match self.commands.next() {
+                Some(Ok(c)) => c,
+                Some(Err(e)) => return Some(Err(e)),
+                None => { self.finish(false); continue; }
+            };
+
+            match c {
+                Command::Timestamp(t) if t > self.end => { self.finish(true); continue; }
+                Command::Timestamp(t) => self.time = Some(t),
+                Command::Begin(s) => self.open = Some(s),
+                Command::End(_) => self.open = None,
+                _ => self.state.update(&c),
+            }
+            return Some(Ok(c));
+        }
+    }
+}
+
+/// Write `header` and the data section in `commands` to `w`, ending it at timestamp `end` with a
+/// final `$dumpall`.
+pub fn truncate<I>(header: &Header, commands: I, end: u64, w: &mut io::Write) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let mut writer = Writer::new(w);
+    try!(writer.header(header));
+    for c in Truncate::new(commands, end) {
+        try!(writer.command(&try!(c)));
+    }
+    Ok(())
+}
+
+/// Iterator adapter that extracts the part of a command stream within a time window, as a
+/// standalone data section.
+///
+/// The output starts with a timestamp at the start of the window and a `$dumpvars` block holding
+/// the value of every variable at that time, including changes made at exactly the start time.
+/// It ends before the first timestamp after the end of the window, with a final timestamp at the
+/// end of the window if the stream was cut.
+pub struct Window<I> {
+    commands: I,
+    start: u64,
+    end: u64,
+    started: bool,
+    time: Option<u64>,
+    state: State,
+    queue: VecDeque<Command>,
+    done: bool,
+}
+
+impl<I> Window<I> where I: Iterator<Item=Result<Command, Error>> {
+    /// Wrap the data section of a VCD, keeping timestamps `start` to `end` inclusive
+    pub fn new<C>(commands: C, start: u64, end: u64) -> Window<I>
+        where C: IntoIterator<Item=Result<Command, Error>, IntoIter=I> {
+        Window {
+            commands: commands.into_iter(),
+            start: start,
+            end: end,
+            started: false,
+            time: None,
+            state: State::default(),
+            queue: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn begin(&mut self) {
+        self.started = true;
+        self.time = Some(self.start);
+        self.queue.push_back(Command::Timestamp(self.start));
+        self.state.dump(SimulationCommand::Dumpvars, &mut self.queue);
+    }
+}
+
+impl<I> Iterator for Window<I> where I: Iterator<Item=Result<Command, Error>> {
+    type Item = Result<Command, Error>;
+
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        loop {
+            if let Some(c) = self.queue.pop_front() {
+                return Some(Ok(c));
+            }
+            if self.done {
+                return None;
+            }
+
+            let c = match self.commands.next() {
+                Some(Ok(c)) => c,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.done = true;
+                    if !self.started { self.begin(); }
+                    continue;
+                }
+            };
+
+            if !self.started {
+                match c {
+                    Command::Timestamp(t) if t > self.start => self.begin(),
+                    _ => { self.state.update(&c); continue; }
+                }
+            }
+
+            match c {
+                Command::Timestamp(t) if t > self.end => {
+                    self.done = true;
+                    if self.time.map_or(true, |time| time < self.end) {
+                        self.queue.push_back(Command::Timestamp(self.end));
+                    }
+                    continue;
+                }
+                Command::Timestamp(t) => self.time = Some(t),
+                _ => (),
+            }
+            self.queue.push_back(c);
+        }
+    }
+}
+
+/// Iterator adapter that passes commands through until the first timestamp after `end`, and
+/// stops there without adding anything.
+pub struct TakeUntil<I> {
+    commands: I,
+    end: u64,
+    done: bool,
+}
+
+impl<I> TakeUntil<I> where I: Iterator<Item=Result<Command, Error>> {
+    /// Wrap the data section of a VCD, keeping the commands up to timestamp `end` inclusive
+    pub fn new<C>(commands: C, end: u64) -> TakeUntil<I>
+        where C: IntoIterator<Item=Result<Command, Error>, IntoIter=I> {
+        TakeUntil { commands: commands.into_iter(), end: end, done: false }
+    }
+}
+
+impl<I> Iterator for TakeUntil<I> where I: Iterator<Item=Result<Command, Error>> {
+    type Item = Result<Command, Error>;
+
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        if self.done {
+            return None;
+        }
+        match self.commands.next() {
+            Some(Ok(Command::Timestamp(t))) if t > self.end => {
+                self.done = true;
+                None
+            }
+            c => c,
+        }
+    }
+}
+
+/// Write `header` and the part of the data section in `commands` between timestamps `start` and
+/// `end` to `w`, as a standalone VCD starting with the state at `start`.
+pub fn window<I>(header: &Header, commands: I, start: u64, end: u64, w: &mut io::Write)
+    -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let mut writer = Writer::new(w);
+    try!(writer.header(header));
+    for c in Window::new(commands, start, end) {
+        try!(writer.command(&try!(c)));
+    }
+    Ok(())
+}
+
+/// Iterator adapter that inserts a `$dumpall` snapshot of every variable at least once every
+/// `interval` time units, so that a reader can start from the nearest snapshot instead of the
+/// beginning of the file.
+///
+/// A snapshot is inserted after the first timestamp at or past each multiple of `interval`,
+/// holding the values before the changes at that timestamp, which follow it. Snapshots are not
+/// inserted inside another simulation command or before any variable has a value.
+pub struct Checkpoint<I> {
+    commands: I,
+    interval: u64,
+    next: u64,
+    open: bool,
+    state: State,
+    queue: VecDeque<Command>,
+}
+
+impl<I> Checkpoint<I> where I: Iterator<Item=Result<Command, Error>> {
+    /// Wrap the data section of a VCD, adding a snapshot every `interval` time units
+    pub fn new<C>(commands: C, interval: u64) -> Checkpoint<I>
+        where C: IntoIterator<Item=Result<Command, Error>, IntoIter=I> {
+        let interval = ::std::cmp::max(interval, 1);
+        Checkpoint {
+            commands: commands.into_iter(),
+            interval: interval,
+            next: interval,
+            open: false,
+            state: State::default(),
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<I> Iterator for Checkpoint<I> where I: Iterator<Item=Result<Command, Error>> {
+    type Item = Result<Command, Error>;
+
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        if let Some(c) = self.queue.pop_front() {
+            return Some(Ok(c));
+        }
+
+        let c = match self.commands.next() {
+            Some(Ok(c)) => c,
+            other => return other,
+        };
+
+        match c {
+            Command::Timestamp(t) if t >= self.next => {
+                self.next = (t / self.interval + 1).saturating_mul(self.interval);
+                if !self.open && !self.state.values.is_empty() {
+                    self.state.dump(SimulationCommand::Dumpall, &mut self.queue);
+                }
+            }
+            Command::Begin(_) => self.open = true,
+            Command::End(_) => self.open = false,
+            _ => self.state.update(&c),
+        }
+        Some(Ok(c))
+    }
+}
+
+/// Write `header` and the data section in `commands` to `w`, with a `$dumpall` snapshot at least
+/// every `interval` time units as described for `Checkpoint`.
+pub fn checkpoint<I>(header: &Header, commands: I, interval: u64, w: &mut io::Write)
+    -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let mut writer = Writer::new(w);
+    try!(writer.header(header));
+    for c in Checkpoint::new(commands, interval) {
+        try!(writer.command(&try!(c)));
+    }
+    Ok(())
+}
+
+fn filter_scope<F>(scope: &Scope, prefix: &str, keep: &mut F, ids: &mut HashSet<IdCode>)
+    -> Option<Scope> where F: FnMut(&str, &Var) -> bool {
+    let path = format!("{}{}", prefix, scope.identifier);
+    let children: Vec<ScopeItem> = scope.children.iter().filter_map(|item| match *item {
+        ScopeItem::Var(ref v) => {
+            if keep(&format!("{}.{}", path, v.reference), v) {
+                ids.insert(v.code);
+                Some(ScopeItem::Var(v.clone()))
+            } else {
+                None
+            }
+        }
+        ScopeItem::Scope(ref s) => {
+            filter_scope(s, &format!("{}.", path), keep, ids).map(ScopeItem::Scope)
+        }
+    }).collect();
+
+    if children.is_empty() && prefix != "" {
+        None
+    } else {
+        Some(Scope { children: children, ..scope.clone() })
+    }
+}
+
+/// Copy `header`, keeping only the variables for which `keep` returns true, and removing scopes
+/// left empty.
+///
+/// `keep` is passed each variable along with its dot-separated path from the top scope, e.g.
+/// `top.cpu.pc`. Returns the new header and the `IdCode`s of the variables kept, to pass to
+/// `Filter`.
+pub fn filter_header<F>(header: &Header, mut keep: F) -> (Header, HashSet<IdCode>)
+    where F: FnMut(&str, &Var) -> bool {
+    let mut ids = HashSet::new();
+    let mut vars = Vec::new();
+    for v in &header.vars {
+        if keep(&v.reference, v) {
+            ids.insert(v.code);
+            vars.push(v.clone());
+        }
+    }
+    let mut scopes = header.top_scopes()
+        .filter_map(|s| filter_scope(s, "", &mut keep, &mut ids)).collect::<Vec<_>>().into_iter();
+    let header = Header {
+        comment: header.comment.clone(),
+        date: header.date.clone(),
+        version: header.version.clone(),
+        timescale: header.timescale,
+        vars: vars,
+        scope: scopes.next().unwrap_or_default(),
+        other_scopes: scopes.collect(),
+        unknown: header.unknown.clone(),
+    };
+    (header, ids)
+}
+
+/// Iterator adapter that keeps only the value changes of a set of variables.
+///
+/// Timestamps are dropped when no kept change follows them, and simulation commands left empty
+/// are dropped as well.
+pub struct Filter<I> {
+    commands: I,
+    ids: HashSet<IdCode>,
+    timestamp: Option<Command>,
+    begin: Option<Command>,
+    queue: VecDeque<Command>,
+}
+
+impl<I> Filter<I> where I: Iterator<Item=Result<Command, Error>> {
+    /// Wrap the data section of a VCD, keeping the changes to the variables in `ids`
+    pub fn new<C>(commands: C, ids: HashSet<IdCode>) -> Filter<I>
+        where C: IntoIterator<Item=Result<Command, Error>, IntoIter=I> {
+        Filter {
+            commands: commands.into_iter(),
+            ids: ids,
+            timestamp: None,
+            begin: None,
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<I> Iterator for Filter<I> where I: Iterator<Item=Result<Command, Error>> {
+    type Item = Result<Command, Error>;
+
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        loop {
+            if let Some(c) = self.queue.pop_front() {
+                return Some(Ok(c));
+            }
+
+            let c = match self.commands.next() {
+                Some(Ok(c)) => c,
+                other => return other,
+            };
+
+            match c {
+                Command::Timestamp(_) => self.timestamp = Some(c),
+                Command::Begin(_) => self.begin = Some(c),
+                Command::End(_) if self.begin.take().is_some() => (),
+                c => {
+                    let keep = match c.change_value() {
+                        Some((id, _)) => self.ids.contains(&id),
+                        None => true,
+                    };
+                    if keep {
+                        self.queue.extend(self.timestamp.take());
+                        self.queue.extend(self.begin.take());
+                        self.queue.push_back(c);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Write `header` and the data section in `commands` to `w`, keeping only the variables for which
+/// `keep` returns true, as described for `filter_header`.
+pub fn filter<I, F>(header: &Header, commands: I, keep: F, w: &mut io::Write) -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>>, F: FnMut(&str, &Var) -> bool {
+    let (header, ids) = filter_header(header, keep);
+    let mut writer = Writer::new(w);
+    try!(writer.header(&header));
+    for c in Filter::new(commands, ids) {
+        try!(writer.command(&try!(c)));
+    }
+    Ok(())
+}
+
+/// Iterator adapter that converts the timestamps of a command stream from one timescale to
+/// another.
+///
+/// Timestamps are rounded down when converting to a coarser timescale, and a timestamp that
+/// rounds to the same time as the one before it is dropped.
+pub struct Rescale<I> {
+    commands: I,
+    from: Timescale,
+    to: Timescale,
+    time: Option<u64>,
+}
+
+impl<I> Rescale<I> where I: Iterator<Item=Result<Command, Error>> {
+    /// Wrap the data section of a VCD with timescale `from`, producing timestamps in `to`
+    pub fn new<C>(commands: C, from: Timescale, to: Timescale) -> Rescale<I>
+        where C: IntoIterator<Item=Result<Command, Error>, IntoIter=I> {
+        Rescale { commands: commands.into_iter(), from: from, to: to, time: None }
+    }
+}
+
+impl<I> Iterator for Rescale<I> where I: Iterator<Item=Result<Command, Error>> {
+    type Item = Result<Command, Error>;
+
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        loop {
+            return match self.commands.next() {
+                Some(Ok(Command::Timestamp(t))) => {
+                    let t = self.from.rescale(t, self.to);
+                    if self.time == Some(t) { continue; }
+                    self.time = Some(t);
+                    Some(Ok(Command::Timestamp(t)))
+                }
+                other => other,
+            };
+        }
+    }
+}
+
+/// Write `header` and the data section in `commands` to `w`, converted to timescale `to`. A
+/// header without a `$timescale` is taken to be in 1s steps.
+pub fn rescale<I>(header: &Header, commands: I, to: Timescale, w: &mut io::Write)
+    -> Result<(), Error>
+    where I: IntoIterator<Item=Result<Command, Error>> {
+    let from = header.timescale.map_or(Timescale::new(1, ::TimescaleUnit::S), Timescale::from);
+    let header = Header {
+        comment: header.comment.clone(),
+        date: header.date.clone(),
+        version: header.version.clone(),
+        timescale: Some(to.into()),
+        vars: header.vars.clone(),
+        scope: header.scope.clone(),
+        other_scopes: header.other_scopes.clone(),
+        unknown: header.unknown.clone(),
+    };
+    let mut writer = Writer::new(w);
+    try!(writer.header(&header));
+    for c in Rescale::new(commands, from, to) {
+        try!(writer.command(&try!(c)));
+    }
+    Ok(())
+}
+
+/// How `DumpFilter` presents the time between a `$dumpoff` and the next `$dumpon`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DumpMode {
+    /// Every scalar and vector variable with a value is set to `x` by the `$dumpoff` block, even
+    /// if the block does not list it, and is given its value again by the `$dumpon` block.
+    Materialize,
+
+    /// The blackout is left implicit: the changes in `$dumpoff` blocks are dropped, and so are
+    /// changes that do not alter a variable's value, such as those in a `$dumpon` or `$dumpall`
+    /// block restoring the value from before the `$dumpoff`.
+    Suppress,
+}
+
+/// The value of an unknown scalar or vector, with the same width as `v`
+fn unknown(v: &VarValue) -> Option<VarValue> {
+    match *v {
+        VarValue::Scalar(_) => Some(VarValue::Scalar(Value::X)),
+        VarValue::Vector(ref b) => Some(VarValue::Vector(b.iter().map(|_| Value::X).collect())),
+        _ => None,
+    }
+}
+
+/// Iterator adapter that applies the semantics of `$dumpoff` and `$dumpon` to a command stream,
+/// according to a `DumpMode`.
+///
+/// While dumping is off, variables are not meant to change, so any changes outside a simulation
+/// command before the next `$dumpon` are dropped.
+pub struct DumpFilter<I> {
+    commands: I,
+    mode: DumpMode,
+    /// Values as of the last change while dumping was on
+    state: State,
+    off: bool,
+    block: Option<SimulationCommand>,
+    /// Variables listed in the current `$dumpoff` block
+    listed: HashSet<IdCode>,
+    queue: VecDeque<Command>,
+}
+
+impl<I> DumpFilter<I> where I: Iterator<Item=Result<Command, Error>> {
+    /// Wrap the data section of a VCD
+    pub fn new<C>(commands: C, mode: DumpMode) -> DumpFilter<I>
+        where C: IntoIterator<Item=Result<Command, Error>, IntoIter=I> {
+        DumpFilter {
+            commands: commands.into_iter(),
+            mode: mode,
+            state: State::default(),
+            off: false,
+            block: None,
+            listed: HashSet::new(),
+            queue: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, c: Command) {
+        match c {
+            Command::Begin(k) => {
+                self.block = Some(k);
+                match k {
+                    SimulationCommand::Dumpoff => { self.off = true; self.listed.clear(); }
+                    SimulationCommand::Dumpon => self.off = false,
+                    _ => (),
+                }
+            }
+            Command::End(SimulationCommand::Dumpoff) if self.mode == DumpMode::Materialize => {
+                for &(id, ref v) in &self.state.values {
+                    if self.listed.contains(&id) { continue; }
+                    self.queue.extend(unknown(v).map(|x| x.to_command(id)));
+                }
+                self.block = None;
+            }
+            Command::End(_) => self.block = None,
+            _ => (),
+        }
+
+        let (id, v) = match c.change_value() {
+            Some(change) => change,
+            None => return self.queue.push_back(c),
+        };
+        if self.block == Some(SimulationCommand::Dumpoff) {
+            if self.mode == DumpMode::Materialize {
+                self.listed.insert(id);
+                self.queue.extend(unknown(&v).map(|x| x.to_command(id)));
+            }
+        } else if self.off && self.block.is_none() {
+            // Not expected while dumping is off
+        } else if self.mode == DumpMode::Suppress && self.state.get(id) == Some(&v) {
+            // No change
+        } else {
+            self.state.update(&c);
+            self.queue.push_back(c);
+        }
+    }
+}
+
+impl<I> Iterator for DumpFilter<I> where I: Iterator<Item=Result<Command, Error>> {
+    type Item = Result<Command, Error>;
+
+    fn next(&mut self) -> Option<Result<Command, Error>> {
+        loop {
+            if let Some(c) = self.queue.pop_front() {
+                return Some(Ok(c));
+            }
+            match self.commands.next() {
+                Some(Ok(c)) => self.push(c),
+                other => return other,
+            }
+        }
+    }
+}
+
+#[test]
+fn truncate_mid_dump() {
+    use super::Parser;
+    use super::Value::*;
+    use super::Command::*;
+    use super::SimulationCommand::*;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! a $end
+    $var wire 4 \" b $end
+    $upscope $end
+    $enddefinitions $end
+    $dumpvars 0! b0000 \" $end
+    #10 1!
+    #20 b1010 \"
+    #30 0!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    let cmds: Vec<Command> = Truncate::new(p, 25).map(Result::unwrap).collect();
+
+    assert_eq!(cmds, vec![
+        Begin(Dumpvars),
+        ChangeScalar(IdCode(0), V0),
+        ChangeVector(IdCode(1), vec![V0, V0, V0, V0].into()),
+        End(Dumpvars),
+        Timestamp(10),
+        ChangeScalar(IdCode(0), V1),
+        Timestamp(20),
+        ChangeVector(IdCode(1), vec![V1, V0, V1, V0].into()),
+        Timestamp(25),
+        Begin(Dumpall),
+        ChangeScalar(IdCode(0), V1),
+        ChangeVector(IdCode(1), vec![V1, V0, V1, V0].into()),
+        End(Dumpall),
+    ]);
+}
+
+#[test]
+fn filter_signals() {
+    use super::Parser;
+    use super::Value::*;
+    use super::Command::*;
+    use super::SimulationCommand::*;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $scope module cpu $end
+    $var wire 4 \" pc $end
+    $upscope $end
+    $scope module mem $end
+    $var wire 1 # we $end
+    $upscope $end
+    $upscope $end
+    $enddefinitions $end
+    $dumpvars 0! b0000 \" 0# $end
+    #10 1!
+    #20 b0001 \" 1#
+    #30 0!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let (filtered, ids) = filter_header(&header, |path, _| path.starts_with("top.cpu."));
+    assert_eq!(filtered.scope.children.len(), 1);
+    match filtered.scope.children[0] {
+        ScopeItem::Scope(ref s) => assert_eq!(s.identifier, "cpu"),
+        _ => panic!(),
+    }
+
+    let cmds: Vec<Command> = Filter::new(p, ids).map(Result::unwrap).collect();
+    assert_eq!(cmds, vec![
+        Begin(Dumpvars),
+        ChangeVector(IdCode(1), vec![V0, V0, V0, V0].into()),
+        End(Dumpvars),
+        Timestamp(20),
+        ChangeVector(IdCode(1), vec![V0, V0, V0, V1].into()),
+    ]);
+}
+
+#[test]
+fn window_snapshot() {
+    use super::Parser;
+    use super::Value::*;
+    use super::Command::*;
+    use super::SimulationCommand::*;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! a $end
+    $var wire 2 \" b $end
+    $upscope $end
+    $enddefinitions $end
+    $dumpvars 0! b00 \" $end
+    #10 1!
+    #20 b01 \"
+    #30 0!
+    #40 b10 \"
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    let cmds: Vec<Command> = Window::new(p, 20, 35).map(Result::unwrap).collect();
+
+    assert_eq!(cmds, vec![
+        Timestamp(20),
+        Begin(Dumpvars),
+        ChangeScalar(IdCode(0), V1),
+        ChangeVector(IdCode(1), vec![V0, V1].into()),
+        End(Dumpvars),
+        Timestamp(30),
+        ChangeScalar(IdCode(0), V0),
+        Timestamp(35),
+    ]);
+}
+
+#[test]
+fn checkpoints() {
+    use super::Parser;
+    use super::Value::*;
+    use super::Command::*;
+    use super::SimulationCommand::*;
+
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! a $end
+    $var wire 2 \" b $end
+    $upscope $end
+    $enddefinitions $end
+    $dumpvars 0! b00 \" $end
+    #10 1!
+    #20 b01 \"
+    #50 0!
+    #55 1!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    p.parse_header().unwrap();
+    let cmds: Vec<Command> = Checkpoint::new(p, 20).map(Result::unwrap).collect();
+
+    assert_eq!(cmds, vec![
+        Begin(Dumpvars),
+        ChangeScalar(IdCode(0), V0),
+        ChangeVector(IdCode(1), vec![V0, V0].into()),
+        End(Dumpvars),
+        Timestamp(10),
+        ChangeScalar(IdCode(0), V1),
+        Timestamp(20),
+        Begin(Dumpall),
+        ChangeScalar(IdCode(0), V1),
+        ChangeVector(IdCode(1), vec![V0, V0].into()),
+        End(Dumpall),
+        ChangeVector(IdCode(1), vec![V0, V1].into()),
+        Timestamp(50),
+        Begin(Dumpall),
+        ChangeScalar(IdCode(0), V1),
+        ChangeVector(IdCode(1), vec![V0, V1].into()),
+        End(Dumpall),
+        ChangeScalar(IdCode(0), V0),
+        Timestamp(55),
+        ChangeScalar(IdCode(0), V1),
+    ]);
+}
+
+#[test]
+fn rescale_timestamps() {
+    use super::{Parser, TimescaleUnit};
+
+    let sample = b"
+    $timescale 100 ps $end
+    $scope module top $end $var wire 1 ! clk $end $upscope $end
+    $enddefinitions $end
+    #0 0! #5 1! #10 0! #25 1!
+    ";
+
+    let mut p = Parser::new(&sample[..]);
+    let header = p.parse_header().unwrap();
+    let mut buf = Vec::new();
+    rescale(&header, p, Timescale::new(1, TimescaleUnit::NS), &mut buf).unwrap();
+
+    let mut p = Parser::new(&buf[..]);
+    let header = p.parse_header().unwrap();
+    assert_eq!(header.timescale, Some((1, TimescaleUnit::NS)));
+    let times: Vec<u64> = p.filter_map(|c| match c.unwrap() {
+        Command::Timestamp(t) => Some(t),
+        _ => None,
+    }).collect();
+    assert_eq!(times, vec![0, 1, 2]);
+}
+
+#[test]
+fn dump_blackout() {
+    use super::Parser;
+    use super::Value::*;
+    use super::Command::*;
+    use super::SimulationCommand::*;
+
+    let sample = b"#0 0! b10 \" r1 #
+#5 $dumpoff x! $end
+#6 1!
+#8 $dumpon 0! b11 \" r1 # $end
+#9 1!
+";
+    let run = |mode| -> Vec<Command> {
+        DumpFilter::new(Parser::new(&sample[..]), mode).map(Result::unwrap).collect()
+    };
+    let (a, b, r) = (IdCode(0), IdCode(1), IdCode(2));
+
+    assert_eq!(run(DumpMode::Materialize)[4..], [
+        Timestamp(5),
+        Begin(Dumpoff),
+        ChangeScalar(a, X),
+        ChangeVector(b, vec![X, X].into()),
+        End(Dumpoff),
+        Timestamp(6),
+        Timestamp(8),
+        Begin(Dumpon),
+        ChangeScalar(a, V0),
+        ChangeVector(b, vec![V1, V1].into()),
+        ChangeReal(r, 1.0),
+        End(Dumpon),
+        Timestamp(9),
+        ChangeScalar(a, V1),
+    ][..]);
+
+    assert_eq!(run(DumpMode::Suppress)[4..], [
+        Timestamp(5),
+        Begin(Dumpoff),
+        End(Dumpoff),
+        Timestamp(6),
+        Timestamp(8),
+        Begin(Dumpon),
+        ChangeVector(b, vec![V1, V1].into()),
+        End(Dumpon),
+        Timestamp(9),
+        ChangeScalar(a, V1),
+    ][..]);
+}
@@ -0,0 +1,304 @@
+//! Packed storage for vector values
+
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::{Index, Range};
+
+use Value;
+
+/// The value of a vector variable, stored as packed bits with the most significant bit first, in
+/// the order they appear in a VCD file.
+///
+/// Each bit of a 4-state value takes two bits of storage: one in a plane of values and one in a
+/// mask of `x` and `z` bits, using the same encoding as `bits::to_bitvec`. A 64-bit bus takes 16
+/// bytes instead of the 64 of a `Vec<Value>`. Values using any of the 9-state values other than
+/// `x` and `z` are stored unpacked, one `Value` per bit.
+#[derive(Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BitVector {
+    len: usize,
+    bits: Vec<u64>,
+    mask: Vec<u64>,
+
+    /// Set, with `bits` and `mask` empty, when the value uses 9-state values
+    values: Option<Vec<Value>>,
+}
+
+impl BitVector {
+    pub fn new() -> BitVector {
+        BitVector::default()
+    }
+
+    /// The number of bits
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The bit at index `i`, counting from the most significant bit
+    pub fn get(&self, i: usize) -> Option<Value> {
+        if i >= self.len { return None; }
+        if let Some(ref values) = self.values { return Some(values[i]); }
+        let (word, bit) = (i / 64, i % 64);
+        Some(match (self.bits[word] >> bit & 1, self.mask[word] >> bit & 1) {
+            (0, 0) => Value::V0,
+            (_, 0) => Value::V1,
+            (0, _) => Value::Z,
+            _ => Value::X,
+        })
+    }
+
+    /// Append a bit, which becomes the least significant bit
+    pub fn push(&mut self, v: Value) {
+        if let Some(ref mut values) = self.values {
+            values.push(v);
+            self.len += 1;
+            return;
+        }
+        let (bit, unknown) = match v {
+            Value::V0 => (0, 0),
+            Value::V1 => (1, 0),
+            Value::Z => (0, 1),
+            Value::X => (1, 1),
+            _ => {
+                let mut values = self.to_values();
+                values.push(v);
+                *self = BitVector { len: values.len(), bits: Vec::new(), mask: Vec::new(),
+                    values: Some(values) };
+                return;
+            }
+        };
+        let (word, pos) = (self.len / 64, self.len % 64);
+        if pos == 0 {
+            self.bits.push(0);
+            self.mask.push(0);
+        }
+        self.bits[word] |= bit << pos;
+        self.mask[word] |= unknown << pos;
+        self.len += 1;
+    }
+
+    /// Remove all bits, keeping the storage for reuse
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.bits.clear();
+        self.mask.clear();
+        self.values = None;
+    }
+
+    /// The bits at indexes `range`, counting from the most significant bit
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> BitVector {
+        assert!(range.start <= range.end && range.end <= self.len, "BitVector slice out of bounds");
+        range.map(|i| self.get(i).unwrap()).collect()
+    }
+
+    /// Iterate over the bits from the most significant
+    pub fn iter(&self) -> Iter {
+        Iter { vector: self, front: 0, back: self.len }
+    }
+
+    /// Extend the value on the left to `width` bits, as a VCD reader does with a value shorter
+    /// than its variable: with `0`s if the leftmost bit is `0` or `1`, or otherwise with copies of
+    /// the leftmost bit, so `b1` becomes `b00000001` and `bz1` becomes `bzzzzzz1`. An empty value
+    /// is extended with `0`s. Does nothing if the value already has at least `width` bits.
+    pub fn extend_to(&mut self, width: usize) {
+        if self.len >= width { return; }
+        let fill = self.fill();
+        let mut extended = BitVector::new();
+        for _ in self.len..width { extended.push(fill); }
+        for v in self.iter() { extended.push(v); }
+        *self = extended;
+    }
+
+    /// The value the vector is extended with by `extend_to`
+    fn fill(&self) -> Value {
+        match self.get(0) {
+            None | Some(Value::V0) | Some(Value::V1) => Value::V0,
+            Some(v) => v,
+        }
+    }
+
+    /// The bit numbered `bit` counting from 0 at the least significant bit, extending the value
+    /// as for `extend_to` past its most significant bit
+    fn bit(&self, bit: usize) -> Value {
+        if bit < self.len { self.get(self.len - 1 - bit).unwrap() } else { self.fill() }
+    }
+
+    /// Iterate over the bits that differ between this value and `other` as `(bit, old, new)`,
+    /// where `old` is the bit in this value and `new` the bit in `other`. Bits are numbered from
+    /// 0 at the least significant bit, as in a `[msb:0]` bus, and listed in that order. A value
+    /// with fewer bits than the other is extended as for `extend_to`.
+    ///
+    /// ```
+    /// use vcd::{BitVector, Value::*};
+    ///
+    /// let old = BitVector::from(vec![V0, V1, V1, X]);
+    /// let new = BitVector::from(vec![V1, V1, V0, V0]);
+    /// let changed: Vec<_> = old.diff(&new).collect();
+    /// assert_eq!(changed, [(0, X, V0), (1, V1, V0), (3, V0, V1)]);
+    /// ```
+    pub fn diff<'a>(&'a self, other: &'a BitVector) -> Diff<'a> {
+        Diff { old: self, new: other, bit: 0, len: self.len.max(other.len) }
+    }
+
+    /// The bits as a `Vec<Value>`, most significant first
+    pub fn to_values(&self) -> Vec<Value> {
+        self.iter().collect()
+    }
+}
+
+/// Every `Value`, so that indexing can return a reference
+static VALUES: [Value; 9] = [Value::V0, Value::V1, Value::X, Value::Z, Value::U, Value::W, Value::L,
+    Value::H, Value::DontCare];
+
+impl Index<usize> for BitVector {
+    type Output = Value;
+
+    /// The bit at index `i`, counting from the most significant bit. Panics if `i` is out of
+    /// bounds.
+    fn index(&self, i: usize) -> &Value {
+        let v = self.get(i).expect("BitVector index out of bounds");
+        VALUES.iter().find(|&&b| b == v).unwrap()
+    }
+}
+
+impl FromIterator<Value> for BitVector {
+    fn from_iter<I: IntoIterator<Item=Value>>(iter: I) -> BitVector {
+        let mut v = BitVector::new();
+        for b in iter { v.push(b); }
+        v
+    }
+}
+
+impl<'a> From<&'a [Value]> for BitVector {
+    fn from(v: &'a [Value]) -> BitVector {
+        v.iter().cloned().collect()
+    }
+}
+
+impl From<Vec<Value>> for BitVector {
+    fn from(v: Vec<Value>) -> BitVector {
+        v.into_iter().collect()
+    }
+}
+
+impl fmt::Display for BitVector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in self { try!(write!(f, "{}", b)); }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BitVector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BitVector(\"{}\")", self)
+    }
+}
+
+/// Iterator over the bits of a `BitVector`, from `BitVector::iter`
+pub struct Iter<'a> {
+    vector: &'a BitVector,
+    front: usize,
+    back: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Value;
+    fn next(&mut self) -> Option<Value> {
+        if self.front == self.back { return None; }
+        self.front += 1;
+        self.vector.get(self.front - 1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.back - self.front, Some(self.back - self.front))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<Value> {
+        if self.front == self.back { return None; }
+        self.back -= 1;
+        self.vector.get(self.back)
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+/// Iterator over the bits that differ between two `BitVector`s, from `BitVector::diff`
+pub struct Diff<'a> {
+    old: &'a BitVector,
+    new: &'a BitVector,
+    bit: usize,
+    len: usize,
+}
+
+impl<'a> Iterator for Diff<'a> {
+    type Item = (usize, Value, Value);
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.bit < self.len {
+            let bit = self.bit;
+            self.bit += 1;
+            let (old, new) = (self.old.bit(bit), self.new.bit(bit));
+            if old != new { return Some((bit, old, new)); }
+        }
+        None
+    }
+}
+
+impl<'a> IntoIterator for &'a BitVector {
+    type Item = Value;
+    type IntoIter = Iter<'a>;
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[test]
+fn packing() {
+    use Value::*;
+
+    let values: Vec<Value> = (0..150).map(|i| [V0, V1, X, Z][i * 7 % 4]).collect();
+    let v = BitVector::from(values.clone());
+    assert_eq!((v.len(), v.bits.len(), v.values.is_none()), (150, 3, true));
+    assert_eq!(v.to_values(), values);
+    let reversed: Vec<Value> = values.iter().rev().cloned().collect();
+    assert_eq!(v.iter().rev().collect::<Vec<_>>(), reversed);
+    assert_eq!(v.get(149), Some(values[149]));
+    assert_eq!(v.get(150), None);
+    assert_eq!(v.slice(60..70).to_values(), &values[60..70]);
+    assert_eq!(BitVector::from(vec![V1, V0, X, Z]).to_string(), "10xz");
+
+    let mut nine = BitVector::from(vec![V1, Z]);
+    nine.push(H);
+    nine.push(V0);
+    assert_eq!(nine.to_string(), "1zh0");
+    assert_eq!(nine, BitVector::from(vec![V1, Z, H, V0]));
+    assert!(nine != BitVector::from(vec![V1, Z, V1, V0]));
+
+    let mut short = BitVector::from(vec![V1, V0]);
+    short.extend_to(70);
+    assert_eq!((short.len(), short.get(67), short.get(68)), (70, Some(V0), Some(V1)));
+    for &(value, extended) in &[("x1", "xxxx1"), ("z", "zzzzz"), ("", "00000"), ("u0", "uuuu0")] {
+        let mut v: BitVector = value.chars().map(|c| Value::parse(c as u8).unwrap()).collect();
+        v.extend_to(5);
+        assert_eq!(v.to_string(), extended);
+    }
+    short.extend_to(4);
+    assert_eq!(short.len(), 70);
+
+    let mut wide = short.clone();
+    assert_eq!(short.diff(&wide).count(), 0);
+    wide.extend_to(100);
+    assert_eq!(short.diff(&wide).count(), 0);
+    let narrow = BitVector::from(vec![X, V1]);
+    let expected: Vec<_> = vec![(0, V1, V0), (1, X, V1)].into_iter()
+        .chain((2..100).map(|i| (i, X, V0))).collect();
+    assert_eq!(narrow.diff(&wide).collect::<Vec<_>>(), expected);
+    assert_eq!(BitVector::new().diff(&BitVector::from(vec![H])).collect::<Vec<_>>(), [(0, V0, H)]);
+}
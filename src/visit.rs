@@ -0,0 +1,134 @@
+//! Callbacks for each command, as an alternative to iterating over `Command`s
+
+use std::io;
+
+use {
+    Value,
+    PortState,
+    IdCode,
+    SimulationCommand,
+    BitVector,
+    CommandRef,
+    Error,
+    Parser,
+    SliceParser,
+};
+
+/// Receives the commands of a VCD as they are parsed by `Parser::drive`, without a `Command` being
+/// built for each. Every method does nothing by default, so an implementation only needs those
+/// it is interested in.
+///
+/// ```
+/// use vcd::{IdCode, Value, Visitor};
+///
+/// struct Edges(u32);
+///
+/// impl Visitor for Edges {
+///     fn on_scalar_change(&mut self, _id: IdCode, v: Value) {
+///         if v == Value::V1 { self.0 += 1; }
+///     }
+/// }
+///
+/// let mut parser = vcd::Parser::new(&b"#0 0! #5 1! #10 0! #15 1!\n"[..]);
+/// let mut edges = Edges(0);
+/// parser.drive(&mut edges).unwrap();
+/// assert_eq!(edges.0, 2);
+/// ```
+pub trait Visitor {
+    /// A `#xxx` timestamp
+    fn on_timestamp(&mut self, _time: u64) {}
+
+    /// A change to a scalar variable
+    fn on_scalar_change(&mut self, _id: IdCode, _v: Value) {}
+
+    /// A change to a vector variable
+    fn on_vector_change(&mut self, _id: IdCode, _v: &BitVector) {}
+
+    /// A change to a real variable
+    fn on_real_change(&mut self, _id: IdCode, _v: f64) {}
+
+    /// A change to a string variable
+    fn on_string_change(&mut self, _id: IdCode, _v: &str) {}
+
+    /// A change to a port in an extended VCD file
+    fn on_port_change(&mut self, _id: IdCode, _v: &[PortState], _strength0: u8, _strength1: u8) {}
+
+    /// The beginning of a simulation command such as `$dumpvars`
+    fn on_begin(&mut self, _c: SimulationCommand) {}
+
+    /// The `$end` of a simulation command
+    fn on_end(&mut self, _c: SimulationCommand) {}
+
+    /// Any other command, such as the header commands if the header has not been parsed
+    fn on_command(&mut self, _c: &CommandRef) {}
+}
+
+fn visit<V: Visitor>(c: CommandRef, v: &mut V) {
+    match c {
+        CommandRef::Timestamp(t) => v.on_timestamp(t),
+        CommandRef::ChangeScalar(id, x) => v.on_scalar_change(id, x),
+        CommandRef::ChangeVector(id, x) => v.on_vector_change(id, x),
+        CommandRef::ChangeReal(id, x) => v.on_real_change(id, x),
+        CommandRef::ChangeString(id, x) => v.on_string_change(id, x),
+        CommandRef::ChangePort(id, x, s0, s1) => v.on_port_change(id, x, s0, s1),
+        CommandRef::Begin(c) => v.on_begin(c),
+        CommandRef::End(c) => v.on_end(c),
+        ref c => v.on_command(c),
+    }
+}
+
+impl<R: io::Read> Parser<R> {
+    /// Parse the rest of the input, calling the methods of `visitor` for each command
+    pub fn drive<V: Visitor>(&mut self, visitor: &mut V) -> Result<(), Error> {
+        while let Some(c) = try!(self.next_event()) {
+            visit(c, visitor);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> SliceParser<'a> {
+    /// Parse the rest of the input, calling the methods of `visitor` for each command
+    pub fn drive<V: Visitor>(&mut self, visitor: &mut V) -> Result<(), Error> {
+        while let Some(c) = try!(self.next_event()) {
+            visit(c, visitor);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn visit_all() {
+    #[derive(Default)]
+    struct Record(Vec<String>);
+
+    impl Visitor for Record {
+        fn on_timestamp(&mut self, t: u64) { self.0.push(format!("#{}", t)); }
+        fn on_scalar_change(&mut self, id: IdCode, v: Value) {
+            self.0.push(format!("{}{}", v, id));
+        }
+        fn on_vector_change(&mut self, id: IdCode, v: &BitVector) {
+            self.0.push(format!("b{} {}", v, id));
+        }
+        fn on_begin(&mut self, c: SimulationCommand) { self.0.push(format!("${}", c)); }
+        fn on_end(&mut self, _: SimulationCommand) { self.0.push("$end".to_string()); }
+        fn on_command(&mut self, c: &CommandRef) { self.0.push(format!("{:?}", c)); }
+    }
+
+    let sample = b"$scope module top $end $var wire 1 ! a $end $var wire 2 \" b $end
+$upscope $end $enddefinitions $end
+$dumpvars 0! bxx \" $end #5 1! r1 # b10 \"
+";
+    let mut parser = Parser::new(&sample[..]);
+    parser.parse_header().unwrap();
+    let mut record = Record::default();
+    parser.drive(&mut record).unwrap();
+    assert_eq!(record.0, ["$dumpvars", "0!", "bxx \"", "$end", "#5", "1!", "b10 \""]);
+
+    let mut slice = SliceParser::new(&sample[..]);
+    let mut all = Record::default();
+    slice.drive(&mut all).unwrap();
+    assert_eq!(all.0.len(), 12);
+    assert_eq!(all.0[0], "ScopeDef(Module, \"top\")");
+    assert_eq!(all.0[1], "VarDef(Wire, 1, IdCode(0), \"a\", None)");
+}
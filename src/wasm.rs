@@ -0,0 +1,70 @@
+//! JavaScript bindings for use from WebAssembly (requires the `wasm` feature)
+//!
+//! Values cross into JavaScript as JSON strings in the formats used by `export::json`: the header
+//! as a tree of scopes and variables, and changes as `{"time", "id", "value"}` objects. Times are
+//! JSON numbers, so `JSON.parse` reads them exactly only up to 2^53.
+
+use wasm_bindgen::prelude::*;
+
+use Error;
+use js::{self, Batches};
+
+fn js_error(e: Error) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+/// A VCD parser over a complete file held in memory, returning its contents in batches so large
+/// files can be processed without converting them all at once.
+#[wasm_bindgen]
+pub struct VcdParser {
+    batches: Batches,
+}
+
+#[wasm_bindgen]
+impl VcdParser {
+    /// Create a parser for the VCD file in `data`
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: Vec<u8>) -> VcdParser {
+        VcdParser { batches: Batches::new(data) }
+    }
+
+    /// Parse the header, returning it as JSON. Must be called before `nextChanges`.
+    #[wasm_bindgen(js_name = parseHeader)]
+    pub fn parse_header(&mut self) -> Result<String, JsValue> {
+        self.batches.parse_header().map(|h| h.to_string()).map_err(js_error)
+    }
+
+    /// Return up to `max` value changes as a JSON array, which is empty once the file has been
+    /// read to the end.
+    #[wasm_bindgen(js_name = nextChanges)]
+    pub fn next_changes(&mut self, max: usize) -> Result<String, JsValue> {
+        self.batches.next_changes(max).map(|c| json!(c).to_string()).map_err(js_error)
+    }
+}
+
+/// Extract the part of the VCD file in `data` between times `start` and `end` inclusive, as a
+/// standalone VCD file starting with the value of every signal at `start`.
+#[wasm_bindgen(js_name = extractWindow)]
+pub fn extract_window(data: &[u8], start: u64, end: u64) -> Result<String, JsValue> {
+    js::extract(data, start, end).map_err(js_error)
+}
+
+#[test]
+fn batches() {
+    let sample = b"
+    $scope module top $end
+    $var wire 1 ! clk $end
+    $upscope $end
+    $enddefinitions $end
+    #0 0!
+    #5 1!
+    #10 0!
+    ";
+
+    let mut p = VcdParser::new(sample.to_vec());
+    assert!(p.parse_header().unwrap().contains("\"name\":\"clk\""));
+    assert_eq!(p.next_changes(2).unwrap(),
+        r#"[{"id":"!","time":0,"value":"0"},{"id":"!","time":5,"value":"1"}]"#);
+    assert_eq!(p.next_changes(2).unwrap(), r#"[{"id":"!","time":10,"value":"0"}]"#);
+    assert_eq!(p.next_changes(2).unwrap(), "[]");
+}
@@ -0,0 +1,284 @@
+//! Loading a whole waveform into memory for random access
+
+use std::collections::HashMap;
+use std::slice;
+
+use {
+    IdCode,
+    Var,
+    Vars,
+    VarValue,
+    Header,
+    Command,
+    Error,
+    WaveformSource,
+};
+
+/// The value changes of every variable in a waveform, held in memory so that the value of any
+/// variable at any time can be looked up without reading the file again. A `Waveform` is `Send`
+/// and `Sync`, so threads can share one behind an `Arc` without copying it.
+///
+/// ```
+/// use vcd::{Parser, Value, VarValue, Waveform};
+///
+/// let data = b"$scope module top $end $var wire 1 ! clk $end $upscope $end
+/// $enddefinitions $end #0 0! #5 1! #10 0!
+/// ";
+/// let wave = Waveform::load(Parser::new(&data[..])).unwrap();
+/// let clk = wave.header().find_var(&["top", "clk"]).unwrap().code;
+/// assert_eq!(wave.value_at(clk, 7), Some(&VarValue::Scalar(Value::V1)));
+/// assert_eq!(wave.changes_between(clk, 5, 20).len(), 2);
+/// ```
+pub struct Waveform {
+    header: Header,
+    signals: Vec<(IdCode, Vec<(u64, VarValue)>)>,
+    index: HashMap<IdCode, usize>,
+    /// The variables declared with the code of each signal, in the same order as `signals`
+    declared: Vec<Vec<Var>>,
+    end_time: u64,
+}
+
+impl Waveform {
+    /// Read the header and data section of `source`
+    ///
+    /// Changes to variables not declared in the header are ignored. Where a variable changes more
+    /// than once at the same time, only the last change is kept.
+    pub fn load<S: WaveformSource>(mut source: S) -> Result<Waveform, Error> {
+        let header = try!(source.parse_header());
+        let mut signals = Vec::new();
+        let mut index = HashMap::new();
+        let mut declared: Vec<Vec<Var>> = Vec::new();
+        for (_, var) in header.iter_vars() {
+            let i = *index.entry(var.code).or_insert(signals.len());
+            if i == signals.len() {
+                signals.push((var.code, Vec::new()));
+                declared.push(Vec::new());
+            }
+            declared[i].push(var.clone());
+        }
+
+        let mut time = 0;
+        for c in source {
+            let c = try!(c);
+            if let Command::Timestamp(t) = c {
+                time = t;
+                continue;
+            }
+            let (id, value) = match c.change_value() {
+                Some(change) => change,
+                None => continue,
+            };
+            if let Some(&i) = index.get(&id) {
+                let changes = &mut signals[i].1;
+                if changes.last().map_or(false, |&(t, _)| t == time) {
+                    changes.pop();
+                }
+                changes.push((time, value));
+            }
+        }
+
+        Ok(Waveform {
+            header: header,
+            signals: signals,
+            index: index,
+            declared: declared,
+            end_time: time,
+        })
+    }
+
+    /// Extend each vector change with fewer bits than its variable to the width of the variable,
+    /// as described for `BitVector::extend_to`, so that `b1` for an 8-bit variable is looked up
+    /// as `b00000001`
+    pub fn extend_vectors(&mut self) {
+        let mut widths = HashMap::new();
+        for (_, var) in self.header.iter_vars() {
+            widths.entry(var.code).or_insert(var.size as usize);
+        }
+        for &mut (id, ref mut changes) in &mut self.signals {
+            let width = widths[&id];
+            for &mut (_, ref mut value) in changes.iter_mut() {
+                if let VarValue::Vector(ref mut v) = *value {
+                    v.extend_to(width);
+                }
+            }
+        }
+    }
+
+    /// The header of the waveform
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The time of the last timestamp
+    pub fn end_time(&self) -> u64 {
+        self.end_time
+    }
+
+    /// All changes to the variable `id` as `(time, value)` in time order, or an empty slice if
+    /// there is no such variable
+    pub fn changes(&self, id: IdCode) -> &[(u64, VarValue)] {
+        match self.index.get(&id) {
+            Some(&i) => &self.signals[i].1,
+            None => &[],
+        }
+    }
+
+    /// All variables declared with the code `id`, as for `Header::vars_for_id`, but looked up
+    /// in an index built when loading
+    pub fn vars_for_id(&self, id: IdCode) -> &[Var] {
+        match self.index.get(&id) {
+            Some(&i) => &self.declared[i],
+            None => &[],
+        }
+    }
+
+    /// The value of the variable `id` at `time`, which is its last change at or before `time`,
+    /// or `None` if it had not yet been given a value
+    pub fn value_at(&self, id: IdCode, time: u64) -> Option<&VarValue> {
+        let changes = self.changes(id);
+        let n = match changes.binary_search_by_key(&time, |&(t, _)| t) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        };
+        changes[..n].last().map(|&(_, ref v)| v)
+    }
+
+    /// The changes to the variable `id` at times from `start` up to but not including `end`
+    pub fn changes_between(&self, id: IdCode, start: u64, end: u64) -> &[(u64, VarValue)] {
+        let changes = self.changes(id);
+        let from = match changes.binary_search_by_key(&start, |&(t, _)| t) {
+            Ok(i) | Err(i) => i,
+        };
+        let to = match changes.binary_search_by_key(&end, |&(t, _)| t) {
+            Ok(i) | Err(i) => i,
+        };
+        &changes[from..to.max(from)]
+    }
+
+    /// Iterate over the variables in the order they are declared, with the changes of each.
+    /// Variables sharing an `IdCode` appear once.
+    pub fn signals(&self) -> Signals {
+        Signals { iter: self.signals.iter() }
+    }
+
+    /// The changes to the variable at `path`, as for `Header::find_var`, or `None` if there is
+    /// no such variable. Aliases sharing an `IdCode` have the same changes.
+    pub fn find_changes<S: AsRef<str>>(&self, path: &[S]) -> Option<&[(u64, VarValue)]> {
+        self.header.find_var(path).map(|v| self.changes(v.code))
+    }
+
+    /// Iterate over every declared variable with its path and changes, in the order they are
+    /// declared. Unlike `signals`, each alias of a variable appears, with the changes of the
+    /// `IdCode` they share.
+    pub fn vars(&self) -> VarChanges {
+        VarChanges { vars: self.header.iter_vars(), wave: self }
+    }
+}
+
+/// Iterator over the declared variables of a `Waveform` with their changes, from
+/// `Waveform::vars`
+pub struct VarChanges<'a> {
+    vars: Vars<'a>,
+    wave: &'a Waveform,
+}
+
+impl<'a> Iterator for VarChanges<'a> {
+    type Item = (Vec<&'a str>, &'a Var, &'a [(u64, VarValue)]);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.vars.next().map(|(path, var)| (path, var, self.wave.changes(var.code)))
+    }
+}
+
+/// Iterator over the variables of a `Waveform` and their changes, from `Waveform::signals`
+pub struct Signals<'a> {
+    iter: slice::Iter<'a, (IdCode, Vec<(u64, VarValue)>)>,
+}
+
+impl<'a> Iterator for Signals<'a> {
+    type Item = (IdCode, &'a [(u64, VarValue)]);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|&(id, ref changes)| (id, &changes[..]))
+    }
+}
+
+#[test]
+fn lookup() {
+    use super::Parser;
+    use super::Value::*;
+
+    let sample = b"$scope module top $end
+$var wire 1 ! a $end $var wire 2 \" b $end $var wire 1 ! alias $end
+$upscope $end $enddefinitions $end
+#0 0! b00 \"
+#5 1! 0! 1!
+#10 b01 \" 1#
+#20
+";
+    let wave = Waveform::load(Parser::new(&sample[..])).unwrap();
+    let (a, b) = (IdCode(0), IdCode(1));
+    assert_eq!(wave.end_time(), 20);
+    assert_eq!(wave.changes(a), &[(0, VarValue::Scalar(V0)), (5, VarValue::Scalar(V1))][..]);
+    assert_eq!(wave.value_at(a, 4), Some(&VarValue::Scalar(V0)));
+    assert_eq!(wave.value_at(a, 5), Some(&VarValue::Scalar(V1)));
+    assert_eq!(wave.value_at(b, 100), Some(&VarValue::Vector(vec![V0, V1].into())));
+    assert_eq!(wave.value_at(IdCode(2), 10), None);
+    assert_eq!(wave.changes_between(b, 1, 10).len(), 0);
+    assert_eq!(wave.changes_between(b, 0, 11).len(), 2);
+    assert_eq!(wave.changes_between(b, 10, 0).len(), 0);
+    assert_eq!(wave.signals().map(|(id, c)| (id, c.len())).collect::<Vec<_>>(),
+        vec![(a, 2), (b, 2)]);
+    assert_eq!(wave.find_changes(&["top", "alias"]), Some(wave.changes(a)));
+    assert_eq!(wave.find_changes(&["top", "c"]), None);
+    let vars: Vec<(Vec<&str>, usize)> = wave.vars().map(|(p, _, c)| (p, c.len())).collect();
+    assert_eq!(vars, vec![(vec!["top", "a"], 2), (vec!["top", "b"], 2), (vec!["top", "alias"], 2)]);
+    assert_eq!(wave.vars_for_id(a).len(), 2);
+
+    let mut wave = Waveform::load(Parser::new(&b"$scope module top $end $var wire 4 ! v $end
+$upscope $end $enddefinitions $end #0 b1 ! #5 bx0 ! #10 b0101 !
+"[..])).unwrap();
+    wave.extend_vectors();
+    let values: Vec<String> = wave.changes(a).iter().map(|c| match c.1 {
+        VarValue::Vector(ref v) => v.to_string(),
+        _ => panic!("{:?}", c),
+    }).collect();
+    assert_eq!(values, ["0001", "xxx0", "0101"]);
+}
+
+#[test]
+fn aliases() {
+    use super::Parser;
+
+    let sample = b"$scope module top $end $var wire 1 ! clk $end $var wire 1 \" d $end
+$scope module a $end $var wire 1 ! clk_a $end $upscope $end
+$scope module b $end $var wire 1 ! clk_b $end $scope module c $end $var wire 1 ! clk_c $end
+$upscope $end $upscope $end $upscope $end $enddefinitions $end #0 1!
+";
+    let wave = Waveform::load(Parser::new(&sample[..])).unwrap();
+    let names = |id| wave.vars_for_id(id).iter().map(|v| v.reference.clone()).collect::<Vec<_>>();
+    assert_eq!(names(IdCode(0)), ["clk", "clk_a", "clk_b", "clk_c"]);
+    assert_eq!(names(IdCode(1)), ["d"]);
+    assert!(wave.vars_for_id(IdCode(2)).is_empty());
+    assert_eq!(wave.vars_for_id(IdCode(0)).len(), wave.header().vars_for_id(IdCode(0)).len());
+}
+
+#[test]
+fn send_sync() {
+    use std::sync::Arc;
+    use std::thread;
+    use super::Parser;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Waveform>();
+
+    let sample = b"$scope module top $end $var wire 1 ! clk $end $upscope $end
+$enddefinitions $end #0 0! #5 1!
+";
+    let wave = Arc::new(Waveform::load(Parser::new(&sample[..])).unwrap());
+    let readers: Vec<_> = (0..2).map(|_| {
+        let wave = wave.clone();
+        thread::spawn(move || wave.changes(IdCode(0)).len())
+    }).collect();
+    for r in readers {
+        assert_eq!(r.join().unwrap(), 2);
+    }
+}
@@ -1,8 +1,10 @@
 use std::io;
+use std::collections::{HashMap, HashSet};
 
 use {
     TimescaleUnit,
     Value,
+    PortState,
     IdCode,
     Scope,
     Var,
@@ -11,12 +13,19 @@ use {
     Header,
     ScopeType,
     VarType,
+    ReferenceIndex,
+    VarValue,
     Command
 };
+use read::whitespace_byte;
 
 /// Struct wrapping an `io::Write` with methods for writing VCD commands and data.
 pub struct Writer<'w> {
 	writer: &'w mut io::Write,
+	minimal_vectors: bool,
+	escape_strings: bool,
+	next_id: u128,
+	declared: HashSet<IdCode>,
 }
 
 impl<'s> Writer<'s> {
@@ -27,7 +36,28 @@ impl<'s> Writer<'s> {
     /// let mut vcd = vcd::Writer::new(&mut buf);
     /// ```
     pub fn new(writer: &mut io::Write) -> Writer {
-        Writer { writer: writer }
+        Writer {
+            writer: writer,
+            minimal_vectors: false,
+            escape_strings: false,
+            next_id: 0,
+            declared: HashSet::new(),
+        }
+    }
+
+    /// Write vector values in their shortest form, dropping leading bits that VCD readers restore
+    /// by extending the value to the width of the variable. Leading `0`s are dropped before a `0`
+    /// or `1`, leading `x`s before an `x`, and leading `z`s before a `z`, so `b00001010` is
+    /// written as `b1010` and `bxxxx` as `bx`.
+    pub fn set_minimal_vectors(&mut self, minimal: bool) {
+        self.minimal_vectors = minimal;
+    }
+
+    /// Set whether `change_string` escapes whitespace, other control characters and `\` as
+    /// `\xHH` and `\\`. This is off by default, as other readers take the escapes literally. A
+    /// `Parser` decodes them when `set_unescape_strings` is on.
+    pub fn set_escape_strings(&mut self, escape: bool) {
+        self.escape_strings = escape;
     }
 
     /// Write a header with the data from a `Header` struct
@@ -36,12 +66,20 @@ impl<'s> Writer<'s> {
         if let Some(ref s) = h.version  { try!(self.version(s)); }
         if let Some(ref s) = h.comment  { try!(self.comment(s)); }
         if let Some((v, u)) = h.timescale { try!(self.timescale(v, u)); }
-        try!(self.scope(&h.scope));
+        for &(ref k, ref v) in &h.unknown {
+            try!(writeln!(self.writer, "${} {} $end", k, v));
+        }
+        for v in &h.vars { try!(self.var(v)); }
+        for s in h.top_scopes() { try!(self.scope(s)); }
         self.enddefinitions()
     }
 
-    /// Write a `$comment` command
+    /// Write a `$comment` command, in the header or among the value changes. Fails with
+    /// `InvalidInput` if the text contains `$end`, which would end the comment early.
     pub fn comment(&mut self, v: &str) -> io::Result<()> {
+        if v.contains("$end") {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "comment contains $end"));
+        }
         writeln!(self.writer, "$comment\n    {}\n$end", v)
     }
 
@@ -73,7 +111,7 @@ impl<'s> Writer<'s> {
     /// Write a `$scope` command, a series of `$var` commands, and an `$upscope` commands from
     /// a `Scope` structure
     pub fn scope(&mut self, s: &Scope) -> io::Result<()> {
-        try!(self.scope_def(s.scope_type, &s.identifier[..]));
+        try!(self.scope_def(s.scope_type.clone(), &s.identifier[..]));
         for i in &s.children {
             match *i {
                 ScopeItem::Var(ref v) => try!(self.var(v)),
@@ -85,12 +123,83 @@ impl<'s> Writer<'s> {
 
     /// Write a `$var` command
     pub fn var_def(&mut self, t: VarType, s: u32, i: IdCode, r: &str) -> io::Result<()> {
-        writeln!(self.writer, "$var {} {} {} {} $end", t, s, i, r)
+        self.var_def_index(&t, s, i, r, None)
+    }
+
+    /// Write a `$var` command with a bit index or range after the reference
+    pub fn var_def_index(&mut self, t: &VarType, s: u32, i: IdCode, r: &str,
+        index: Option<ReferenceIndex>) -> io::Result<()> {
+        self.next_id = ::std::cmp::max(self.next_id, i.0 + 1);
+        self.declared.insert(i);
+        try!(if *t == VarType::Port && s > 1 {
+            // Extended VCD gives the size of vector ports as a range
+            write!(self.writer, "$var {} [{}:0] {} {}", t, s - 1, i, r)
+        } else {
+            write!(self.writer, "$var {} {} {} {}", t, s, i, r)
+        });
+        if let Some(index) = index { try!(write!(self.writer, " {}", index)); }
+        writeln!(self.writer, " $end")
     }
 
     /// Write a `$var` command from a `Var` structure
     pub fn var(&mut self, v: &Var) -> io::Result<()> {
-        self.var_def(v.var_type, v.size, v.code, &v.reference[..])
+        self.var_def_index(&v.var_type, v.size, v.code, &v.reference[..], v.index)
+    }
+
+    /// Write a `$scope module` command. Close the scope with `upscope`.
+    pub fn add_module(&mut self, identifier: &str) -> io::Result<()> {
+        self.scope_def(ScopeType::Module, identifier)
+    }
+
+    /// Write a `$var wire` command with the next unused identifier code, returning the code to
+    /// use for its value changes
+    ///
+    /// ```
+    /// let mut buf = Vec::new();
+    /// let mut vcd = vcd::Writer::new(&mut buf);
+    /// vcd.timescale(1, vcd::TimescaleUnit::NS).unwrap();
+    /// vcd.add_module("top").unwrap();
+    /// let clk = vcd.add_wire(1, "clk").unwrap();
+    /// let data = vcd.add_wire(8, "data").unwrap();
+    /// vcd.upscope().unwrap();
+    /// vcd.enddefinitions().unwrap();
+    ///
+    /// vcd.timestamp(0).unwrap();
+    /// vcd.change_scalar(clk, vcd::Value::V1).unwrap();
+    /// vcd.change_vector(data, &[vcd::Value::V0, vcd::Value::V1]).unwrap();
+    /// ```
+    pub fn add_wire(&mut self, width: u32, reference: &str) -> io::Result<IdCode> {
+        self.new_var(VarType::Wire, width, reference)
+    }
+
+    /// Declare a new variable with the next unused identifier code, returning its code
+    fn new_var(&mut self, t: VarType, s: u32, r: &str) -> io::Result<IdCode> {
+        let id = IdCode(self.next_id);
+        try!(self.var_def(t, s, id, r));
+        Ok(id)
+    }
+
+    /// Write a `$var` command for a 1-bit wire, returning a handle for setting its value
+    pub fn bool_var(&mut self, reference: &str) -> io::Result<BoolSignal> {
+        self.new_var(VarType::Wire, 1, reference).map(BoolSignal)
+    }
+
+    /// Write a `$var` command for a `WIDTH`-bit wire, returning a handle for setting its value.
+    /// A `WIDTH` outside `1..=32` fails to compile.
+    pub fn u32_var<const WIDTH: u32>(&mut self, reference: &str)
+        -> io::Result<U32Signal<WIDTH>> {
+        let () = U32Signal::<WIDTH>::VALID;
+        self.new_var(VarType::Wire, WIDTH, reference).map(U32Signal)
+    }
+
+    /// Write a `$var` command for a real variable, returning a handle for setting its value
+    pub fn f64_var(&mut self, reference: &str) -> io::Result<F64Signal> {
+        self.new_var(VarType::Real, 64, reference).map(F64Signal)
+    }
+
+    /// Write a `$var` command for a string variable, returning a handle for setting its value
+    pub fn string_var(&mut self, reference: &str) -> io::Result<StringSignal> {
+        self.new_var(VarType::String, 1, reference).map(StringSignal)
     }
 
     /// Write a `$enddefinitions` command
@@ -98,6 +207,17 @@ impl<'s> Writer<'s> {
         writeln!(self.writer, "$enddefinitions $end")
     }
 
+    /// Write an `$attrbegin` command with the text of its arguments, such as
+    /// `misc 07 state_names 4`
+    pub fn attribute(&mut self, v: &str) -> io::Result<()> {
+        writeln!(self.writer, "$attrbegin {} $end", v)
+    }
+
+    /// Write an `$attrend` command
+    pub fn attribute_end(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "$attrend $end")
+    }
+
     /// Write a `#xxx` timestamp
     pub fn timestamp(&mut self, ts: u64) -> io::Result<()> {
         writeln!(self.writer, "#{}", ts)
@@ -110,6 +230,7 @@ impl<'s> Writer<'s> {
 
     /// Write a change to a vector variable
     pub fn change_vector(&mut self, id: IdCode, v: &[Value]) -> io::Result<()> {
+        let v = if self.minimal_vectors { minimal_vector(v) } else { v };
         try!(write!(self.writer, "b"));
         for i in v { try!(write!(self.writer, "{}", i)) }
         writeln!(self.writer, " {}", id)
@@ -120,9 +241,33 @@ impl<'s> Writer<'s> {
         writeln!(self.writer, "r{} {}", v, id)
     }
 
-    /// Write a change to a string variable
+    /// Write a change to a string variable. The empty string has no representation and fails with
+    /// `InvalidInput`, as does a string containing whitespace unless `set_escape_strings` is on.
     pub fn change_string(&mut self, id: IdCode, v: &str) -> io::Result<()> {
-        writeln!(self.writer, "s{} {}", v, id)
+        if v.is_empty() { return Err(invalid("empty string value")); }
+        if !self.escape_strings {
+            if v.bytes().any(whitespace_byte) {
+                return Err(invalid("string value contains whitespace"));
+            }
+            return writeln!(self.writer, "s{} {}", v, id);
+        }
+        try!(write!(self.writer, "s"));
+        for b in v.bytes() {
+            try!(match b {
+                b'\\' => write!(self.writer, "\\\\"),
+                0..=b' ' | 0x7f => write!(self.writer, "\\x{:02x}", b),
+                b => self.writer.write_all(&[b]),
+            });
+        }
+        writeln!(self.writer, " {}", id)
+    }
+
+    /// Write a change to a port in an extended VCD file
+    pub fn change_port(&mut self, id: IdCode, v: &[PortState], strength0: u8, strength1: u8)
+        -> io::Result<()> {
+        try!(write!(self.writer, "p"));
+        for i in v { try!(write!(self.writer, "{}", i)) }
+        writeln!(self.writer, " {} {} {}", strength0, strength1, id)
     }
 
     /// Write the beginning of a simulation command
@@ -130,6 +275,38 @@ impl<'s> Writer<'s> {
         writeln!(self.writer, "${}", c)
     }
 
+    /// Write the beginning of a `$dumpvars` command. End it with `end`.
+    pub fn begin_dumpvars(&mut self) -> io::Result<()> {
+        self.begin(SimulationCommand::Dumpvars)
+    }
+
+    /// Write a `$dumpvars` command giving the initial value of every variable. Fails with
+    /// `InvalidInput`, without writing anything, unless `values` has exactly one value for each
+    /// variable declared by this writer.
+    pub fn dump_initial(&mut self, values: &[(IdCode, VarValue)]) -> io::Result<()> {
+        let mut seen = HashSet::new();
+        for &(id, _) in values {
+            let msg = if !self.declared.contains(&id) {
+                "$dumpvars value for an undeclared variable"
+            } else if !seen.insert(id) {
+                "$dumpvars has more than one value for a variable"
+            } else {
+                continue;
+            };
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        if seen.len() != self.declared.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                "$dumpvars is missing the value of a declared variable"));
+        }
+
+        try!(self.begin_dumpvars());
+        for &(id, ref v) in values {
+            try!(self.command(&v.to_command(id)));
+        }
+        self.end()
+    }
+
     /// Write an `$end` to end a simulation command
     pub fn end(&mut self) -> io::Result<()> {
         writeln!(self.writer, "$end")
@@ -143,17 +320,386 @@ impl<'s> Writer<'s> {
             Date(ref c) => self.date(&c[..]),
             Version(ref c) => self.version(&c[..]),
             Timescale(v, u) => self.timescale(v, u),
-            ScopeDef(t, ref i) => self.scope_def(t, &i[..]),
+            ScopeDef(ref t, ref i) => self.scope_def(t.clone(), &i[..]),
             Upscope => self.upscope(),
-            VarDef(t, s, i, ref r) => self.var_def(t, s, i, &r[..]),
+            VarDef(ref t, s, i, ref r, index) => self.var_def_index(t, s, i, &r[..], index),
             Enddefinitions => self.enddefinitions(),
+            Attribute(ref a) => self.attribute(a),
+            AttributeEnd => self.attribute_end(),
+            Unknown(ref k, ref v) => writeln!(self.writer, "${} {} $end", k, v),
             Timestamp(t) => self.timestamp(t),
+            WideTimestamp(t) => writeln!(self.writer, "#{}", t),
             ChangeScalar(i, v) => self.change_scalar(i, v),
-            ChangeVector(i, ref v) => self.change_vector(i, &v[..]),
+            ChangeVector(i, ref v) if self.minimal_vectors => self.change_vector(i, &v.to_values()),
+            ChangeVector(i, ref v) => writeln!(self.writer, "b{} {}", v, i),
             ChangeReal(i, v) => self.change_real(i, v),
             ChangeString(i, ref v) => self.change_string(i, v),
+            ChangePort(i, ref v, s0, s1) => self.change_port(i, v, s0, s1),
             Begin(c) => self.begin(c),
             End(_) => self.end(),
         }
     }
 }
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// A `Writer` that checks value changes against the declared variables, to catch mistakes in a
+/// program generating a VCD when writing rather than when the output is viewed.
+///
+/// Writing a value change fails with `InvalidInput`, and writes nothing, if it comes before
+/// `$enddefinitions`, is for a variable that has not been declared, or is a vector change whose
+/// width differs from the declared size of the variable.
+pub struct CheckedWriter<'w> {
+    writer: Writer<'w>,
+    sizes: HashMap<IdCode, u32>,
+    in_header: bool,
+}
+
+impl<'w> CheckedWriter<'w> {
+    /// Create a CheckedWriter, wrapping an io::Write
+    pub fn new(writer: &mut io::Write) -> CheckedWriter {
+        CheckedWriter { writer: Writer::new(writer), sizes: HashMap::new(), in_header: true }
+    }
+
+    /// Write a header with the data from a `Header` struct, declaring its variables
+    pub fn header(&mut self, h: &Header) -> io::Result<()> {
+        for (_, v) in h.iter_vars() {
+            self.sizes.insert(v.code, v.size);
+        }
+        self.in_header = false;
+        self.writer.header(h)
+    }
+
+    fn check(&self, id: IdCode, width: Option<usize>) -> io::Result<()> {
+        if self.in_header {
+            return Err(invalid("value change before $enddefinitions"));
+        }
+        match self.sizes.get(&id) {
+            None => Err(invalid("value change for an undeclared variable")),
+            Some(&size) if width.map_or(false, |w| w != size as usize) => {
+                Err(invalid("vector width differs from the size of the variable"))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Write a command, checking value changes and recording `$var` declarations
+    pub fn command(&mut self, c: &Command) -> io::Result<()> {
+        match *c {
+            Command::VarDef(_, size, id, _, _) => { self.sizes.insert(id, size); }
+            Command::Enddefinitions => self.in_header = false,
+            Command::ChangeVector(id, ref v) => try!(self.check(id, Some(v.len()))),
+            Command::ChangePort(id, ref v, _, _) => try!(self.check(id, Some(v.len()))),
+            ref c => if let Some((id, _)) = c.change_value() { try!(self.check(id, None)); },
+        }
+        self.writer.command(c)
+    }
+
+    /// Write a `#xxx` timestamp
+    pub fn timestamp(&mut self, ts: u64) -> io::Result<()> {
+        self.writer.timestamp(ts)
+    }
+
+    /// Write a change to a scalar variable
+    pub fn change_scalar(&mut self, id: IdCode, v: Value) -> io::Result<()> {
+        try!(self.check(id, None));
+        self.writer.change_scalar(id, v)
+    }
+
+    /// Write a change to a vector variable
+    pub fn change_vector(&mut self, id: IdCode, v: &[Value]) -> io::Result<()> {
+        try!(self.check(id, Some(v.len())));
+        self.writer.change_vector(id, v)
+    }
+
+    /// Write a change to a real variable
+    pub fn change_real(&mut self, id: IdCode, v: f64) -> io::Result<()> {
+        try!(self.check(id, None));
+        self.writer.change_real(id, v)
+    }
+
+    /// Write a change to a string variable
+    pub fn change_string(&mut self, id: IdCode, v: &str) -> io::Result<()> {
+        try!(self.check(id, None));
+        self.writer.change_string(id, v)
+    }
+
+    /// Return the wrapped `Writer`, to write without checks
+    pub fn into_inner(self) -> Writer<'w> {
+        self.writer
+    }
+}
+
+impl Command {
+    /// Write the command to `w` in VCD syntax, as `Writer::command` does.
+    ///
+    /// Reading the output back with `Parser` does not always give an equal `Command`: whitespace
+    /// around the text of a `$comment`, `$date` or `$version` is trimmed, a name containing
+    /// whitespace is read as several tokens, a real NaN never equals itself, an `End` is only
+    /// read after a `Begin` and an `Unknown` command only by a parser that is not strict. String
+    /// values containing whitespace fail with `InvalidInput`.
+    pub fn write_to<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        Writer::new(&mut w).command(self)
+    }
+}
+
+/// Handle to a 1-bit variable declared with `Writer::bool_var`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BoolSignal(IdCode);
+
+impl BoolSignal {
+    pub fn id(&self) -> IdCode { self.0 }
+
+    /// Write a change to the variable
+    pub fn set(&self, w: &mut Writer, v: bool) -> io::Result<()> {
+        w.change_scalar(self.0, if v { Value::V1 } else { Value::V0 })
+    }
+}
+
+/// Handle to a `WIDTH`-bit variable declared with `Writer::u32_var`
+///
+/// Only the width is checked by the compiler: a `WIDTH` outside `1..=32` fails to compile, and a
+/// handle always writes vectors of its declared width. Whether a value fits in `WIDTH` bits can
+/// only be checked when it is set.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct U32Signal<const WIDTH: u32>(IdCode);
+
+impl<const WIDTH: u32> U32Signal<WIDTH> {
+    const VALID: () = assert!(WIDTH >= 1 && WIDTH <= 32, "U32Signal width must be 1 to 32 bits");
+
+    pub fn id(&self) -> IdCode { self.0 }
+
+    /// Write a change to the variable. Checks at runtime that `v` fits in `WIDTH` bits, failing
+    /// with `InvalidInput` rather than truncating it.
+    pub fn set(&self, w: &mut Writer, v: u32) -> io::Result<()> {
+        if WIDTH < 32 && v >> WIDTH != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                "value does not fit in the width of the variable"));
+        }
+        let bits: Vec<Value> = (0..WIDTH).rev().map(|i| {
+            if v >> i & 1 == 1 { Value::V1 } else { Value::V0 }
+        }).collect();
+        w.change_vector(self.0, &bits)
+    }
+}
+
+/// Handle to a real variable declared with `Writer::f64_var`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct F64Signal(IdCode);
+
+impl F64Signal {
+    pub fn id(&self) -> IdCode { self.0 }
+
+    /// Write a change to the variable
+    pub fn set(&self, w: &mut Writer, v: f64) -> io::Result<()> {
+        w.change_real(self.0, v)
+    }
+}
+
+/// Handle to a string variable declared with `Writer::string_var`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StringSignal(IdCode);
+
+impl StringSignal {
+    pub fn id(&self) -> IdCode { self.0 }
+
+    /// Write a change to the variable
+    pub fn set(&self, w: &mut Writer, v: &str) -> io::Result<()> {
+        w.change_string(self.0, v)
+    }
+}
+
+/// Remove the leading bits of `v` that would be restored by extending it to its full width
+fn minimal_vector(mut v: &[Value]) -> &[Value] {
+    while v.len() > 1 {
+        let fill = match v[1] {
+            Value::V0 | Value::V1 => Value::V0,
+            Value::X | Value::Z => v[1],
+            // Readers do not extend 9-state values
+            _ => break,
+        };
+        if v[0] != fill { break; }
+        v = &v[1..];
+    }
+    v
+}
+
+#[test]
+fn minimal_vectors() {
+    use super::Value::*;
+
+    let mut buf = Vec::new();
+    {
+        let mut w = Writer::new(&mut buf);
+        w.set_minimal_vectors(true);
+        let id = IdCode::from(0);
+        w.change_vector(id, &[V0, V0, V0, V0, V1, V0, V1, V0]).unwrap();
+        w.change_vector(id, &[V0, V0, V0, V0]).unwrap();
+        w.change_vector(id, &[X, X, X, V1]).unwrap();
+        w.change_vector(id, &[Z, Z, Z, Z]).unwrap();
+        w.change_vector(id, &[V0, X, X]).unwrap();
+        w.change_vector(id, &[V1, V0, V0]).unwrap();
+    }
+    assert_eq!(String::from_utf8(buf).unwrap(), "b1010 !\nb0 !\nbx1 !\nbz !\nb0xx !\nb100 !\n");
+}
+
+#[test]
+fn typed_signals() {
+    use super::{Parser, VarValue};
+    use super::Value::*;
+
+    let mut buf = Vec::new();
+    {
+        let mut w = Writer::new(&mut buf);
+        w.scope_def(ScopeType::Module, "top").unwrap();
+        w.var_def(VarType::Wire, 1, IdCode::from(3), "rst").unwrap();
+        let clk = w.bool_var("clk").unwrap();
+        let count = w.u32_var::<4>("count").unwrap();
+        let level = w.f64_var("level").unwrap();
+        let state = w.string_var("state").unwrap();
+        w.upscope().unwrap();
+        w.enddefinitions().unwrap();
+        assert_eq!(clk.id(), IdCode::from(4));
+
+        w.timestamp(0).unwrap();
+        clk.set(&mut w, true).unwrap();
+        count.set(&mut w, 5).unwrap();
+        assert_eq!(count.set(&mut w, 16).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        level.set(&mut w, 0.5).unwrap();
+        state.set(&mut w, "idle").unwrap();
+    }
+
+    let mut p = Parser::new(&buf[..]);
+    let header = p.parse_header().unwrap();
+    match header.scope.children[4] {
+        ScopeItem::Var(ref v) => assert_eq!(v.var_type, VarType::String),
+        ref i => panic!("expected var, found {:?}", i),
+    }
+    let changes: Vec<_> = p.filter_map(|c| c.unwrap().change_value()).collect();
+    assert_eq!(changes, vec![
+        (IdCode::from(4), VarValue::Scalar(V1)),
+        (IdCode::from(5), VarValue::Vector(vec![V0, V1, V0, V1].into())),
+        (IdCode::from(6), VarValue::Real(0.5)),
+        (IdCode::from(7), VarValue::String("idle".to_string())),
+    ]);
+}
+
+#[test]
+fn initial_dump() {
+    use super::{Parser, Command};
+
+    let mut buf = Vec::new();
+    {
+        let mut w = Writer::new(&mut buf);
+        w.add_module("top").unwrap();
+        let a = w.add_wire(1, "a").unwrap();
+        let b = w.add_wire(2, "b").unwrap();
+        w.upscope().unwrap();
+        w.enddefinitions().unwrap();
+        let scalar = VarValue::Scalar(Value::X);
+        assert!(w.dump_initial(&[(a, scalar.clone())]).is_err());
+        assert!(w.dump_initial(&[(a, scalar.clone()), (a, scalar.clone())]).is_err());
+        assert!(w.dump_initial(&[(a, scalar.clone()), (IdCode(5), scalar.clone())]).is_err());
+        w.dump_initial(&[(b, VarValue::Vector(vec![Value::V0, Value::Z].into())), (a, scalar)])
+            .unwrap();
+    }
+    let mut p = Parser::new(&buf[..]);
+    p.parse_header().unwrap();
+    let commands: Vec<Command> = p.map(Result::unwrap).collect();
+    assert_eq!(commands, vec![
+        Command::Begin(SimulationCommand::Dumpvars),
+        Command::ChangeVector(IdCode(1), vec![Value::V0, Value::Z].into()),
+        Command::ChangeScalar(IdCode(0), Value::X),
+        Command::End(SimulationCommand::Dumpvars),
+    ]);
+}
+
+#[test]
+fn checked() {
+    use super::Command;
+
+    let mut buf = Vec::new();
+    {
+        let mut w = CheckedWriter::new(&mut buf);
+        w.command(&Command::ScopeDef(ScopeType::Module, "top".into())).unwrap();
+        w.command(&Command::VarDef(VarType::Wire, 4, IdCode(0), "bus".into(), None)).unwrap();
+        w.command(&Command::Upscope).unwrap();
+        assert!(w.change_vector(IdCode(0), &[Value::V1; 4]).is_err());
+        w.command(&Command::Enddefinitions).unwrap();
+
+        w.timestamp(0).unwrap();
+        w.change_vector(IdCode(0), &[Value::V1; 4]).unwrap();
+        assert!(w.change_vector(IdCode(0), &[Value::V1; 3]).is_err());
+        assert!(w.command(&Command::ChangeVector(IdCode(0), vec![Value::V0].into())).is_err());
+        assert!(w.change_scalar(IdCode(1), Value::V0).is_err());
+    }
+    assert!(buf.ends_with(b"#0\nb1111 !\n"));
+}
+
+#[test]
+fn string_escapes() {
+    use super::{Parser, SliceParser, Command};
+
+    let values = ["plain", "two words", "tab\tand\nnewline", r"C:\path\x41", "ünïcode", "\\"];
+    let mut buf = Vec::new();
+    {
+        let mut w = Writer::new(&mut buf);
+        w.set_escape_strings(true);
+        for (i, v) in values.iter().enumerate() {
+            w.change_string(IdCode(i as u128), v).unwrap();
+        }
+        assert!(w.change_string(IdCode(0), "").is_err());
+    }
+    assert!(buf.starts_with(b"splain !\nstwo\\x20words \"\nstab\\x09and\\x0anewline #\n"));
+
+    let expected: Vec<Command> = values.iter().enumerate()
+        .map(|(i, v)| Command::ChangeString(IdCode(i as u128), v.to_string())).collect();
+    let mut p = Parser::new(&buf[..]);
+    p.set_unescape_strings(true);
+    assert_eq!(p.map(Result::unwrap).collect::<Vec<_>>(), expected);
+    let mut p = SliceParser::new(&buf);
+    p.set_unescape_strings(true);
+    assert_eq!(p.map(Result::unwrap).collect::<Vec<_>>(), expected);
+
+    // Backslashes not forming an escape are kept
+    let mut p = Parser::new(&b"sa\\b\\x4 !\n"[..]);
+    p.set_unescape_strings(true);
+    assert_eq!(p.next().unwrap().unwrap(), Command::ChangeString(IdCode(0), r"a\b\x4".into()));
+
+    // Strings from other writers are left as they are unless unescaping is turned on
+    let legacy = b"sC:\\path\\x41 !\n";
+    let expected = vec![Command::ChangeString(IdCode(0), r"C:\path\x41".into())];
+    assert_eq!(Parser::new(&legacy[..]).map(Result::unwrap).collect::<Vec<_>>(), expected);
+    assert_eq!(SliceParser::new(legacy).map(Result::unwrap).collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn string_round_trip() {
+    use super::{Parser, Command};
+
+    // Without escaping, strings are written as they are and read back by a default Parser
+    let mut buf = Vec::new();
+    {
+        let mut w = Writer::new(&mut buf);
+        w.change_string(IdCode(0), r"C:\path\x41").unwrap();
+        assert!(w.change_string(IdCode(0), "two words").is_err());
+        assert!(w.change_string(IdCode(0), "").is_err());
+    }
+    assert_eq!(buf, b"sC:\\path\\x41 !\n");
+    assert_eq!(Parser::new(&buf[..]).map(Result::unwrap).collect::<Vec<_>>(),
+        vec![Command::ChangeString(IdCode(0), r"C:\path\x41".into())]);
+
+    // With escaping on both sides, a space and a backslash survive
+    let mut buf = Vec::new();
+    {
+        let mut w = Writer::new(&mut buf);
+        w.set_escape_strings(true);
+        w.change_string(IdCode(0), r"a b\c").unwrap();
+    }
+    let mut p = Parser::new(&buf[..]);
+    p.set_unescape_strings(true);
+    assert_eq!(p.map(Result::unwrap).collect::<Vec<_>>(),
+        vec![Command::ChangeString(IdCode(0), r"a b\c".into())]);
+}